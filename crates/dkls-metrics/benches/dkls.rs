@@ -67,6 +67,63 @@ fn bench_dsg(c: &mut Criterion, n: u8, t: u8) {
     });
 }
 
+// Reports estimated wall-clock time of executions over simulated
+// networks of all profiles, see netsim::Timing.
+#[cfg(feature = "netsim")]
+fn bench_netsim(c: &mut Criterion, n: u8, t: u8) {
+    use std::time::Duration;
+
+    use dkls_metrics::netsim;
+
+    let shares = Runtime::new()
+        .unwrap()
+        .block_on(dkg::run_inner(None, None, n, t, None));
+    let signers = &shares[0..t as usize];
+
+    let mut group = c.benchmark_group("netsim");
+    group.sample_size(10);
+
+    for profile in &netsim::PROFILES {
+        let link = &profile.link;
+
+        group.bench_function(
+            format!("dkg-{}x{}-{}", n, t, profile.name),
+            |b| {
+                b.iter_custom(|iters| {
+                    let rt = netsim::runtime();
+                    (0..iters)
+                        .map(|i| {
+                            let seed = netsim::seed(i);
+                            let (_, timing) = rt.block_on(netsim::dkg(
+                                link, seed, None, n, t,
+                            ));
+                            timing.wall_clock()
+                        })
+                        .sum::<Duration>()
+                })
+            },
+        );
+
+        group.bench_function(
+            format!("dsg-{}x{}-{}", n, t, profile.name),
+            |b| {
+                b.iter_custom(|iters| {
+                    let rt = netsim::runtime();
+                    (0..iters)
+                        .map(|i| {
+                            let seed = netsim::seed(i);
+                            rt.block_on(netsim::dsg(link, seed, signers))
+                                .wall_clock()
+                        })
+                        .sum::<Duration>()
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
 fn dkg_2x2(c: &mut Criterion) {
     bench_dkg(c, 2, 2);
 }
@@ -115,6 +172,16 @@ fn dsg_27x15(c: &mut Criterion) {
     bench_dsg(c, 27, 15);
 }
 
+#[cfg(feature = "netsim")]
+fn netsim_3x2(c: &mut Criterion) {
+    bench_netsim(c, 3, 2);
+}
+
+#[cfg(feature = "netsim")]
+fn netsim_5x3(c: &mut Criterion) {
+    bench_netsim(c, 5, 3);
+}
+
 criterion_group!(
     name =
         benches;
@@ -132,4 +199,11 @@ criterion_group!(
 
 );
 
+#[cfg(feature = "netsim")]
+criterion_group!(netsim, netsim_3x2, netsim_5x3);
+
+#[cfg(feature = "netsim")]
+criterion_main!(benches, netsim);
+
+#[cfg(not(feature = "netsim"))]
 criterion_main!(benches);
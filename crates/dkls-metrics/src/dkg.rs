@@ -6,28 +6,46 @@ use std::{
     io::{BufRead, BufReader, Write},
     path::Path,
     sync::{Arc, Mutex},
-    time::Instant,
 };
 
 use anyhow::anyhow;
 use tokio::task::JoinSet;
 
 use msg_relay::MsgRelay;
-use sl_dkls23::keygen::{self, utils::setup_keygen, KeygenError, Keyshare};
+use sl_dkls23::{
+    k256::elliptic_curve::group::GroupEncoding,
+    keygen::{self, KeygenError, Keyshare},
+    setup::KeygenSetupMessage,
+    transcript::{verify_dkg, Transcript},
+};
 use sl_mpc_mate::{
     coord::stats::{RelayStats, Stats},
     message::*,
 };
 
 use crate::{
-    dsg, flags, key_refresh,
+    flags,
     relay::{MessageTrace, Tracing},
+    setup::setup_keygen,
+    stats,
 };
 
-#[allow(dead_code)]
+/// A traced execution of a protocol and key shares it uses or
+/// generates.
+///
+/// A saved trace is a set of files in a directory:
+/// - `<name>.instance`: instance ID of the execution
+/// - `<name>.messages`: list of message IDs
+/// - `<msg-id>.msg`: messages of the execution
+/// - `<key-id>.share.00`: first key share
+/// - ...
+///
+/// The name of a trace of DKG is a key ID of generated key shares. For
+/// other protocols it is `<key-id>.<protocol>`.
 pub struct Trace {
     instance: [u8; 32],
     shares: Vec<Arc<Keyshare>>,
+    messages: Vec<Vec<u8>>,
     relay: MsgRelay,
 }
 
@@ -39,17 +57,22 @@ impl Trace {
     ) -> Self {
         let relay = MsgRelay::new(None);
 
-        for msg in messages {
-            relay.send(msg);
+        for msg in &messages {
+            relay.send(msg.clone()).expect("traced message");
         }
 
         Trace {
             instance,
             shares,
+            messages,
             relay,
         }
     }
 
+    pub fn instance(&self) -> [u8; 32] {
+        self.instance
+    }
+
     pub fn n(&self) -> u8 {
         self.shares[0].total_parties
     }
@@ -70,59 +93,83 @@ impl Trace {
         &self.shares
     }
 
-    pub fn load<P: AsRef<Path>>(
-        key_id: &[u8],
-        base: P,
-    ) -> anyhow::Result<Trace> {
-        let base = base.as_ref().to_path_buf();
-        let key_id_str = hex::encode(key_id);
+    /// Returns a transcript of the execution.
+    pub fn transcript(&self) -> Transcript {
+        Transcript::new(self.messages.iter().cloned())
+    }
 
-        let relay = MsgRelay::new(None);
+    /// Loads a trace by a path name of its instance file.
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Trace> {
+        let path = path.as_ref();
 
-        let msg_list_file = BufReader::new(File::open(
-            base.join(format!("{}.messages", key_id_str)),
-        )?);
+        let base = path.parent().unwrap_or(Path::new("."));
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".instance"))
+            .ok_or_else(|| anyhow!("invalid instance file name"))?;
 
-        for msg_id in msg_list_file.lines() {
-            relay.send(std::fs::read(base.join(format!("{}.msg", msg_id?)))?);
-        }
+        // <key-id> or <key-id>.<protocol>
+        let key_id = name.split('.').next().unwrap_or(name);
 
-        let instance =
-            std::fs::read(base.join(format!("{}.instance", key_id_str)))?
-                .try_into()
-                .map_err(|_| anyhow!("invalid size of instance-id"))?;
+        let (instance, messages) = Self::load_messages(base, name)?;
 
         let mut shares = Vec::new();
 
         shares.push(Arc::new(
             Keyshare::from_bytes(&std::fs::read(
-                base.join(format!("{}.share.00", key_id_str)),
+                base.join(format!("{}.share.00", key_id)),
             )?)
-            .unwrap(),
+            .ok_or_else(|| anyhow!("key share decode 0"))?,
         ));
 
         for idx in 1..shares[0].total_parties {
             shares.push(Arc::new(
                 Keyshare::from_bytes(&std::fs::read(
-                    base.join(format!("{}.share.{:02}", key_id_str, idx)),
+                    base.join(format!("{}.share.{:02}", key_id, idx)),
                 )?)
                 .ok_or_else(|| anyhow!("key share decode {}", idx))?,
             ));
         }
 
-        Ok(Trace {
-            instance,
-            shares,
-            relay,
-        })
+        sort_shares(&mut shares);
+
+        Ok(Trace::new(instance, shares, messages))
     }
 
+    pub fn load_messages<P: AsRef<Path>>(
+        base: P,
+        name: &str,
+    ) -> anyhow::Result<([u8; 32], Vec<Vec<u8>>)> {
+        let base = base.as_ref();
+
+        let msg_list_file = BufReader::new(File::open(
+            base.join(format!("{}.messages", name)),
+        )?);
+
+        let mut messages = Vec::new();
+
+        for msg_id in msg_list_file.lines() {
+            let path = base.join(format!("{}.msg", msg_id?));
+            messages.push(std::fs::read(path)?);
+        }
+
+        let instance =
+            std::fs::read(base.join(format!("{}.instance", name)))?
+                .try_into()
+                .map_err(|_| anyhow!("invalid size of instance-id"))?;
+
+        Ok((instance, messages))
+    }
+
+    /// Saves a trace and returns a path name of its instance file.
     pub fn save(
         base: &Path,
+        protocol: Option<&str>,
         instance: &[u8],
         messages: Vec<Vec<u8>>,
-        shares: Vec<Arc<Keyshare>>,
-    ) -> anyhow::Result<()> {
+        shares: &[Arc<Keyshare>],
+    ) -> anyhow::Result<std::path::PathBuf> {
         let mut message_list = Vec::new();
 
         for msg in messages {
@@ -136,8 +183,13 @@ impl Trace {
 
         let key_id = hex::encode(shares[0].key_id);
 
+        let name = match protocol {
+            Some(protocol) => format!("{}.{}", key_id, protocol),
+            None => key_id.clone(),
+        };
+
         std::fs::write(
-            base.join(format!("{}.messages", &key_id)),
+            base.join(format!("{}.messages", &name)),
             message_list,
         )?;
 
@@ -150,9 +202,10 @@ impl Trace {
             )?;
         }
 
-        std::fs::write(base.join(format!("{}.instance", key_id)), instance)?;
+        let path = base.join(format!("{}.instance", name));
+        std::fs::write(&path, instance)?;
 
-        Ok(())
+        Ok(path)
     }
 
     pub async fn run_one(
@@ -175,6 +228,16 @@ impl Trace {
     }
 }
 
+// Sort by ranks, thus at least one share with rank 0 will be at
+// position 0, then by party IDs to have the same order for a live and
+// a loaded trace.
+pub(crate) fn sort_shares(shares: &mut [Arc<Keyshare>]) {
+    shares.sort_by_key(|s| {
+        let ranks = s.rank_list();
+        (ranks[s.party_id as usize], s.party_id)
+    });
+}
+
 pub async fn run_inner(
     instance: Option<[u8; 32]>,
     ranks: Option<&[u8]>,
@@ -197,82 +260,149 @@ pub async fn run_inner(
         keyshares.push(Arc::new(fini.unwrap()));
     }
 
-    // sort by ranks, thus at least one share with rank 0 will be at
-    // position 0.
-    keyshares.sort_by_key(|s| {
-        let ranks = s.rank_list();
-        ranks[s.party_id as usize]
-    });
+    sort_shares(&mut keyshares);
 
     keyshares
 }
 
+/// Runs DKG and saves its trace into `save`, if given.
+pub async fn keygen(
+    ranks: &[u8],
+    n: u8,
+    t: u8,
+    save: Option<&Path>,
+) -> anyhow::Result<Trace> {
+    let instance = rand::random();
+
+    let trace = MessageTrace::new();
+    let shares =
+        run_inner(Some(instance), Some(ranks), n, t, Some(trace.clone()))
+            .await;
+
+    let messages = trace.messages();
+
+    stats::report_trace("DKG", &messages);
+
+    if let Some(base) = save {
+        let path =
+            Trace::save(base, None, &instance, messages.clone(), &shares)?;
+        println!("DKG: trace {}", path.display());
+    }
+
+    Ok(Trace::new(instance, shares, messages))
+}
+
+pub async fn trace_cmd(
+    ranks: Vec<u8>,
+    opts: flags::TraceDkg,
+) -> Result<(), anyhow::Error> {
+    let trace = keygen(&ranks, opts.n, opts.t, Some(&opts.trace)).await?;
+
+    println!("key id: {}", hex::encode(trace.shares()[0].key_id));
+
+    Ok(())
+}
+
 pub async fn run_cmd(
     ranks: Vec<u8>,
     opts: flags::Dkg,
 ) -> Result<(), anyhow::Error> {
-    let trace = {
-        let instance = rand::random();
+    let trace = match &opts.trace {
+        Some(path) => Trace::load(path)?,
+        None => keygen(&ranks, opts.n, opts.t, None).await?,
+    };
 
-        let trace = MessageTrace::new();
+    let (n, t, ranks) = (trace.n(), trace.t(), trace.ranks());
 
-        let shares = run_inner(
-            Some(instance),
-            Some(&ranks),
-            opts.n,
-            opts.t,
-            Some(trace.clone()),
-        )
-        .await;
+    println!("DKG: N = {:2}, T = {:2}, ranks {:?}", n, t, ranks);
 
-        let messages = trace.messages();
+    stats::replay(
+        "DKG",
+        trace.relay(),
+        n as usize,
+        opts.k.unwrap_or(100),
+        |party, relay| {
+            let (setup, seed) =
+                setup_keygen(Some(trace.instance()), t, n, Some(&ranks))
+                    .swap_remove(party);
 
-        println!(
-            "DKG: traced {} messages, {} total bytes",
-            messages.len(),
-            messages.iter().map(|v| v.len()).sum::<usize>()
-        );
+            async move {
+                keygen::run(setup, seed, relay).await?;
+                Ok(())
+            }
+        },
+    )
+    .await?;
 
-        Trace::new(instance, shares, messages)
-    };
+    Ok(())
+}
 
-    let k = opts.k.unwrap_or(100);
+pub fn verify_cmd(
+    ranks: Vec<u8>,
+    opts: flags::VerifyDkg,
+) -> Result<(), anyhow::Error> {
+    let key_id = hex::decode(&opts.key_id)?;
+    let (instance, messages) =
+        Trace::load_messages(&opts.trace, &opts.key_id)?;
 
-    let mut last = None;
+    let (setup, _) =
+        setup_keygen(Some(instance), opts.t, opts.n, Some(&ranks))
+            .into_iter()
+            .next()
+            .unwrap();
 
-    let start = Instant::now();
+    let transcript = Transcript::new(messages);
+    let output = verify_dkg(&setup, &transcript, None)?;
 
-    for _ in 0..k {
-        let stats = trace.run_one(Some(&ranks), opts.n, opts.t).await?;
-        last = Some(stats);
-    }
+    stats::report_verified("DKG", &output, &transcript);
 
-    let d = start.elapsed();
-    let one = d / k as u32;
-
-    println!(
-        "DKG: N = {:2}, T = {:2}, K = {:3}, t = {:?}, {:?}",
-        opts.n, opts.t, k, one, d
-    );
-
-    if let Some(stats) = last {
-        let stats = stats.lock().unwrap();
-        println!(
-            "DKG: send {} {}, recv {} {}",
-            stats.send_count,
-            stats.send_size,
-            stats.recv_count,
-            stats.recv_size,
-        );
+    if setup.derive_key_id(&output.public_key.to_bytes()) != key_id.as_slice()
+    {
+        return Err(anyhow!("key id does not match the public key"));
     }
 
-    if opts.dsg {
-        dsg::run(&trace.shares, &opts).await?;
-    }
+    Ok(())
+}
 
-    if opts.key_refresh {
-        key_refresh::run(&trace.shares, &ranks, &opts).await?;
+#[cfg(test)]
+mod tests {
+    use crate::tests::{instance_file, trace_dir};
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn trace_replay_verify() {
+        let dir = trace_dir("dkg");
+
+        let opts = flags::TraceDkg {
+            n: 3,
+            t: 2,
+            rank: vec![],
+            trace: dir.clone(),
+        };
+        trace_cmd(vec![0; 3], opts).await.unwrap();
+
+        let path = instance_file(&dir, None);
+        let key_id = path.file_stem().unwrap().to_str().unwrap().to_owned();
+
+        let opts = flags::Dkg {
+            n: 3,
+            t: 2,
+            rank: vec![],
+            k: Some(1),
+            trace: Some(path),
+        };
+        run_cmd(vec![0; 3], opts).await.unwrap();
+
+        let opts = flags::VerifyDkg {
+            n: 3,
+            t: 2,
+            rank: vec![],
+            key_id,
+            trace: dir.clone(),
+        };
+        verify_cmd(vec![0; 3], opts).unwrap();
+
+        std::fs::remove_dir_all(dir).unwrap();
     }
-
-    Ok(())
 }
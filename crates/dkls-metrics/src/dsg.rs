@@ -3,27 +3,37 @@
 
 use std::{
     sync::{Arc, Mutex},
-    time::{Duration, Instant},
+    time::Duration,
 };
 
 use tokio::task::JoinSet;
 
 use msg_relay::MsgRelay;
-use sl_dkls23::{keygen::Keyshare, sign};
+use sl_dkls23::{
+    k256::ecdsa::Signature,
+    keygen::Keyshare,
+    sign,
+    transcript::{verify_dsg, DsgParams},
+};
 use sl_mpc_mate::coord::stats::{RelayStats, Stats};
 
 use crate::{
+    dkg::{self, Trace},
     flags,
     relay::{MessageTrace, Tracing},
+    setup::setup_dsg,
+    stats,
 };
 
+pub const CHAIN_PATH: &str = "m";
+
 pub async fn run_one(
     instance: [u8; 32],
     subset: &[Arc<Keyshare>],
     chain_path: &str,
     relay: &MsgRelay,
 ) -> Arc<Mutex<Stats>> {
-    let (setup, seed) = sign::setup_dsg(Some(instance), subset, chain_path)
+    let (setup, seed) = setup_dsg(Some(instance), subset, chain_path)
         .into_iter()
         .next()
         .unwrap();
@@ -36,85 +46,133 @@ pub async fn run_one(
     stats
 }
 
-pub async fn run(
-    keyshares: &[Arc<Keyshare>],
-    opts: &flags::Dkg,
-) -> Result<(), anyhow::Error> {
-    let chain_path = "m";
-    let subset = &keyshares[0..opts.t as usize];
-
-    let (instance, messages) = {
-        let instance = rand::random();
+/// Returns first T shares of a trace, a share with rank 0 first.
+pub fn signers(trace: &Trace) -> &[Arc<Keyshare>] {
+    &trace.shares()[0..trace.t() as usize]
+}
 
-        let trace = MessageTrace::new();
+/// Runs DSG with `signers` and saves its trace into `save`, if given.
+pub async fn record(
+    signers: &[Arc<Keyshare>],
+    save: Option<&std::path::Path>,
+) -> anyhow::Result<Trace> {
+    let instance = rand::random();
 
-        run_inner(Some(instance), subset, chain_path, Some(trace.clone()))
+    let trace = MessageTrace::new();
+    let signature =
+        run_inner(Some(instance), signers, CHAIN_PATH, Some(trace.clone()))
             .await;
 
-        (instance, trace.messages())
-    };
+    let messages = trace.messages();
+
+    stats::report_trace("DSG", &messages);
+
+    if let Some(base) = save {
+        let path = Trace::save(
+            base,
+            Some("dsg"),
+            &instance,
+            messages.clone(),
+            signers,
+        )?;
+        std::fs::write(
+            path.with_extension("signature"),
+            signature.to_bytes(),
+        )?;
+        println!("DSG: trace {}", path.display());
+    }
 
-    println!(
-        "DSG: traced {} messages, {} total bytes",
-        messages.len(),
-        messages.iter().map(|v| v.len()).sum::<usize>()
-    );
+    Ok(Trace::new(instance, signers.to_vec(), messages))
+}
 
-    let relay = MsgRelay::new(None);
+pub async fn trace_cmd(
+    ranks: Vec<u8>,
+    opts: flags::TraceDsg,
+) -> Result<(), anyhow::Error> {
+    let keys = dkg::keygen(&ranks, opts.n, opts.t, Some(&opts.trace)).await?;
 
-    for msg in messages {
-        relay.send(msg);
-    }
+    record(signers(&keys), Some(&opts.trace)).await?;
 
-    let k: usize = opts.k.unwrap_or(100);
+    Ok(())
+}
 
-    let start = Instant::now();
+/// Verifies a saved trace of DSG and its signature.
+pub fn verify_cmd(opts: flags::VerifyDsg) -> Result<(), anyhow::Error> {
+    let trace = Trace::load(&opts.trace)?;
+    let signature = Signature::from_slice(&std::fs::read(
+        opts.trace.with_extension("signature"),
+    )?)?;
 
-    let mut last = None;
+    // message IDs and verifying keys of signers, and public data of
+    // their key shares
+    let (setup, _) =
+        setup_dsg(Some(trace.instance()), signers(&trace), CHAIN_PATH)
+            .swap_remove(0);
+    let params = DsgParams::from_setup(&setup)?;
 
-    for _ in 0..k {
-        let stats = run_one(instance, subset, chain_path, &relay).await;
-        last = Some(stats);
-    }
+    let transcript = trace.transcript();
+    let output = verify_dsg(&setup, &params, &transcript, &signature)?;
 
-    let d = start.elapsed();
-
-    if let Some(stats) = last {
-        let stats = stats.lock().unwrap();
-        println!(
-            "DSG: send {} {}, recv {} {}",
-            stats.send_count,
-            stats.send_size,
-            stats.recv_count,
-            stats.recv_size,
-        );
-    }
+    stats::report_verified("DSG", &output, &transcript);
+
+    Ok(())
+}
+
+pub async fn run_cmd(
+    ranks: Vec<u8>,
+    opts: flags::Dsg,
+) -> Result<(), anyhow::Error> {
+    let trace = match &opts.trace {
+        Some(path) => Trace::load(path)?,
+        None => {
+            let save = opts.save.as_deref();
+            let keys = dkg::keygen(&ranks, opts.n, opts.t, save).await?;
+            record(signers(&keys), save).await?
+        }
+    };
+
+    let subset = signers(&trace);
 
-    let one = d / k as u32;
     println!(
-        "DSG: N = {:2}, T = {:2}, K = {:3}, t = {:?}, {:?}",
-        opts.n, opts.t, k, one, d
+        "DSG: N = {:2}, T = {:2}, ranks {:?}",
+        trace.n(),
+        trace.t(),
+        trace.ranks()
     );
 
-    // println!("pre-sign: t={:?}", pre_total / k as u32,);
-    // println!("finish:   t={:?}", fin_total / k as u32);
+    stats::replay(
+        "DSG",
+        trace.relay(),
+        subset.len(),
+        opts.k.unwrap_or(100),
+        |party, relay| {
+            let (setup, seed) =
+                setup_dsg(Some(trace.instance()), subset, CHAIN_PATH)
+                    .swap_remove(party);
+
+            async move {
+                sign::run(setup, seed, relay).await?;
+                Ok(())
+            }
+        },
+    )
+    .await?;
 
     Ok(())
 }
 
+/// Runs DSG and returns the signature.
 pub async fn run_inner(
     instance: Option<[u8; 32]>,
     shares: &[Arc<Keyshare>],
     chain_path: &str,
     trace: Option<Arc<MessageTrace>>,
-) {
+) -> Signature {
     let relay = MsgRelay::new(None);
 
     let mut parties = JoinSet::new();
 
-    // let start = Instant::now();
-
-    for (setup, seed) in sign::setup_dsg(instance, shares, chain_path) {
+    for (setup, seed) in setup_dsg(instance, shares, chain_path) {
         let relay = Tracing::new(relay.connect(), trace.clone());
         parties.spawn(sign::run(
             setup.with_ttl(Duration::from_secs(1000)),
@@ -123,6 +181,8 @@ pub async fn run_inner(
         ));
     }
 
+    let mut signature = None;
+
     while let Some(fini) = parties.join_next().await {
         let fini = fini.unwrap();
 
@@ -130,6 +190,43 @@ pub async fn run_inner(
             println!("error {err:?}");
         }
 
-        let _fini = fini.unwrap();
+        let (sign, _) = fini.unwrap();
+        signature = Some(sign);
+    }
+
+    signature.expect("at least one signer")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{instance_file, trace_dir};
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn save_and_replay() {
+        let dir = trace_dir("dsg");
+
+        let opts = |save, trace| flags::Dsg {
+            n: 4,
+            t: 3,
+            rank: vec![],
+            k: Some(1),
+            save,
+            trace,
+        };
+
+        run_cmd(vec![0; 4], opts(Some(dir.clone()), None))
+            .await
+            .unwrap();
+
+        let path = instance_file(&dir, Some("dsg"));
+        run_cmd(vec![0; 4], opts(None, Some(path.clone())))
+            .await
+            .unwrap();
+
+        verify_cmd(flags::VerifyDsg { trace: path }).unwrap();
+
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
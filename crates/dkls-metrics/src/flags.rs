@@ -20,12 +20,6 @@ xflags::xflags! {
             /// Execute DKG K times in a row. Default 100.
             optional --k k: usize
 
-            optional --dsg
-
-            optional --pre-sign
-
-            optional --key-refresh
-
             /// Path name of an instance file generated by subcommand trace-dkg.
             /// If this option is given then options `--n`, `--t` and `--rank`
             /// will be ignored.
@@ -60,16 +54,212 @@ xflags::xflags! {
             required --trace trace: PathBuf
         }
 
-        // cmd dsg {
-        //     /// Number of participants
-        //     required --n n: u8
+        /// Verify a transcript of DKG saved by subcommand trace-dkg
+        /// and report the public key.
+        cmd verify-dkg {
+            /// Number of participants
+            required --n n: u8
+
+            /// Threshold
+            required --t t: u8
+
+            /// Assign a rank to the next participant. If the number
+            /// of given ranks is less than N, assign zero.
+            repeated --rank ranks: u8
+
+            /// Key ID printed by subcommand trace-dkg
+            required --key-id key_id: String
+
+            /// Base directory of the trace
+            required --trace trace: PathBuf
+        }
+
+        /// Run DSG and report execution time.
+        cmd dsg {
+            /// Number of participants
+            required --n n: u8
+
+            /// Threshold
+            required --t t: u8
+
+            /// Assign a rank to the next participant. If the number
+            /// of given ranks is less than N, assign zero.
+            repeated --rank ranks: u8
+
+            /// Replay DSG K times in a row. Default 100.
+            optional --k k: usize
+
+            /// Save traces of DKG and DSG into given directory.
+            optional --save save: PathBuf
+
+            /// Path name of an instance file of a saved trace of DSG.
+            /// If this option is given then options `--n`, `--t` and `--rank`
+            /// will be ignored.
+            optional --trace trace: PathBuf
+        }
+
+        /// Run DKG and DSG with given parameters and save full traces
+        /// of the executions into given directory.
+        cmd trace-dsg {
+            /// Assign a rank to the next participant. If the number
+            /// of given ranks is less than N, assign zero.
+            repeated --rank ranks: u8
+
+            /// Number of participants
+            required --n n: u8
+
+            /// Threshold
+            required --t t: u8
+
+            /// Base directory to save messages, key shares, and
+            /// instance-ids of DKG and DSG. The trace of DSG is
+            /// saved as <key-id>.dsg.instance and <key-id>.dsg.messages,
+            /// the signature as <key-id>.dsg.signature.
+            required --trace trace: PathBuf
+        }
+
+        /// Verify a transcript of DSG saved by subcommand trace-dsg
+        /// and the resulting signature.
+        cmd verify-dsg {
+            /// Path name of the instance file of the trace of DSG.
+            required --trace trace: PathBuf
+        }
+
+        /// Run pre-sign and finish phases of DSG and report
+        /// execution time of each phase.
+        cmd pre-sign {
+            /// Number of participants
+            required --n n: u8
+
+            /// Threshold
+            required --t t: u8
+
+            /// Assign a rank to the next participant. If the number
+            /// of given ranks is less than N, assign zero.
+            repeated --rank ranks: u8
+
+            /// Replay pre-sign K times in a row. Default 100.
+            optional --k k: usize
+
+            /// Save traces of DKG and pre-sign into given directory.
+            optional --save save: PathBuf
+
+            /// Path name of an instance file of a saved trace of pre-sign.
+            /// If this option is given then options `--n`, `--t` and `--rank`
+            /// will be ignored.
+            optional --trace trace: PathBuf
+        }
+
+        /// Run key refresh and report execution time.
+        cmd key-refresh {
+            /// Number of participants
+            required --n n: u8
+
+            /// Threshold
+            required --t t: u8
+
+            /// Assign a rank to the next participant. If the number
+            /// of given ranks is less than N, assign zero.
+            repeated --rank ranks: u8
+
+            /// Replay key refresh K times in a row. Default 100.
+            optional --k k: usize
+
+            /// Save traces of DKG and key refresh into given directory.
+            optional --save save: PathBuf
+
+            /// Path name of an instance file of a saved trace of key refresh.
+            /// If this option is given then options `--n`, `--t` and `--rank`
+            /// will be ignored.
+            optional --trace trace: PathBuf
+        }
+
+        /// Verify a transcript of key refresh saved by subcommand
+        /// key-refresh and check that the public key is kept.
+        cmd verify-key-refresh {
+            /// Path name of the instance file of the trace of key refresh.
+            required --trace trace: PathBuf
+        }
+
+        /// Run quorum change of all parties to a new threshold and
+        /// report execution time.
+        cmd quorum-change {
+            /// Number of participants
+            required --n n: u8
+
+            /// Threshold
+            required --t t: u8
+
+            /// Assign a rank to the next participant. If the number
+            /// of given ranks is less than N, assign zero.
+            repeated --rank ranks: u8
+
+            /// New threshold. Default T.
+            optional --new-t new_t: u8
+
+            /// Replay quorum change K times in a row. Default 100.
+            optional --k k: usize
+
+            /// Save traces of DKG and quorum change into given directory.
+            optional --save save: PathBuf
+
+            /// Path name of an instance file of a saved trace of quorum change.
+            /// If this option is given then options `--n`, `--t` and `--rank`
+            /// will be ignored.
+            optional --trace trace: PathBuf
+        }
+
+        /// Verify a transcript of quorum change saved by subcommand
+        /// quorum-change and check that the public key is kept.
+        cmd verify-quorum-change {
+            /// Path name of the instance file of the trace of quorum change.
+            required --trace trace: PathBuf
+        }
+
+        /// Run export of T key shares to one party and report
+        /// execution time.
+        cmd key-export {
+            /// Number of participants
+            required --n n: u8
+
+            /// Threshold
+            required --t t: u8
 
-        //     /// Threshold
-        //     required --t t: u8
+            /// Assign a rank to the next participant. If the number
+            /// of given ranks is less than N, assign zero.
+            repeated --rank ranks: u8
+
+            /// Replay key export K times in a row. Default 100.
+            optional --k k: usize
 
-        // }
+            /// Save traces of DKG and key export into given directory.
+            optional --save save: PathBuf
 
-        // cmd trace-dsg {
-        // }
+            /// Path name of an instance file of a saved trace of key export.
+            /// If this option is given then options `--n`, `--t` and `--rank`
+            /// will be ignored.
+            optional --trace trace: PathBuf
+        }
+
+        /// Run DKG and DSG over a simulated network and report
+        /// estimated wall-clock time. Needs the netsim feature.
+        cmd netsim {
+            /// Number of participants
+            required --n n: u8
+
+            /// Threshold
+            required --t t: u8
+
+            /// Assign a rank to the next participant. If the number
+            /// of given ranks is less than N, assign zero.
+            repeated --rank ranks: u8
+
+            /// Network profile: datacenter, cross-region or mobile.
+            /// Run all profiles if not given.
+            repeated --profile profile: String
+
+            /// Execute DKG and DSG K times for each profile. Default 10.
+            optional --k k: usize
+        }
     }
 }
@@ -0,0 +1,196 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+use std::{sync::Arc, time::Duration};
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
+use tokio::task::JoinSet;
+use x25519_dalek::{PublicKey, ReusableSecret};
+
+use msg_relay::MsgRelay;
+use sl_dkls23::{
+    key_export::{export_keyshare, receive_keyshares},
+    keygen::Keyshare,
+    setup::{
+        key_export::{exporter::KeyExporter, receiver::KeyExportReceiver},
+        NoSigningKey, NoVerifyingKey,
+    },
+};
+use sl_mpc_mate::{coord::*, message::InstanceId};
+
+use crate::{
+    dkg::{self, Trace},
+    dsg::signers,
+    flags,
+    relay::{MessageTrace, Tracing},
+    stats,
+};
+
+// Random number generators of all parties are derived from the
+// instance ID to replay the execution.
+fn rng(instance: [u8; 32], party: usize) -> ChaCha20Rng {
+    ChaCha20Rng::from_seed(
+        Sha256::new()
+            .chain_update(instance)
+            .chain_update(b"key-export-party-seed")
+            .chain_update([party as u8 + 1])
+            .finalize()
+            .into(),
+    )
+}
+
+// Party 0 receives key shares, other parties export them.
+async fn export_one<R: Relay>(
+    instance: [u8; 32],
+    shares: &[Arc<Keyshare>],
+    party: usize,
+    mut relay: R,
+) -> anyhow::Result<()> {
+    let enc_key = ReusableSecret::random_from_rng(rng(instance, 0));
+    let party_vk: Vec<NoVerifyingKey> =
+        (0..shares.len()).map(NoVerifyingKey::new).collect();
+
+    if party == 0 {
+        let setup = <KeyExportReceiver>::new(
+            InstanceId::new(instance),
+            NoSigningKey,
+            0,
+            party_vk,
+            shares[0].clone(),
+            enc_key,
+        )
+        .with_ttl(Duration::from_secs(1000));
+
+        receive_keyshares(setup, relay).await?;
+    } else {
+        let setup = KeyExporter::new(
+            InstanceId::new(instance),
+            NoSigningKey,
+            party,
+            party_vk,
+            shares[party].clone(),
+            PublicKey::from(&enc_key),
+        )
+        .with_ttl(Duration::from_secs(1000));
+
+        let msg = export_keyshare(rng(instance, party), &setup)
+            .ok_or_else(|| anyhow::anyhow!("key share export"))?;
+
+        relay
+            .send(msg)
+            .await
+            .map_err(|_| anyhow::anyhow!("send message"))?;
+    }
+
+    Ok(())
+}
+
+/// Runs key export of first T key shares of a trace and saves its
+/// trace into `save`, if given.
+pub async fn record(
+    keys: &Trace,
+    save: Option<&std::path::Path>,
+) -> anyhow::Result<Trace> {
+    let instance = rand::random();
+    let shares = signers(keys);
+
+    let relay = MsgRelay::new(None);
+    let trace = MessageTrace::new();
+
+    let mut parties = JoinSet::new();
+    for party in 0..shares.len() {
+        let shares = shares.to_vec();
+        let relay = Tracing::new(relay.connect(), Some(trace.clone()));
+
+        parties.spawn(async move {
+            export_one(instance, &shares, party, relay).await
+        });
+    }
+
+    for fini in parties.join_all().await {
+        fini?;
+    }
+
+    let messages = trace.messages();
+
+    stats::report_trace("EXP", &messages);
+
+    if let Some(base) = save {
+        let path = Trace::save(
+            base,
+            Some("key-export"),
+            &instance,
+            messages.clone(),
+            shares,
+        )?;
+        println!("EXP: trace {}", path.display());
+    }
+
+    Ok(Trace::new(instance, shares.to_vec(), messages))
+}
+
+pub async fn run_cmd(
+    ranks: Vec<u8>,
+    opts: flags::KeyExport,
+) -> Result<(), anyhow::Error> {
+    let trace = match &opts.trace {
+        Some(path) => Trace::load(path)?,
+        None => {
+            let save = opts.save.as_deref();
+            let keys = dkg::keygen(&ranks, opts.n, opts.t, save).await?;
+            record(&keys, save).await?
+        }
+    };
+
+    let shares = signers(&trace);
+
+    println!(
+        "EXP: N = {:2}, T = {:2}, ranks {:?}",
+        trace.n(),
+        trace.t(),
+        trace.ranks()
+    );
+
+    stats::replay(
+        "EXP",
+        trace.relay(),
+        shares.len(),
+        opts.k.unwrap_or(100),
+        |party, relay| export_one(trace.instance(), shares, party, relay),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{instance_file, trace_dir};
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn save_and_replay() {
+        let dir = trace_dir("key-export");
+
+        let opts = |save, trace| flags::KeyExport {
+            n: 3,
+            t: 2,
+            rank: vec![],
+            k: Some(1),
+            save,
+            trace,
+        };
+
+        run_cmd(vec![0; 3], opts(Some(dir.clone()), None))
+            .await
+            .unwrap();
+
+        let path = instance_file(&dir, Some("key-export"));
+        run_cmd(vec![0; 3], opts(None, Some(path))).await.unwrap();
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -3,72 +3,199 @@
 
 use std::sync::Arc;
 
-use sl_dkls23::keygen::key_refresh::KeyshareForRefresh;
-use sl_dkls23::keygen::{
-    key_refresh::run as run_key_refresh, key_refresh::setup_key_refresh,
-    Keyshare,
-};
-use sl_mpc_mate::coord::SimpleMessageRelay;
 use tokio::task::JoinSet;
 
-pub async fn run(
+use msg_relay::MsgRelay;
+use sl_dkls23::{
+    keygen::{
+        key_refresh::{run as run_key_refresh, KeyshareForRefresh},
+        Keyshare,
+    },
+    transcript::verify_key_refresh,
+};
+use sl_mpc_mate::coord::Relay;
+
+use crate::{
+    dkg::{self, Trace},
+    flags,
+    relay::{MessageTrace, Tracing},
+    setup::setup_keygen,
+    stats,
+};
+
+async fn refresh_one<R: Relay>(
+    instance: [u8; 32],
     old_shares: &[Arc<Keyshare>],
-    ranks: &[u8],
-    opts: &crate::flags::Dkg,
-) -> Result<(), anyhow::Error> {
-    let k = opts.k.unwrap_or(100);
-
-    let start = std::time::Instant::now();
-
-    let key_shares_for_refresh: Vec<KeyshareForRefresh> = old_shares
-        .iter()
-        .map(|share| KeyshareForRefresh::from_keyshare(share, None))
-        .collect();
-
-    for _ in 0..k {
-        let coord = SimpleMessageRelay::new();
-
-        let mut parties = JoinSet::new();
-        for (setup, seed, share) in setup_key_refresh(
-            opts.t,
-            opts.n,
-            Some(ranks),
-            key_shares_for_refresh.clone(),
-        ) {
-            parties.spawn(run_key_refresh(
-                setup,
-                seed,
-                coord.connect(),
-                share,
-            ));
-        }
+    party: usize,
+    relay: R,
+) -> anyhow::Result<Keyshare> {
+    let share = &old_shares[party];
 
-        let mut new_keyshares = vec![];
+    let (setup, seed) = setup_keygen(
+        Some(instance),
+        share.threshold,
+        share.total_parties,
+        Some(&share.rank_list()),
+    )
+    .swap_remove(party);
 
-        while let Some(fini) = parties.join_next().await {
-            let fini = fini.unwrap();
+    let share = KeyshareForRefresh::from_keyshare(share, None);
 
-            if let Err(ref err) = fini {
-                println!("error {}", err);
-            }
+    Ok(run_key_refresh(setup, seed, relay, share).await?)
+}
 
-            assert!(fini.is_ok());
+// Key shares in order of party IDs, a party with index P refreshes a
+// key share with party ID P.
+fn old_shares(trace: &Trace) -> Vec<Arc<Keyshare>> {
+    let mut shares = trace.shares().to_vec();
+    shares.sort_by_key(|share| share.party_id);
+    shares
+}
 
-            let new_share = fini.unwrap();
+/// Runs key refresh of key shares of a trace and saves its trace into
+/// `save`, if given.
+pub async fn record(
+    keys: &Trace,
+    save: Option<&std::path::Path>,
+) -> anyhow::Result<Trace> {
+    let instance = rand::random();
+    let old_shares = old_shares(keys);
 
-            new_keyshares.push(new_share)
-        }
+    let relay = MsgRelay::new(None);
+    let trace = MessageTrace::new();
+
+    let mut parties = JoinSet::new();
+    for party in 0..old_shares.len() {
+        let old_shares = old_shares.clone();
+        let relay = Tracing::new(relay.connect(), Some(trace.clone()));
+
+        parties.spawn(async move {
+            refresh_one(instance, &old_shares, party, relay).await
+        });
+    }
+
+    for fini in parties.join_all().await {
+        fini?;
     }
 
-    let d = start.elapsed();
+    let messages = trace.messages();
+
+    stats::report_trace("ROT", &messages);
+
+    if let Some(base) = save {
+        let path = Trace::save(
+            base,
+            Some("key-refresh"),
+            &instance,
+            messages.clone(),
+            &old_shares,
+        )?;
+        println!("ROT: trace {}", path.display());
+    }
+
+    Ok(Trace::new(instance, old_shares, messages))
+}
+
+/// Verifies a saved trace of key refresh. The refreshed key must have
+/// the public key and the root chain code of the old key shares.
+pub fn verify_cmd(
+    opts: flags::VerifyKeyRefresh,
+) -> Result<(), anyhow::Error> {
+    let trace = Trace::load(&opts.trace)?;
+    let share = &old_shares(&trace)[0];
+
+    let (setup, _) = setup_keygen(
+        Some(trace.instance()),
+        share.threshold,
+        share.total_parties,
+        Some(&share.rank_list()),
+    )
+    .swap_remove(0);
+
+    let transcript = trace.transcript();
+    let output = verify_key_refresh(
+        &setup,
+        &transcript,
+        &share.public_key(),
+        &[],
+        Some(&share.root_chain_code()),
+    )?;
+
+    stats::report_verified("ROT", &output, &transcript);
+
+    Ok(())
+}
+
+pub async fn run_cmd(
+    ranks: Vec<u8>,
+    opts: flags::KeyRefresh,
+) -> Result<(), anyhow::Error> {
+    let trace = match &opts.trace {
+        Some(path) => Trace::load(path)?,
+        None => {
+            let save = opts.save.as_deref();
+            let keys = dkg::keygen(&ranks, opts.n, opts.t, save).await?;
+            record(&keys, save).await?
+        }
+    };
+
+    let old_shares = old_shares(&trace);
+
     println!(
-        "ROT: N={}, T={}, t={:?}, K={}, {:?}",
-        opts.n,
-        opts.t,
-        d / k as u32,
-        k,
-        d
+        "ROT: N = {:2}, T = {:2}, ranks {:?}",
+        trace.n(),
+        trace.t(),
+        trace.ranks()
     );
 
+    stats::replay(
+        "ROT",
+        trace.relay(),
+        old_shares.len(),
+        opts.k.unwrap_or(100),
+        |party, relay| {
+            let (instance, old_shares) = (trace.instance(), &old_shares);
+            async move {
+                refresh_one(instance, old_shares, party, relay).await?;
+                Ok(())
+            }
+        },
+    )
+    .await?;
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{instance_file, trace_dir};
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn save_and_replay() {
+        let dir = trace_dir("key-refresh");
+
+        let opts = |save, trace| flags::KeyRefresh {
+            n: 3,
+            t: 2,
+            rank: vec![],
+            k: Some(1),
+            save,
+            trace,
+        };
+
+        run_cmd(vec![0; 3], opts(Some(dir.clone()), None))
+            .await
+            .unwrap();
+
+        let path = instance_file(&dir, Some("key-refresh"));
+        run_cmd(vec![0; 3], opts(None, Some(path.clone())))
+            .await
+            .unwrap();
+
+        verify_cmd(flags::VerifyKeyRefresh { trace: path }).unwrap();
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -4,8 +4,15 @@
 pub mod dkg;
 pub mod dsg;
 pub mod flags;
+pub mod key_export;
 pub mod key_refresh;
+#[cfg(any(test, feature = "netsim"))]
+pub mod netsim;
+pub mod pre_sign;
+pub mod quorum_change;
 pub mod relay;
+pub mod setup;
+pub mod stats;
 
 pub fn dkg_ranks(n: u8, ranks: &[u8]) -> Vec<u8> {
     ranks
@@ -15,3 +22,48 @@ pub fn dkg_ranks(n: u8, ranks: &[u8]) -> Vec<u8> {
         .cloned()
         .collect()
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::path::{Path, PathBuf};
+
+    /// Returns a new empty directory to save traces of a test.
+    pub(crate) fn trace_dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "dkls-metrics-{}-{}-{:x}",
+            name,
+            std::process::id(),
+            rand::random::<u64>()
+        ));
+        std::fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    /// Returns a path name of the instance file of a saved trace of
+    /// `protocol`, or of DKG.
+    pub(crate) fn instance_file(
+        dir: &Path,
+        protocol: Option<&str>,
+    ) -> PathBuf {
+        std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| {
+                let name = path.file_name().and_then(|name| name.to_str());
+                let name =
+                    name.and_then(|name| name.strip_suffix(".instance"));
+
+                name.is_some_and(|name| {
+                    name.split_once('.').map(|(_, protocol)| protocol)
+                        == protocol
+                })
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn ranks() {
+        assert_eq!(super::dkg_ranks(3, &[1]), [1, 0, 0]);
+        assert_eq!(super::dkg_ranks(2, &[0, 1, 1]), [0, 1]);
+    }
+}
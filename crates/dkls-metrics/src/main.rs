@@ -1,32 +1,13 @@
 // Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
 // This software is licensed under the Silence Laboratories License Agreement.
 
-use dkls_metrics::{dkg, flags, relay::MessageTrace};
+use dkls_metrics::{
+    dkg, dkg_ranks, dsg, flags, key_export, key_refresh, pre_sign,
+    quorum_change,
+};
 
 use flags::{DklsMetrics, DklsMetricsCmd};
 
-async fn do_trace_dkg(opts: flags::TraceDkg) -> anyhow::Result<()> {
-    let instance = rand::random();
-
-    let trace = MessageTrace::new();
-    let shares = dkg::run_inner(
-        Some(instance),
-        None,
-        opts.n,
-        opts.t,
-        Some(trace.clone()),
-    )
-    .await;
-
-    let key_id = hex::encode(shares[0].key_id);
-
-    dkg::Trace::save(&opts.trace, &instance, trace.messages(), shares)?;
-
-    println!("key id: {}", key_id);
-
-    Ok(())
-}
-
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<(), anyhow::Error> {
     tracing_subscriber::fmt::init();
@@ -34,11 +15,60 @@ async fn main() -> Result<(), anyhow::Error> {
     let flags = DklsMetrics::from_env_or_exit();
 
     match flags.subcommand {
-        DklsMetricsCmd::TraceDkg(opts) => do_trace_dkg(opts).await,
+        DklsMetricsCmd::TraceDkg(opts) => {
+            dkg::trace_cmd(dkg_ranks(opts.n, &opts.rank), opts).await
+        }
 
         DklsMetricsCmd::Dkg(opts) => {
-            dkg::run_cmd(dkls_metrics::dkg_ranks(opts.n, &opts.rank), opts)
-                .await
+            dkg::run_cmd(dkg_ranks(opts.n, &opts.rank), opts).await
+        }
+
+        DklsMetricsCmd::VerifyDkg(opts) => {
+            dkg::verify_cmd(dkg_ranks(opts.n, &opts.rank), opts)
+        }
+
+        DklsMetricsCmd::Dsg(opts) => {
+            dsg::run_cmd(dkg_ranks(opts.n, &opts.rank), opts).await
+        }
+
+        DklsMetricsCmd::TraceDsg(opts) => {
+            dsg::trace_cmd(dkg_ranks(opts.n, &opts.rank), opts).await
+        }
+
+        DklsMetricsCmd::VerifyDsg(opts) => dsg::verify_cmd(opts),
+
+        DklsMetricsCmd::PreSign(opts) => {
+            pre_sign::run_cmd(dkg_ranks(opts.n, &opts.rank), opts).await
+        }
+
+        DklsMetricsCmd::KeyRefresh(opts) => {
+            key_refresh::run_cmd(dkg_ranks(opts.n, &opts.rank), opts).await
+        }
+
+        DklsMetricsCmd::VerifyKeyRefresh(opts) => {
+            key_refresh::verify_cmd(opts)
+        }
+
+        DklsMetricsCmd::QuorumChange(opts) => {
+            quorum_change::run_cmd(dkg_ranks(opts.n, &opts.rank), opts).await
         }
+
+        DklsMetricsCmd::VerifyQuorumChange(opts) => {
+            quorum_change::verify_cmd(opts)
+        }
+
+        DklsMetricsCmd::KeyExport(opts) => {
+            key_export::run_cmd(dkg_ranks(opts.n, &opts.rank), opts).await
+        }
+
+        #[cfg(feature = "netsim")]
+        DklsMetricsCmd::Netsim(opts) => {
+            dkls_metrics::netsim::run_cmd(dkg_ranks(opts.n, &opts.rank), opts)
+        }
+
+        #[cfg(not(feature = "netsim"))]
+        DklsMetricsCmd::Netsim(_) => Err(anyhow::anyhow!(
+            "dkls-metrics is built without the netsim feature"
+        )),
     }
 }
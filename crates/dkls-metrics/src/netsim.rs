@@ -0,0 +1,263 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! DKG and DSG over a simulated network, see [`SimRelay`].
+//!
+//! An execution runs all parties on one thread of a runtime with
+//! paused time, see [`runtime()`]. So the simulated time of the
+//! execution is network time only: latency, transmission and
+//! retransmission delays of messages on the critical path. Computation
+//! does not advance simulated time.
+//!
+//! Wall-clock time of an execution on separate hosts is estimated as
+//! the network time plus the compute time of one party, see
+//! [`Timing`]. The compute time of one party is the real time of the
+//! whole execution divided by the number of parties, as all parties
+//! run on the same thread one after another. The estimate assumes that
+//! all parties take the same time to compute and never compute while
+//! their messages are in flight, so it overestimates executions where
+//! computation overlaps with network transfers.
+
+use std::{sync::Arc, time::Duration};
+
+use anyhow::anyhow;
+use tokio::{
+    runtime::{Builder, Runtime},
+    task::JoinSet,
+    time::Instant,
+};
+
+use msg_relay::MsgRelay;
+use sl_dkls23::{
+    keygen::{self, Keyshare},
+    sign,
+};
+
+use crate::{
+    dkg::sort_shares,
+    dsg::CHAIN_PATH,
+    flags,
+    setup::{setup_dsg, setup_keygen},
+    stats::Latency,
+};
+
+mod network;
+
+pub use network::{Link, Network, SimRelay, RETRANSMIT_TIMEOUT};
+
+/// A network of parties connected to a relay by the same link.
+pub struct Profile {
+    pub name: &'static str,
+    pub link: Link,
+}
+
+pub const PROFILES: [Profile; 3] = [
+    Profile {
+        name: "datacenter",
+        link: Link {
+            latency: Duration::from_micros(250),
+            jitter: Duration::from_micros(50),
+            bandwidth: 1_250_000_000,
+            loss: 0.0,
+        },
+    },
+    Profile {
+        name: "cross-region",
+        link: Link {
+            latency: Duration::from_millis(40),
+            jitter: Duration::from_millis(5),
+            bandwidth: 125_000_000,
+            loss: 0.001,
+        },
+    },
+    Profile {
+        name: "mobile",
+        link: Link {
+            latency: Duration::from_millis(60),
+            jitter: Duration::from_millis(30),
+            bandwidth: 1_250_000,
+            loss: 0.01,
+        },
+    },
+];
+
+pub fn profile(name: &str) -> anyhow::Result<&'static Profile> {
+    PROFILES
+        .iter()
+        .find(|p| p.name == name)
+        .ok_or_else(|| anyhow!("unknown network profile {}", name))
+}
+
+/// Time of a simulated execution.
+#[derive(Clone, Copy)]
+pub struct Timing {
+    /// Simulated time of the execution
+    pub network: Duration,
+
+    /// Real time of the execution divided by the number of parties
+    pub compute: Duration,
+}
+
+impl Timing {
+    /// Estimated wall-clock time, network plus compute time, see the
+    /// module documentation.
+    pub fn wall_clock(&self) -> Duration {
+        self.network + self.compute
+    }
+}
+
+/// Returns a runtime to simulate a network with paused time.
+pub fn runtime() -> Runtime {
+    Builder::new_current_thread()
+        .enable_time()
+        .start_paused(true)
+        .build()
+        .unwrap()
+}
+
+/// Seed of jitter and losses of the i-th execution.
+pub fn seed(i: u64) -> [u8; 32] {
+    let mut seed = [0; 32];
+    seed[..8].copy_from_slice(&i.to_le_bytes());
+    seed
+}
+
+/// Runs DKG over a simulated network.
+///
+/// Must be called in a runtime with paused time, see [`runtime()`].
+pub async fn dkg(
+    link: &Link,
+    seed: [u8; 32],
+    ranks: Option<&[u8]>,
+    n: u8,
+    t: u8,
+) -> (Vec<Arc<Keyshare>>, Timing) {
+    let network = Network::uniform(*link, n as usize, seed);
+    let relay = MsgRelay::new(None);
+
+    let start = (Instant::now(), std::time::Instant::now());
+
+    let mut parties = JoinSet::new();
+    for (p, (setup, seed)) in
+        setup_keygen(None, t, n, ranks).into_iter().enumerate()
+    {
+        let relay = SimRelay::new(relay.connect(), network.clone(), p);
+        parties.spawn(keygen::run(setup, seed, relay));
+    }
+
+    let mut shares = Vec::with_capacity(n as usize);
+    while let Some(fini) = parties.join_next().await {
+        shares.push(Arc::new(fini.unwrap().unwrap()));
+    }
+
+    let timing = Timing {
+        network: start.0.elapsed(),
+        compute: start.1.elapsed() / n as u32,
+    };
+
+    sort_shares(&mut shares);
+
+    (shares, timing)
+}
+
+/// Runs DSG with `signers` over a simulated network.
+///
+/// Must be called in a runtime with paused time, see [`runtime()`].
+pub async fn dsg(
+    link: &Link,
+    seed: [u8; 32],
+    signers: &[Arc<Keyshare>],
+) -> Timing {
+    let network = Network::uniform(*link, signers.len(), seed);
+    let relay = MsgRelay::new(None);
+
+    let start = (Instant::now(), std::time::Instant::now());
+
+    let mut parties = JoinSet::new();
+    for (p, (setup, seed)) in
+        setup_dsg(None, signers, CHAIN_PATH).into_iter().enumerate()
+    {
+        let relay = SimRelay::new(relay.connect(), network.clone(), p);
+        parties.spawn(sign::run(setup, seed, relay));
+    }
+
+    while let Some(fini) = parties.join_next().await {
+        fini.unwrap().unwrap();
+    }
+
+    Timing {
+        network: start.0.elapsed(),
+        compute: start.1.elapsed() / signers.len() as u32,
+    }
+}
+
+fn report(label: &str, profile: &str, timing: &[Timing]) {
+    let network = Latency::new(timing.iter().map(|t| t.network).collect());
+    let compute = Latency::new(timing.iter().map(|t| t.compute).collect());
+    let wall_clock =
+        Latency::new(timing.iter().map(Timing::wall_clock).collect());
+
+    println!(
+        "{} {:12}: network p50 {:?}, compute p50 {:?}, \
+         wall-clock mean {:?}, p50 {:?}, p90 {:?}, max {:?}",
+        label,
+        profile,
+        network.percentile(50),
+        compute.percentile(50),
+        wall_clock.mean(),
+        wall_clock.percentile(50),
+        wall_clock.percentile(90),
+        wall_clock.percentile(100),
+    );
+}
+
+pub fn run_cmd(ranks: Vec<u8>, opts: flags::Netsim) -> anyhow::Result<()> {
+    let profiles = if opts.profile.is_empty() {
+        PROFILES.iter().collect()
+    } else {
+        opts.profile
+            .iter()
+            .map(|name| profile(name))
+            .collect::<anyhow::Result<Vec<_>>>()?
+    };
+
+    let k = opts.k.unwrap_or(10) as u64;
+    let (n, t) = (opts.n, opts.t);
+
+    println!("N = {:2}, T = {:2}, ranks {:?}, K = {}", n, t, ranks, k);
+
+    // paused time needs its own current-thread runtime
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let rt = runtime();
+
+            for profile in profiles {
+                let mut dkg_timing = vec![];
+                let mut dsg_timing = vec![];
+
+                for i in 0..k {
+                    let (shares, timing) = rt.block_on(dkg(
+                        &profile.link,
+                        seed(i),
+                        Some(&ranks),
+                        n,
+                        t,
+                    ));
+                    dkg_timing.push(timing);
+
+                    let signers = &shares[..t as usize];
+                    dsg_timing.push(rt.block_on(dsg(
+                        &profile.link,
+                        seed(i),
+                        signers,
+                    )));
+                }
+
+                report("DKG", profile.name, &dkg_timing);
+                report("DSG", profile.name, &dsg_timing);
+            }
+        });
+    });
+
+    Ok(())
+}
@@ -0,0 +1,543 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! A relay wrapper simulating a network.
+//!
+//! All parties of a protocol execution are connected to a relay by
+//! links of a [`Network`]. A [`Link`] has a latency, a jitter, a
+//! bandwidth and a loss rate. A message from party A to party B goes
+//! up the link of A and then down the link of B. Each party wraps its
+//! connection into a [`SimRelay`], which delivers a received message
+//! at the time it would arrive over both links.
+//!
+//! A link sends one message at a time. Messages go down a link in
+//! order of their arrival at the relay, or of the time the relay
+//! passes them to the party if it is later, e.g. when the party asks
+//! for a message after its arrival.
+//!
+//! Time is measured by [`tokio::time`]. With paused time in a
+//! current-thread runtime, see [`tokio::time::pause()`], the runtime
+//! skips delays instead of waiting for them, and computation takes no
+//! time. Elapsed time of an execution then depends only on the network
+//! and the seed of the network, not on the host.
+//!
+//! Timers of tokio have a resolution of a millisecond, thus a message
+//! is delivered no earlier than at the next millisecond after its
+//! arrival time.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use tokio::time::{sleep_until, Instant, Sleep};
+
+use sl_mpc_mate::{coord::*, message::*};
+
+/// Time to retransmit a lost message, in addition to the latency.
+pub const RETRANSMIT_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// A link between a party and the relay. Parameters are the same in
+/// both directions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Link {
+    /// One-way delay of a message
+    pub latency: Duration,
+
+    /// Upper bound of a uniformly distributed random delay added to
+    /// the latency of each message
+    pub jitter: Duration,
+
+    /// Bytes per second, zero for unlimited bandwidth
+    pub bandwidth: u64,
+
+    /// Probability to lose a message, less than 1. A lost message is
+    /// retransmitted after [`RETRANSMIT_TIMEOUT`], and could be lost
+    /// again.
+    pub loss: f64,
+}
+
+impl Link {
+    /// A link with given latency, no jitter, unlimited bandwidth and
+    /// no loss.
+    pub const fn with_latency(latency: Duration) -> Self {
+        Self {
+            latency,
+            jitter: Duration::ZERO,
+            bandwidth: 0,
+            loss: 0.0,
+        }
+    }
+
+    fn transmit_time(&self, size: usize) -> Duration {
+        if self.bandwidth == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(size as f64 / self.bandwidth as f64)
+        }
+    }
+
+    // Sends a message of given size over the link when it is free, and
+    // returns the time the message arrives at the other end.
+    fn transfer(
+        &self,
+        rng: &mut ChaCha20Rng,
+        free: &mut Option<Instant>,
+        start: Instant,
+        size: usize,
+    ) -> Instant {
+        let start = free.map_or(start, |free| free.max(start));
+        let sent = start + self.transmit_time(size);
+        *free = Some(sent);
+
+        let mut arrive = sent + self.latency + self.jitter.mul_f64(rng.gen());
+
+        while self.loss > 0.0 && rng.gen_bool(self.loss) {
+            arrive += RETRANSMIT_TIMEOUT + self.latency;
+        }
+
+        arrive
+    }
+}
+
+// Time a message arrives at the relay and time the message expires.
+type Arrival = (Instant, Instant);
+
+struct State {
+    rng: ChaCha20Rng,
+    uplink: Vec<Option<Instant>>,
+    downlink: Vec<Option<Instant>>,
+    arrivals: HashMap<MsgId, Arrival>,
+}
+
+/// Links of all parties of a protocol execution.
+pub struct Network {
+    links: Vec<Link>,
+    state: Mutex<State>,
+}
+
+impl Network {
+    /// Creates a network with a link per party.
+    ///
+    /// # Arguments
+    /// * `links` - A link of each party, by an index of the party
+    /// * `seed` - Seed of random jitter and losses
+    ///
+    /// # Panics
+    /// If a loss rate of a link is not in range `0.0..1.0`.
+    pub fn new(links: Vec<Link>, seed: [u8; 32]) -> Arc<Self> {
+        assert!(links.iter().all(|l| (0.0..1.0).contains(&l.loss)));
+
+        let n = links.len();
+
+        Arc::new(Self {
+            links,
+            state: Mutex::new(State {
+                rng: ChaCha20Rng::from_seed(seed),
+                uplink: vec![None; n],
+                downlink: vec![None; n],
+                arrivals: HashMap::new(),
+            }),
+        })
+    }
+
+    /// Creates a network of `n` parties with the same link.
+    pub fn uniform(link: Link, n: usize, seed: [u8; 32]) -> Arc<Self> {
+        Self::new(vec![link; n], seed)
+    }
+
+    /// Returns a link of a party.
+    pub fn link(&self, party: usize) -> &Link {
+        &self.links[party]
+    }
+
+    // Records the time a message sent now by a party arrives at the
+    // relay. The record is kept until the TTL of the message expires,
+    // the relay does not pass the message to anyone after that.
+    fn upload(&self, party: usize, msg: &[u8]) {
+        let Ok(hdr) = <&MsgHdr>::try_from(msg) else {
+            return;
+        };
+
+        let now = Instant::now();
+        let state = &mut *self.state.lock().unwrap();
+
+        state.arrivals.retain(|_, (_, expires)| *expires > now);
+
+        let arrive = self.links[party].transfer(
+            &mut state.rng,
+            &mut state.uplink[party],
+            now,
+            msg.len(),
+        );
+
+        state
+            .arrivals
+            .insert(*hdr.id(), (arrive, arrive + hdr.ttl()));
+    }
+
+    // Returns the time a message is at the relay, and not earlier than
+    // now.
+    fn arrival(&self, msg: &[u8]) -> Instant {
+        let now = Instant::now();
+
+        let Ok(hdr) = <&MsgHdr>::try_from(msg) else {
+            return now;
+        };
+
+        // a message from outside of the network is at the relay
+        let state = self.state.lock().unwrap();
+        match state.arrivals.get(hdr.id()) {
+            Some(&(arrive, _)) => arrive.max(now),
+            None => now,
+        }
+    }
+
+    // Sends a message of given size down the link of a party, starting
+    // at time `start`, and returns the time it arrives at the party.
+    fn download(&self, party: usize, start: Instant, size: usize) -> Instant {
+        let state = &mut *self.state.lock().unwrap();
+
+        self.links[party].transfer(
+            &mut state.rng,
+            &mut state.downlink[party],
+            start,
+            size,
+        )
+    }
+}
+
+/// A relay delivering messages over links of a [`Network`].
+///
+/// Asks for messages are not delayed.
+pub struct SimRelay<R> {
+    relay: R,
+    party: usize,
+    network: Arc<Network>,
+    // messages at the relay, by the time they could go down the link
+    incoming: Vec<(Instant, Vec<u8>)>,
+    // messages on the way down the link, by the time they arrive
+    pending: Vec<(Instant, Vec<u8>)>,
+    sleep: Pin<Box<Sleep>>,
+    closed: bool,
+}
+
+// Inserts a message into a queue ordered by time, after all messages
+// with the same time.
+fn enqueue(queue: &mut Vec<(Instant, Vec<u8>)>, at: Instant, msg: Vec<u8>) {
+    let idx = queue.partition_point(|(t, _)| *t <= at);
+    queue.insert(idx, (at, msg));
+}
+
+impl<R: Relay> SimRelay<R> {
+    /// Wraps a connection of a party.
+    ///
+    /// # Arguments
+    /// * `relay` - The underlying relay
+    /// * `network` - The network
+    /// * `party` - Index of a link of the party in the network
+    pub fn new(relay: R, network: Arc<Network>, party: usize) -> Self {
+        Self {
+            relay,
+            party,
+            network,
+            incoming: vec![],
+            pending: vec![],
+            sleep: Box::pin(sleep_until(Instant::now())),
+            closed: false,
+        }
+    }
+
+    fn receive(&mut self, msg: Vec<u8>) {
+        if msg.len() > MESSAGE_HEADER_SIZE {
+            let at = self.network.arrival(&msg);
+            enqueue(&mut self.incoming, at, msg);
+        } else {
+            enqueue(&mut self.pending, Instant::now(), msg);
+        }
+    }
+
+    // Sends messages at the relay down the link in order of their
+    // arrival. A message which is passed to the party later has a later
+    // start time, so all messages which start before now are known.
+    fn download(&mut self) {
+        let now = Instant::now();
+
+        let ready = self.incoming.partition_point(|(t, _)| *t <= now);
+
+        for (start, msg) in self.incoming.drain(..ready) {
+            let at = self.network.download(self.party, start, msg.len());
+            enqueue(&mut self.pending, at, msg);
+        }
+    }
+}
+
+impl<R: Relay> Stream for SimRelay<R> {
+    type Item = Vec<u8>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        while !this.closed {
+            match this.relay.poll_next_unpin(cx) {
+                Poll::Ready(Some(msg)) => this.receive(msg),
+                Poll::Ready(None) => this.closed = true,
+                Poll::Pending => break,
+            }
+        }
+
+        loop {
+            this.download();
+
+            let next = [this.incoming.first(), this.pending.first()]
+                .into_iter()
+                .flatten()
+                .map(|(t, _)| *t)
+                .min();
+
+            let Some(at) = next else {
+                return if this.closed {
+                    Poll::Ready(None)
+                } else {
+                    Poll::Pending
+                };
+            };
+
+            if this
+                .pending
+                .first()
+                .is_some_and(|(t, _)| *t <= Instant::now())
+            {
+                return Poll::Ready(Some(this.pending.remove(0).1));
+            }
+
+            this.sleep.as_mut().reset(at);
+
+            if this.sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl<R: Relay> Sink<Vec<u8>> for SimRelay<R> {
+    type Error = MessageSendError;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().relay.poll_ready_unpin(cx)
+    }
+
+    fn start_send(
+        self: Pin<&mut Self>,
+        item: Vec<u8>,
+    ) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+
+        if item.len() > MESSAGE_HEADER_SIZE {
+            this.network.upload(this.party, &item);
+        }
+
+        this.relay.start_send_unpin(item)
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().relay.poll_flush_unpin(cx)
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().relay.poll_close_unpin(cx)
+    }
+}
+
+impl<R: Relay> Relay for SimRelay<R> {}
+
+#[cfg(test)]
+mod tests {
+    use tokio::task::JoinSet;
+
+    use msg_relay::MsgRelay;
+    use sl_dkls23::{keygen, sign};
+
+    use crate::setup::{setup_dsg, setup_keygen};
+
+    use super::*;
+
+    const LATENCY: Duration = Duration::from_millis(10);
+
+    async fn dkg(network: &Arc<Network>) -> Duration {
+        let relay = MsgRelay::new(None);
+        let start = Instant::now();
+
+        let mut parties = JoinSet::new();
+        for (p, (setup, seed)) in
+            setup_keygen(None, 2, 3, None).into_iter().enumerate()
+        {
+            let relay = SimRelay::new(relay.connect(), network.clone(), p);
+            parties.spawn(keygen::run(setup, seed, relay));
+        }
+
+        while let Some(fini) = parties.join_next().await {
+            fini.unwrap().unwrap();
+        }
+
+        start.elapsed()
+    }
+
+    async fn dsg(network: &Arc<Network>) -> Duration {
+        let shares = keygen::utils::gen_keyshares(2, 3, None).await;
+
+        let relay = MsgRelay::new(None);
+        let start = Instant::now();
+
+        let mut parties = JoinSet::new();
+        for (p, (setup, seed)) in
+            setup_dsg(None, &shares[..2], "m").into_iter().enumerate()
+        {
+            let relay = SimRelay::new(relay.connect(), network.clone(), p);
+            parties.spawn(sign::run(setup, seed, relay));
+        }
+
+        while let Some(fini) = parties.join_next().await {
+            fini.unwrap().unwrap();
+        }
+
+        start.elapsed()
+    }
+
+    fn message(id: u8, size: usize) -> Vec<u8> {
+        allocate_message(
+            &MsgId::from([id; 32]),
+            10,
+            0,
+            &vec![0; size - MESSAGE_HEADER_SIZE],
+        )
+    }
+
+    // Without jitter, each message takes exactly two latencies, and
+    // computation takes no time.
+    #[tokio::test(start_paused = true)]
+    async fn latency() {
+        let network =
+            Network::uniform(Link::with_latency(LATENCY), 3, [0; 32]);
+
+        let elapsed = dkg(&network).await;
+
+        assert!(elapsed > Duration::ZERO);
+        assert_eq!(elapsed.as_nanos() % (2 * LATENCY).as_nanos(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bandwidth() {
+        let fast = Network::uniform(Link::with_latency(LATENCY), 2, [0; 32]);
+        let slow = Network::uniform(
+            Link {
+                bandwidth: 100_000,
+                ..Link::with_latency(LATENCY)
+            },
+            2,
+            [0; 32],
+        );
+
+        assert!(dsg(&slow).await > dsg(&fast).await + LATENCY);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn deterministic() {
+        let link = Link {
+            latency: LATENCY,
+            jitter: LATENCY,
+            bandwidth: 1_000_000,
+            loss: 0.1,
+        };
+
+        let elapsed = dkg(&Network::uniform(link, 3, [1; 32])).await;
+
+        assert!(elapsed >= 2 * LATENCY);
+        assert_eq!(dkg(&Network::uniform(link, 3, [1; 32])).await, elapsed);
+    }
+
+    // A message of a far party is passed to the receiver first, but a
+    // message of a near party arrives at the relay first and takes the
+    // narrow link of the receiver first.
+    #[tokio::test(start_paused = true)]
+    async fn arrival_order() {
+        const SIZE: usize = 1000;
+
+        let network = Network::new(
+            vec![
+                Link::with_latency(Duration::from_millis(100)),
+                Link::with_latency(LATENCY),
+                Link {
+                    // 50ms per message
+                    bandwidth: 20_000,
+                    ..Link::with_latency(LATENCY)
+                },
+            ],
+            [0; 32],
+        );
+
+        let relay = MsgRelay::new(None);
+        let mut far = SimRelay::new(relay.connect(), network.clone(), 0);
+        let mut near = SimRelay::new(relay.connect(), network.clone(), 1);
+        let mut receiver = SimRelay::new(relay.connect(), network, 2);
+
+        let (m0, m1) = (message(0, SIZE), message(1, SIZE));
+
+        for msg in [&m0, &m1] {
+            let id = <&MsgHdr>::try_from(msg.as_slice()).unwrap().id();
+            receiver.ask(id, 10).await.unwrap();
+        }
+        receiver.flush().await.unwrap();
+
+        let start = Instant::now();
+
+        far.send(m0.clone()).await.unwrap();
+        near.send(m1.clone()).await.unwrap();
+
+        // 10ms up, 50ms down and 10ms of latency
+        assert_eq!(receiver.next().await.unwrap(), m1);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(70), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(72), "{elapsed:?}");
+
+        // 100ms up, 50ms down and 10ms of latency
+        assert_eq!(receiver.next().await.unwrap(), m0);
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(160), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(162), "{elapsed:?}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn arrivals_expire() {
+        let network =
+            Network::uniform(Link::with_latency(LATENCY), 2, [0; 32]);
+
+        // TTL of 10 seconds
+        network.upload(0, &message(0, 100));
+        network.upload(0, &message(1, 100));
+        assert_eq!(network.state.lock().unwrap().arrivals.len(), 2);
+
+        tokio::time::advance(Duration::from_secs(11)).await;
+
+        network.upload(1, &message(2, 100));
+        assert_eq!(network.state.lock().unwrap().arrivals.len(), 1);
+    }
+}
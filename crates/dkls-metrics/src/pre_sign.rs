@@ -0,0 +1,223 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use sha2::{Digest, Sha256};
+use tokio::task::JoinSet;
+
+use msg_relay::MsgRelay;
+use sl_dkls23::{
+    keygen::Keyshare,
+    setup::{finish::SetupMessage, NoSigningKey, NoVerifyingKey},
+    sign::{self, PreSign},
+};
+use sl_mpc_mate::{coord::Relay, message::InstanceId};
+
+use crate::{
+    dkg::{self, Trace},
+    dsg::{signers, CHAIN_PATH},
+    flags,
+    relay::{MessageTrace, Tracing},
+    setup::setup_dsg,
+    stats,
+};
+
+// The finish phase is a separate protocol execution, its instance ID
+// is derived from the instance ID of the pre-sign phase.
+fn setup_finish(
+    instance: [u8; 32],
+    signers: &[Arc<Keyshare>],
+    pre_signs: &[PreSign],
+) -> Vec<SetupMessage> {
+    let instance: [u8; 32] = Sha256::new()
+        .chain_update(instance)
+        .chain_update(b"finish")
+        .finalize()
+        .into();
+
+    let party_vk: Vec<NoVerifyingKey> = signers
+        .iter()
+        .map(|share| NoVerifyingKey::new(share.party_id as _))
+        .collect();
+
+    pre_signs
+        .iter()
+        .enumerate()
+        .map(|(party_idx, pre)| {
+            SetupMessage::new(
+                InstanceId::new(instance),
+                party_idx,
+                NoSigningKey,
+                party_vk.clone(),
+                *pre,
+            )
+        })
+        .collect()
+}
+
+async fn pre_sign_one<R: Relay>(
+    instance: [u8; 32],
+    signers: &[Arc<Keyshare>],
+    party: usize,
+    relay: R,
+) -> anyhow::Result<PreSign> {
+    let (setup, seed) =
+        setup_dsg(Some(instance), signers, CHAIN_PATH).swap_remove(party);
+
+    let pre = sign::pre_signature(
+        setup.with_ttl(Duration::from_secs(1000)),
+        seed,
+        relay,
+    )
+    .await?;
+
+    Ok(pre)
+}
+
+/// Runs pre-sign and finish phases with `signers` and saves the trace
+/// into `save`, if given.
+pub async fn record(
+    signers: &[Arc<Keyshare>],
+    save: Option<&std::path::Path>,
+) -> anyhow::Result<Trace> {
+    let instance = rand::random();
+
+    let relay = MsgRelay::new(None);
+    let trace = MessageTrace::new();
+
+    let mut parties = JoinSet::new();
+    for party in 0..signers.len() {
+        let signers = signers.to_vec();
+        let relay = Tracing::new(relay.connect(), Some(trace.clone()));
+
+        parties.spawn(async move {
+            let pre = pre_sign_one(instance, &signers, party, relay).await;
+            (party, pre)
+        });
+    }
+
+    let mut pre_signs = parties.join_all().await;
+    pre_signs.sort_by_key(|(party, _)| *party);
+
+    let pre_signs = pre_signs
+        .into_iter()
+        .map(|(_, pre)| pre)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut parties = JoinSet::new();
+    for setup in setup_finish(instance, signers, &pre_signs) {
+        let relay = Tracing::new(relay.connect(), Some(trace.clone()));
+        parties.spawn(sign::finish(setup, relay));
+    }
+
+    for fini in parties.join_all().await {
+        fini?;
+    }
+
+    let messages = trace.messages();
+
+    stats::report_trace("PRE", &messages);
+
+    if let Some(base) = save {
+        let path = Trace::save(
+            base,
+            Some("pre-sign"),
+            &instance,
+            messages.clone(),
+            signers,
+        )?;
+        println!("PRE: trace {}", path.display());
+    }
+
+    Ok(Trace::new(instance, signers.to_vec(), messages))
+}
+
+pub async fn run_cmd(
+    ranks: Vec<u8>,
+    opts: flags::PreSign,
+) -> Result<(), anyhow::Error> {
+    let trace = match &opts.trace {
+        Some(path) => Trace::load(path)?,
+        None => {
+            let save = opts.save.as_deref();
+            let keys = dkg::keygen(&ranks, opts.n, opts.t, save).await?;
+            record(signers(&keys), save).await?
+        }
+    };
+
+    let subset = signers(&trace);
+    let k = opts.k.unwrap_or(100);
+
+    println!(
+        "PRE: N = {:2}, T = {:2}, ranks {:?}",
+        trace.n(),
+        trace.t(),
+        trace.ranks()
+    );
+
+    // pre-signatures of the last replay of each party
+    let pre_signs = Mutex::new(vec![None; subset.len()]);
+
+    stats::replay("PRE", trace.relay(), subset.len(), k, |party, relay| {
+        let (instance, pre_signs) = (trace.instance(), &pre_signs);
+        async move {
+            let pre = pre_sign_one(instance, subset, party, relay).await?;
+            pre_signs.lock().unwrap()[party] = Some(pre);
+            Ok(())
+        }
+    })
+    .await?;
+
+    let pre_signs: Vec<PreSign> = pre_signs
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect();
+
+    stats::replay("FIN", trace.relay(), subset.len(), k, |party, relay| {
+        let setup = setup_finish(trace.instance(), subset, &pre_signs)
+            .swap_remove(party);
+        async move {
+            sign::finish(setup, relay).await?;
+            Ok(())
+        }
+    })
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{instance_file, trace_dir};
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn save_and_replay() {
+        let dir = trace_dir("pre-sign");
+
+        let opts = |save, trace| flags::PreSign {
+            n: 3,
+            t: 2,
+            rank: vec![],
+            k: Some(1),
+            save,
+            trace,
+        };
+
+        run_cmd(vec![0; 3], opts(Some(dir.clone()), None))
+            .await
+            .unwrap();
+
+        let path = instance_file(&dir, Some("pre-sign"));
+        run_cmd(vec![0; 3], opts(None, Some(path))).await.unwrap();
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
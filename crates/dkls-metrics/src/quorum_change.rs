@@ -0,0 +1,249 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+use std::{path::Path, sync::Arc, time::Duration};
+
+use sha2::{Digest, Sha256};
+use tokio::task::JoinSet;
+
+use msg_relay::MsgRelay;
+use sl_dkls23::{
+    keygen::{quorum_change, Keyshare},
+    setup::{quorum_change::SetupMessage, NoSigningKey, NoVerifyingKey},
+    transcript::verify_quorum_change,
+    Seed,
+};
+use sl_mpc_mate::{coord::Relay, message::InstanceId};
+
+use crate::{
+    dkg::{self, Trace},
+    flags,
+    relay::{MessageTrace, Tracing},
+    stats,
+};
+
+// Quorum change of all parties of a key to a new threshold. Unlike
+// setup_quorum_change_threshold(), seeds of parties are derived from
+// the instance ID to replay the execution.
+fn setup(
+    instance: [u8; 32],
+    old_shares: &[Arc<Keyshare>],
+    new_t: u8,
+) -> Vec<(SetupMessage, Seed)> {
+    let parties = (0..old_shares.len()).collect::<Vec<_>>();
+    let new_parties = old_shares[0]
+        .rank_list()
+        .into_iter()
+        .enumerate()
+        .collect::<Vec<_>>();
+
+    let party_vk: Vec<NoVerifyingKey> =
+        parties.iter().map(|&p| NoVerifyingKey::new(p)).collect();
+
+    old_shares
+        .iter()
+        .enumerate()
+        .map(|(p, share)| {
+            let setup = SetupMessage::new(
+                InstanceId::new(instance),
+                p,
+                &parties,
+                &new_parties,
+                new_t as usize,
+                NoSigningKey,
+                party_vk.clone(),
+                share.public_key(),
+            )
+            .with_keyshare(share.clone())
+            .with_ttl(Duration::from_secs(1000));
+
+            let seed = Sha256::new()
+                .chain_update(instance)
+                .chain_update(b"qc-party-seed")
+                .chain_update([p as u8 + 1])
+                .finalize()
+                .into();
+
+            (setup, seed)
+        })
+        .collect()
+}
+
+async fn change_one<R: Relay>(
+    instance: [u8; 32],
+    old_shares: &[Arc<Keyshare>],
+    new_t: u8,
+    party: usize,
+    relay: R,
+) -> anyhow::Result<()> {
+    let (setup, seed) = setup(instance, old_shares, new_t).swap_remove(party);
+
+    quorum_change::run(setup, seed, relay).await?;
+
+    Ok(())
+}
+
+// Key shares in order of party IDs, a party with index P holds a key
+// share with party ID P.
+fn old_shares(trace: &Trace) -> Vec<Arc<Keyshare>> {
+    let mut shares = trace.shares().to_vec();
+    shares.sort_by_key(|share| share.party_id);
+    shares
+}
+
+/// Runs quorum change of key shares of a trace to a new threshold and
+/// saves its trace into `save`, if given. The new threshold is a part
+/// of the name of the trace.
+pub async fn record(
+    keys: &Trace,
+    new_t: u8,
+    save: Option<&std::path::Path>,
+) -> anyhow::Result<Trace> {
+    let instance = rand::random();
+    let old_shares = old_shares(keys);
+
+    let relay = MsgRelay::new(None);
+    let trace = MessageTrace::new();
+
+    let mut parties = JoinSet::new();
+    for party in 0..old_shares.len() {
+        let old_shares = old_shares.clone();
+        let relay = Tracing::new(relay.connect(), Some(trace.clone()));
+
+        parties.spawn(async move {
+            change_one(instance, &old_shares, new_t, party, relay).await
+        });
+    }
+
+    for fini in parties.join_all().await {
+        fini?;
+    }
+
+    let messages = trace.messages();
+
+    stats::report_trace("QC", &messages);
+
+    if let Some(base) = save {
+        let path = Trace::save(
+            base,
+            Some(&format!("quorum-change-{}", new_t)),
+            &instance,
+            messages.clone(),
+            &old_shares,
+        )?;
+        println!("QC: trace {}", path.display());
+    }
+
+    Ok(Trace::new(instance, old_shares, messages))
+}
+
+// Returns the new threshold of a saved trace of quorum change.
+fn new_threshold(path: &Path) -> anyhow::Result<u8> {
+    path.to_str()
+        .and_then(|path| path.strip_suffix(".instance"))
+        .and_then(|path| path.rsplit_once("quorum-change-"))
+        .and_then(|(_, new_t)| new_t.parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("not a quorum change trace"))
+}
+
+/// Verifies a saved trace of quorum change. The new key shares must
+/// have the public key of the old ones.
+pub fn verify_cmd(
+    opts: flags::VerifyQuorumChange,
+) -> Result<(), anyhow::Error> {
+    let new_t = new_threshold(&opts.trace)?;
+    let trace = Trace::load(&opts.trace)?;
+    let old_shares = old_shares(&trace);
+
+    let (setup, _) =
+        setup(trace.instance(), &old_shares, new_t).swap_remove(0);
+
+    let transcript = trace.transcript();
+    let output = verify_quorum_change(&setup, &transcript)?;
+
+    stats::report_verified("QC", &output, &transcript);
+
+    if output.public_key != old_shares[0].public_key() {
+        return Err(anyhow::anyhow!("public key of the key changed"));
+    }
+
+    Ok(())
+}
+
+pub async fn run_cmd(
+    ranks: Vec<u8>,
+    opts: flags::QuorumChange,
+) -> Result<(), anyhow::Error> {
+    let (trace, new_t) = match &opts.trace {
+        Some(path) => (Trace::load(path)?, new_threshold(path)?),
+
+        None => {
+            let new_t = opts.new_t.unwrap_or(opts.t);
+            let save = opts.save.as_deref();
+            let keys = dkg::keygen(&ranks, opts.n, opts.t, save).await?;
+
+            (record(&keys, new_t, save).await?, new_t)
+        }
+    };
+
+    let old_shares = old_shares(&trace);
+
+    println!(
+        "QC: N = {:2}, T = {:2}, new T = {:2}, ranks {:?}",
+        trace.n(),
+        trace.t(),
+        new_t,
+        trace.ranks()
+    );
+
+    stats::replay(
+        "QC",
+        trace.relay(),
+        old_shares.len(),
+        opts.k.unwrap_or(100),
+        |party, relay| {
+            let (instance, old_shares) = (trace.instance(), &old_shares);
+            async move {
+                change_one(instance, old_shares, new_t, party, relay).await
+            }
+        },
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{instance_file, trace_dir};
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn save_and_replay() {
+        let dir = trace_dir("quorum-change");
+
+        let opts = |save, trace| flags::QuorumChange {
+            n: 3,
+            t: 2,
+            rank: vec![],
+            new_t: Some(3),
+            k: Some(1),
+            save,
+            trace,
+        };
+
+        run_cmd(vec![0; 3], opts(Some(dir.clone()), None))
+            .await
+            .unwrap();
+
+        let path = instance_file(&dir, Some("quorum-change-3"));
+        run_cmd(vec![0; 3], opts(None, Some(path.clone())))
+            .await
+            .unwrap();
+
+        verify_cmd(flags::VerifyQuorumChange { trace: path }).unwrap();
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Setup messages of measured protocols.
+//!
+//! Parties do not sign messages, and seeds are derived from the
+//! instance ID, so a recorded execution could be replayed.
+
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use derivation_path::DerivationPath;
+use sha2::{Digest, Sha256};
+
+use sl_dkls23::{
+    keygen::Keyshare,
+    setup::{
+        keygen, sign, NoSigningKey, NoVerifyingKey, ProtocolParticipant,
+    },
+    Seed,
+};
+use sl_mpc_mate::message::InstanceId;
+
+// Long enough for the slowest measured execution.
+const TTL: Duration = Duration::from_secs(1000);
+
+fn seed(instance: &[u8; 32], label: &[u8], party: usize) -> Seed {
+    Sha256::new()
+        .chain_update(instance)
+        .chain_update(label)
+        .chain_update([party as u8 + 1])
+        .finalize()
+        .into()
+}
+
+/// Returns DKG setups and seeds of `n` parties.
+pub fn setup_keygen(
+    instance: Option<[u8; 32]>,
+    t: u8,
+    n: u8,
+    ranks: Option<&[u8]>,
+) -> Vec<(keygen::SetupMessage, Seed)> {
+    let ranks = match ranks {
+        Some(ranks) => {
+            assert_eq!(ranks.len(), n as usize);
+            ranks.to_vec()
+        }
+        None => vec![0; n as usize],
+    };
+
+    let instance = instance.unwrap_or_else(rand::random);
+
+    let party_vk: Vec<_> = (0..n as usize).map(NoVerifyingKey::new).collect();
+
+    (0..n as usize)
+        .map(|party_id| {
+            let setup = keygen::SetupMessage::new(
+                InstanceId::new(instance),
+                NoSigningKey,
+                party_id,
+                party_vk.clone(),
+                &ranks,
+                t as usize,
+            )
+            .with_ttl(TTL);
+
+            let seed = seed(&instance, b"party-seed", party_id);
+
+            (setup, seed)
+        })
+        .collect()
+}
+
+/// Returns DSG setups and seeds of signers holding `shares`.
+pub fn setup_dsg(
+    instance: Option<[u8; 32]>,
+    shares: &[Arc<Keyshare>],
+    chain_path: &str,
+) -> Vec<(sign::SetupMessage, Seed)> {
+    let instance = instance.unwrap_or_else(rand::random);
+
+    let chain_path = DerivationPath::from_str(chain_path).unwrap();
+
+    assert!(shares.len() >= shares[0].threshold as usize);
+
+    let party_vk: Vec<_> = shares
+        .iter()
+        .map(|share| NoVerifyingKey::new(share.party_id as _))
+        .collect();
+
+    shares
+        .iter()
+        .enumerate()
+        .map(|(party_idx, share)| {
+            let setup = sign::SetupMessage::new(
+                InstanceId::new(instance),
+                NoSigningKey,
+                party_idx,
+                party_vk.clone(),
+                share.clone(),
+            )
+            .with_chain_path(chain_path.clone())
+            .with_hash([1; 32])
+            .with_ttl(TTL);
+
+            let seed =
+                seed(&instance, b"dsg-party-seed", setup.participant_index());
+
+            (setup, seed)
+        })
+        .collect()
+}
@@ -0,0 +1,169 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use sl_mpc_mate::message::MESSAGE_HEADER_SIZE;
+
+use msg_relay::{MsgRelay, MsgRelayConnection, MsgRelayConnectionStats};
+use sl_dkls23::{
+    k256::elliptic_curve::group::GroupEncoding,
+    transcript::{PublicOutput, Transcript},
+};
+
+/// Latency of a number of executions.
+pub struct Latency {
+    samples: Vec<Duration>,
+}
+
+impl Latency {
+    pub fn new(mut samples: Vec<Duration>) -> Self {
+        samples.sort();
+        Self { samples }
+    }
+
+    /// Returns the `p`-th percentile, p in 0..=100.
+    pub fn percentile(&self, p: usize) -> Duration {
+        if self.samples.is_empty() {
+            return Duration::ZERO;
+        }
+
+        let idx = (self.samples.len() * p).div_ceil(100).max(1) - 1;
+
+        self.samples[idx.min(self.samples.len() - 1)]
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.samples.is_empty() {
+            return Duration::ZERO;
+        }
+
+        self.samples.iter().sum::<Duration>() / self.samples.len() as u32
+    }
+}
+
+/// Prints the total number and size of traced messages.
+pub fn report_trace(label: &str, messages: &[Vec<u8>]) {
+    println!(
+        "{}: traced {} messages, {} total bytes",
+        label,
+        messages.len(),
+        messages.iter().map(|v| v.len()).sum::<usize>()
+    );
+}
+
+/// Prints numbers of verified messages and the public key of a
+/// verified transcript.
+pub fn report_verified(
+    label: &str,
+    output: &PublicOutput,
+    transcript: &Transcript,
+) {
+    println!(
+        "{}: verified {} broadcast, {} P2P of {} messages",
+        label,
+        output.broadcasts,
+        output.p2p,
+        transcript.len()
+    );
+    println!("public key: {}", hex::encode(output.public_key.to_bytes()));
+}
+
+/// Replays a traced execution.
+///
+/// First, replays each party once against `relay`, which holds all
+/// messages of the execution, and prints the number and size of
+/// messages the party publishes, asks and receives. Then replays
+/// party 0 `k` times in a row and prints latency percentiles.
+///
+/// A party is a function of its seed, so it publishes the traced
+/// messages again, and the relay accepts them as repeated ones.
+///
+/// `replay` executes a party with given index over a connection.
+pub async fn replay<F, Fut>(
+    label: &str,
+    relay: &MsgRelay,
+    parties: usize,
+    k: usize,
+    replay: F,
+) -> anyhow::Result<Latency>
+where
+    F: Fn(usize, MsgRelayConnection) -> Fut,
+    Fut: Future<Output = anyhow::Result<()>>,
+{
+    for party in 0..parties {
+        let conn = relay.connect();
+        let sender = conn.sender();
+
+        replay(party, conn).await?;
+
+        let MsgRelayConnectionStats {
+            send_count,
+            send_size,
+            ask_count,
+            recv_count,
+            recv_size,
+            ..
+        } = sender.stats();
+
+        // an ask is a message header sent to the relay
+        println!(
+            "{}: party {:2}: send {} {} bytes, {} asks, recv {} {} bytes",
+            label,
+            party,
+            send_count - ask_count,
+            send_size - ask_count * MESSAGE_HEADER_SIZE,
+            ask_count,
+            recv_count,
+            recv_size
+        );
+    }
+
+    let mut samples = Vec::with_capacity(k);
+
+    for _ in 0..k {
+        let start = Instant::now();
+        replay(0, relay.connect()).await?;
+        samples.push(start.elapsed());
+    }
+
+    let latency = Latency::new(samples);
+
+    println!(
+        "{}: K = {:3}, mean {:?}, p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+        label,
+        k,
+        latency.mean(),
+        latency.percentile(50),
+        latency.percentile(90),
+        latency.percentile(99),
+        latency.percentile(100),
+    );
+
+    Ok(latency)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles() {
+        let ms = Duration::from_millis;
+
+        let latency = Latency::new((1..=10).rev().map(ms).collect());
+
+        assert_eq!(latency.percentile(0), ms(1));
+        assert_eq!(latency.percentile(50), ms(5));
+        assert_eq!(latency.percentile(90), ms(9));
+        assert_eq!(latency.percentile(100), ms(10));
+        assert_eq!(latency.mean(), Duration::from_micros(5500));
+
+        let empty = Latency::new(vec![]);
+        assert_eq!(empty.percentile(50), Duration::ZERO);
+        assert_eq!(empty.mean(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,845 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! A [`Relay`] connecting parties directly over TCP, without a central
+//! relay.
+//!
+//! Each party runs a [`MeshNode`]: a local [`MsgRelay`] keeping the
+//! messages the party publishes, served to peers over TCP. A
+//! [`MeshRelay`] publishes messages to the local relay and connects to
+//! the nodes of the other parties.
+//!
+//! A message ID does not tell who is the sender, so a node announces
+//! IDs of the messages it publishes to all connected peers. A
+//! [`MeshRelay`] sends an ASK only to the peer which announced the
+//! message, and keeps the ASK until the announcement arrives. The
+//! semantics are the same as of a central relay.
+//!
+//! The first peer announcing a message is its owner: announcements
+//! of the same ID by other peers are ignored, and the message is
+//! accepted only from the owner. A node accepts connections only
+//! from the static list of peer addresses passed to
+//! [`MeshNode::bind()`]. Plain TCP authenticates nothing else, so the
+//! mesh should run on a private network.
+//!
+//! On the wire, each frame is a 4-byte big-endian length followed by
+//! a message, an ASK from a peer or an announced message ID.
+//!
+//! ```no_run
+//! use std::net::SocketAddr;
+//!
+//! use msg_relay_mesh::{MeshNode, MeshOptions};
+//!
+//! # async fn example() -> std::io::Result<()> {
+//! let peers: [SocketAddr; 1] = ["127.0.0.1:9001".parse().unwrap()];
+//! let hosts: Vec<_> = peers.iter().map(|addr| addr.ip()).collect();
+//!
+//! let node = MeshNode::bind("127.0.0.1:9000", &hosts).await?;
+//!
+//! // pass to keygen::run(), sign::run(), etc.
+//! let relay = node.connect(&peers, MeshOptions::default()).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream, ToSocketAddrs,
+    },
+    sync::mpsc::{self, error::TrySendError},
+    task::{JoinHandle, JoinSet},
+};
+
+use msg_relay::{
+    MessageSendError, MsgRelay, MsgRelayConnection, MsgRelaySender, Relay,
+    Sink, Stream, StreamExt, MESSAGE_HEADER_SIZE,
+};
+use sl_mpc_mate::message::{MsgHdr, MsgId, MESSAGE_ID_SIZE};
+
+/// Upper limit of the size of a relay message.
+pub const MAX_MESSAGE_SIZE: usize = 16 << 20;
+
+// Number of frames queued for a peer. A peer which does not read
+// them is disconnected instead of stalling the party.
+const PEER_QUEUE_SIZE: usize = 1024;
+
+async fn write_frame<W: AsyncWrite + Unpin>(
+    w: &mut W,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(payload.len() + 4);
+    buf.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    buf.extend_from_slice(payload);
+
+    w.write_all(&buf).await?;
+    w.flush().await
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(r: &mut R) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 4];
+    r.read_exact(&mut len).await?;
+
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message too long",
+        ));
+    }
+
+    // do not trust the length before the payload arrives
+    let mut payload = vec![];
+    r.take(len as u64).read_to_end(&mut payload).await?;
+
+    if payload.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(payload)
+}
+
+// Writes queued frames to a peer until the queue is closed.
+async fn write_peer(
+    mut output: OwnedWriteHalf,
+    mut frames: mpsc::Receiver<Vec<u8>>,
+) -> io::Result<()> {
+    while let Some(frame) = frames.recv().await {
+        write_frame(&mut output, &frame).await?;
+    }
+
+    output.shutdown().await
+}
+
+// A peer closing its connection is not an error.
+fn is_closed(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+    )
+}
+
+// State of a node shared with its relays.
+struct Shared {
+    relay: MsgRelay,
+    // queues of connections of peers, to announce published messages
+    peers: Mutex<HashMap<u64, mpsc::Sender<Vec<u8>>>>,
+}
+
+impl Shared {
+    // Publishes a message to the local relay and announces it.
+    fn publish(
+        &self,
+        local: &MsgRelayConnection,
+        msg: Vec<u8>,
+    ) -> Result<(), MessageSendError> {
+        let id = MsgId::try_from(&msg[..]).map_err(|_| MessageSendError)?;
+
+        // new peers receive IDs of stored messages under the lock
+        let mut peers = self.peers.lock().unwrap();
+
+        local.send_message(msg).map_err(|_| MessageSendError)?;
+
+        peers.retain(|peer, queue| match queue.try_send(id.to_vec()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                tracing::warn!("peer {} does not read, disconnect", peer);
+                false
+            }
+            Err(TrySendError::Closed(_)) => false,
+        });
+
+        Ok(())
+    }
+}
+
+/// A party of a mesh: serves messages published by the party to
+/// its peers until the node is dropped.
+pub struct MeshNode {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    accept: JoinHandle<()>,
+}
+
+impl MeshNode {
+    /// Binds a listening socket for peers.
+    ///
+    /// # Arguments
+    /// * `addr` - Address to listen on, use port 0 to pick a free port
+    /// * `peers` - Hosts of other parties, connections from other
+    ///   hosts are refused
+    pub async fn bind<A: ToSocketAddrs>(
+        addr: A,
+        peers: &[IpAddr],
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+
+        let shared = Arc::new(Shared {
+            relay: MsgRelay::new(None),
+            peers: Mutex::default(),
+        });
+
+        let accept = tokio::spawn(accept_loop(
+            listener,
+            shared.clone(),
+            peers.iter().copied().collect(),
+        ));
+
+        Ok(Self {
+            addr,
+            shared,
+            accept,
+        })
+    }
+
+    /// Returns the address peers should connect to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the local relay, e.g. to set limits.
+    pub fn relay(&self) -> &MsgRelay {
+        &self.shared.relay
+    }
+
+    /// Connects to peers and returns a relay for a protocol run.
+    ///
+    /// Peers may start listening later than this node, so the
+    /// connection to each of them is retried as configured by
+    /// `opts`. Peers fetch published messages from this node, so the
+    /// node has to live until all peers finish the protocol.
+    ///
+    /// # Arguments
+    /// * `peers` - Addresses of [`MeshNode`]s of other parties
+    /// * `opts` - Options of connections to the peers
+    pub async fn connect(
+        &self,
+        peers: &[SocketAddr],
+        opts: MeshOptions,
+    ) -> io::Result<MeshRelay> {
+        let mut streams = vec![];
+        for addr in peers {
+            streams.push(connect_peer(*addr, &opts).await?);
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let router = Arc::new(Mutex::new(Router {
+            outputs: vec![],
+            owners: HashMap::new(),
+            pending: HashMap::new(),
+            failures: tx.downgrade(),
+        }));
+
+        let mut tasks = JoinSet::new();
+
+        for (peer, stream) in streams.into_iter().enumerate() {
+            let (input, output) = stream.into_split();
+            let (queue, frames) = mpsc::channel(PEER_QUEUE_SIZE);
+            router.lock().unwrap().outputs.push(queue);
+
+            tasks.spawn(async move {
+                if let Err(err) = write_peer(output, frames).await {
+                    // the peer is gone and the message never arrives
+                    tracing::debug!("ask peer {}: {}", peer, err);
+                }
+            });
+            tasks.spawn(read_peer(peer, input, router.clone(), tx.clone()));
+        }
+
+        Ok(MeshRelay {
+            shared: self.shared.clone(),
+            local: self.shared.relay.connect(),
+            router,
+            rx,
+            failed: false,
+            _tasks: tasks,
+        })
+    }
+}
+
+impl Drop for MeshNode {
+    fn drop(&mut self) {
+        // closes connections of all peers
+        self.accept.abort();
+    }
+}
+
+/// Options of connections to peers of a mesh.
+#[derive(Clone, Debug)]
+pub struct MeshOptions {
+    /// Number of attempts to connect to a peer which does not listen
+    /// yet
+    pub retries: usize,
+
+    /// Delay between attempts to connect
+    pub retry_delay: Duration,
+}
+
+impl Default for MeshOptions {
+    fn default() -> Self {
+        Self {
+            retries: 10,
+            retry_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+async fn connect_peer(
+    addr: SocketAddr,
+    opts: &MeshOptions,
+) -> io::Result<TcpStream> {
+    let mut attempt = 0;
+
+    loop {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => {
+                stream.set_nodelay(true)?;
+                return Ok(stream);
+            }
+            Err(err)
+                if attempt < opts.retries
+                    && err.kind() == io::ErrorKind::ConnectionRefused =>
+            {
+                attempt += 1;
+                tokio::time::sleep(opts.retry_delay).await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    shared: Arc<Shared>,
+    hosts: HashSet<IpAddr>,
+) {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+    // dropped with the node, which aborts all connections
+    let mut conns = JoinSet::new();
+
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                tracing::debug!("accept: {}", err);
+                continue;
+            }
+        };
+
+        while conns.try_join_next().is_some() {}
+
+        if !hosts.contains(&addr.ip()) {
+            tracing::warn!("refused connection of unknown peer {}", addr);
+            continue;
+        }
+
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let shared = shared.clone();
+
+        conns.spawn(async move {
+            if let Err(err) = serve_peer(stream, &shared, id).await {
+                tracing::debug!("peer {} closed: {}", id, err);
+            }
+            shared.peers.lock().unwrap().remove(&id);
+        });
+    }
+}
+
+// Answers ASKs of a peer and announces published messages.
+async fn serve_peer(
+    stream: TcpStream,
+    shared: &Shared,
+    id: u64,
+) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+
+    let conn = shared.relay.connect();
+    let sender = conn.sender();
+    let (mut input, output) = stream.into_split();
+    let (queue, announces) = mpsc::channel(PEER_QUEUE_SIZE);
+
+    let mut stored = vec![];
+    {
+        let mut peers = shared.peers.lock().unwrap();
+
+        shared.relay.messages(|id, waiters| {
+            if waiters == 0 {
+                stored.push(*id);
+            }
+        });
+
+        peers.insert(id, queue);
+    }
+
+    // the connection ends when the peer closes it or stops reading
+    tokio::select! {
+        res = read_asks(&mut input, &sender, id) => res,
+        res = serve_output(output, stored, announces, conn) => res,
+    }
+}
+
+async fn read_asks(
+    input: &mut OwnedReadHalf,
+    sender: &MsgRelaySender,
+    id: u64,
+) -> io::Result<()> {
+    loop {
+        match read_frame(input).await {
+            // peers may only ask for messages
+            Ok(ask) if ask.len() == MESSAGE_HEADER_SIZE => {
+                if let Err(err) = sender.send_message(ask) {
+                    tracing::warn!("peer {}: {}", id, err);
+                }
+            }
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "unexpected message",
+                ))
+            }
+            Err(err) if is_closed(&err) => return Ok(()),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// Writes IDs of stored and published messages and answers to ASKs.
+async fn serve_output(
+    mut output: OwnedWriteHalf,
+    stored: Vec<MsgId>,
+    mut announces: mpsc::Receiver<Vec<u8>>,
+    mut conn: MsgRelayConnection,
+) -> io::Result<()> {
+    for id in stored {
+        write_frame(&mut output, &id).await?;
+    }
+
+    loop {
+        let frame = tokio::select! {
+            id = announces.recv() => id,
+            msg = conn.next() => msg,
+        };
+
+        // the node disconnected the peer
+        let Some(frame) = frame else {
+            return output.shutdown().await;
+        };
+
+        write_frame(&mut output, &frame).await?;
+    }
+}
+
+// Routes ASKs to peers which announced the messages.
+struct Router {
+    // queues of frames to peers
+    outputs: Vec<mpsc::Sender<Vec<u8>>>,
+    // peers which published messages
+    owners: HashMap<MsgId, usize>,
+    // ASKs of messages nobody has announced yet
+    pending: HashMap<MsgId, (Instant, Vec<u8>)>,
+    // does not keep the stream of messages open
+    failures: mpsc::WeakUnboundedSender<io::Result<Vec<u8>>>,
+}
+
+impl Router {
+    fn send(&mut self, peer: usize, ask: Vec<u8>) {
+        match self.outputs[peer].try_send(ask) {
+            Ok(()) => {}
+
+            // The peer does not read ASKs and the message might not
+            // arrive.
+            Err(TrySendError::Full(_)) => {
+                if let Some(failures) = self.failures.upgrade() {
+                    let err = io::Error::other(format!(
+                        "peer {} does not read ASKs",
+                        peer
+                    ));
+                    let _ = failures.send(Err(err));
+                }
+            }
+
+            // the peer is gone and the message never arrives
+            Err(TrySendError::Closed(_)) => {
+                tracing::debug!("ask peer {}: closed", peer);
+            }
+        }
+    }
+
+    fn ask(&mut self, id: MsgId, ask: Vec<u8>, expire: Instant) {
+        match self.owners.get(&id) {
+            Some(&peer) => self.send(peer, ask),
+            None => {
+                let now = Instant::now();
+                self.pending.retain(|_, (expire, _)| *expire > now);
+                self.pending.insert(id, (expire, ask));
+            }
+        }
+    }
+
+    fn announced(&mut self, id: MsgId, peer: usize) {
+        match self.owners.entry(id) {
+            Entry::Vacant(owner) => {
+                owner.insert(peer);
+            }
+
+            // a peer may not take over ASKs of a message of another peer
+            Entry::Occupied(owner) => {
+                if *owner.get() != peer {
+                    tracing::warn!(
+                        "peer {} announced a message of peer {}",
+                        peer,
+                        owner.get()
+                    );
+                }
+                return;
+            }
+        }
+
+        if let Some((expire, ask)) = self.pending.remove(&id) {
+            if expire > Instant::now() {
+                self.send(peer, ask);
+            }
+        }
+    }
+}
+
+// Reads messages and announcements of a peer.
+async fn read_peer(
+    peer: usize,
+    mut input: OwnedReadHalf,
+    router: Arc<Mutex<Router>>,
+    tx: mpsc::UnboundedSender<io::Result<Vec<u8>>>,
+) {
+    loop {
+        let res = match read_frame(&mut input).await {
+            Ok(frame) if frame.len() == MESSAGE_ID_SIZE => {
+                let id = MsgId::try_from(&frame[..]).unwrap();
+                router.lock().unwrap().announced(id, peer);
+                continue;
+            }
+            Ok(msg) if msg.len() > MESSAGE_HEADER_SIZE => {
+                let id = MsgId::try_from(&msg[..]).unwrap();
+                if router.lock().unwrap().owners.get(&id) != Some(&peer) {
+                    tracing::warn!("peer {} sent a message of another", peer);
+                    continue;
+                }
+                Ok(msg)
+            }
+            Ok(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid message",
+            )),
+            Err(err) if is_closed(&err) => {
+                tracing::debug!("peer {} closed", peer);
+                return;
+            }
+            Err(err) => Err(err),
+        };
+
+        let failed = res.is_err();
+        if tx.send(res).is_err() || failed {
+            return;
+        }
+    }
+}
+
+/// A relay of a party of a mesh, see [`MeshNode::connect()`].
+///
+/// The stream of incoming messages ends when all peers close their
+/// connections or a connection fails.
+pub struct MeshRelay {
+    shared: Arc<Shared>,
+    local: MsgRelayConnection,
+    router: Arc<Mutex<Router>>,
+    rx: mpsc::UnboundedReceiver<io::Result<Vec<u8>>>,
+    failed: bool,
+    // tasks reading from and writing to peers, aborted on drop
+    _tasks: JoinSet<()>,
+}
+
+impl Stream for MeshRelay {
+    type Item = Vec<u8>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if this.failed {
+            return Poll::Ready(None);
+        }
+
+        // answers to ASKs of own messages
+        if let Poll::Ready(Some(msg)) = this.local.poll_next_unpin(cx) {
+            return Poll::Ready(Some(msg));
+        }
+
+        match ready!(this.rx.poll_recv(cx)) {
+            Some(Ok(msg)) => Poll::Ready(Some(msg)),
+
+            // A connection failed and messages of the peer might not
+            // arrive.
+            Some(Err(err)) => {
+                tracing::warn!("mesh peer: {}", err);
+                this.failed = true;
+                Poll::Ready(None)
+            }
+
+            // all peers are gone
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+impl Sink<Vec<u8>> for MeshRelay {
+    type Error = MessageSendError;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(
+        self: Pin<&mut Self>,
+        item: Vec<u8>,
+    ) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+
+        let hdr = <&MsgHdr>::try_from(item.as_slice())
+            .map_err(|_| MessageSendError)?;
+        let id = *hdr.id();
+        let expire = Instant::now() + hdr.ttl();
+
+        let mut router = this.router.lock().unwrap();
+
+        if item.len() > MESSAGE_HEADER_SIZE {
+            // an ASK of an own message
+            if let Some((_, ask)) = router.pending.remove(&id) {
+                this.local.send_message(ask).map_err(|_| MessageSendError)?;
+            }
+
+            return this.shared.publish(&this.local, item);
+        }
+
+        if this.local.get(&id).is_some() {
+            this.local.send_message(item).map_err(|_| MessageSendError)
+        } else {
+            router.ask(id, item, expire);
+            Ok(())
+        }
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Relay for MeshRelay {}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use sl_dkls23::{keygen, sign};
+    use sl_mpc_mate::{
+        coord::SinkExt,
+        message::{allocate_message, AskMsg},
+    };
+
+    use super::*;
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    async fn mesh(n: usize) -> (Vec<MeshNode>, Vec<MeshRelay>) {
+        let mut nodes = vec![];
+        for _ in 0..n {
+            nodes.push(
+                MeshNode::bind("127.0.0.1:0", &[LOCALHOST]).await.unwrap(),
+            );
+        }
+        let addrs: Vec<_> = nodes.iter().map(|n| n.local_addr()).collect();
+
+        let mut relays = vec![];
+        for (i, node) in nodes.iter().enumerate() {
+            let peers: Vec<_> = addrs
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, addr)| *addr)
+                .collect();
+            relays.push(
+                node.connect(&peers, MeshOptions::default()).await.unwrap(),
+            );
+        }
+
+        (nodes, relays)
+    }
+
+    async fn recv_timeout(
+        relay: &mut MeshRelay,
+        timeout: Duration,
+    ) -> Result<Option<Vec<u8>>, tokio::time::error::Elapsed> {
+        tokio::time::timeout(timeout, relay.next()).await
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn ask_before_publish() {
+        let (nodes, mut relays) = mesh(3).await;
+        let id = MsgId::from([1; 32]);
+        let msg = allocate_message(&id, 100, 0, b"payload");
+
+        relays[0].send(AskMsg::allocate(&id, 100)).await.unwrap();
+        relays[2].send(msg.clone()).await.unwrap();
+
+        let recv = recv_timeout(&mut relays[0], Duration::from_secs(10));
+        assert_eq!(recv.await.unwrap(), Some(msg));
+
+        // only the owner of the message received the ASK
+        assert_eq!(nodes[1].relay().stats().1, 0);
+        assert_eq!(nodes[2].relay().stats().1, 2);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn own_message() {
+        let (_nodes, mut relays) = mesh(2).await;
+        let id = MsgId::from([1; 32]);
+        let msg = allocate_message(&id, 100, 0, b"payload");
+
+        relays[0].send(AskMsg::allocate(&id, 100)).await.unwrap();
+        relays[0].send(msg.clone()).await.unwrap();
+
+        let recv = recv_timeout(&mut relays[0], Duration::from_secs(10));
+        assert_eq!(recv.await.unwrap(), Some(msg));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn end_of_stream() {
+        let (mut nodes, mut relays) = mesh(3).await;
+        let short = Duration::from_millis(200);
+
+        // one peer is gone, another one may still send messages
+        drop(relays.pop());
+        drop(nodes.pop());
+        assert!(recv_timeout(&mut relays[0], short).await.is_err());
+
+        drop(relays.pop());
+        drop(nodes.pop());
+        let recv = recv_timeout(&mut relays[0], Duration::from_secs(10));
+        assert_eq!(recv.await.unwrap(), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unknown_peer() {
+        let node = MeshNode::bind("127.0.0.1:0", &[]).await.unwrap();
+        let other =
+            MeshNode::bind("127.0.0.1:0", &[LOCALHOST]).await.unwrap();
+
+        let peers = [node.local_addr()];
+        let opts = MeshOptions::default();
+        let mut relay = other.connect(&peers, opts).await.unwrap();
+
+        // the node closes the connection
+        let recv = recv_timeout(&mut relay, Duration::from_secs(10));
+        assert_eq!(recv.await.unwrap(), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn announcement_of_another_peer() {
+        let node = MeshNode::bind("127.0.0.1:0", &[LOCALHOST]).await.unwrap();
+        let owner =
+            MeshNode::bind("127.0.0.1:0", &[LOCALHOST]).await.unwrap();
+        let fake = TcpListener::bind("127.0.0.1:0").await.unwrap();
+
+        let id = MsgId::from([1; 32]);
+        let msg = allocate_message(&id, 100, 0, b"payload");
+
+        let peers = [owner.local_addr(), fake.local_addr().unwrap()];
+        let opts = MeshOptions::default();
+        let mut relay = node.connect(&peers, opts.clone()).await.unwrap();
+        let mut owner_relay = owner.connect(&[], opts).await.unwrap();
+        let (mut fake, _) = fake.accept().await.unwrap();
+
+        owner_relay.send(msg.clone()).await.unwrap();
+        relay.send(AskMsg::allocate(&id, 100)).await.unwrap();
+
+        let recv = recv_timeout(&mut relay, Duration::from_secs(10));
+        assert_eq!(recv.await.unwrap(), Some(msg.clone()));
+
+        // another peer claims the message and sends a forged one
+        let forged = allocate_message(&id, 100, 0, b"forged");
+        write_frame(&mut fake, &id).await.unwrap();
+        write_frame(&mut fake, &forged).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        relay.send(AskMsg::allocate(&id, 100)).await.unwrap();
+
+        let recv = recv_timeout(&mut relay, Duration::from_secs(10));
+        assert_eq!(recv.await.unwrap(), Some(msg));
+
+        // the ASK went to the owner only
+        let ask = read_frame(&mut fake);
+        let short = Duration::from_millis(200);
+        assert!(tokio::time::timeout(short, ask).await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keygen_and_sign() {
+        let (_nodes, relays) = mesh(3).await;
+
+        let mut parties = JoinSet::new();
+        let setups = keygen::utils::setup_keygen(None, 2, 3, None);
+        for ((setup, seed), relay) in setups.into_iter().zip(relays) {
+            parties.spawn(keygen::run(setup, seed, relay));
+        }
+
+        let mut shares = vec![];
+        while let Some(share) = parties.join_next().await {
+            shares.push(Arc::new(share.unwrap().unwrap()));
+        }
+
+        let (_nodes, relays) = mesh(2).await;
+
+        let mut parties = JoinSet::new();
+        let setups = sign::setup_dsg(None, &shares[..2], "m");
+        for ((setup, seed), relay) in setups.into_iter().zip(relays) {
+            parties.spawn(sign::run(setup, seed, relay));
+        }
+
+        while let Some(sign) = parties.join_next().await {
+            sign.unwrap().unwrap();
+        }
+    }
+}
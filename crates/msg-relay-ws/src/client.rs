@@ -0,0 +1,455 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! A [`Relay`] connected to a [`RelayServer`](crate::RelayServer) over
+//! WebSocket.
+//!
+//! The connection is handled by a task spawned on the runtime of the
+//! caller of [`WsRelay::connect()`]. If the connection breaks, the
+//! task reconnects and sends again all ASK
+//! messages that have not been answered yet, as well as all messages
+//! published by the client which have not expired. A relay that
+//! survived the disconnect ignores the repeated messages, a restarted
+//! one will receive them again.
+//!
+//! If the server closes the connection because the relay rejected a
+//! message, the client does not reconnect and the stream of incoming
+//! messages ends. If the server reports that the queue of the
+//! connection is full, the client sends the ASK again after a delay,
+//! which doubles with each attempt, until the ASK expires.
+
+use std::{
+    collections::HashMap,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{lookup_host, TcpStream, ToSocketAddrs},
+    sync::mpsc,
+    time::{interval, sleep, timeout},
+};
+use tokio_rustls::{rustls::ClientConfig, TlsConnector};
+use tokio_tungstenite::{
+    tungstenite::{
+        self,
+        client::IntoClientRequest,
+        http::{header::AUTHORIZATION, HeaderValue, StatusCode},
+        protocol::frame::coding::CloseCode,
+        Message,
+    },
+    WebSocketStream,
+};
+
+use msg_relay::RelayError;
+use sl_mpc_mate::{
+    coord::{MessageSendError, Relay, Sink, SinkExt, Stream, StreamExt},
+    message::{MsgHdr, MsgId, MESSAGE_HEADER_SIZE},
+};
+
+use crate::server::{ws_config, HANDSHAKE_TIMEOUT};
+
+/// A client pings the server this often, so the server does not
+/// close an idle connection.
+pub const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+// The delay before sending again an ASK rejected by a full queue
+// stops doubling after this many attempts.
+const MAX_BACKOFF_SHIFT: u32 = 6;
+
+/// Options of a client connection.
+#[derive(Clone, Debug)]
+pub struct ClientOptions {
+    /// Request path of the WebSocket handshake
+    pub path: String,
+
+    /// Number of attempts to reconnect after the connection breaks
+    pub retries: usize,
+
+    /// Delay between attempts to reconnect, and the initial delay
+    /// before sending again an ASK rejected by a full queue
+    pub retry_delay: Duration,
+
+    /// Bearer token to authenticate to the server
+    pub token: Option<String>,
+
+    /// Connect over TLS, see [`crate::tls::client_config()`]
+    pub tls: Option<Arc<ClientConfig>>,
+
+    /// Name of the server to verify its certificate, by default its
+    /// IP address
+    pub server_name: Option<String>,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            path: "/".into(),
+            retries: 10,
+            retry_delay: Duration::from_millis(500),
+            token: None,
+            tls: None,
+            server_name: None,
+        }
+    }
+}
+
+enum Event {
+    Send(Vec<u8>),
+    Close,
+}
+
+/// A message relay client over WebSocket.
+pub struct WsRelay {
+    events: mpsc::UnboundedSender<Event>,
+    rx: mpsc::UnboundedReceiver<Vec<u8>>,
+}
+
+impl WsRelay {
+    /// Connects to a relay server with default options.
+    pub async fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        Self::connect_with(addr, ClientOptions::default()).await
+    }
+
+    /// Connects to a relay server. The connection is handled by a task
+    /// spawned on the current runtime.
+    ///
+    /// # Arguments
+    /// * `addr` - Address of the server
+    /// * `opts` - Connection options
+    pub async fn connect_with<A: ToSocketAddrs>(
+        addr: A,
+        opts: ClientOptions,
+    ) -> io::Result<Self> {
+        let addrs: Vec<SocketAddr> = lookup_host(addr).await?.collect();
+
+        let (events, events_rx) = mpsc::unbounded_channel();
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        let conn = Connection {
+            addrs,
+            opts,
+            incoming: tx,
+            asks: HashMap::new(),
+            backoff: HashMap::new(),
+            published: Vec::new(),
+        };
+
+        let ws = conn.open().await?;
+
+        tokio::spawn(conn.run(ws, events_rx));
+
+        Ok(Self { events, rx })
+    }
+}
+
+impl Drop for WsRelay {
+    fn drop(&mut self) {
+        let _ = self.events.send(Event::Close);
+    }
+}
+
+impl Stream for WsRelay {
+    type Item = Vec<u8>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.get_mut().rx.poll_recv(cx)
+    }
+}
+
+impl Sink<Vec<u8>> for WsRelay {
+    type Error = MessageSendError;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(
+        self: Pin<&mut Self>,
+        item: Vec<u8>,
+    ) -> Result<(), Self::Error> {
+        self.events
+            .send(Event::Send(item))
+            .map_err(|_| MessageSendError)
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Relay for WsRelay {}
+
+trait Io: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> Io for S {}
+
+type Ws = WebSocketStream<Box<dyn Io>>;
+
+// Returns the ID of a message named by a "queue full" error of the
+// server, see `RelayServer`.
+fn queue_full(error: &str) -> Option<MsgId> {
+    let id = error.strip_prefix(&RelayError::QueueFull.to_string())?;
+
+    let mut bytes = [0; 32];
+    hex::decode_to_slice(id.trim(), &mut bytes).ok()?;
+
+    Some(bytes.into())
+}
+
+fn ws_error(err: tungstenite::Error) -> io::Error {
+    match err {
+        tungstenite::Error::Io(err) => err,
+        tungstenite::Error::Http(resp)
+            if resp.status() == StatusCode::UNAUTHORIZED =>
+        {
+            io::ErrorKind::PermissionDenied.into()
+        }
+        err => io::Error::other(err),
+    }
+}
+
+struct Connection {
+    addrs: Vec<SocketAddr>,
+    opts: ClientOptions,
+    incoming: mpsc::UnboundedSender<Vec<u8>>,
+    // not yet answered ASK messages
+    asks: HashMap<MsgId, (Instant, Vec<u8>)>,
+    // number of times an ASK was rejected by a full queue
+    backoff: HashMap<MsgId, u32>,
+    // published messages which have not expired
+    published: Vec<(Instant, Vec<u8>)>,
+}
+
+impl Connection {
+    async fn open(&self) -> io::Result<Ws> {
+        let stream = TcpStream::connect(&self.addrs[..]).await?;
+        stream.set_nodelay(true)?;
+
+        let peer = stream.peer_addr()?;
+
+        let stream: Box<dyn Io> = match &self.opts.tls {
+            Some(config) => {
+                let name = crate::tls::server_name(
+                    self.opts.server_name.as_deref(),
+                    &peer,
+                )?;
+                let connect =
+                    TlsConnector::from(config.clone()).connect(name, stream);
+                let stream = timeout(HANDSHAKE_TIMEOUT, connect)
+                    .await
+                    .map_err(|_| io::ErrorKind::TimedOut)??;
+                Box::new(stream)
+            }
+            None => Box::new(stream),
+        };
+
+        let scheme = if self.opts.tls.is_some() { "wss" } else { "ws" };
+        let url = format!("{scheme}://{peer}{}", self.opts.path);
+        let mut req = url.into_client_request().map_err(ws_error)?;
+
+        if let Some(token) = &self.opts.token {
+            let value = HeaderValue::from_str(&format!("Bearer {token}"))
+                .map_err(io::Error::other)?;
+            req.headers_mut().insert(AUTHORIZATION, value);
+        }
+
+        let handshake = tokio_tungstenite::client_async_with_config(
+            req,
+            stream,
+            Some(ws_config()),
+        );
+
+        let (ws, _) = timeout(HANDSHAKE_TIMEOUT, handshake)
+            .await
+            .map_err(|_| io::ErrorKind::TimedOut)?
+            .map_err(ws_error)?;
+
+        Ok(ws)
+    }
+
+    fn remember(&mut self, msg: &[u8]) {
+        let Ok(hdr) = <&MsgHdr>::try_from(msg) else {
+            return;
+        };
+
+        let expire = Instant::now() + hdr.ttl();
+
+        if msg.len() == MESSAGE_HEADER_SIZE {
+            self.asks.insert(*hdr.id(), (expire, msg.to_vec()));
+        } else {
+            self.published.push((expire, msg.to_vec()));
+        }
+    }
+
+    // Returns the delay before sending again an ASK rejected by a full
+    // queue.
+    fn delay(&mut self, id: MsgId) -> Duration {
+        let attempt = self.backoff.entry(id).or_default();
+        let delay = self.opts.retry_delay * (1 << *attempt);
+        *attempt = (*attempt + 1).min(MAX_BACKOFF_SHIFT);
+        delay
+    }
+
+    async fn resend(&mut self, ws: &mut Ws) -> io::Result<()> {
+        let now = Instant::now();
+
+        self.asks.retain(|_, (expire, _)| *expire > now);
+        self.backoff.retain(|id, _| self.asks.contains_key(id));
+        self.published.retain(|(expire, _)| *expire > now);
+
+        let msgs = self.asks.values().chain(self.published.iter());
+
+        for (_, msg) in msgs {
+            ws.feed(Message::Binary(msg.clone()))
+                .await
+                .map_err(ws_error)?;
+        }
+
+        ws.flush().await.map_err(ws_error)
+    }
+
+    async fn reconnect(&mut self) -> Option<Ws> {
+        for attempt in 1..=self.opts.retries {
+            sleep(self.opts.retry_delay).await;
+
+            let mut ws = match self.open().await {
+                Ok(ws) => ws,
+                Err(err) => {
+                    tracing::debug!("reconnect attempt {}: {}", attempt, err);
+                    continue;
+                }
+            };
+
+            match self.resend(&mut ws).await {
+                Ok(()) => {
+                    tracing::debug!("reconnected, attempt {}", attempt);
+                    return Some(ws);
+                }
+                Err(err) => {
+                    tracing::debug!("reconnect attempt {}: {}", attempt, err);
+                }
+            }
+        }
+
+        None
+    }
+
+    async fn run(
+        mut self,
+        mut ws: Ws,
+        mut events: mpsc::UnboundedReceiver<Event>,
+    ) {
+        let mut ping = interval(PING_INTERVAL);
+        let (retry, mut retries) = mpsc::unbounded_channel();
+
+        loop {
+            let connected = tokio::select! {
+                event = events.recv() => match event {
+                    Some(Event::Send(msg)) => {
+                        self.remember(&msg);
+                        ws.send(Message::Binary(msg)).await.is_ok()
+                    }
+
+                    Some(Event::Close) | None => break,
+                },
+
+                msg = ws.next() => match msg {
+                    Some(Ok(Message::Binary(msg))) => {
+                        if let Ok(id) = <&MsgId>::try_from(msg.as_slice()) {
+                            self.asks.remove(id);
+                            self.backoff.remove(id);
+                        }
+                        if self.incoming.send(msg).is_err() {
+                            break;
+                        }
+                        true
+                    }
+
+                    Some(Ok(Message::Text(error))) => {
+                        match queue_full(&error) {
+                            Some(id) if self.asks.contains_key(&id) => {
+                                let delay = self.delay(id);
+                                tracing::debug!(
+                                    "queue full, ask {:X} again in {:?}",
+                                    id,
+                                    delay
+                                );
+                                let retry = retry.clone();
+                                tokio::spawn(async move {
+                                    sleep(delay).await;
+                                    let _ = retry.send(id);
+                                });
+                            }
+                            _ => tracing::warn!("relay error: {}", error),
+                        }
+                        true
+                    }
+
+                    Some(Ok(Message::Close(Some(frame))))
+                        if frame.code == CloseCode::Policy =>
+                    {
+                        tracing::warn!(
+                            "the relay rejected a message: {}",
+                            frame.reason
+                        );
+                        return;
+                    }
+
+                    // the library answers pings
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => false,
+                    Some(Ok(_)) => true,
+                },
+
+                Some(id) = retries.recv() => {
+                    let now = Instant::now();
+                    match self.asks.get(&id) {
+                        Some((expire, ask)) if *expire > now => {
+                            let ask = Message::Binary(ask.clone());
+                            ws.send(ask).await.is_ok()
+                        }
+                        _ => {
+                            self.asks.remove(&id);
+                            self.backoff.remove(&id);
+                            true
+                        }
+                    }
+                }
+
+                _ = ping.tick() => {
+                    ws.send(Message::Ping(vec![])).await.is_ok()
+                }
+            };
+
+            if !connected {
+                match self.reconnect().await {
+                    Some(new) => ws = new,
+                    None => return,
+                }
+            }
+        }
+
+        let _ = ws.close(None).await;
+    }
+}
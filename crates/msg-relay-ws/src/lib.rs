@@ -0,0 +1,326 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! A networked message relay: a WebSocket server in front of a
+//! [`MsgRelay`](msg_relay::MsgRelay) and a client implementing
+//! [`Relay`](sl_mpc_mate::coord::Relay), so parties on different
+//! machines can execute protocols through a shared relay.
+//!
+//! ```no_run
+//! use msg_relay::MsgRelay;
+//! use msg_relay_ws::{RelayServer, WsRelay};
+//!
+//! # async fn example() -> std::io::Result<()> {
+//! let server = RelayServer::bind("127.0.0.1:8080", MsgRelay::new(None))?;
+//!
+//! // pass to keygen::run(), sign::run(), etc.
+//! let relay = WsRelay::connect(server.local_addr()).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod client;
+pub mod metrics;
+pub mod server;
+pub mod tls;
+
+pub use client::{ClientOptions, WsRelay};
+pub use server::RelayServer;
+
+/// Upper limit of the size of a relay message.
+pub const MAX_MESSAGE_SIZE: usize = 16 << 20;
+
+#[cfg(test)]
+mod tests {
+    use std::{io, sync::Arc, time::Duration};
+
+    use tokio::task::JoinSet;
+
+    use msg_relay::{Limits, MsgRelay, Session, TokenAuthenticator};
+    use sl_dkls23::{
+        keygen::{self, utils::setup_keygen},
+        sign,
+    };
+    use sl_mpc_mate::{
+        coord::{Relay, SinkExt, StreamExt},
+        message::{allocate_message, AskMsg, MessageTag, MsgId},
+    };
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keygen_and_sign() {
+        let server =
+            RelayServer::bind("127.0.0.1:0", MsgRelay::new(None)).unwrap();
+        let addr = server.local_addr();
+
+        let mut parties = JoinSet::new();
+        for (setup, seed) in setup_keygen(None, 2, 3, None) {
+            let relay = WsRelay::connect(addr).await.unwrap();
+            parties.spawn(keygen::run(setup, seed, relay));
+        }
+
+        let mut shares = vec![];
+        while let Some(share) = parties.join_next().await {
+            shares.push(Arc::new(share.unwrap().unwrap()));
+        }
+
+        let mut parties = JoinSet::new();
+        for (setup, seed) in sign::setup_dsg(None, &shares[..2], "m") {
+            let relay = WsRelay::connect(addr).await.unwrap();
+            parties.spawn(sign::run(setup, seed, relay));
+        }
+
+        while let Some(sign) = parties.join_next().await {
+            sign.unwrap().unwrap();
+        }
+    }
+
+    async fn recv_timeout<R: Relay>(relay: &mut R) -> Option<Vec<u8>> {
+        tokio::time::timeout(Duration::from_secs(10), relay.next())
+            .await
+            .ok()
+            .flatten()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn reask_after_reconnect() {
+        let relay = MsgRelay::new(None);
+        let server = RelayServer::bind("127.0.0.1:0", relay.clone()).unwrap();
+        let addr = server.local_addr();
+
+        let opts = ClientOptions {
+            retry_delay: Duration::from_millis(50),
+            ..Default::default()
+        };
+
+        let id =
+            MsgId::broadcast(&[1; 32].into(), &[2; 32], MessageTag::tag(3));
+
+        let mut receiver = WsRelay::connect_with(addr, opts).await.unwrap();
+        receiver.send(AskMsg::allocate(&id, 100)).await.unwrap();
+
+        // restart the front end, the state of the relay is kept
+        // but the ASK from the closed connection is lost
+        server.shutdown();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let server = RelayServer::bind(addr, relay).unwrap();
+
+        let msg = allocate_message(&id, 100, 0, b"payload");
+        let mut sender = WsRelay::connect(server.local_addr()).await.unwrap();
+        sender.send(msg.clone()).await.unwrap();
+
+        assert_eq!(recv_timeout(&mut receiver).await, Some(msg));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn authentication() {
+        let mut auth = TokenAuthenticator::new();
+        auth.add_token("secret", "p0");
+
+        let relay = MsgRelay::new(None);
+        relay.set_authenticator(Box::new(auth));
+
+        let server = RelayServer::bind("127.0.0.1:0", relay).unwrap();
+        let addr = server.local_addr();
+
+        let err = WsRelay::connect(addr).await.err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+
+        let opts = ClientOptions {
+            token: Some("secret".into()),
+            ..Default::default()
+        };
+        let mut relay = WsRelay::connect_with(addr, opts).await.unwrap();
+
+        let id = MsgId::from([1; 32]);
+        let msg = allocate_message(&id, 100, 0, b"payload");
+        relay.send(msg.clone()).await.unwrap();
+        relay.send(AskMsg::allocate(&id, 100)).await.unwrap();
+
+        assert_eq!(recv_timeout(&mut relay).await, Some(msg));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tls() {
+        let data =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+        let config = tls::server_config(
+            &data.join("server.pem"),
+            &data.join("server.key"),
+        )
+        .unwrap();
+
+        let server =
+            RelayServer::bind_tls("127.0.0.1:0", MsgRelay::new(None), config)
+                .unwrap();
+        let addr = server.local_addr();
+
+        // a plain connection fails the handshake
+        assert!(WsRelay::connect(addr).await.is_err());
+
+        let opts = ClientOptions {
+            tls: Some(tls::client_config(&data.join("ca.pem")).unwrap()),
+            ..Default::default()
+        };
+        let mut relay = WsRelay::connect_with(addr, opts).await.unwrap();
+
+        let id = MsgId::from([1; 32]);
+        let msg = allocate_message(&id, 100, 0, b"payload");
+        relay.send(msg.clone()).await.unwrap();
+        relay.send(AskMsg::allocate(&id, 100)).await.unwrap();
+
+        assert_eq!(recv_timeout(&mut relay).await, Some(msg));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn acl_requires_tls() {
+        let data =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata");
+
+        let mut auth = TokenAuthenticator::new();
+        auth.add_token("secret", "p0");
+
+        let instance = [1; 32].into();
+        let id = MsgId::broadcast(&instance, b"p0", MessageTag::tag(1));
+
+        let relay = MsgRelay::new(None);
+        relay.set_authenticator(Box::new(auth));
+        relay.add_session(Session::new(instance).allow(b"p0", [id]));
+
+        let opts = ClientOptions {
+            token: Some("secret".into()),
+            ..Default::default()
+        };
+
+        let server = RelayServer::bind("127.0.0.1:0", relay.clone()).unwrap();
+        assert!(WsRelay::connect_with(server.local_addr(), opts.clone())
+            .await
+            .is_err());
+
+        let config = tls::server_config(
+            &data.join("server.pem"),
+            &data.join("server.key"),
+        )
+        .unwrap();
+        let server =
+            RelayServer::bind_tls("127.0.0.1:0", relay, config).unwrap();
+
+        let opts = ClientOptions {
+            tls: Some(tls::client_config(&data.join("ca.pem")).unwrap()),
+            ..opts
+        };
+        let mut relay = WsRelay::connect_with(server.local_addr(), opts)
+            .await
+            .unwrap();
+
+        let msg = allocate_message(&id, 100, 0, b"payload");
+        relay.send(msg.clone()).await.unwrap();
+        relay.send(AskMsg::allocate(&id, 100)).await.unwrap();
+
+        assert_eq!(recv_timeout(&mut relay).await, Some(msg));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejected_message() {
+        let relay = MsgRelay::new(None);
+        relay.set_limits(Limits {
+            max_messages: 1,
+            ..Default::default()
+        });
+
+        let server = RelayServer::bind("127.0.0.1:0", relay).unwrap();
+        let mut relay = WsRelay::connect(server.local_addr()).await.unwrap();
+
+        for i in 0..2 {
+            let id = MsgId::from([i; 32]);
+            let msg = allocate_message(&id, 100, 0, b"payload");
+            relay.send(msg).await.unwrap();
+        }
+
+        // the server closes the connection and the client gives up
+        assert_eq!(recv_timeout(&mut relay).await, None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn transient_error() {
+        let relay = MsgRelay::new(None);
+        relay.set_limits(Limits {
+            queue_size: 1,
+            ..Default::default()
+        });
+
+        let opts = ClientOptions {
+            retry_delay: Duration::from_millis(10),
+            ..Default::default()
+        };
+
+        let server = RelayServer::bind("127.0.0.1:0", relay).unwrap();
+        let mut receiver = WsRelay::connect_with(server.local_addr(), opts)
+            .await
+            .unwrap();
+        let mut sender = WsRelay::connect(server.local_addr()).await.unwrap();
+
+        // the second ASK does not fit into the queue
+        let ids = [MsgId::from([1; 32]), MsgId::from([2; 32])];
+        for id in &ids {
+            receiver.send(AskMsg::allocate(id, 100)).await.unwrap();
+        }
+
+        // but the connection stays open
+        for id in &ids {
+            let msg = allocate_message(id, 100, 0, b"payload");
+            sender.send(msg.clone()).await.unwrap();
+
+            // the client asks again until the queue has a free slot
+            assert_eq!(recv_timeout(&mut receiver).await, Some(msg));
+        }
+    }
+
+    #[test]
+    fn metrics() {
+        use std::{
+            io::{Read, Write},
+            net::{TcpListener, TcpStream},
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let relay = MsgRelay::new(None);
+        let _conn = relay.connect();
+
+        std::thread::spawn(move || metrics::serve(listener, relay));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: h\r\n\r\n")
+            .unwrap();
+
+        let mut resp = String::new();
+        stream.read_to_string(&mut resp).unwrap();
+
+        assert!(resp.starts_with("HTTP/1.1 200 OK"));
+        assert!(resp.contains("\nmsg_relay_connections 1\n"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn end_of_stream_without_server() {
+        let server =
+            RelayServer::bind("127.0.0.1:0", MsgRelay::new(None)).unwrap();
+
+        let opts = ClientOptions {
+            retries: 2,
+            retry_delay: Duration::from_millis(10),
+            ..Default::default()
+        };
+
+        let mut relay = WsRelay::connect_with(server.local_addr(), opts)
+            .await
+            .unwrap();
+
+        server.shutdown();
+
+        assert_eq!(recv_timeout(&mut relay).await, None);
+    }
+}
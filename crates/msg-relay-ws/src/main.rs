@@ -0,0 +1,286 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+use std::{net::TcpListener, path::Path, thread, time::Duration};
+
+use msg_relay::{
+    Acl, Limits, LogStore, MsgRelay, Session, TokenAuthenticator,
+};
+use msg_relay_ws::{metrics, tls, RelayServer};
+use sl_mpc_mate::message::{InstanceId, MessageTag, MsgId};
+
+mod flags {
+    use std::path::PathBuf;
+
+    xflags::xflags! {
+        /// Run a WebSocket message relay server.
+        cmd msg-relay-ws {
+            /// Address to listen on. Default 127.0.0.1:8080
+            optional --listen addr: String
+
+            /// Keep published messages in a log file, so they
+            /// survive a restart of the server.
+            optional --store path: PathBuf
+
+            /// Require clients to authenticate. Each line of the file
+            /// is a token and an identity in hex, separated by a space.
+            optional --tokens path: PathBuf
+
+            /// Accept only messages of sessions listed in the file.
+            /// Requires --tokens and TLS. A line `session <instance-id>`
+            /// starts a session, `allow <identity> <msg-id>...` allows
+            /// an identity to publish messages, `sender <identity>
+            /// <sender> <tag,...> <receiver>...` to publish messages of
+            /// a sender. IDs, identities and keys are in hex.
+            optional --acl path: PathBuf
+
+            /// Accept TLS connections only, with the certificate chain
+            /// in a PEM file. Requires --tls-key.
+            optional --tls-cert path: PathBuf
+
+            /// PEM file with the private key of the TLS certificate
+            optional --tls-key path: PathBuf
+
+            /// Serve metrics in Prometheus format at
+            /// http://<addr>/metrics
+            optional --metrics addr: String
+
+            /// Capacity of the queue of pending ASKs and undelivered
+            /// messages of a connection. Default 1024
+            optional --queue-size n: usize
+
+            /// Upper limit of total size of stored messages in bytes
+            optional --max-size bytes: usize
+
+            /// Upper limit of the number of stored messages
+            optional --max-messages n: usize
+
+            /// Upper limit of total size of stored messages of one
+            /// session in bytes
+            optional --max-session-size bytes: usize
+
+            /// Upper limit of the number of stored messages of one
+            /// session
+            optional --max-session-messages n: usize
+
+            /// Upper limit of total size of stored messages published
+            /// by one connection in bytes
+            optional --max-conn-size bytes: usize
+
+            /// Upper limit of the number of stored messages published
+            /// by one connection
+            optional --max-conn-messages n: usize
+
+            /// Messages and ASKs expire after at most this number of
+            /// seconds. Default 3600
+            optional --max-ttl secs: u64
+        }
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let flags = flags::MsgRelayWs::from_env_or_exit();
+    let addr = flags.listen.as_deref().unwrap_or("127.0.0.1:8080");
+
+    let relay = match &flags.store {
+        Some(path) => {
+            MsgRelay::with_store(None, Box::new(LogStore::open(path)?))
+        }
+        None => MsgRelay::new(None),
+    };
+
+    relay.set_limits(limits(&flags));
+
+    if let Some(path) = &flags.tokens {
+        relay.set_authenticator(Box::new(load_tokens(path)?));
+    }
+
+    if let Some(path) = &flags.acl {
+        if flags.tokens.is_none() || flags.tls_cert.is_none() {
+            anyhow::bail!(
+                "--acl requires --tokens, --tls-cert and --tls-key"
+            );
+        }
+        relay.set_acl(load_acl(path)?);
+    }
+
+    if let Some(addr) = &flags.metrics {
+        let listener = TcpListener::bind(addr)?;
+        let relay = relay.clone();
+        thread::spawn(move || metrics::serve(listener, relay));
+    }
+
+    let server = match (&flags.tls_cert, &flags.tls_key) {
+        (Some(cert), Some(key)) => {
+            let config = tls::server_config(cert, key)?;
+            RelayServer::bind_tls(addr, relay, config)?
+        }
+        (None, None) => RelayServer::bind(addr, relay)?,
+        _ => anyhow::bail!("--tls-cert and --tls-key go together"),
+    };
+
+    println!("listening on {}", server.local_addr());
+
+    server.join();
+
+    Ok(())
+}
+
+fn limits(flags: &flags::MsgRelayWs) -> Limits {
+    let default = Limits::default();
+
+    Limits {
+        queue_size: flags.queue_size.unwrap_or(default.queue_size),
+        max_size: flags.max_size.unwrap_or(default.max_size),
+        max_messages: flags.max_messages.unwrap_or(default.max_messages),
+        max_session_size: flags
+            .max_session_size
+            .unwrap_or(default.max_session_size),
+        max_session_messages: flags
+            .max_session_messages
+            .unwrap_or(default.max_session_messages),
+        max_conn_size: flags.max_conn_size.unwrap_or(default.max_conn_size),
+        max_conn_messages: flags
+            .max_conn_messages
+            .unwrap_or(default.max_conn_messages),
+        max_ttl: flags.max_ttl.map_or(default.max_ttl, Duration::from_secs),
+    }
+}
+
+fn load_tokens(path: &Path) -> anyhow::Result<TokenAuthenticator> {
+    let mut auth = TokenAuthenticator::new();
+
+    for line in std::fs::read_to_string(path)?.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (token, identity) = line
+            .split_once(' ')
+            .ok_or_else(|| anyhow::anyhow!("invalid token line: {line}"))?;
+
+        auth.add_token(token, hex::decode(identity.trim())?);
+    }
+
+    Ok(auth)
+}
+
+fn hex_array(s: &str) -> anyhow::Result<[u8; 32]> {
+    <[u8; 32]>::try_from(hex::decode(s)?)
+        .map_err(|_| anyhow::anyhow!("expected 32 bytes: {s}"))
+}
+
+fn parse_acl(text: &str) -> anyhow::Result<Acl> {
+    let mut acl = Acl::new();
+    let mut session: Option<Session> = None;
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        let mut next = || {
+            words
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("invalid ACL line: {line}"))
+        };
+
+        let kind = next()?;
+        if kind == "session" {
+            let instance = InstanceId::from(hex_array(next()?)?);
+            if let Some(session) = session.replace(Session::new(instance)) {
+                acl.add_session(session);
+            }
+            continue;
+        }
+
+        let current = session
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("no session: {line}"))?;
+        let identity = hex::decode(next()?)?;
+
+        session = Some(match kind {
+            "allow" => {
+                let ids = words
+                    .map(|id| hex_array(id).map(MsgId::from))
+                    .collect::<Result<Vec<_>, _>>()?;
+                current.allow(&identity, ids)
+            }
+
+            "sender" => {
+                let sender = hex::decode(next()?)?;
+                let tags = next()?
+                    .split(',')
+                    .map(|tag| Ok(MessageTag::tag(tag.parse()?)))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let receivers =
+                    words.map(hex::decode).collect::<Result<Vec<_>, _>>()?;
+                let receivers: Vec<&[u8]> =
+                    receivers.iter().map(Vec::as_slice).collect();
+
+                current.allow_sender(&identity, &sender, &receivers, &tags)
+            }
+
+            _ => anyhow::bail!("invalid ACL line: {line}"),
+        });
+    }
+
+    if let Some(session) = session {
+        acl.add_session(session);
+    }
+
+    Ok(acl)
+}
+
+fn load_acl(path: &Path) -> anyhow::Result<Acl> {
+    parse_acl(&std::fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acl_file() {
+        let instance = [1u8; 32];
+        let tag = MessageTag::tag(5);
+        let bcast = MsgId::broadcast(&instance.into(), b"p0", tag);
+        let p2p = MsgId::new(&instance.into(), b"p0", Some(b"p1"), tag);
+        let other = [9u8; 32];
+
+        let text = format!(
+            "# a session\n\
+             session {}\n\
+             sender {} {} 4,5 {} {}\n\
+             allow {} {}\n",
+            hex::encode(instance),
+            hex::encode("p0"),
+            hex::encode("p0"),
+            hex::encode("p0"),
+            hex::encode("p1"),
+            hex::encode("p1"),
+            hex::encode(other),
+        );
+
+        let acl = parse_acl(&text).unwrap();
+
+        assert!(acl.may_publish(Some(b"p0"), &bcast));
+        assert!(acl.may_publish(Some(b"p0"), &p2p));
+        assert!(acl.may_publish(Some(b"p1"), &MsgId::from(other)));
+        assert!(acl.may_ask(Some(b"p1"), &bcast));
+        assert!(!acl.may_publish(Some(b"p1"), &bcast));
+
+        assert!(parse_acl("allow 00").is_err());
+        assert!(parse_acl("session 00").is_err());
+        assert!(parse_acl(&format!(
+            "session {}\nfoo 00",
+            hex::encode(instance)
+        ))
+        .is_err());
+    }
+}
@@ -0,0 +1,95 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! A plain HTTP endpoint exporting the status of a [`MsgRelay`] in
+//! the Prometheus text format, see [`RelayStatus::to_prometheus()`].
+//!
+//! [`RelayStatus::to_prometheus()`]: msg_relay::RelayStatus::to_prometheus
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use msg_relay::MsgRelay;
+
+// Upper limit of the number of requests served at the same time,
+// further connections are closed immediately.
+const MAX_REQUESTS: usize = 8;
+
+// Upper limit of the size of a request head.
+const MAX_HEAD_SIZE: u64 = 8 << 10;
+
+// A client has to send its request and receive the response within
+// this time.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Accepts connections and answers `GET /metrics` requests. Runs
+/// until the listener fails.
+pub fn serve(listener: TcpListener, relay: MsgRelay) -> io::Result<()> {
+    let active = Arc::new(AtomicUsize::new(0));
+
+    loop {
+        let (stream, _) = listener.accept()?;
+
+        if active.fetch_add(1, Ordering::SeqCst) >= MAX_REQUESTS {
+            active.fetch_sub(1, Ordering::SeqCst);
+            tracing::debug!("too many metrics requests");
+            let _ = stream.shutdown(Shutdown::Both);
+            continue;
+        }
+
+        let relay = relay.clone();
+        let active = active.clone();
+
+        thread::spawn(move || {
+            if let Err(err) = serve_request(stream, &relay) {
+                tracing::debug!("metrics request: {}", err);
+            }
+            active.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+}
+
+fn serve_request(mut stream: TcpStream, relay: &MsgRelay) -> io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+
+    let mut input = BufReader::new((&stream).take(MAX_HEAD_SIZE));
+
+    // only the request line matters, but read the whole head
+    let mut head = String::new();
+    input.read_line(&mut head)?;
+
+    let mut line = String::new();
+    while !matches!(line.as_str(), "\r\n" | "\n") {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+    }
+
+    let (status, body) = match head.split(' ').take(2).collect::<Vec<_>>()[..]
+    {
+        ["GET", "/metrics"] => ("200 OK", relay.status().to_prometheus()),
+        _ => ("404 Not Found", String::new()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\n\
+         Content-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n\
+         {body}",
+        body.len()
+    )?;
+
+    stream.flush()
+}
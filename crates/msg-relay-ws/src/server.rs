@@ -0,0 +1,376 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! WebSocket front end of a [`MsgRelay`].
+//!
+//! Each accepted connection becomes a connection of the relay. Binary
+//! messages received from a client are passed to the relay, messages
+//! the client has asked for are sent back as binary messages. A
+//! server may accept TLS connections only, see [`crate::tls`].
+//!
+//! If the relay requires authentication, a client presents its
+//! credentials as a bearer token in the `Authorization` header of the
+//! opening handshake.
+//!
+//! If the relay rejects a message, e.g. because of a quota, the server
+//! closes the connection with status code 1008 (policy violation) and
+//! the error as a reason. A transient error, a full queue of the
+//! connection, is reported by a text message with the error and the
+//! ID of the message instead, and the connection stays open.
+//!
+//! A client has to complete the handshake within
+//! [`HANDSHAKE_TIMEOUT`], and a connection without any message,
+//! including pings, for [`IDLE_TIMEOUT`] is closed.
+
+use std::{
+    io,
+    net::{SocketAddr, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+    sync::watch,
+    time::timeout,
+};
+use tokio_rustls::{rustls::ServerConfig, TlsAcceptor};
+use tokio_tungstenite::tungstenite::{
+    handshake::server::{ErrorResponse, Request, Response},
+    http::{header::AUTHORIZATION, StatusCode},
+    protocol::{frame::coding::CloseCode, CloseFrame, WebSocketConfig},
+    Message,
+};
+
+use msg_relay::{
+    MsgRelay, MsgRelayConnection, RelayError, SinkExt, StreamExt,
+    MESSAGE_HEADER_SIZE,
+};
+use sl_mpc_mate::message::MsgId;
+
+use crate::MAX_MESSAGE_SIZE;
+
+/// Time to complete the TLS and WebSocket handshakes.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A connection without incoming messages for this time is closed.
+/// Clients send pings more often, see
+/// [`PING_INTERVAL`](crate::client::PING_INTERVAL).
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+pub(crate) fn ws_config() -> WebSocketConfig {
+    WebSocketConfig {
+        max_message_size: Some(MAX_MESSAGE_SIZE),
+        max_frame_size: Some(MAX_MESSAGE_SIZE),
+        ..Default::default()
+    }
+}
+
+/// A WebSocket server of a message relay.
+pub struct RelayServer {
+    addr: SocketAddr,
+    stop: watch::Sender<bool>,
+    conns: Arc<AtomicUsize>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl RelayServer {
+    /// Binds a listening socket to a given address and starts to
+    /// accept connections.
+    ///
+    /// Identities and messages of plain connections are not
+    /// protected, so while the relay has an access control list,
+    /// connections are closed. Use [`RelayServer::bind_tls()`].
+    ///
+    /// # Arguments
+    /// * `addr` - Address to listen on, use port 0 to pick a free port
+    /// * `relay` - The message relay to serve
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        relay: MsgRelay,
+    ) -> io::Result<Self> {
+        Self::bind_inner(addr, relay, None)
+    }
+
+    /// Same as [`RelayServer::bind()`], but accepts TLS connections
+    /// only.
+    pub fn bind_tls<A: ToSocketAddrs>(
+        addr: A,
+        relay: MsgRelay,
+        config: Arc<ServerConfig>,
+    ) -> io::Result<Self> {
+        Self::bind_inner(addr, relay, Some(TlsAcceptor::from(config)))
+    }
+
+    fn bind_inner<A: ToSocketAddrs>(
+        addr: A,
+        relay: MsgRelay,
+        tls: Option<TlsAcceptor>,
+    ) -> io::Result<Self> {
+        let listener = std::net::TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?;
+
+        let (stop, stop_rx) = watch::channel(false);
+        let conns = Arc::new(AtomicUsize::new(0));
+
+        let handle = thread::spawn({
+            let conns = conns.clone();
+            move || {
+                runtime.block_on(async move {
+                    match TcpListener::from_std(listener) {
+                        Ok(listener) => {
+                            accept_loop(listener, relay, tls, stop_rx, conns)
+                                .await
+                        }
+                        Err(err) => tracing::error!("listen: {}", err),
+                    }
+                });
+                // dropping the runtime closes all connections
+            }
+        });
+
+        tracing::info!("listening on {}", addr);
+
+        Ok(Self {
+            addr,
+            stop,
+            conns,
+            handle: Some(handle),
+        })
+    }
+
+    /// Returns the address the server listens on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns number of open connections.
+    pub fn connections(&self) -> usize {
+        self.conns.load(Ordering::SeqCst)
+    }
+
+    /// Waits until the server stops accepting connections.
+    pub fn join(mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+
+    /// Stops accepting new connections and closes all open ones.
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        let Some(handle) = self.handle.take() else {
+            return;
+        };
+
+        let _ = self.stop.send(true);
+        let _ = handle.join();
+    }
+}
+
+impl Drop for RelayServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// Counts open connections.
+struct ConnGuard(Arc<AtomicUsize>);
+
+impl ConnGuard {
+    fn new(conns: &Arc<AtomicUsize>) -> Self {
+        conns.fetch_add(1, Ordering::SeqCst);
+        Self(conns.clone())
+    }
+}
+
+impl Drop for ConnGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    relay: MsgRelay,
+    tls: Option<TlsAcceptor>,
+    mut stop: watch::Receiver<bool>,
+    conns: Arc<AtomicUsize>,
+) {
+    loop {
+        let stream = tokio::select! {
+            res = listener.accept() => match res {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    tracing::debug!("accept error {}", err);
+                    continue;
+                }
+            },
+            _ = stop.changed() => break,
+        };
+
+        let relay = relay.clone();
+        let tls = tls.clone();
+        let stop = stop.clone();
+        let guard = ConnGuard::new(&conns);
+
+        tokio::spawn(async move {
+            let peer = stream.peer_addr();
+            if let Err(err) = accept(stream, &relay, tls, stop).await {
+                tracing::debug!("connection {:?} closed: {}", peer, err);
+            }
+            drop(guard);
+        });
+    }
+}
+
+async fn accept(
+    stream: TcpStream,
+    relay: &MsgRelay,
+    tls: Option<TlsAcceptor>,
+    stop: watch::Receiver<bool>,
+) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+
+    match tls {
+        Some(tls) => {
+            let stream = timeout(HANDSHAKE_TIMEOUT, tls.accept(stream))
+                .await
+                .map_err(|_| io::ErrorKind::TimedOut)??;
+            serve_connection(stream, relay, stop).await
+        }
+        None if relay.has_acl() => {
+            tracing::warn!("plain connection to a relay with an ACL");
+            Err(io::ErrorKind::PermissionDenied.into())
+        }
+        None => serve_connection(stream, relay, stop).await,
+    }
+}
+
+fn bearer_token(req: &Request) -> Option<&str> {
+    req.headers()
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+async fn serve_connection<S>(
+    stream: S,
+    relay: &MsgRelay,
+    mut stop: watch::Receiver<bool>,
+) -> io::Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut conn: Option<MsgRelayConnection> = None;
+
+    // the signature is given by the library
+    #[allow(clippy::result_large_err)]
+    let callback = |req: &Request, resp: Response| {
+        conn = if relay.requires_authentication() {
+            bearer_token(req).and_then(|t| relay.authenticate(t.as_bytes()))
+        } else {
+            Some(relay.connect())
+        };
+
+        match conn {
+            Some(_) => Ok(resp),
+            None => {
+                let mut resp = ErrorResponse::new(None);
+                *resp.status_mut() = StatusCode::UNAUTHORIZED;
+                Err(resp)
+            }
+        }
+    };
+
+    let handshake = tokio_tungstenite::accept_hdr_async_with_config(
+        stream,
+        callback,
+        Some(ws_config()),
+    );
+
+    let mut ws = timeout(HANDSHAKE_TIMEOUT, handshake)
+        .await
+        .map_err(|_| io::ErrorKind::TimedOut)?
+        .map_err(io::Error::other)?;
+
+    let mut conn = conn.expect("authorized connection");
+    let sender = conn.sender();
+
+    loop {
+        tokio::select! {
+            msg = conn.recv() => {
+                let Some(msg) = msg else {
+                    break;
+                };
+
+                ws.send(Message::Binary(msg))
+                    .await
+                    .map_err(io::Error::other)?;
+            }
+
+            msg = timeout(IDLE_TIMEOUT, ws.next()) => {
+                let msg = match msg {
+                    Ok(Some(msg)) => msg.map_err(io::Error::other)?,
+                    Ok(None) => break,
+                    Err(_) => return Err(io::ErrorKind::TimedOut.into()),
+                };
+
+                let msg = match msg {
+                    Message::Binary(msg) if msg.len() >= MESSAGE_HEADER_SIZE => {
+                        msg
+                    }
+
+                    // ignore garbage shorter than a message header,
+                    // the library answers pings and close frames
+                    _ => continue,
+                };
+
+                let id = MsgId::try_from(&msg[..]).unwrap_or(MsgId::ZERO_ID);
+
+                match sender.send_message(msg) {
+                    Ok(()) => {}
+
+                    Err(RelayError::QueueFull) => {
+                        let text =
+                            format!("{} {:x}", RelayError::QueueFull, id);
+                        ws.send(Message::Text(text))
+                            .await
+                            .map_err(io::Error::other)?;
+                    }
+
+                    Err(err) => {
+                        let frame = CloseFrame {
+                            code: CloseCode::Policy,
+                            reason: err.to_string().into(),
+                        };
+                        let _ = ws.close(Some(frame)).await;
+                        return Err(io::Error::other(err));
+                    }
+                }
+            }
+
+            _ = stop.changed() => {
+                let _ = ws.close(None).await;
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
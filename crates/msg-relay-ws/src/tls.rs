@@ -0,0 +1,76 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! TLS configuration of a [`RelayServer`](crate::RelayServer) and its
+//! clients.
+//!
+//! Certificates and keys are read from PEM files. A client trusts
+//! only the certificate authorities it is given, there are no
+//! built-in roots: a relay is usually deployed with a private CA.
+
+use std::{io, path::Path, sync::Arc};
+
+use tokio_rustls::rustls::{
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName},
+    ClientConfig, RootCertStore, ServerConfig,
+};
+
+fn invalid_data<E: std::fmt::Display>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+}
+
+fn certificates(path: &Path) -> io::Result<Vec<CertificateDer<'static>>> {
+    CertificateDer::pem_file_iter(path)
+        .map_err(invalid_data)?
+        .collect::<Result<_, _>>()
+        .map_err(invalid_data)
+}
+
+/// Returns a server configuration.
+///
+/// # Arguments
+/// * `cert` - PEM file with the certificate chain of the server
+/// * `key` - PEM file with the private key of the server
+pub fn server_config(
+    cert: &Path,
+    key: &Path,
+) -> io::Result<Arc<ServerConfig>> {
+    let chain = certificates(cert)?;
+    let key = PrivateKeyDer::from_pem_file(key).map_err(invalid_data)?;
+
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .map_err(invalid_data)?;
+
+    Ok(Arc::new(config))
+}
+
+/// Returns a client configuration trusting given certificate
+/// authorities.
+///
+/// # Arguments
+/// * `ca` - PEM file with certificates of trusted authorities
+pub fn client_config(ca: &Path) -> io::Result<Arc<ClientConfig>> {
+    let mut roots = RootCertStore::empty();
+    for cert in certificates(ca)? {
+        roots.add(cert).map_err(invalid_data)?;
+    }
+
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    Ok(Arc::new(config))
+}
+
+pub(crate) fn server_name(
+    name: Option<&str>,
+    addr: &std::net::SocketAddr,
+) -> io::Result<ServerName<'static>> {
+    match name {
+        Some(name) => ServerName::try_from(name.to_owned())
+            .map_err(|_| invalid_data("invalid server name")),
+        None => Ok(ServerName::IpAddress(addr.ip().into())),
+    }
+}
@@ -0,0 +1,315 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Authentication of connections and access control of a
+//! [`MsgRelay`](crate::MsgRelay).
+//!
+//! A connection authenticated by an [`Authenticator`] has an
+//! identity, usually a public key of a party. An [`Acl`] consists of
+//! sessions, one per protocol instance. A session lists message IDs
+//! and the identity allowed to publish each of them. Any member of a
+//! session may ask for any message of the session.
+//!
+//! A message ID is derived from an instance ID, a sender, an optional
+//! receiver and a message tag, see [`MsgId::new()`]. So the relay
+//! can't find out who is the sender of a message and the owner of a
+//! session has to enumerate IDs of the messages a party sends, see
+//! [`Session::allow_sender()`].
+
+use std::collections::{HashMap, HashSet};
+
+use sl_mpc_mate::message::{InstanceId, MessageTag, MsgId};
+
+use crate::limits::Usage;
+
+/// Authenticates connections to a relay.
+pub trait Authenticator: Send {
+    /// Returns identity of a connection presenting given
+    /// credentials, or `None` if the credentials are not valid.
+    fn authenticate(&self, credentials: &[u8]) -> Option<Vec<u8>>;
+}
+
+/// Authenticates connections by pre-shared tokens.
+#[derive(Default)]
+pub struct TokenAuthenticator {
+    tokens: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl TokenAuthenticator {
+    /// Creates an authenticator without tokens.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a token.
+    ///
+    /// # Arguments
+    /// * `token` - A secret token presented by a client
+    /// * `identity` - Identity of connections presenting the token
+    pub fn add_token(
+        &mut self,
+        token: impl Into<Vec<u8>>,
+        identity: impl Into<Vec<u8>>,
+    ) {
+        self.tokens.insert(token.into(), identity.into());
+    }
+}
+
+impl Authenticator for TokenAuthenticator {
+    fn authenticate(&self, credentials: &[u8]) -> Option<Vec<u8>> {
+        self.tokens.get(credentials).cloned()
+    }
+}
+
+// InstanceId does not expose its bytes nor implements Hash, sessions
+// are identified by a fingerprint of the instance ID.
+fn session_id(instance: &InstanceId) -> MsgId {
+    MsgId::broadcast(instance, &[], MessageTag::tag(0))
+}
+
+/// Access rules of one protocol instance.
+pub struct Session {
+    instance: InstanceId,
+    id: MsgId,
+    label: String,
+    members: HashSet<Vec<u8>>,
+    publishers: HashMap<MsgId, Vec<u8>>,
+    pub(crate) usage: Usage,
+}
+
+impl Session {
+    /// Creates a session without members.
+    pub fn new(instance: InstanceId) -> Self {
+        let id = session_id(&instance);
+
+        Self {
+            instance,
+            id,
+            label: format!("{:x}", id),
+            members: HashSet::new(),
+            publishers: HashMap::new(),
+            usage: Usage::default(),
+        }
+    }
+
+    /// Returns the instance ID of the session.
+    pub fn instance_id(&self) -> &InstanceId {
+        &self.instance
+    }
+
+    /// Sets a label of the session for logs and metrics, e.g. the
+    /// instance ID in hex. By default, the label is a fingerprint of
+    /// the instance ID.
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.label = label.into();
+        self
+    }
+
+    /// Returns the label of the session.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Returns IDs of messages of the session.
+    pub fn message_ids(&self) -> impl Iterator<Item = &MsgId> {
+        self.publishers.keys()
+    }
+
+    /// Returns size and number of stored messages of the session.
+    pub fn usage(&self) -> Usage {
+        self.usage
+    }
+
+    /// Allows connections with given identity to publish messages
+    /// with given IDs and to ask for any message of the session.
+    pub fn allow(
+        mut self,
+        identity: &[u8],
+        ids: impl IntoIterator<Item = MsgId>,
+    ) -> Self {
+        self.members.insert(identity.to_vec());
+        for id in ids {
+            self.publishers.insert(id, identity.to_vec());
+        }
+        self
+    }
+
+    /// Allows connections with given identity to publish messages
+    /// on behalf of a sender: broadcast messages with given tags and
+    /// P2P messages with given tags to each of the receivers.
+    ///
+    /// # Arguments
+    /// * `identity` - Identity of the connection
+    /// * `sender` - Public key of the sender
+    /// * `receivers` - Public keys of the receivers of P2P messages.
+    ///   The sender is skipped if present.
+    /// * `tags` - Message tags
+    pub fn allow_sender(
+        self,
+        identity: &[u8],
+        sender: &[u8],
+        receivers: &[&[u8]],
+        tags: &[MessageTag],
+    ) -> Self {
+        let instance = self.instance;
+
+        let broadcast = tags
+            .iter()
+            .map(|tag| MsgId::broadcast(&instance, sender, *tag));
+
+        let p2p = receivers
+            .iter()
+            .filter(|receiver| **receiver != sender)
+            .flat_map(|receiver| {
+                tags.iter().map(move |tag| {
+                    MsgId::new(&instance, sender, Some(receiver), *tag)
+                })
+            });
+
+        let ids: Vec<_> = broadcast.chain(p2p).collect();
+
+        self.allow(identity, ids)
+    }
+}
+
+/// Access control list of a relay.
+///
+/// Messages which do not belong to any session are rejected.
+#[derive(Default)]
+pub struct Acl {
+    sessions: HashMap<MsgId, Session>,
+    // session ID of each message of all sessions
+    messages: HashMap<MsgId, MsgId>,
+}
+
+impl Acl {
+    /// Creates an empty list, which rejects all messages.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a session, replacing a session with the same instance ID.
+    pub fn add_session(&mut self, session: Session) {
+        self.remove_session(&session.instance);
+
+        for id in session.message_ids() {
+            self.messages.insert(*id, session.id);
+        }
+        self.sessions.insert(session.id, session);
+    }
+
+    /// Removes a session and returns it.
+    pub fn remove_session(
+        &mut self,
+        instance: &InstanceId,
+    ) -> Option<Session> {
+        let session = self.sessions.remove(&session_id(instance))?;
+
+        for id in session.message_ids() {
+            self.messages.remove(id);
+        }
+
+        Some(session)
+    }
+
+    /// Returns all sessions, in no particular order.
+    pub fn sessions(&self) -> impl Iterator<Item = &Session> {
+        self.sessions.values()
+    }
+
+    /// Returns the session a message with given ID belongs to.
+    pub fn session(&self, id: &MsgId) -> Option<&Session> {
+        self.sessions.get(self.messages.get(id)?)
+    }
+
+    pub(crate) fn sessions_mut(
+        &mut self,
+    ) -> impl Iterator<Item = &mut Session> {
+        self.sessions.values_mut()
+    }
+
+    pub(crate) fn session_mut(&mut self, id: &MsgId) -> Option<&mut Session> {
+        self.sessions.get_mut(self.messages.get(id)?)
+    }
+
+    /// Returns true if a connection with given identity may publish
+    /// a message with given ID.
+    pub fn may_publish(&self, identity: Option<&[u8]>, id: &MsgId) -> bool {
+        let publisher = self.session(id).and_then(|s| s.publishers.get(id));
+
+        matches!((publisher, identity), (Some(p), Some(i)) if p == i)
+    }
+
+    /// Returns true if a connection with given identity may ask for a
+    /// message with given ID.
+    pub fn may_ask(&self, identity: Option<&[u8]>, id: &MsgId) -> bool {
+        match (self.session(id), identity) {
+            (Some(session), Some(identity)) => {
+                session.members.contains(identity)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAG: MessageTag = MessageTag::tag(1);
+
+    #[test]
+    fn sender_ids() {
+        let instance = InstanceId::from([1; 32]);
+        let parties: [&[u8]; 3] = [b"p0", b"p1", b"p2"];
+
+        let mut acl = Acl::new();
+        acl.add_session(
+            Session::new(instance)
+                .allow_sender(b"p0", b"p0", &parties, &[TAG])
+                .allow(b"p1", []),
+        );
+
+        let bcast = MsgId::broadcast(&instance, b"p0", TAG);
+        let p2p = MsgId::new(&instance, b"p0", Some(b"p2"), TAG);
+        let to_self = MsgId::new(&instance, b"p0", Some(b"p0"), TAG);
+        let other = MsgId::broadcast(&instance, b"p1", TAG);
+
+        assert!(acl.may_publish(Some(b"p0"), &bcast));
+        assert!(acl.may_publish(Some(b"p0"), &p2p));
+        assert!(!acl.may_publish(Some(b"p0"), &to_self));
+        assert!(!acl.may_publish(Some(b"p0"), &other));
+        assert!(!acl.may_publish(Some(b"p1"), &bcast));
+        assert!(!acl.may_publish(None, &bcast));
+
+        assert!(acl.may_ask(Some(b"p1"), &bcast));
+        assert!(!acl.may_ask(Some(b"p2"), &bcast));
+        assert!(!acl.may_ask(None, &bcast));
+
+        acl.remove_session(&instance);
+        assert!(!acl.may_publish(Some(b"p0"), &bcast));
+    }
+
+    #[test]
+    fn replace_session() {
+        let instance = InstanceId::from([1; 32]);
+        let id1 = MsgId::from([1; 32]);
+        let id2 = MsgId::from([2; 32]);
+
+        let mut acl = Acl::new();
+        acl.add_session(Session::new(instance).allow(b"p0", [id1]));
+        acl.add_session(Session::new(instance).allow(b"p0", [id2]));
+
+        assert_eq!(acl.sessions().count(), 1);
+        assert!(acl.session(&id1).is_none());
+        assert!(acl.may_publish(Some(b"p0"), &id2));
+
+        let other = InstanceId::from([2; 32]);
+        acl.add_session(Session::new(other).allow(b"p1", [id1]));
+        assert_eq!(acl.session(&id1).unwrap().instance_id(), &other);
+
+        acl.remove_session(&instance);
+        assert!(acl.session(&id2).is_none());
+        assert!(acl.session(&id1).is_some());
+    }
+}
@@ -6,9 +6,12 @@ use std::{
     cmp::Ordering,
     collections::{hash_map::Entry, BinaryHeap, HashMap},
     pin::Pin,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicUsize, Ordering as AtomicOrdering},
+        Arc, Mutex,
+    },
     task::{ready, Context, Poll},
-    time::Instant,
+    time::{Instant, SystemTime},
 };
 
 use tokio::sync::mpsc;
@@ -17,6 +20,25 @@ use sl_mpc_mate::message::*;
 
 pub use sl_mpc_mate::{coord::*, message::MESSAGE_HEADER_SIZE};
 
+pub mod acl;
+pub mod limits;
+pub mod status;
+pub mod store;
+
+pub use acl::{Acl, Authenticator, Session, TokenAuthenticator};
+pub use limits::{Limits, Usage};
+pub use status::{
+    AskStatus, ConnectionStatus, Counters, IdentityStatus, RelayStatus,
+    SessionStatus,
+};
+pub use store::{
+    LogStore, MemoryStore, MessageStore, MAX_STORED_MESSAGE_SIZE,
+};
+
+// Upper limit of the number of recorded equivocations, newer ones
+// are only logged.
+const MAX_EQUIVOCATIONS: usize = 1024;
+
 struct Expire(Instant, MsgId, Kind);
 
 impl PartialEq for Expire {
@@ -39,19 +61,123 @@ impl Ord for Expire {
     }
 }
 
-enum MsgEntry {
-    Waiters {
-        expire: Instant,
-        waiters: Vec<(u64, mpsc::UnboundedSender<Vec<u8>>)>,
-    },
-
-    Ready {
-        msg: Vec<u8>,
-    },
+// Connections waiting for a message. Published messages are kept
+// in the message store.
+struct Waiters {
+    expire: Instant,
+    // time of the first ASK
+    since: Instant,
+    // each waiter holds a reserved slot of its connection's queue
+    waiters: Vec<(u64, mpsc::OwnedPermit<Vec<u8>>)>,
 }
 
 pub type OnAskMessage = dyn Fn(&[u8]) + Send + 'static;
 
+/// Reasons to reject a message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum RelayError {
+    /// The message is shorter than a message header
+    #[error("invalid message")]
+    InvalidMessage,
+
+    /// The message is larger than [`MAX_STORED_MESSAGE_SIZE`]
+    #[error("message too large")]
+    MessageTooLarge,
+
+    /// The access control list denies the message
+    #[error("access denied")]
+    AccessDenied,
+
+    /// The connection has too many pending ASKs and undelivered
+    /// messages
+    #[error("connection queue is full")]
+    QueueFull,
+
+    /// The relay stores too many messages
+    #[error("relay quota exceeded")]
+    QuotaExceeded,
+
+    /// The session of the message stores too many messages
+    #[error("session quota exceeded")]
+    SessionQuotaExceeded,
+
+    /// The connection has published too many stored messages
+    #[error("connection quota exceeded")]
+    ConnectionQuotaExceeded,
+
+    /// A different message with the same ID has been published
+    #[error("conflicting message")]
+    Equivocation,
+}
+
+/// A connection published a message with an ID of an already
+/// published message, but with different content.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Equivocation {
+    /// ID of the message
+    pub id: MsgId,
+    /// ID of the connection
+    pub conn_id: u64,
+    /// Identity of the connection, if authenticated
+    pub identity: Option<Vec<u8>>,
+}
+
+// A connection sending a message to the relay.
+struct Conn<'a> {
+    id: u64,
+    tx: &'a mpsc::Sender<Vec<u8>>,
+    identity: Option<&'a [u8]>,
+    counters: Option<&'a ConnCounters>,
+}
+
+// Statistics of a connection, shared with the relay for
+// introspection.
+#[derive(Default)]
+struct ConnCounters {
+    send_count: AtomicUsize,
+    send_size: AtomicUsize,
+    ask_count: AtomicUsize,
+    recv_count: AtomicUsize,
+    recv_size: AtomicUsize,
+    reject_count: AtomicUsize,
+}
+
+impl ConnCounters {
+    fn sent(&self, msg: &[u8]) {
+        self.send_count.fetch_add(1, AtomicOrdering::Relaxed);
+        self.send_size.fetch_add(msg.len(), AtomicOrdering::Relaxed);
+        if msg.len() == MESSAGE_HEADER_SIZE {
+            self.ask_count.fetch_add(1, AtomicOrdering::Relaxed);
+        }
+    }
+
+    fn received(&self, msg: Option<&Vec<u8>>) {
+        // skip end of stream and the wake up signal
+        if let Some(msg) = msg.filter(|msg| !msg.is_empty()) {
+            self.recv_count.fetch_add(1, AtomicOrdering::Relaxed);
+            self.recv_size.fetch_add(msg.len(), AtomicOrdering::Relaxed);
+        }
+    }
+
+    fn snapshot(&self) -> MsgRelayConnectionStats {
+        MsgRelayConnectionStats {
+            send_count: self.send_count.load(AtomicOrdering::Relaxed),
+            send_size: self.send_size.load(AtomicOrdering::Relaxed),
+            ask_count: self.ask_count.load(AtomicOrdering::Relaxed),
+            recv_count: self.recv_count.load(AtomicOrdering::Relaxed),
+            recv_size: self.recv_size.load(AtomicOrdering::Relaxed),
+            reject_count: self.reject_count.load(AtomicOrdering::Relaxed),
+        }
+    }
+}
+
+struct ConnEntry {
+    identity: Option<Arc<[u8]>>,
+    counters: Arc<ConnCounters>,
+    // stored messages published by the connection
+    usage: Usage,
+}
+
 #[derive(Clone)]
 pub struct MsgRelay {
     inner: Arc<Mutex<Inner>>,
@@ -59,28 +185,170 @@ pub struct MsgRelay {
 
 impl MsgRelay {
     pub fn new(on_ask_msg: Option<Box<OnAskMessage>>) -> Self {
+        Self::with_store(on_ask_msg, Box::new(MemoryStore::new()))
+    }
+
+    /// Creates a relay keeping published messages in a given store.
+    /// Messages already in the store are available to clients and
+    /// expire at their original expiration time.
+    pub fn with_store(
+        on_ask_msg: Option<Box<OnAskMessage>>,
+        store: Box<dyn MessageStore>,
+    ) -> Self {
+        let mut inner = Inner {
+            conn_id: 0,
+            expire: BinaryHeap::new(),
+            waiters: HashMap::new(),
+            store,
+            total_size: 0,
+            total_count: 0,
+            usage: Usage::default(),
+            owners: HashMap::new(),
+            limits: Limits::default(),
+            on_ask_msg,
+            authenticator: None,
+            acl: None,
+            equivocations: vec![],
+            counters: Counters::default(),
+            connections: HashMap::new(),
+            closed: HashMap::new(),
+        };
+
+        let now = Instant::now();
+        let sys_now = SystemTime::now();
+
+        for (id, expire) in inner.store.entries() {
+            let expire =
+                now + expire.duration_since(sys_now).unwrap_or_default();
+            inner.cleanup_later(id, expire, Kind::Pub);
+
+            if let Some(size) = inner.store.len(&id) {
+                inner.usage.add(size);
+            }
+        }
+
+        inner.cleanup(now);
+
         Self {
-            inner: Arc::new(Mutex::new(Inner {
-                conn_id: 0,
-                expire: BinaryHeap::new(),
-                messages: HashMap::new(),
-                total_size: 0,
-                total_count: 0,
-                on_ask_msg,
-            })),
+            inner: Arc::new(Mutex::new(inner)),
         }
     }
 
     pub fn handle_message(
         &self,
         msg: Vec<u8>,
-        tx: Option<(u64, &mpsc::UnboundedSender<Vec<u8>>)>,
-    ) {
-        self.inner.lock().unwrap().handle_message(msg, tx);
+        tx: Option<(u64, &mpsc::Sender<Vec<u8>>)>,
+    ) -> Result<(), RelayError> {
+        let conn = tx.map(|(id, tx)| Conn {
+            id,
+            tx,
+            identity: None,
+            counters: None,
+        });
+        self.inner.lock().unwrap().handle_message(msg, conn)
+    }
+
+    /// Publishes a message on behalf of the relay itself. The message
+    /// is not checked against the access control list.
+    pub fn send(&self, msg: Vec<u8>) -> Result<(), RelayError> {
+        self.handle_message(msg, None)
+    }
+
+    /// Sets limits of the relay. The queue size applies to new
+    /// connections only.
+    pub fn set_limits(&self, limits: Limits) {
+        self.inner.lock().unwrap().limits = limits;
+    }
+
+    /// Returns size and number of stored messages.
+    pub fn usage(&self) -> Usage {
+        self.inner.lock().unwrap().usage
     }
 
-    pub fn send(&self, msg: Vec<u8>) {
-        self.handle_message(msg, None);
+    /// Requires connections to authenticate. Connections created by
+    /// [`MsgRelay::connect()`] have no identity and so are rejected
+    /// by an access control list.
+    pub fn set_authenticator(&self, authenticator: Box<dyn Authenticator>) {
+        self.inner.lock().unwrap().authenticator = Some(authenticator);
+    }
+
+    /// Returns true if an authenticator is set.
+    pub fn requires_authentication(&self) -> bool {
+        self.inner.lock().unwrap().authenticator.is_some()
+    }
+
+    /// Returns true if an access control list is set.
+    pub fn has_acl(&self) -> bool {
+        self.inner.lock().unwrap().acl.is_some()
+    }
+
+    /// Creates a connection with identity of given credentials.
+    /// Returns `None` if there is no authenticator or it rejects the
+    /// credentials.
+    pub fn authenticate(
+        &self,
+        credentials: &[u8],
+    ) -> Option<MsgRelayConnection> {
+        let identity = self
+            .inner
+            .lock()
+            .unwrap()
+            .authenticator
+            .as_ref()?
+            .authenticate(credentials)?;
+
+        Some(self.connect_as(Some(identity.into())))
+    }
+
+    /// Sets an access control list. Without a list the relay accepts
+    /// any message from any connection. Stored messages, e.g. loaded
+    /// from a store after a restart, count towards quotas of their
+    /// sessions.
+    pub fn set_acl(&self, acl: Acl) {
+        let mut inner = self.inner.lock().unwrap();
+        let inner = &mut *inner;
+
+        let acl = inner.acl.insert(acl);
+        for session in acl.sessions_mut() {
+            session.usage = stored_usage(&*inner.store, session);
+        }
+    }
+
+    /// Adds a session to the access control list, creating the list
+    /// if necessary. Stored messages of the session count towards its
+    /// quota.
+    pub fn add_session(&self, mut session: Session) {
+        let mut inner = self.inner.lock().unwrap();
+
+        session.usage = stored_usage(&*inner.store, &session);
+
+        inner.acl.get_or_insert_with(Acl::new).add_session(session);
+    }
+
+    /// Removes a session from the access control list and evicts
+    /// stored messages of the session.
+    pub fn remove_session(&self, instance: &InstanceId) {
+        let mut inner = self.inner.lock().unwrap();
+
+        let Some(session) = inner
+            .acl
+            .as_mut()
+            .and_then(|acl| acl.remove_session(instance))
+        else {
+            return;
+        };
+
+        for id in session.message_ids() {
+            if inner.store.contains(id) {
+                inner.remove_message(id);
+                inner.counters.evicted_messages += 1;
+            }
+        }
+    }
+
+    /// Returns and forgets recorded equivocations.
+    pub fn take_equivocations(&self) -> Vec<Equivocation> {
+        std::mem::take(&mut self.inner.lock().unwrap().equivocations)
     }
 
     pub fn stats(&self) -> (u64, u64) {
@@ -88,74 +356,134 @@ impl MsgRelay {
         (state.total_size, state.total_count)
     }
 
+    /// Returns a snapshot of the state of the relay.
+    pub fn status(&self) -> RelayStatus {
+        self.inner.lock().unwrap().status(Instant::now())
+    }
+
     pub fn connect(&self) -> MsgRelayConnection {
-        // TODO make buffer size configurable
-        let (tx, rx) = mpsc::unbounded_channel::<Vec<u8>>();
+        self.connect_as(None)
+    }
+
+    fn connect_as(&self, identity: Option<Arc<[u8]>>) -> MsgRelayConnection {
+        let mut inner = self.inner.lock().unwrap();
+
+        // one more slot for MsgRelaySender::wake_receiver()
+        let (tx, rx) =
+            mpsc::channel::<Vec<u8>>(inner.limits.queue_size.max(1) + 1);
+
+        let tx_id = inner.next_conn_id();
+        let counters = Arc::new(ConnCounters::default());
+
+        inner.connections.insert(
+            tx_id,
+            ConnEntry {
+                identity: identity.clone(),
+                counters: counters.clone(),
+                usage: Usage::default(),
+            },
+        );
 
         MsgRelayConnection {
             tx,
             rx,
-            tx_id: self.inner.lock().unwrap().next_conn_id(),
+            tx_id,
+            identity,
             inner: self.inner.clone(),
-            stats: Default::default(),
+            counters,
         }
     }
 
-    pub fn messages<F>(&self, f: F)
+    pub fn messages<F>(&self, mut f: F)
     where
-        F: Fn(&MsgId, usize),
+        F: FnMut(&MsgId, usize),
     {
         let lock = self.inner.lock().unwrap();
 
-        for (k, v) in &lock.messages {
-            let waiters = match v {
-                MsgEntry::Ready { msg: _ } => 0,
-                MsgEntry::Waiters { expire: _, waiters } => waiters.len(),
-            };
+        for (k, _) in lock.store.entries() {
+            f(&k, 0);
+        }
 
-            f(k, waiters);
+        for (k, v) in &lock.waiters {
+            f(k, v.waiters.len());
         }
     }
 }
 
-#[derive(Default, Clone)]
+/// Message counters of a connection. Sent messages include ASKs and
+/// rejected messages.
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
 pub struct MsgRelayConnectionStats {
     pub send_count: usize,
     pub send_size: usize,
     pub ask_count: usize,
     pub recv_count: usize,
     pub recv_size: usize,
+    /// Number of messages and ASKs rejected with an error
+    pub reject_count: usize,
+}
+
+impl MsgRelayConnectionStats {
+    fn add(&mut self, other: &Self) {
+        self.send_count += other.send_count;
+        self.send_size += other.send_size;
+        self.ask_count += other.ask_count;
+        self.recv_count += other.recv_count;
+        self.recv_size += other.recv_size;
+        self.reject_count += other.reject_count;
+    }
 }
 
 pub struct MsgRelayConnection {
     tx_id: u64,
-    tx: mpsc::UnboundedSender<Vec<u8>>,
-    rx: mpsc::UnboundedReceiver<Vec<u8>>,
+    identity: Option<Arc<[u8]>>,
+    tx: mpsc::Sender<Vec<u8>>,
+    rx: mpsc::Receiver<Vec<u8>>,
     inner: Arc<Mutex<Inner>>,
-    stats: MsgRelayConnectionStats,
+    counters: Arc<ConnCounters>,
 }
 
 impl MsgRelayConnection {
     /// Send message to the connection.
-    pub fn send_message(&self, msg: Vec<u8>) {
-        self.inner
-            .lock()
-            .unwrap()
-            .handle_message(msg, Some((self.tx_id, &self.tx)))
+    pub fn send_message(&self, msg: Vec<u8>) -> Result<(), RelayError> {
+        let conn = Conn {
+            id: self.tx_id,
+            tx: &self.tx,
+            identity: self.identity.as_deref(),
+            counters: Some(&self.counters),
+        };
+        self.inner.lock().unwrap().handle_message(msg, Some(conn))
     }
 
     pub fn get(&mut self, id: &MsgId) -> Option<Vec<u8>> {
-        let tbl = self.inner.lock().ok()?;
-
-        match tbl.messages.get(id) {
-            Some(MsgEntry::Ready { msg }) => Some(msg.clone()),
-            _ => None,
-        }
+        self.inner.lock().ok()?.store.get(id)
     }
 
     /// Receive an ASKed message.
     pub async fn recv(&mut self) -> Option<Vec<u8>> {
-        self.rx.recv().await
+        let msg = self.rx.recv().await;
+        self.counters.received(msg.as_ref());
+        msg
+    }
+
+    /// Receive an ASKed message, blocking the current thread.
+    /// Must not be called from an async context.
+    pub fn blocking_recv(&mut self) -> Option<Vec<u8>> {
+        let msg = self.rx.blocking_recv();
+        self.counters.received(msg.as_ref());
+        msg
+    }
+
+    /// Returns a handle to send messages on behalf of the connection
+    /// from another thread or task.
+    pub fn sender(&self) -> MsgRelaySender {
+        MsgRelaySender {
+            tx_id: self.tx_id,
+            identity: self.identity.clone(),
+            tx: self.tx.clone(),
+            inner: self.inner.clone(),
+            counters: self.counters.clone(),
+        }
     }
 
     /// Returns connection ID
@@ -163,18 +491,85 @@ impl MsgRelayConnection {
         self.tx_id
     }
 
+    /// Returns identity of an authenticated connection.
+    pub fn identity(&self) -> Option<&[u8]> {
+        self.identity.as_deref()
+    }
+
     pub fn stats(&mut self) -> MsgRelayConnectionStats {
-        self.stats.clone()
+        self.counters.snapshot()
+    }
+}
+
+impl Drop for MsgRelayConnection {
+    fn drop(&mut self) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.connections.remove(&self.tx_id);
+            inner
+                .closed
+                .entry(self.identity.clone())
+                .or_default()
+                .add(&self.counters.snapshot());
+        }
+    }
+}
+
+/// A handle to send messages on behalf of a connection, see
+/// [`MsgRelayConnection::sender()`].
+#[derive(Clone)]
+pub struct MsgRelaySender {
+    tx_id: u64,
+    identity: Option<Arc<[u8]>>,
+    tx: mpsc::Sender<Vec<u8>>,
+    inner: Arc<Mutex<Inner>>,
+    counters: Arc<ConnCounters>,
+}
+
+impl MsgRelaySender {
+    /// Send message to the connection.
+    pub fn send_message(&self, msg: Vec<u8>) -> Result<(), RelayError> {
+        let conn = Conn {
+            id: self.tx_id,
+            tx: &self.tx,
+            identity: self.identity.as_deref(),
+            counters: Some(&self.counters),
+        };
+        self.inner.lock().unwrap().handle_message(msg, Some(conn))
+    }
+
+    /// Wakes up a receiver of the connection by an empty message.
+    /// The relay never delivers empty messages, so the receiver may
+    /// use it as a signal to stop.
+    pub fn wake_receiver(&self) {
+        let _ = self.tx.try_send(vec![]);
+    }
+
+    /// Returns statistics of the connection, also after the
+    /// connection is dropped.
+    pub fn stats(&self) -> MsgRelayConnectionStats {
+        self.counters.snapshot()
     }
 }
 
 struct Inner {
     conn_id: u64,
     expire: BinaryHeap<Expire>,
-    messages: HashMap<MsgId, MsgEntry>,
+    waiters: HashMap<MsgId, Waiters>,
+    store: Box<dyn MessageStore>,
     total_size: u64,
     total_count: u64,
+    usage: Usage,
+    // connections which published stored messages
+    owners: HashMap<MsgId, u64>,
+    limits: Limits,
     on_ask_msg: Option<Box<OnAskMessage>>,
+    authenticator: Option<Box<dyn Authenticator>>,
+    acl: Option<Acl>,
+    equivocations: Vec<Equivocation>,
+    counters: Counters,
+    connections: HashMap<u64, ConnEntry>,
+    // counters of closed connections by identity
+    closed: HashMap<Option<Arc<[u8]>>, MsgRelayConnectionStats>,
 }
 
 impl Inner {
@@ -183,6 +578,95 @@ impl Inner {
         self.conn_id
     }
 
+    fn remove_message(&mut self, id: &MsgId) {
+        let Some(size) = self.store.len(id) else {
+            return;
+        };
+
+        self.store.remove(id);
+        self.usage.sub(size);
+
+        if let Some(session) =
+            self.acl.as_mut().and_then(|acl| acl.session_mut(id))
+        {
+            session.usage.sub(size);
+        }
+
+        if let Some(entry) = self
+            .owners
+            .remove(id)
+            .and_then(|conn_id| self.connections.get_mut(&conn_id))
+        {
+            entry.usage.sub(size);
+        }
+    }
+
+    // Check quotas and account a new message published by a given
+    // connection.
+    fn reserve(
+        &mut self,
+        id: &MsgId,
+        size: usize,
+        conn_id: Option<u64>,
+    ) -> Result<(), RelayError> {
+        let limits = &self.limits;
+
+        if !self.usage.fits(size, limits.max_size, limits.max_messages) {
+            return Err(RelayError::QuotaExceeded);
+        }
+
+        let conn = conn_id.and_then(|id| self.connections.get_mut(&id));
+
+        if let Some(entry) = &conn {
+            if !entry.usage.fits(
+                size,
+                limits.max_conn_size,
+                limits.max_conn_messages,
+            ) {
+                return Err(RelayError::ConnectionQuotaExceeded);
+            }
+        }
+
+        if let Some(session) =
+            self.acl.as_mut().and_then(|acl| acl.session_mut(id))
+        {
+            if !session.usage.fits(
+                size,
+                limits.max_session_size,
+                limits.max_session_messages,
+            ) {
+                return Err(RelayError::SessionQuotaExceeded);
+            }
+
+            session.usage.add(size);
+        }
+
+        if let Some(entry) = conn {
+            entry.usage.add(size);
+            self.owners.insert(*id, conn_id.unwrap());
+        }
+
+        self.usage.add(size);
+
+        Ok(())
+    }
+
+    fn equivocation(&mut self, id: MsgId, conn: Option<Conn>) {
+        let (conn_id, identity) = conn
+            .map(|c| (c.id, c.identity.map(<[u8]>::to_vec)))
+            .unwrap_or_default();
+
+        tracing::warn!("equivocation {:X} conn {}", id, conn_id);
+
+        if self.equivocations.len() < MAX_EQUIVOCATIONS {
+            self.equivocations.push(Equivocation {
+                id,
+                conn_id,
+                identity,
+            });
+        }
+    }
+
     fn cleanup_later(&mut self, id: MsgId, expire: Instant, kind: Kind) {
         self.expire.push(Expire(expire, id, kind));
     }
@@ -197,17 +681,19 @@ impl Inner {
 
             tracing::debug!("expire {:?} {:X}", kind, id);
 
-            if let Entry::Occupied(ocp) = self.messages.entry(id) {
-                match ocp.get() {
-                    MsgEntry::Ready { .. } => {
-                        if kind == Kind::Pub {
-                            ocp.remove();
-                        }
+            match kind {
+                Kind::Pub => {
+                    if self.store.contains(&id) {
+                        self.remove_message(&id);
+                        self.counters.expired_messages += 1;
                     }
+                }
 
-                    MsgEntry::Waiters { expire, .. } => {
-                        if kind == Kind::Ask && *expire <= now {
+                Kind::Ask => {
+                    if let Entry::Occupied(ocp) = self.waiters.entry(id) {
+                        if ocp.get().expire <= now {
                             ocp.remove();
+                            self.counters.expired_asks += 1;
                         }
                     }
                 }
@@ -215,15 +701,117 @@ impl Inner {
         }
     }
 
+    fn status(&self, now: Instant) -> RelayStatus {
+        let acl = self.acl.as_ref();
+
+        let asks: Vec<AskStatus> = self
+            .waiters
+            .iter()
+            .map(|(id, w)| AskStatus {
+                id: *id,
+                waiters: w.waiters.len(),
+                age: now.saturating_duration_since(w.since),
+                session: acl
+                    .and_then(|acl| acl.session(id))
+                    .map(|s| s.label().to_owned()),
+            })
+            .collect();
+
+        let mut sessions: Vec<_> = acl
+            .into_iter()
+            .flat_map(|acl| acl.sessions())
+            .map(|session| {
+                let label = session.label();
+                let pending = asks
+                    .iter()
+                    .filter(|a| a.session.as_deref() == Some(label));
+
+                SessionStatus {
+                    label: label.to_owned(),
+                    usage: session.usage(),
+                    pending_asks: pending.clone().count(),
+                    oldest_ask: pending.map(|a| a.age).max(),
+                }
+            })
+            .collect();
+        sessions.sort_by(|a, b| a.label.cmp(&b.label));
+
+        let mut identities: HashMap<_, IdentityStatus> = self
+            .closed
+            .iter()
+            .map(|(identity, stats)| {
+                let status = IdentityStatus {
+                    identity: identity.as_deref().map(<[u8]>::to_vec),
+                    connections: 0,
+                    stats: stats.clone(),
+                };
+                (identity.clone(), status)
+            })
+            .collect();
+
+        for entry in self.connections.values() {
+            let status = identities
+                .entry(entry.identity.clone())
+                .or_insert_with(|| IdentityStatus {
+                    identity: entry.identity.as_deref().map(<[u8]>::to_vec),
+                    connections: 0,
+                    stats: Default::default(),
+                });
+            status.connections += 1;
+            status.stats.add(&entry.counters.snapshot());
+        }
+
+        let connections = self
+            .connections
+            .iter()
+            .map(|(conn_id, entry)| ConnectionStatus {
+                conn_id: *conn_id,
+                identity: entry.identity.as_deref().map(<[u8]>::to_vec),
+                stats: entry.counters.snapshot(),
+                usage: entry.usage,
+            })
+            .collect();
+
+        RelayStatus {
+            usage: self.usage,
+            total_size: self.total_size,
+            total_count: self.total_count,
+            counters: self.counters,
+            sessions,
+            asks,
+            connections,
+            identities: identities.into_values().collect(),
+        }
+    }
+
     fn handle_message(
         &mut self,
         msg: Vec<u8>,
-        tx: Option<(u64, &mpsc::UnboundedSender<Vec<u8>>)>,
-    ) {
-        let hdr = match <&MsgHdr>::try_from(msg.as_slice()) {
-            Ok(hdr) => hdr,
-            Err(_) => return, // TODO report invalid message?
-        };
+        conn: Option<Conn>,
+    ) -> Result<(), RelayError> {
+        let counters = conn.as_ref().and_then(|c| c.counters);
+        if let Some(counters) = counters {
+            counters.sent(&msg);
+        }
+
+        let res = self.handle(msg, conn);
+        if res.is_err() {
+            self.counters.rejected_messages += 1;
+            if let Some(counters) = counters {
+                counters.reject_count.fetch_add(1, AtomicOrdering::Relaxed);
+            }
+        }
+
+        res
+    }
+
+    fn handle(
+        &mut self,
+        msg: Vec<u8>,
+        conn: Option<Conn>,
+    ) -> Result<(), RelayError> {
+        let hdr = <&MsgHdr>::try_from(msg.as_slice())
+            .map_err(|_| RelayError::InvalidMessage)?;
 
         let kind = if msg.len() == MESSAGE_HEADER_SIZE {
             Kind::Ask
@@ -231,8 +819,9 @@ impl Inner {
             Kind::Pub
         };
         let id = *hdr.id();
+        let ttl = hdr.ttl().min(self.limits.max_ttl);
         let ts = Instant::now();
-        let msg_expire = ts + hdr.ttl();
+        let msg_expire = ts + ttl;
 
         self.total_size += msg.len() as u64;
         self.total_count += 1;
@@ -240,94 +829,132 @@ impl Inner {
         // we have a locked state, let's cleanup some old entries
         self.cleanup(ts);
 
-        match self.messages.entry(id) {
-            Entry::Occupied(mut ocp) => {
-                match ocp.get_mut() {
-                    MsgEntry::Ready { msg } => {
-                        if kind == Kind::Ask {
-                            // Got an ASK for a Ready message.
-                            // Send the message immediately.
-                            if let Some((_, tx)) = tx {
-                                tracing::debug!(
-                                    "rdy-msg {:X} {}",
-                                    id,
-                                    msg.len()
-                                );
-                                let _ = tx.send(msg.clone());
-                            }
-                        }
-                    }
+        if let (Some(acl), Some(conn)) = (&self.acl, &conn) {
+            let allowed = match kind {
+                Kind::Ask => acl.may_ask(conn.identity, &id),
+                Kind::Pub => acl.may_publish(conn.identity, &id),
+            };
+
+            if !allowed {
+                tracing::warn!("deny {:?} {:X} conn {}", kind, id, conn.id);
+                return Err(RelayError::AccessDenied);
+            }
+        }
+
+        if kind == Kind::Ask {
+            let Some(Conn { id: w_id, tx, .. }) = conn else {
+                return Ok(());
+            };
+
+            let joined = self
+                .waiters
+                .get(&id)
+                .is_some_and(|w| w.waiters.iter().any(|(i, _)| *i == w_id));
+
+            if joined {
+                return Ok(());
+            }
+
+            // keep the last slot of the queue for wake_receiver()
+            if tx.capacity() <= 1 {
+                tracing::warn!("queue full {:X} conn {}", id, w_id);
+                return Err(RelayError::QueueFull);
+            }
+
+            if let Some(ready) = self.store.get(&id) {
+                // Got an ASK for a Ready message.
+                // Send the message immediately.
+                tracing::debug!("rdy-msg {:X} {}", id, ready.len());
+                let _ = tx.try_send(ready);
+                return Ok(());
+            }
+
+            let Ok(permit) = tx.clone().try_reserve_owned() else {
+                // the connection is closed
+                return Ok(());
+            };
+
+            let entry = self.waiters.entry(id).or_insert_with(|| Waiters {
+                expire: msg_expire,
+                since: ts,
+                waiters: vec![],
+            });
+
+            // join other waiters
+            tracing::debug!(
+                "add-ask {:X} {} {}",
+                id,
+                msg.len(),
+                entry.waiters.len() + 1
+            );
+
+            entry.expire = msg_expire.max(entry.expire);
+            entry.waiters.push((w_id, permit));
+
+            if let Some(on_ask_msg) = &self.on_ask_msg {
+                on_ask_msg(&msg);
+            }
+        } else {
+            if msg.len() > MAX_STORED_MESSAGE_SIZE {
+                tracing::warn!("too large {:X} {}", id, msg.len());
+                return Err(RelayError::MessageTooLarge);
+            }
+
+            if let Some(ready) = self.store.get(&id) {
+                // A repeated message is fine, e.g. a client sends its
+                // messages again after a reconnect.
+                if ready != msg {
+                    self.equivocation(id, conn);
+                    return Err(RelayError::Equivocation);
+                }
+                return Ok(());
+            }
 
-                    MsgEntry::Waiters { expire, waiters } => {
-                        if kind == Kind::Ask {
-                            // join other waiters
-                            if let Some((w_id, tx)) = tx {
-                                let cnt = waiters.len();
-                                if !waiters
-                                    .iter()
-                                    .any(|(tx_id, _)| *tx_id == w_id)
-                                {
-                                    tracing::debug!(
-                                        "add-ask {:X} {} {}",
-                                        id,
-                                        msg.len(),
-                                        cnt + 1
-                                    );
-
-                                    *expire = msg_expire.max(*expire);
-                                    waiters.push((w_id, tx.clone()));
-                                    if let Some(on_ask_msg) = &self.on_ask_msg
-                                    {
-                                        on_ask_msg(&msg);
-                                    }
-                                }
-                            }
-                        } else {
-                            tracing::debug!(
-                                "wak-msg {:X} {} {}",
-                                id,
-                                msg.len(),
-                                waiters.len()
-                            );
-                            // wake up all waiters
-                            for (_, tx) in waiters.drain(..) {
-                                let _ = tx.send(msg.clone()); // TODO handle error
-                            }
-                            // and replace with a Read message
-                            ocp.insert(MsgEntry::Ready { msg });
-                        };
-
-                        // remember to cleanup this entry later
-                        self.cleanup_later(id, msg_expire, kind);
+            let conn_id = conn.as_ref().map(|c| c.id);
+            if let Err(err) = self.reserve(&id, msg.len(), conn_id) {
+                tracing::warn!("{} {:X} {}", err, id, msg.len());
+                return Err(err);
+            }
+
+            match self.waiters.remove(&id) {
+                Some(Waiters { waiters, .. }) => {
+                    tracing::debug!(
+                        "wak-msg {:X} {} {}",
+                        id,
+                        msg.len(),
+                        waiters.len()
+                    );
+                    // wake up all waiters
+                    for (_, permit) in waiters {
+                        permit.send(msg.clone());
                     }
                 }
+
+                None => tracing::debug!("add-msg {:X} {}", id, msg.len()),
             }
 
-            Entry::Vacant(vac) => {
-                if matches!(kind, Kind::Ask) {
-                    // This is the first ASK for the message
-                    if let Some((w_id, tx)) = tx {
-                        vac.insert(MsgEntry::Waiters {
-                            expire: msg_expire,
-                            waiters: vec![(w_id, tx.clone())],
-                        });
+            let sys_expire = SystemTime::now() + ttl;
+            self.store.put(id, sys_expire, msg);
+        }
 
-                        tracing::debug!("add-ask {:X} {} 1", id, msg.len());
+        // remember to cleanup this entry later
+        self.cleanup_later(id, msg_expire, kind);
 
-                        if let Some(on_ask_msg) = &self.on_ask_msg {
-                            on_ask_msg(&msg);
-                        }
-                    }
-                } else {
-                    tracing::debug!("add-msg {:X} {}", id, msg.len());
+        Ok(())
+    }
+}
 
-                    vac.insert(MsgEntry::Ready { msg });
-                };
+// Size and number of stored messages of a session.
+fn stored_usage(store: &dyn MessageStore, session: &Session) -> Usage {
+    let mut usage = Usage::default();
 
-                self.cleanup_later(id, msg_expire, kind);
-            }
-        };
+    for id in session.message_ids() {
+        if let Some(size) = store.len(id) {
+            usage.add(size);
+        }
     }
+
+    usage
 }
 
 impl Stream for MsgRelayConnection {
@@ -340,10 +967,7 @@ impl Stream for MsgRelayConnection {
         let this = self.get_mut();
         let msg = ready!(this.rx.poll_recv(cx));
 
-        if let Some(msg) = &msg {
-            this.stats.recv_count += 1;
-            this.stats.recv_size += msg.len();
-        }
+        this.counters.received(msg.as_ref());
 
         Poll::Ready(msg)
     }
@@ -363,17 +987,7 @@ impl Sink<Vec<u8>> for MsgRelayConnection {
         self: Pin<&mut Self>,
         item: Vec<u8>,
     ) -> Result<(), Self::Error> {
-        let this = self.get_mut();
-        this.stats.send_count += 1;
-        this.stats.send_size += item.len();
-
-        if item.len() == MESSAGE_HEADER_SIZE {
-            this.stats.ask_count += 1;
-        }
-
-        this.send_message(item);
-
-        Ok(())
+        self.send_message(item).map_err(|_| MessageSendError)
     }
 
     fn poll_flush(
@@ -395,6 +1009,8 @@ impl Relay for MsgRelayConnection {}
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use super::*;
 
     fn dummy_msg(ttl: u32, size: usize) -> Vec<u8> {
@@ -407,13 +1023,358 @@ mod tests {
 
     #[tokio::test]
     async fn handle_msg() {
-        let (tx, _rx) = mpsc::unbounded_channel::<Vec<u8>>();
+        let (tx, _rx) = mpsc::channel::<Vec<u8>>(1);
         let app = MsgRelay::new(None);
 
         let msg = dummy_msg(10, 100);
 
         let _hdr = <&MsgHdr>::try_from(msg.as_slice()).unwrap();
 
-        app.handle_message(msg, Some((0, &tx)));
+        app.handle_message(msg, Some((0, &tx))).unwrap();
+    }
+
+    #[tokio::test]
+    async fn conflicting_publish() {
+        let relay = MsgRelay::new(None);
+        let id = MsgId::from([3; 32]);
+        let msg = allocate_message(&id, 100, 0, b"first");
+
+        let conn = relay.connect();
+        conn.send_message(msg.clone()).unwrap();
+        conn.send_message(msg.clone()).unwrap();
+        assert_eq!(
+            conn.send_message(allocate_message(&id, 100, 0, b"second")),
+            Err(RelayError::Equivocation)
+        );
+
+        let equivocations = relay.take_equivocations();
+        assert_eq!(equivocations.len(), 1);
+        assert_eq!(equivocations[0].id, id);
+        assert_eq!(equivocations[0].conn_id, conn.conn_id());
+
+        let mut asker = relay.connect();
+        asker.send_message(AskMsg::allocate(&id, 100)).unwrap();
+        assert_eq!(asker.recv().await, Some(msg));
+    }
+
+    #[tokio::test]
+    async fn too_large_message() {
+        let relay = MsgRelay::new(None);
+        let id = MsgId::from([4; 32]);
+
+        let mut asker = relay.connect();
+        asker.send_message(AskMsg::allocate(&id, 100)).unwrap();
+
+        let large = allocate_message(
+            &id,
+            100,
+            0,
+            &vec![0; MAX_STORED_MESSAGE_SIZE - MESSAGE_HEADER_SIZE + 1],
+        );
+        assert_eq!(relay.send(large), Err(RelayError::MessageTooLarge));
+        assert_eq!(relay.status().counters.rejected_messages, 1);
+
+        // the waiter is still there
+        let msg = allocate_message(&id, 100, 0, b"payload");
+        relay.send(msg.clone()).unwrap();
+        assert_eq!(asker.recv().await, Some(msg));
+    }
+
+    #[tokio::test]
+    async fn acl() {
+        let instance = InstanceId::from([1; 32]);
+        let tag = MessageTag::tag(1);
+        let id = MsgId::broadcast(&instance, b"p0", tag);
+        let msg = allocate_message(&id, 100, 0, b"payload");
+
+        let mut auth = TokenAuthenticator::new();
+        auth.add_token("t0", "p0");
+        auth.add_token("t1", "p1");
+
+        let relay = MsgRelay::new(None);
+        relay.set_authenticator(Box::new(auth));
+        relay.add_session(
+            Session::new(instance)
+                .allow_sender(b"p0", b"p0", &[], &[tag])
+                .allow(b"p1", []),
+        );
+
+        assert!(relay.authenticate(b"bad").is_none());
+
+        let p0 = relay.authenticate(b"t0").unwrap();
+        let mut p1 = relay.authenticate(b"t1").unwrap();
+        assert_eq!(p1.identity(), Some(&b"p1"[..]));
+
+        // neither an unauthenticated connection nor another
+        // member may publish the message
+        let forged = allocate_message(&id, 100, 0, b"forged");
+        assert_eq!(
+            relay.connect().send_message(forged.clone()),
+            Err(RelayError::AccessDenied)
+        );
+        assert_eq!(p1.send_message(forged), Err(RelayError::AccessDenied));
+
+        p0.send_message(msg.clone()).unwrap();
+
+        let mut other = relay.connect();
+        assert_eq!(
+            other.send_message(AskMsg::allocate(&id, 100)),
+            Err(RelayError::AccessDenied)
+        );
+        p1.send_message(AskMsg::allocate(&id, 100)).unwrap();
+
+        assert_eq!(p1.recv().await, Some(msg));
+        assert!(other.rx.try_recv().is_err());
+        assert!(relay.take_equivocations().is_empty());
+    }
+
+    #[tokio::test]
+    async fn queue_limit() {
+        let relay = MsgRelay::new(None);
+        relay.set_limits(Limits {
+            queue_size: 2,
+            ..Default::default()
+        });
+
+        let mut conn = relay.connect();
+        let ids: Vec<_> = (0..3).map(|i| MsgId::from([i; 32])).collect();
+
+        conn.send_message(AskMsg::allocate(&ids[0], 100)).unwrap();
+        conn.send_message(AskMsg::allocate(&ids[1], 100)).unwrap();
+        assert_eq!(
+            conn.send_message(AskMsg::allocate(&ids[2], 100)),
+            Err(RelayError::QueueFull)
+        );
+
+        // a received message frees a slot
+        let msg = allocate_message(&ids[0], 100, 0, b"payload");
+        relay.send(msg.clone()).unwrap();
+        assert_eq!(conn.recv().await, Some(msg));
+
+        conn.send_message(AskMsg::allocate(&ids[2], 100)).unwrap();
+    }
+
+    #[test]
+    fn quotas() {
+        let instance = InstanceId::from([1; 32]);
+        let tags: Vec<_> = (0..4).map(MessageTag::tag).collect();
+        let id = |i: usize| MsgId::broadcast(&instance, b"p0", tags[i]);
+
+        let mut auth = TokenAuthenticator::new();
+        auth.add_token("t0", "p0");
+
+        let relay = MsgRelay::new(None);
+        relay.set_authenticator(Box::new(auth));
+        relay.set_limits(Limits {
+            max_messages: 3,
+            max_session_messages: 2,
+            ..Default::default()
+        });
+        relay.add_session(Session::new(instance).allow_sender(
+            b"p0",
+            b"p0",
+            &[],
+            &tags,
+        ));
+
+        let p0 = relay.authenticate(b"t0").unwrap();
+        p0.send_message(allocate_message(&id(0), 100, 0, b"0"))
+            .unwrap();
+        p0.send_message(allocate_message(&id(1), 100, 0, b"1"))
+            .unwrap();
+        assert_eq!(
+            p0.send_message(allocate_message(&id(2), 100, 0, b"2")),
+            Err(RelayError::SessionQuotaExceeded)
+        );
+
+        relay
+            .send(allocate_message(&MsgId::from([9; 32]), 100, 0, b"r"))
+            .unwrap();
+        assert_eq!(
+            relay.send(allocate_message(&MsgId::ZERO_ID, 100, 0, b"z")),
+            Err(RelayError::QuotaExceeded)
+        );
+
+        assert_eq!(relay.usage().messages, 3);
+    }
+
+    #[test]
+    fn connection_quota() {
+        let relay = MsgRelay::new(None);
+        relay.set_limits(Limits {
+            max_conn_messages: 1,
+            ..Default::default()
+        });
+
+        let c0 = relay.connect();
+        let c1 = relay.connect();
+        let msg =
+            |i: u8| allocate_message(&MsgId::from([i; 32]), 100, 0, b"m");
+
+        c0.send_message(msg(0)).unwrap();
+        assert_eq!(
+            c0.send_message(msg(1)),
+            Err(RelayError::ConnectionQuotaExceeded)
+        );
+        c1.send_message(msg(1)).unwrap();
+
+        let status = relay.status();
+        let usage = |id| {
+            status
+                .connections
+                .iter()
+                .find(|c| c.conn_id == id)
+                .map(|c| c.usage.messages)
+        };
+        assert_eq!(usage(c0.conn_id()), Some(1));
+        assert_eq!(usage(c1.conn_id()), Some(1));
+    }
+
+    #[tokio::test]
+    async fn session_usage_after_restart() {
+        let path = store::tests::temp_path("session-usage");
+        let instance = InstanceId::from([1; 32]);
+        let tag = MessageTag::tag(1);
+        let id = MsgId::broadcast(&instance, b"p0", tag);
+        let session =
+            || Session::new(instance).allow_sender(b"p0", b"p0", &[], &[tag]);
+
+        {
+            let relay = MsgRelay::with_store(
+                None,
+                Box::new(LogStore::open(&path).unwrap()),
+            );
+            relay.add_session(session());
+            relay.send(allocate_message(&id, 100, 0, b"0")).unwrap();
+        }
+
+        let relay = MsgRelay::with_store(
+            None,
+            Box::new(LogStore::open(&path).unwrap()),
+        );
+        relay.add_session(session());
+
+        assert_eq!(relay.status().sessions[0].usage.messages, 1);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn max_ttl() {
+        let relay = MsgRelay::new(None);
+        relay.set_limits(Limits {
+            max_ttl: Duration::ZERO,
+            ..Default::default()
+        });
+
+        relay
+            .send(allocate_message(&MsgId::from([9; 32]), 100, 0, b"r"))
+            .unwrap();
+        relay
+            .send(allocate_message(&MsgId::ZERO_ID, 100, 0, b"z"))
+            .unwrap();
+
+        // the first message expired when the second one arrived
+        assert_eq!(relay.usage().messages, 1);
+    }
+
+    #[tokio::test]
+    async fn status() {
+        let instance = InstanceId::from([1; 32]);
+        let tag = MessageTag::tag(1);
+        let id = |p: &[u8]| MsgId::broadcast(&instance, p, tag);
+
+        let mut auth = TokenAuthenticator::new();
+        auth.add_token("t0", "p0");
+        auth.add_token("t1", "p1");
+
+        let relay = MsgRelay::new(None);
+        relay.set_authenticator(Box::new(auth));
+        relay.add_session(
+            Session::new(instance)
+                .with_label("dkg-1")
+                .allow_sender(b"p0", b"p0", &[], &[tag])
+                .allow_sender(b"p1", b"p1", &[], &[tag]),
+        );
+
+        let mut p0 = relay.authenticate(b"t0").unwrap();
+        let p1 = relay.authenticate(b"t1").unwrap();
+
+        // p0 waits for a message p1 never sends
+        p0.send_message(allocate_message(&id(b"p0"), 100, 0, b"0"))
+            .unwrap();
+        p0.send_message(AskMsg::allocate(&id(b"p1"), 100)).unwrap();
+        p1.send_message(AskMsg::allocate(&id(b"p0"), 100)).unwrap();
+        assert!(p1
+            .send_message(AskMsg::allocate(&[0; 32].into(), 1))
+            .is_err());
+
+        let status = relay.status();
+
+        assert_eq!(status.usage.messages, 1);
+        assert_eq!(status.counters.rejected_messages, 1);
+        assert_eq!(status.asks.len(), 1);
+        assert_eq!(status.asks[0].id, id(b"p1"));
+        assert_eq!(status.sessions[0].label, "dkg-1");
+        assert_eq!(status.sessions[0].pending_asks, 1);
+        assert!(status.sessions[0].oldest_ask.is_some());
+        assert_eq!(status.connections.len(), 2);
+
+        let stats = p0.stats();
+        assert_eq!((stats.send_count, stats.ask_count), (2, 1));
+
+        let text = status.to_prometheus();
+        assert!(text.contains("msg_relay_stored_messages 1\n"));
+        assert!(text.contains(
+            "msg_relay_session_pending_asks{session=\"dkg-1\"} 1\n"
+        ));
+        assert!(text.contains("msg_relay_oldest_ask_seconds "));
+        assert!(text.contains(
+            "msg_relay_identity_rejected_messages_total{identity=\"7031\"} 1\n"
+        ));
+        assert!(!text.contains("conn="));
+
+        drop(p1);
+        relay.remove_session(&instance);
+
+        let status = relay.status();
+        assert_eq!(status.connections.len(), 1);
+
+        // counters of closed connections are kept
+        let p1 = status
+            .identities
+            .iter()
+            .find(|s| s.identity.as_deref() == Some(b"p1"))
+            .unwrap();
+        assert_eq!((p1.connections, p1.stats.send_count), (0, 2));
+        assert_eq!(status.usage.messages, 0);
+        assert_eq!(status.counters.evicted_messages, 1);
+    }
+
+    #[tokio::test]
+    async fn restart_with_log_store() {
+        let path = store::tests::temp_path("restart");
+        let id = MsgId::from([7; 32]);
+        let msg = allocate_message(&id, 100, 0, b"payload");
+
+        {
+            let relay = MsgRelay::with_store(
+                None,
+                Box::new(LogStore::open(&path).unwrap()),
+            );
+            relay.send(msg.clone()).unwrap();
+        }
+
+        // a restarted relay answers a replayed ASK
+        let relay = MsgRelay::with_store(
+            None,
+            Box::new(LogStore::open(&path).unwrap()),
+        );
+        let mut conn = relay.connect();
+        conn.send_message(AskMsg::allocate(&id, 100)).unwrap();
+
+        assert_eq!(conn.recv().await, Some(msg));
+
+        std::fs::remove_file(path).unwrap();
     }
 }
@@ -0,0 +1,95 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Resource limits of a [`MsgRelay`](crate::MsgRelay).
+//!
+//! A relay is shared by many protocol sessions, so a single client
+//! should not be able to exhaust its memory. Published messages are
+//! accounted globally, per session of the access control list and
+//! per publishing connection, so a quota applies also to a relay
+//! without a list. Each connection has a bounded queue of messages
+//! it asked for.
+
+use std::time::Duration;
+
+/// Limits of a relay.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// Capacity of the queue of a connection: the number of pending
+    /// ASKs plus the number of delivered messages the connection has
+    /// not received yet.
+    pub queue_size: usize,
+
+    /// Upper limit of total size of stored messages in bytes
+    pub max_size: usize,
+
+    /// Upper limit of the number of stored messages
+    pub max_messages: usize,
+
+    /// Upper limit of total size of stored messages of one session
+    /// of the access control list in bytes
+    pub max_session_size: usize,
+
+    /// Upper limit of the number of stored messages of one session
+    /// of the access control list
+    pub max_session_messages: usize,
+
+    /// Upper limit of total size of stored messages published by one
+    /// connection in bytes
+    pub max_conn_size: usize,
+
+    /// Upper limit of the number of stored messages published by one
+    /// connection
+    pub max_conn_messages: usize,
+
+    /// Messages and ASKs with a longer TTL expire after this time
+    pub max_ttl: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            queue_size: 1024,
+            max_size: 1 << 30,
+            max_messages: 1 << 20,
+            max_session_size: 256 << 20,
+            max_session_messages: 1 << 16,
+            max_conn_size: 64 << 20,
+            max_conn_messages: 1 << 14,
+            max_ttl: Duration::from_secs(3600),
+        }
+    }
+}
+
+/// Size and number of stored messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    /// Total size in bytes
+    pub size: usize,
+    /// Number of messages
+    pub messages: usize,
+}
+
+impl Usage {
+    /// Returns true if a message of given size fits into given
+    /// limits.
+    pub fn fits(
+        &self,
+        size: usize,
+        max_size: usize,
+        max_messages: usize,
+    ) -> bool {
+        self.messages < max_messages
+            && size <= max_size.saturating_sub(self.size)
+    }
+
+    pub(crate) fn add(&mut self, size: usize) {
+        self.size += size;
+        self.messages += 1;
+    }
+
+    pub(crate) fn sub(&mut self, size: usize) {
+        self.size = self.size.saturating_sub(size);
+        self.messages = self.messages.saturating_sub(1);
+    }
+}
@@ -0,0 +1,324 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Introspection of a [`MsgRelay`](crate::MsgRelay).
+//!
+//! [`MsgRelay::status()`](crate::MsgRelay::status) returns a snapshot
+//! of the state of a relay, which may be rendered in the Prometheus
+//! text exposition format. An ASK which stays unanswered for a long
+//! time usually means that a party of a protocol is stuck or gone.
+
+use std::{fmt::Write, time::Duration};
+
+use sl_mpc_mate::message::MsgId;
+
+use crate::{limits::Usage, MsgRelayConnectionStats};
+
+/// Counters of a relay since its start.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counters {
+    /// Messages removed because their TTL passed
+    pub expired_messages: u64,
+    /// ASKs removed unanswered because their TTL passed
+    pub expired_asks: u64,
+    /// Messages removed before expiration, with their session
+    pub evicted_messages: u64,
+    /// Messages and ASKs rejected with an error
+    pub rejected_messages: u64,
+}
+
+/// State of a session of the access control list.
+#[derive(Clone, Debug)]
+pub struct SessionStatus {
+    /// Label of the session, see
+    /// [`Session::with_label()`](crate::Session::with_label)
+    pub label: String,
+    /// Size and number of stored messages
+    pub usage: Usage,
+    /// Number of messages with pending ASKs
+    pub pending_asks: usize,
+    /// Age of the oldest pending ASK
+    pub oldest_ask: Option<Duration>,
+}
+
+/// A message some connections are waiting for.
+#[derive(Clone, Debug)]
+pub struct AskStatus {
+    /// ID of the message
+    pub id: MsgId,
+    /// Number of waiting connections
+    pub waiters: usize,
+    /// Time since the first ASK
+    pub age: Duration,
+    /// Label of the session of the message, if any
+    pub session: Option<String>,
+}
+
+/// Statistics of a connection.
+#[derive(Clone, Debug)]
+pub struct ConnectionStatus {
+    /// ID of the connection
+    pub conn_id: u64,
+    /// Identity of an authenticated connection
+    pub identity: Option<Vec<u8>>,
+    /// Message counters
+    pub stats: MsgRelayConnectionStats,
+    /// Size and number of stored messages published by the connection
+    pub usage: Usage,
+}
+
+/// Statistics of all connections of an identity, including closed
+/// ones.
+#[derive(Clone, Debug)]
+pub struct IdentityStatus {
+    /// Identity of authenticated connections, `None` for anonymous
+    /// ones
+    pub identity: Option<Vec<u8>>,
+    /// Number of open connections
+    pub connections: usize,
+    /// Sum of message counters
+    pub stats: MsgRelayConnectionStats,
+}
+
+/// A snapshot of the state of a relay.
+#[derive(Clone, Debug, Default)]
+pub struct RelayStatus {
+    /// Size and number of stored messages
+    pub usage: Usage,
+    /// Total size of all handled messages, including ASKs
+    pub total_size: u64,
+    /// Number of all handled messages, including ASKs
+    pub total_count: u64,
+    /// Expiration and eviction counters
+    pub counters: Counters,
+    /// Sessions of the access control list
+    pub sessions: Vec<SessionStatus>,
+    /// Pending ASKs
+    pub asks: Vec<AskStatus>,
+    /// Open connections
+    pub connections: Vec<ConnectionStatus>,
+    /// Connections by identity
+    pub identities: Vec<IdentityStatus>,
+}
+
+// name, help and a value of a labeled metric
+type Metric<T, V> = (&'static str, &'static str, fn(&T) -> V);
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP msg_relay_{name} {help}");
+    let _ = writeln!(out, "# TYPE msg_relay_{name} {kind}");
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+impl RelayStatus {
+    /// Renders the status in the Prometheus text exposition format.
+    ///
+    /// Individual pending ASKs are not exported, only their number
+    /// and the age of the oldest one, globally and per session.
+    /// Connection counters are summed up per identity, so the number
+    /// of series does not grow with the number of connections.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        let gauges = [
+            (
+                "stored_messages",
+                "Number of stored messages",
+                self.usage.messages as u64,
+            ),
+            (
+                "stored_bytes",
+                "Total size of stored messages",
+                self.usage.size as u64,
+            ),
+            (
+                "pending_asks",
+                "Number of messages with pending ASKs",
+                self.asks.len() as u64,
+            ),
+            (
+                "waiters",
+                "Number of connections waiting for messages",
+                self.asks.iter().map(|a| a.waiters as u64).sum(),
+            ),
+            (
+                "connections",
+                "Number of open connections",
+                self.connections.len() as u64,
+            ),
+            (
+                "sessions",
+                "Number of sessions of the access control list",
+                self.sessions.len() as u64,
+            ),
+        ];
+
+        for (name, help, value) in gauges {
+            metric(&mut out, name, "gauge", help);
+            let _ = writeln!(out, "msg_relay_{name} {value}");
+        }
+
+        let oldest_ask = self.asks.iter().map(|a| a.age).max();
+        metric(
+            &mut out,
+            "oldest_ask_seconds",
+            "gauge",
+            "Age of the oldest pending ASK",
+        );
+        let _ = writeln!(
+            out,
+            "msg_relay_oldest_ask_seconds {}",
+            oldest_ask.unwrap_or_default().as_secs_f64()
+        );
+
+        let counters = [
+            (
+                "handled_messages_total",
+                "Number of handled messages and ASKs",
+                self.total_count,
+            ),
+            (
+                "handled_bytes_total",
+                "Total size of handled messages and ASKs",
+                self.total_size,
+            ),
+            (
+                "expired_messages_total",
+                "Number of expired messages",
+                self.counters.expired_messages,
+            ),
+            (
+                "expired_asks_total",
+                "Number of expired unanswered ASKs",
+                self.counters.expired_asks,
+            ),
+            (
+                "evicted_messages_total",
+                "Number of messages removed with their session",
+                self.counters.evicted_messages,
+            ),
+            (
+                "rejected_messages_total",
+                "Number of rejected messages and ASKs",
+                self.counters.rejected_messages,
+            ),
+        ];
+
+        for (name, help, value) in counters {
+            metric(&mut out, name, "counter", help);
+            let _ = writeln!(out, "msg_relay_{name} {value}");
+        }
+
+        let sessions: [Metric<SessionStatus, Option<f64>>; 4] = [
+            (
+                "session_stored_messages",
+                "Number of stored messages of a session",
+                |s| Some(s.usage.messages as f64),
+            ),
+            (
+                "session_stored_bytes",
+                "Total size of stored messages of a session",
+                |s| Some(s.usage.size as f64),
+            ),
+            (
+                "session_pending_asks",
+                "Number of messages of a session with pending ASKs",
+                |s| Some(s.pending_asks as f64),
+            ),
+            (
+                "session_oldest_ask_seconds",
+                "Age of the oldest pending ASK of a session",
+                |s| s.oldest_ask.map(|d| d.as_secs_f64()),
+            ),
+        ];
+
+        for (name, help, value) in sessions {
+            metric(&mut out, name, "gauge", help);
+            for session in &self.sessions {
+                if let Some(value) = value(session) {
+                    let label = escape(&session.label);
+                    let _ = writeln!(
+                        out,
+                        "msg_relay_{name}{{session=\"{label}\"}} {value}"
+                    );
+                }
+            }
+        }
+
+        metric(
+            &mut out,
+            "identity_connections",
+            "gauge",
+            "Number of open connections of an identity",
+        );
+        for status in &self.identities {
+            let identity = status.identity.as_deref().map(hex);
+            let _ = writeln!(
+                out,
+                "msg_relay_identity_connections{{identity=\"{}\"}} {}",
+                identity.unwrap_or_default(),
+                status.connections
+            );
+        }
+
+        let identities: [Metric<MsgRelayConnectionStats, usize>; 6] = [
+            (
+                "identity_sent_messages_total",
+                "Number of messages and ASKs sent by an identity",
+                |s| s.send_count,
+            ),
+            (
+                "identity_sent_bytes_total",
+                "Total size of messages and ASKs sent by an identity",
+                |s| s.send_size,
+            ),
+            (
+                "identity_asks_total",
+                "Number of ASKs sent by an identity",
+                |s| s.ask_count,
+            ),
+            (
+                "identity_rejected_messages_total",
+                "Number of messages and ASKs of an identity rejected \
+                 with an error",
+                |s| s.reject_count,
+            ),
+            (
+                "identity_received_messages_total",
+                "Number of messages received by an identity",
+                |s| s.recv_count,
+            ),
+            (
+                "identity_received_bytes_total",
+                "Total size of messages received by an identity",
+                |s| s.recv_size,
+            ),
+        ];
+
+        // anonymous connections have an empty identity label
+        for (name, help, value) in identities {
+            metric(&mut out, name, "counter", help);
+            for status in &self.identities {
+                let identity = status.identity.as_deref().map(hex);
+                let _ = writeln!(
+                    out,
+                    "msg_relay_{name}{{identity=\"{}\"}} {}",
+                    identity.unwrap_or_default(),
+                    value(&status.stats)
+                );
+            }
+        }
+
+        out
+    }
+}
@@ -0,0 +1,539 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Storage of published messages of a [`MsgRelay`](crate::MsgRelay).
+//!
+//! A store keeps published messages until they expire. Expiration
+//! times are wall-clock times, so a persistent store honours TTLs of
+//! messages across restarts of a relay.
+//!
+//! ASKs waiting for a message are bound to connections and are not
+//! stored. Clients replay their pending ASKs when they reconnect to
+//! a restarted relay.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use sl_mpc_mate::message::{MsgId, MESSAGE_HEADER_SIZE};
+
+/// Storage of published messages.
+pub trait MessageStore: Send {
+    /// Returns a copy of a stored message.
+    fn get(&self, id: &MsgId) -> Option<Vec<u8>>;
+
+    /// Returns true if a message with given ID is stored.
+    fn contains(&self, id: &MsgId) -> bool;
+
+    /// Returns size of a stored message.
+    fn len(&self, id: &MsgId) -> Option<usize> {
+        self.get(id).map(|msg| msg.len())
+    }
+
+    /// Stores a message until `expire`.
+    fn put(&mut self, id: MsgId, expire: SystemTime, msg: Vec<u8>);
+
+    /// Removes a message.
+    fn remove(&mut self, id: &MsgId);
+
+    /// Returns IDs and expiration times of all stored messages.
+    fn entries(&self) -> Vec<(MsgId, SystemTime)>;
+}
+
+/// A store keeping messages in memory only.
+#[derive(Default)]
+pub struct MemoryStore {
+    messages: HashMap<MsgId, (SystemTime, Vec<u8>)>,
+}
+
+impl MemoryStore {
+    /// Creates an empty store.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl MessageStore for MemoryStore {
+    fn get(&self, id: &MsgId) -> Option<Vec<u8>> {
+        self.messages.get(id).map(|(_, msg)| msg.clone())
+    }
+
+    fn contains(&self, id: &MsgId) -> bool {
+        self.messages.contains_key(id)
+    }
+
+    fn len(&self, id: &MsgId) -> Option<usize> {
+        self.messages.get(id).map(|(_, msg)| msg.len())
+    }
+
+    fn put(&mut self, id: MsgId, expire: SystemTime, msg: Vec<u8>) {
+        self.messages.insert(id, (expire, msg));
+    }
+
+    fn remove(&mut self, id: &MsgId) {
+        self.messages.remove(id);
+    }
+
+    fn entries(&self) -> Vec<(MsgId, SystemTime)> {
+        self.messages
+            .iter()
+            .map(|(id, (expire, _))| (*id, *expire))
+            .collect()
+    }
+}
+
+/// Upper limit of the size of a stored message. The relay rejects
+/// larger messages.
+pub const MAX_STORED_MESSAGE_SIZE: usize = 64 << 20;
+
+const REC_PUT: u8 = b'P';
+const REC_DEL: u8 = b'D';
+
+// Compact the log when it has more garbage records than this
+// number plus the number of live messages.
+const COMPACT_THRESHOLD: usize = 1024;
+
+type Messages = HashMap<MsgId, (SystemTime, Arc<[u8]>)>;
+
+// A record for the writer thread of a log.
+enum Record {
+    Put(SystemTime, Arc<[u8]>),
+    Del(MsgId),
+    // rewrite the log with these live messages
+    Compact(Vec<(SystemTime, Arc<[u8]>)>),
+}
+
+/// A store keeping messages in memory and in an append-only log on
+/// disk.
+///
+/// A log consists of two kinds of records:
+/// - `P`, expiration time as milliseconds since UNIX epoch (u64 LE),
+///   message length (u32 LE), message
+/// - `D`, message ID
+///
+/// Opening a log replays it, drops expired messages, ignores an
+/// incomplete record at the end left by a crash and rewrites the log
+/// with live messages only. The log is compacted the same way when
+/// it accumulates enough removed messages.
+///
+/// Records are written by a dedicated thread, so disk I/O does not
+/// block the relay. Dropping the store waits for all records to be
+/// written.
+pub struct LogStore {
+    path: PathBuf,
+    messages: Messages,
+    garbage: usize,
+    sync: Arc<AtomicBool>,
+    writer: Option<(mpsc::Sender<Record>, JoinHandle<()>)>,
+}
+
+fn to_millis(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+fn read_exact_or_eof<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    match r.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+fn replay(path: &Path) -> io::Result<Messages> {
+    let mut messages = Messages::new();
+
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Ok(messages)
+        }
+        Err(err) => return Err(err),
+    };
+
+    let mut r = BufReader::new(file);
+    let mut kind = [0u8];
+
+    while read_exact_or_eof(&mut r, &mut kind)? {
+        match kind[0] {
+            REC_PUT => {
+                let mut hdr = [0u8; 12];
+                if !read_exact_or_eof(&mut r, &mut hdr)? {
+                    break;
+                }
+                let millis = u64::from_le_bytes(hdr[..8].try_into().unwrap());
+                let len = u32::from_le_bytes(hdr[8..].try_into().unwrap());
+
+                if len as usize > MAX_STORED_MESSAGE_SIZE {
+                    break;
+                }
+
+                let mut msg = vec![0u8; len as usize];
+                if !read_exact_or_eof(&mut r, &mut msg)? {
+                    break;
+                }
+
+                let Ok(id) = <&MsgId>::try_from(msg.as_slice()) else {
+                    break;
+                };
+
+                let expire = UNIX_EPOCH + Duration::from_millis(millis);
+                messages.insert(*id, (expire, msg.into()));
+            }
+
+            REC_DEL => {
+                let mut id = [0u8; 32];
+                if !read_exact_or_eof(&mut r, &mut id)? {
+                    break;
+                }
+                messages.remove(&MsgId::from(id));
+            }
+
+            // a broken record, ignore the rest of the log
+            _ => break,
+        }
+    }
+
+    Ok(messages)
+}
+
+fn write_put<W: Write>(
+    w: &mut W,
+    expire: SystemTime,
+    msg: &[u8],
+) -> io::Result<()> {
+    w.write_all(&[REC_PUT])?;
+    w.write_all(&to_millis(expire).to_le_bytes())?;
+    w.write_all(&(msg.len() as u32).to_le_bytes())?;
+    w.write_all(msg)
+}
+
+// Write live messages into a new log and atomically replace the old
+// one.
+fn rewrite<'a>(
+    path: &Path,
+    live: impl Iterator<Item = (&'a SystemTime, &'a Arc<[u8]>)>,
+) -> io::Result<BufWriter<File>> {
+    let tmp = path.with_extension("tmp");
+
+    {
+        let mut w = BufWriter::new(File::create(&tmp)?);
+        for (expire, msg) in live {
+            write_put(&mut w, *expire, msg)?;
+        }
+        w.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+    }
+
+    fs::rename(&tmp, path)?;
+
+    let file = OpenOptions::new().append(true).open(path)?;
+
+    Ok(BufWriter::new(file))
+}
+
+// Writer thread of a log. Flushes the log when there are no more
+// pending records.
+fn write_log(
+    path: PathBuf,
+    mut log: BufWriter<File>,
+    sync: Arc<AtomicBool>,
+    rx: mpsc::Receiver<Record>,
+) {
+    let write = |log: &mut BufWriter<File>, rec| match rec {
+        Record::Put(expire, msg) => write_put(log, expire, &msg),
+
+        Record::Del(id) => {
+            log.write_all(&[REC_DEL])?;
+            log.write_all(&id)
+        }
+
+        Record::Compact(live) => {
+            *log = rewrite(&path, live.iter().map(|(e, m)| (e, m)))?;
+            Ok(())
+        }
+    };
+
+    while let Ok(rec) = rx.recv() {
+        let res = std::iter::once(rec)
+            .chain(rx.try_iter())
+            .try_for_each(|rec| write(&mut log, rec))
+            .and_then(|_| log.flush())
+            .and_then(|_| {
+                if sync.load(Ordering::Relaxed) {
+                    log.get_ref().sync_data()?;
+                }
+                Ok(())
+            });
+
+        if let Err(err) = res {
+            tracing::error!("message log {:?}: {}", path, err);
+        }
+    }
+}
+
+impl LogStore {
+    /// Opens or creates a log.
+    ///
+    /// # Arguments
+    /// * `path` - Path name of the log file
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        let mut messages = replay(&path)?;
+
+        let now = SystemTime::now();
+        messages.retain(|_, (expire, _)| *expire > now);
+
+        let log = rewrite(&path, messages.values().map(|(e, m)| (e, m)))?;
+
+        let sync = Arc::new(AtomicBool::new(false));
+        let (tx, rx) = mpsc::channel();
+
+        let writer =
+            thread::Builder::new().name("msg-relay-log".into()).spawn({
+                let path = path.clone();
+                let sync = sync.clone();
+                move || write_log(path, log, sync, rx)
+            })?;
+
+        Ok(Self {
+            path,
+            messages,
+            garbage: 0,
+            sync,
+            writer: Some((tx, writer)),
+        })
+    }
+
+    /// Whether to sync the log to disk after each record. Without
+    /// syncing, a crash of the host may lose the last records.
+    pub fn with_sync(self, sync: bool) -> Self {
+        self.sync.store(sync, Ordering::Relaxed);
+        self
+    }
+
+    /// Returns path name of the log.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn append(&self, rec: Record) {
+        if let Some((tx, _)) = &self.writer {
+            if tx.send(rec).is_err() {
+                tracing::error!("message log {:?}: writer exited", self.path);
+            }
+        }
+    }
+
+    fn maybe_compact(&mut self) {
+        if self.garbage <= self.messages.len() + COMPACT_THRESHOLD {
+            return;
+        }
+
+        self.append(Record::Compact(
+            self.messages.values().cloned().collect(),
+        ));
+        self.garbage = 0;
+    }
+}
+
+impl Drop for LogStore {
+    fn drop(&mut self) {
+        if let Some((tx, writer)) = self.writer.take() {
+            drop(tx);
+            let _ = writer.join();
+        }
+    }
+}
+
+impl MessageStore for LogStore {
+    fn get(&self, id: &MsgId) -> Option<Vec<u8>> {
+        self.messages.get(id).map(|(_, msg)| msg.to_vec())
+    }
+
+    fn contains(&self, id: &MsgId) -> bool {
+        self.messages.contains_key(id)
+    }
+
+    fn len(&self, id: &MsgId) -> Option<usize> {
+        self.messages.get(id).map(|(_, msg)| msg.len())
+    }
+
+    fn put(&mut self, id: MsgId, expire: SystemTime, msg: Vec<u8>) {
+        if msg.len() <= MESSAGE_HEADER_SIZE
+            || msg.len() > MAX_STORED_MESSAGE_SIZE
+        {
+            tracing::warn!("not storing message {:X} {}", id, msg.len());
+            return;
+        }
+
+        let msg: Arc<[u8]> = msg.into();
+
+        self.append(Record::Put(expire, msg.clone()));
+        if self.messages.insert(id, (expire, msg)).is_some() {
+            self.garbage += 1;
+        }
+    }
+
+    fn remove(&mut self, id: &MsgId) {
+        if self.messages.remove(id).is_none() {
+            return;
+        }
+
+        self.append(Record::Del(*id));
+
+        // the removed message and the delete record
+        self.garbage += 2;
+        self.maybe_compact();
+    }
+
+    fn entries(&self) -> Vec<(MsgId, SystemTime)> {
+        self.messages
+            .iter()
+            .map(|(id, (expire, _))| (*id, *expire))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use sl_mpc_mate::message::allocate_message;
+
+    use super::*;
+
+    pub(crate) fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "msg-relay-{}-{}-{}.log",
+            name,
+            std::process::id(),
+            rand_suffix()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn rand_suffix() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64
+    }
+
+    fn msg(i: u8) -> (MsgId, Vec<u8>) {
+        let id = MsgId::from([i; 32]);
+        (id, allocate_message(&id, 100, 0, &[i; 10]))
+    }
+
+    #[test]
+    fn reopen() {
+        let path = temp_path("reopen");
+        let later = SystemTime::now() + Duration::from_secs(100);
+        let past = SystemTime::now() - Duration::from_secs(1);
+
+        let (id1, msg1) = msg(1);
+        let (id2, msg2) = msg(2);
+        let (id3, msg3) = msg(3);
+
+        {
+            let mut store = LogStore::open(&path).unwrap();
+            store.put(id1, later, msg1.clone());
+            store.put(id2, later, msg2);
+            store.put(id3, past, msg3);
+            store.remove(&id2);
+        }
+
+        let store = LogStore::open(&path).unwrap();
+
+        assert_eq!(store.get(&id1), Some(msg1));
+        assert!(!store.contains(&id2));
+        assert!(!store.contains(&id3), "expired message");
+        assert_eq!(store.entries().len(), 1);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn expire_in_millis() {
+        let path = temp_path("millis");
+        let expire = UNIX_EPOCH
+            + Duration::from_millis(to_millis(
+                SystemTime::now() + Duration::from_millis(100_123),
+            ));
+
+        let (id1, msg1) = msg(1);
+
+        {
+            let mut store = LogStore::open(&path).unwrap();
+            store.put(id1, expire, msg1);
+        }
+
+        let store = LogStore::open(&path).unwrap();
+        assert_eq!(store.entries(), vec![(id1, expire)]);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn truncated_log() {
+        let path = temp_path("truncated");
+        let later = SystemTime::now() + Duration::from_secs(100);
+
+        let (id1, msg1) = msg(1);
+        let (id2, msg2) = msg(2);
+
+        {
+            let mut store = LogStore::open(&path).unwrap();
+            store.put(id1, later, msg1.clone());
+            store.put(id2, later, msg2);
+        }
+
+        // cut the last record in the middle
+        let len = fs::metadata(&path).unwrap().len();
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(len - 5).unwrap();
+
+        let store = LogStore::open(&path).unwrap();
+        assert_eq!(store.get(&id1), Some(msg1));
+        assert!(!store.contains(&id2));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn compaction() {
+        let path = temp_path("compaction");
+        let later = SystemTime::now() + Duration::from_secs(100);
+
+        let mut store = LogStore::open(&path).unwrap();
+        // each removed message adds two garbage records, the last
+        // removal triggers compaction
+        for _ in 0..COMPACT_THRESHOLD / 2 + 1 {
+            let (id, m) = msg(1);
+            store.put(id, later, m);
+            store.remove(&id);
+        }
+
+        let (id2, msg2) = msg(2);
+        store.put(id2, later, msg2.clone());
+
+        // wait for the writer thread
+        drop(store);
+
+        // one live record only
+        let len = fs::metadata(&path).unwrap().len() as usize;
+        assert!(len < 2 * (1 + 12 + msg2.len()));
+
+        assert_eq!(LogStore::open(&path).unwrap().get(&id2), Some(msg2));
+
+        fs::remove_file(path).unwrap();
+    }
+}
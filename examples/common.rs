@@ -1,3 +1,5 @@
+#![allow(dead_code)]
+
 pub mod shared {
     use sl_dkls23::keygen;
     use sl_dkls23::keygen::Keyshare;
@@ -0,0 +1,621 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Verifiable backup of a key share to an offline recovery key.
+//!
+//! Each party encrypts its secret share `s_i` to a cold X25519 recovery
+//! public key and attaches a non-interactive cut-and-choose proof that
+//! the ciphertext holds the discrete logarithm of the public share
+//! `S_i = s_i * G` (`Keyshare::big_s(party_id)`).
+//!
+//! For every proof round the party picks a random mask `r_k`, commits to
+//! `R_k = r_k * G` and encrypts both `r_k` and `r_k + s_i`. A Fiat-Shamir
+//! challenge bit selects which of the two ciphertexts is opened. An
+//! auditor checks the opened ciphertexts against `R_k` and `R_k + S_i`
+//! without any secret material. The holder of the recovery key decrypts
+//! an unopened ciphertext and subtracts the opened value to restore
+//! `s_i`. A cheating party passes the audit with probability at most
+//! `2^-BACKUP_PROOF_ROUNDS`.
+
+use std::mem;
+
+use k256::{
+    elliptic_curve::subtle::ConstantTimeEq,
+    sha2::{Digest, Sha256},
+    AffinePoint, NonZeroScalar, ProjectivePoint, Scalar,
+};
+use rand::prelude::*;
+use x25519_dalek::{
+    x25519, PublicKey, ReusableSecret, X25519_BASEPOINT_BYTES,
+};
+use zeroize::{Zeroize, Zeroizing};
+
+use sl_oblivious::label::Label;
+
+use crate::{
+    key_export::combine_shares,
+    keygen::Keyshare,
+    proto::{
+        encode_affine, encode_point, encode_scalar, FixedExternalSize,
+        PointBytes, ScalarBytes, Wrap,
+    },
+    VERSION,
+};
+
+/// Label used to derive the Fiat-Shamir challenge of a backup proof.
+pub const KEY_BACKUP_LABEL: Label = Label::new(VERSION, 300);
+
+/// Label used to derive per-ciphertext encryption pads.
+pub const KEY_BACKUP_PAD_LABEL: Label = Label::new(VERSION, 301);
+
+/// Number of cut-and-choose rounds of a backup proof.
+pub const BACKUP_PROOF_ROUNDS: usize = 128;
+
+/// Key share backup errors
+#[derive(Debug, thiserror::Error)]
+pub enum KeyBackupError {
+    /// Error while deserializing a backup or invalid data length
+    #[error("Error while deserializing backup or invalid data length")]
+    InvalidBackup,
+
+    /// The recovery public key is a low order point
+    #[error("Invalid recovery key")]
+    InvalidRecoveryKey,
+
+    /// The verifiable encryption proof does not hold
+    #[error("Invalid backup proof")]
+    InvalidProof,
+
+    /// Public data of the backup does not match the key share
+    #[error("Backup does not match the keyshare")]
+    KeyshareMismatch,
+
+    /// The recovery key could not decrypt the share
+    #[error("Failed to decrypt the backup")]
+    DecryptionFailed,
+
+    /// Backups belong to different keys or do not combine into the
+    /// public key
+    #[error("Public key mismatch after combining backups")]
+    PublicKeyMismatch,
+}
+
+/// One cut-and-choose round of a backup proof.
+#[derive(Clone)]
+pub struct BackupRound {
+    /// Commitment `R_k = r_k * G` to the random mask.
+    pub big_r: AffinePoint,
+
+    /// Ephemeral X25519 public key of the unopened ciphertext.
+    pub ephemeral: [u8; 32],
+
+    /// Unopened ciphertext.
+    pub ciphertext: [u8; 32],
+
+    /// Opened value, `r_k` or `r_k + s_i` depending on the challenge bit.
+    pub opening: Scalar,
+
+    /// Ephemeral X25519 secret of the opened ciphertext.
+    pub seed: [u8; 32],
+}
+
+impl FixedExternalSize for BackupRound {
+    const SIZE: usize = mem::size_of::<PointBytes>()
+        + 32
+        + 32
+        + mem::size_of::<ScalarBytes>()
+        + 32;
+}
+
+impl Wrap for BackupRound {
+    fn external_size(&self) -> usize {
+        Self::SIZE
+    }
+
+    fn write(&self, buffer: &mut [u8]) {
+        let buffer = self.big_r.encode(buffer);
+        let buffer = self.ephemeral.encode(buffer);
+        let buffer = self.ciphertext.encode(buffer);
+        let buffer = self.opening.encode(buffer);
+        self.seed.encode(buffer);
+    }
+
+    fn read(buffer: &[u8]) -> Option<Self> {
+        let (buffer, big_r) =
+            AffinePoint::decode(buffer, mem::size_of::<PointBytes>())?;
+        let (buffer, ephemeral) = <[u8; 32]>::decode(buffer, 32)?;
+        let (buffer, ciphertext) = <[u8; 32]>::decode(buffer, 32)?;
+        let (buffer, opening) =
+            Scalar::decode(buffer, mem::size_of::<ScalarBytes>())?;
+        let (_, seed) = <[u8; 32]>::decode(buffer, 32)?;
+
+        Some(BackupRound {
+            big_r,
+            ephemeral,
+            ciphertext,
+            opening,
+            seed,
+        })
+    }
+}
+
+/// Verifiable backup of a secret share encrypted to a recovery key.
+#[derive(Clone)]
+pub struct KeyshareBackup {
+    /// Party ID of the key share owner.
+    pub party_id: u8,
+
+    /// Rank of the key share owner.
+    pub rank: u8,
+
+    /// Evaluation point of the key share owner.
+    pub x_i: NonZeroScalar,
+
+    /// Public share `S_i = s_i * G`.
+    pub big_s: ProjectivePoint,
+
+    /// Public key of the distributed key.
+    pub public_key: ProjectivePoint,
+
+    /// Recovery public key the share is encrypted to.
+    pub recovery_key: PublicKey,
+
+    /// Fiat-Shamir challenge.
+    pub challenge: [u8; 32],
+
+    /// Proof rounds.
+    pub rounds: Vec<BackupRound>,
+}
+
+fn challenge_bit(challenge: &[u8; 32], k: usize) -> u8 {
+    (challenge[k / 8] >> (k % 8)) & 1
+}
+
+fn encryption_pad(
+    shared: &[u8; 32],
+    ephemeral: &[u8; 32],
+    recovery_key: &PublicKey,
+    k: usize,
+    bit: u8,
+) -> Zeroizing<[u8; 32]> {
+    Zeroizing::new(
+        Sha256::new()
+            .chain_update(KEY_BACKUP_PAD_LABEL)
+            .chain_update(ephemeral)
+            .chain_update(recovery_key.as_bytes())
+            .chain_update(shared)
+            .chain_update((k as u32).to_be_bytes())
+            .chain_update([bit])
+            .finalize()
+            .into(),
+    )
+}
+
+fn xor(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    for ((o, a), b) in out.iter_mut().zip(a).zip(b) {
+        *o = a ^ b;
+    }
+    out
+}
+
+/// Deterministically encrypt `value` under `recovery_key` using
+/// ephemeral secret `seed`. Returns (ephemeral public key, ciphertext).
+fn encrypt_value(
+    seed: &[u8; 32],
+    value: &Scalar,
+    recovery_key: &PublicKey,
+    k: usize,
+    bit: u8,
+) -> Option<([u8; 32], [u8; 32])> {
+    let ephemeral = x25519(*seed, X25519_BASEPOINT_BYTES);
+    let shared = Zeroizing::new(x25519(*seed, *recovery_key.as_bytes()));
+
+    if bool::from(shared.ct_eq(&[0u8; 32])) {
+        return None;
+    }
+
+    let pad = encryption_pad(&shared, &ephemeral, recovery_key, k, bit);
+    let plaintext = Zeroizing::new(encode_scalar(value));
+
+    Some((ephemeral, xor(&plaintext, &pad)))
+}
+
+fn decrypt_value(
+    recovery_key: &ReusableSecret,
+    ephemeral: &[u8; 32],
+    ciphertext: &[u8; 32],
+    k: usize,
+    bit: u8,
+) -> Option<Scalar> {
+    let shared = recovery_key.diffie_hellman(&PublicKey::from(*ephemeral));
+
+    if !shared.was_contributory() {
+        return None;
+    }
+
+    let pad = encryption_pad(
+        shared.as_bytes(),
+        ephemeral,
+        &PublicKey::from(recovery_key),
+        k,
+        bit,
+    );
+    let plaintext = Zeroizing::new(xor(ciphertext, &pad));
+
+    <Scalar as Wrap>::read(plaintext.as_slice())
+}
+
+impl KeyshareBackup {
+    fn compute_challenge(&self, ciphertexts: &[[[u8; 32]; 4]]) -> [u8; 32] {
+        let hash = Sha256::new()
+            .chain_update(KEY_BACKUP_LABEL)
+            .chain_update([self.party_id, self.rank])
+            .chain_update(encode_scalar(&self.x_i))
+            .chain_update(encode_point(&self.big_s))
+            .chain_update(encode_point(&self.public_key))
+            .chain_update(self.recovery_key.as_bytes());
+
+        self.rounds
+            .iter()
+            .zip(ciphertexts)
+            .fold(hash, |hash, (round, [e0, c0, e1, c1])| {
+                hash.chain_update(encode_affine(&round.big_r))
+                    .chain_update(e0)
+                    .chain_update(c0)
+                    .chain_update(e1)
+                    .chain_update(c1)
+            })
+            .finalize()
+            .into()
+    }
+
+    /// Verify the backup proof against the public share stored in the
+    /// backup. Does not require any secret material.
+    pub fn verify(&self) -> Result<(), KeyBackupError> {
+        if self.rounds.len() != BACKUP_PROOF_ROUNDS {
+            return Err(KeyBackupError::InvalidProof);
+        }
+
+        let mut ciphertexts = Vec::with_capacity(self.rounds.len());
+
+        for (k, round) in self.rounds.iter().enumerate() {
+            let bit = challenge_bit(&self.challenge, k);
+
+            let expected = if bit == 0 {
+                ProjectivePoint::from(round.big_r)
+            } else {
+                self.big_s + round.big_r
+            };
+
+            if ProjectivePoint::GENERATOR * round.opening != expected {
+                return Err(KeyBackupError::InvalidProof);
+            }
+
+            let opened = encrypt_value(
+                &round.seed,
+                &round.opening,
+                &self.recovery_key,
+                k,
+                bit,
+            )
+            .ok_or(KeyBackupError::InvalidRecoveryKey)?;
+
+            let unopened = (round.ephemeral, round.ciphertext);
+
+            let (c0, c1) = if bit == 0 {
+                (opened, unopened)
+            } else {
+                (unopened, opened)
+            };
+
+            ciphertexts.push([c0.0, c0.1, c1.0, c1.1]);
+        }
+
+        let challenge = self.compute_challenge(&ciphertexts);
+
+        if challenge.ct_ne(&self.challenge).into() {
+            return Err(KeyBackupError::InvalidProof);
+        }
+
+        Ok(())
+    }
+
+    /// Verify the backup proof and check that the backup was created
+    /// for the given key, using public data of `share`.
+    ///
+    /// `share` could be a key share of any party of the same key.
+    pub fn verify_with_keyshare(
+        &self,
+        share: &Keyshare,
+    ) -> Result<(), KeyBackupError> {
+        let party_id = self.party_id;
+
+        if party_id >= share.total_parties
+            || share.public_key() != self.public_key
+            || share.big_s(party_id) != self.big_s
+            || *share.get_x_i(party_id) != *self.x_i
+            || share.get_rank(party_id) != self.rank
+        {
+            return Err(KeyBackupError::KeyshareMismatch);
+        }
+
+        self.verify()
+    }
+
+    /// Decrypt the secret share using the recovery key.
+    pub fn decrypt(
+        &self,
+        recovery_key: &ReusableSecret,
+    ) -> Result<Scalar, KeyBackupError> {
+        if PublicKey::from(recovery_key) != self.recovery_key {
+            return Err(KeyBackupError::DecryptionFailed);
+        }
+
+        for (k, round) in self.rounds.iter().enumerate() {
+            let bit = challenge_bit(&self.challenge, k);
+
+            let Some(value) = decrypt_value(
+                recovery_key,
+                &round.ephemeral,
+                &round.ciphertext,
+                k,
+                bit ^ 1,
+            ) else {
+                continue;
+            };
+
+            // bit == 0: opening is r_k, the unopened value is r_k + s_i
+            // bit == 1: opening is r_k + s_i, the unopened value is r_k
+            let s_i = if bit == 0 {
+                value - round.opening
+            } else {
+                round.opening - value
+            };
+
+            if ProjectivePoint::GENERATOR * s_i == self.big_s {
+                return Ok(s_i);
+            }
+        }
+
+        Err(KeyBackupError::DecryptionFailed)
+    }
+
+    /// Serialize the backup.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let size = 2
+            + mem::size_of::<ScalarBytes>()
+            + 2 * mem::size_of::<PointBytes>()
+            + 32
+            + 32
+            + self.rounds.external_size();
+
+        let mut bytes = vec![0u8; size];
+
+        let buf = self.party_id.encode(&mut bytes);
+        let buf = self.rank.encode(buf);
+        let buf = self.x_i.encode(buf);
+        let buf = self.big_s.encode(buf);
+        let buf = self.public_key.encode(buf);
+        let buf = self.recovery_key.encode(buf);
+        let buf = self.challenge.encode(buf);
+        self.rounds.encode(buf);
+
+        bytes
+    }
+
+    /// Deserialize a backup. The proof is not verified.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (bytes, party_id) = u8::decode(bytes, 1)?;
+        let (bytes, rank) = u8::decode(bytes, 1)?;
+        let (bytes, x_i) =
+            NonZeroScalar::decode(bytes, mem::size_of::<ScalarBytes>())?;
+        let (bytes, big_s) =
+            ProjectivePoint::decode(bytes, mem::size_of::<PointBytes>())?;
+        let (bytes, public_key) =
+            ProjectivePoint::decode(bytes, mem::size_of::<PointBytes>())?;
+        let (bytes, recovery_key) = PublicKey::decode(bytes, 32)?;
+        let (bytes, challenge) = <[u8; 32]>::decode(bytes, 32)?;
+
+        if bytes.len() != BACKUP_PROOF_ROUNDS * BackupRound::SIZE {
+            return None;
+        }
+
+        let rounds = Vec::<BackupRound>::read(bytes)?;
+
+        Some(KeyshareBackup {
+            party_id,
+            rank,
+            x_i,
+            big_s,
+            public_key,
+            recovery_key,
+            challenge,
+            rounds,
+        })
+    }
+}
+
+/// Create a verifiable backup of the secret share of `share`
+/// encrypted to `recovery_key`.
+pub fn backup_keyshare<R: RngCore + CryptoRng>(
+    mut rng: R,
+    share: &Keyshare,
+    recovery_key: &PublicKey,
+) -> Result<KeyshareBackup, KeyBackupError> {
+    let party_id = share.party_id;
+    let s_i = Zeroizing::new(share.s_i());
+
+    let mut backup = KeyshareBackup {
+        party_id,
+        rank: share.get_rank(party_id),
+        x_i: share.get_x_i(party_id),
+        big_s: share.big_s(party_id),
+        public_key: share.public_key(),
+        recovery_key: *recovery_key,
+        challenge: [0; 32],
+        rounds: Vec::with_capacity(BACKUP_PROOF_ROUNDS),
+    };
+
+    // (r_k, r_k + s_i, seed_0, seed_1) for each round.
+    let mut secrets = Zeroizing::new(Vec::with_capacity(BACKUP_PROOF_ROUNDS));
+    let mut ciphertexts = Vec::with_capacity(BACKUP_PROOF_ROUNDS);
+
+    for k in 0..BACKUP_PROOF_ROUNDS {
+        let r_k = Scalar::generate_biased(&mut rng);
+        let z_k = r_k + *s_i;
+        let seeds: [[u8; 32]; 2] = rng.gen();
+
+        let c0 = encrypt_value(&seeds[0], &r_k, recovery_key, k, 0)
+            .ok_or(KeyBackupError::InvalidRecoveryKey)?;
+        let c1 = encrypt_value(&seeds[1], &z_k, recovery_key, k, 1)
+            .ok_or(KeyBackupError::InvalidRecoveryKey)?;
+
+        backup.rounds.push(BackupRound {
+            big_r: (ProjectivePoint::GENERATOR * r_k).to_affine(),
+            ephemeral: [0; 32],
+            ciphertext: [0; 32],
+            opening: Scalar::ZERO,
+            seed: [0; 32],
+        });
+
+        ciphertexts.push([c0.0, c0.1, c1.0, c1.1]);
+        secrets.push(BackupSecrets {
+            values: [r_k, z_k],
+            seeds,
+        });
+    }
+
+    backup.challenge = backup.compute_challenge(&ciphertexts);
+
+    for (k, (round, (secrets, c))) in backup
+        .rounds
+        .iter_mut()
+        .zip(secrets.iter().zip(&ciphertexts))
+        .enumerate()
+    {
+        let bit = challenge_bit(&backup.challenge, k) as usize;
+        let other = bit ^ 1;
+
+        round.opening = secrets.values[bit];
+        round.seed = secrets.seeds[bit];
+        round.ephemeral = c[2 * other];
+        round.ciphertext = c[2 * other + 1];
+    }
+
+    Ok(backup)
+}
+
+struct BackupSecrets {
+    values: [Scalar; 2],
+    seeds: [[u8; 32]; 2],
+}
+
+impl Zeroize for BackupSecrets {
+    fn zeroize(&mut self) {
+        self.values.zeroize();
+        self.seeds.zeroize();
+    }
+}
+
+/// Verify and decrypt a set of backups with the recovery key and
+/// combine the shares into the private key.
+///
+/// Requires backups of at least threshold number of parties.
+pub fn restore_private_key(
+    backups: &[KeyshareBackup],
+    recovery_key: &ReusableSecret,
+) -> Result<Scalar, KeyBackupError> {
+    let public_key = backups
+        .first()
+        .ok_or(KeyBackupError::InvalidBackup)?
+        .public_key;
+
+    let mut x_i_list = Vec::with_capacity(backups.len());
+    let mut s_i_list = Zeroizing::new(Vec::with_capacity(backups.len()));
+
+    for backup in backups {
+        if backup.public_key != public_key {
+            return Err(KeyBackupError::PublicKeyMismatch);
+        }
+
+        backup.verify()?;
+
+        x_i_list.push((backup.x_i, backup.rank as usize));
+        s_i_list.push(backup.decrypt(recovery_key)?);
+    }
+
+    combine_shares(&x_i_list, &s_i_list, &public_key)
+        .ok_or(KeyBackupError::PublicKeyMismatch)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keygen::utils::gen_keyshares;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn backup_and_restore() {
+        let mut rng = rand::thread_rng();
+
+        let shares = gen_keyshares(2, 3, None).await;
+        let pk = shares[0].public_key();
+
+        let recovery_key = ReusableSecret::random_from_rng(&mut rng);
+        let recovery_pub_key = PublicKey::from(&recovery_key);
+
+        let backups = shares
+            .iter()
+            .map(|share| {
+                backup_keyshare(&mut rng, share, &recovery_pub_key).unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        for backup in &backups {
+            // any party can audit a backup of any other party
+            backup.verify_with_keyshare(&shares[0]).unwrap();
+
+            let backup =
+                KeyshareBackup::from_bytes(&backup.to_bytes()).unwrap();
+            backup.verify_with_keyshare(&shares[1]).unwrap();
+
+            let s_i = backup.decrypt(&recovery_key).unwrap();
+            assert_eq!(s_i, shares[backup.party_id as usize].s_i());
+        }
+
+        let sk = restore_private_key(&backups[1..], &recovery_key).unwrap();
+        assert_eq!(ProjectivePoint::GENERATOR * sk, pk);
+
+        let other_key = ReusableSecret::random_from_rng(&mut rng);
+        assert!(restore_private_key(&backups, &other_key).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tampered_backup() {
+        let mut rng = rand::thread_rng();
+
+        let shares = gen_keyshares(2, 3, None).await;
+
+        let recovery_key = ReusableSecret::random_from_rng(&mut rng);
+        let recovery_pub_key = PublicKey::from(&recovery_key);
+
+        let backup =
+            backup_keyshare(&mut rng, &shares[0], &recovery_pub_key).unwrap();
+
+        let mut bad = backup.clone();
+        bad.rounds[7].ciphertext[0] ^= 1;
+        assert!(bad.verify().is_err());
+
+        let mut bad = backup.clone();
+        bad.rounds[3].opening += Scalar::ONE;
+        assert!(bad.verify().is_err());
+
+        // backup of party 0 presented as a backup of party 1
+        let mut bad = backup.clone();
+        bad.party_id = 1;
+        assert!(bad.verify_with_keyshare(&shares[0]).is_err());
+        bad.big_s = shares[0].big_s(1);
+        assert!(bad.verify().is_err());
+    }
+}
@@ -9,8 +9,13 @@ use zeroize::Zeroizing;
 use sl_mpc_mate::{
     coord::*,
     math::birkhoff_coeffs,
-    message::{MessageTag, MsgId, MESSAGE_HEADER_SIZE},
+    message::{InstanceId, MessageTag, MsgId, MESSAGE_HEADER_SIZE},
 };
+use sl_oblivious::label::Label;
+
+/// Wallet formats of an exported key.
+#[cfg(feature = "wallet")]
+pub mod wallet;
 
 /// Tag of an enrypted keyshare message
 pub const KEYSHARE_EXPORT_TAG: MessageTag = MessageTag::tag(1);
@@ -18,18 +23,36 @@ pub const KEYSHARE_EXPORT_TAG: MessageTag = MessageTag::tag(1);
 /// Receiver of an encrypted keyshare.
 const EXPORTED_KEYSHARE_RECEIVER: usize = 0;
 
+/// Label used for signatures of abort messages of key export.
+pub const KEY_EXPORT_LABEL: Label = Label::new(VERSION, 400);
+
+/// Context of signatures of abort messages an exporter sends to the
+/// receiver, see [`create_abort_message()`](crate::proto::create_abort_message).
+pub fn signing_context<S>(setup: &S) -> SigningContext
+where
+    S: KeyExporterSetupMessage<PublicKey, Keyshare>,
+{
+    SigningContext::new(
+        &KEY_EXPORT_LABEL,
+        VERSION,
+        &key_exporter_setup_hash(setup),
+    )
+}
+
 use crate::{
     keygen::Keyshare,
     pairs::Pairs,
     proto::{
         check_abort, decode_scalar, tags::*, EncryptedMessage,
-        EncryptionScheme, ScalarBytes,
+        EncryptionScheme, ScalarBytes, SchemeId, SigningContext,
     },
     setup::{
+        approval::{key_export_receiver_setup_hash, key_exporter_setup_hash},
         KeyExportReceiverSetupMessage, KeyExporterSetupMessage,
         ProtocolParticipant, ABORT_MESSAGE_TAG,
     },
     sign::get_lagrange_coeff_list,
+    VERSION,
 };
 
 #[derive(Debug, thiserror::Error)]
@@ -41,6 +64,10 @@ pub enum KeyExportError {
     )]
     InvalidMessage,
 
+    /// A party sent a message which failed decryption
+    #[error("Invalid message from party {0}")]
+    InvalidMessageFrom(usize),
+
     #[error("Public key mismatch after combining keyshares")]
     PublicKeyMismatch,
 
@@ -55,6 +82,10 @@ pub enum KeyExportError {
     /// Some party decided to not participate in the protocol.
     #[error("Abort protocol by party {0}")]
     AbortProtocol(usize),
+
+    /// Indicates that a party should be banned and the protocol aborted
+    #[error("Abort the protocol and ban the party {0}")]
+    AbortProtocolAndBanParty(u8),
 }
 
 impl From<MessageSendError> for KeyExportError {
@@ -69,7 +100,12 @@ impl From<Error> for KeyExportError {
             Error::Abort(p) => KeyExportError::AbortProtocol(p as _),
             Error::Recv => KeyExportError::MissingMessage,
             Error::Send => KeyExportError::SendMessage,
-            Error::InvalidMessage => KeyExportError::InvalidMessage,
+            Error::InvalidMessage | Error::EchoMismatch { .. } => {
+                KeyExportError::InvalidMessage
+            }
+            Error::InvalidMessageFrom(p) => {
+                KeyExportError::InvalidMessageFrom(p)
+            }
         }
     }
 }
@@ -122,7 +158,9 @@ where
     S: KeyExporterSetupMessage<PublicKey, Keyshare>,
     R: RngCore + CryptoRng,
 {
-    let mut scheme = crate::proto::Scheme::new(&mut rng);
+    let mut scheme = setup
+        .encryption_scheme()
+        .new_scheme(setup.instance_id(), &mut rng);
 
     scheme
         .receiver_public_key(0, setup.receiver_public_key().as_bytes())
@@ -145,15 +183,18 @@ where
     trailer[0] = setup.keyshare().party_id;
     ad.copy_from_slice(pub_key);
 
-    msg.encrypt(&mut scheme, 0)
+    msg.encrypt(&mut scheme, 0, KEYSHARE_EXPORT_TAG)
 }
 
-/// Decrypt share encrypted by `export_keyshare()`
+/// Decrypt share encrypted by `export_keyshare()` with encryption
+/// scheme `scheme_id` for protocol instance `instance`.
 pub fn decrypt_share(
     mut msg: Zeroizing<Vec<u8>>,
     enc_key: &ReusableSecret,
+    instance: &InstanceId,
+    scheme_id: SchemeId,
 ) -> Option<(Scalar, u8)> {
-    let mut scheme = crate::proto::Scheme::from_secret(enc_key.clone());
+    let mut scheme = scheme_id.from_secret(instance, enc_key.clone());
 
     let enc_pub_key = msg
         .get(MESSAGE_HEADER_SIZE..)
@@ -162,7 +203,12 @@ pub fn decrypt_share(
     scheme.receiver_public_key(0, enc_pub_key).ok()?;
 
     let (s_i, pid, _) = EncryptedMessage::<[u8; 32]>::decrypt_with_ad(
-        &mut msg, 32, 1, &scheme, 0,
+        &mut msg,
+        32,
+        1,
+        &mut scheme,
+        0,
+        KEYSHARE_EXPORT_TAG,
     )?;
 
     let party_id = pid[0];
@@ -183,7 +229,13 @@ where
 {
     let share = setup.keyshare();
 
-    let mut relay = FilteredMsgRelay::new(relay);
+    let mut relay = FilteredMsgRelay::new(relay).with_signing_context(
+        SigningContext::new(
+            &KEY_EXPORT_LABEL,
+            VERSION,
+            &key_export_receiver_setup_hash(&setup),
+        ),
+    );
 
     relay.ask_messages(&setup, ABORT_MESSAGE_TAG, false).await?;
 
@@ -216,6 +268,7 @@ where
         if is_abort {
             check_abort(
                 &setup,
+                round.relay.signing_context(),
                 &msg,
                 party_idx,
                 KeyExportError::AbortProtocol,
@@ -226,9 +279,13 @@ where
 
         let msg = Zeroizing::new(msg);
 
-        let (s_i, party_id) =
-            decrypt_share(msg, setup.receiver_private_key())
-                .ok_or(KeyExportError::InvalidMessage)?;
+        let (s_i, party_id) = decrypt_share(
+            msg,
+            setup.receiver_private_key(),
+            setup.instance_id(),
+            setup.encryption_scheme(),
+        )
+        .ok_or(KeyExportError::InvalidMessageFrom(party_idx))?;
 
         let x_j = x_i_list
             .get(party_id as usize)
@@ -247,6 +304,27 @@ where
     Ok(private_key)
 }
 
+/// Receive exported key shares and combine them into an
+/// [`ExportedKey`](wallet::ExportedKey) carrying the root chain code
+/// of the keyshare.
+#[cfg(feature = "wallet")]
+pub async fn receive_exported_key<S, R>(
+    setup: S,
+    relay: R,
+) -> Result<wallet::ExportedKey, KeyExportError>
+where
+    S: KeyExportReceiverSetupMessage<ReusableSecret>,
+    R: Relay,
+{
+    let root_chain_code = setup.keyshare().root_chain_code();
+
+    let private_key = receive_keyshares(setup, relay).await?;
+
+    // combine_shares() checked the public key, so it is not zero
+    wallet::ExportedKey::new(private_key, root_chain_code)
+        .map_err(|_| KeyExportError::PublicKeyMismatch)
+}
+
 /// Generate message receiver map.
 ///
 /// Call the passed closure for each pair (msg_id, receiver)
@@ -275,16 +353,19 @@ mod tests {
     use crate::{
         key_import::ecdsa_secret_shares,
         keygen::utils::gen_keyshares,
+        proto::create_abort_message,
         setup::{
             key_export::{
                 exporter::KeyExporter, receiver::KeyExportReceiver,
             },
-            NoSigningKey, NoVerifyingKey,
+            Ed25519Signature, Ed25519SigningKey, NoSigningKey,
+            NoVerifyingKey,
         },
     };
 
     use super::{
-        combine_shares, export_keyshare, receive_keyshares, PublicKey,
+        combine_shares, export_keyshare, receive_keyshares, signing_context,
+        KeyExportError, PublicKey,
     };
 
     #[test]
@@ -391,4 +472,56 @@ mod tests {
             }
         }
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn abort_from_exporter() {
+        let mut rng = rand::thread_rng();
+
+        let shares = gen_keyshares(2, 3, None).await;
+        let inst = InstanceId::new(rand::random());
+
+        let sk: Vec<_> = (0..shares.len())
+            .map(|_| Ed25519SigningKey::generate(&mut rng))
+            .collect();
+        let vk: Vec<_> =
+            sk.iter().map(Ed25519SigningKey::verifying_key).collect();
+
+        let enc_key = ReusableSecret::random_from_rng(&mut rng);
+        let enc_pub_key = PublicKey::from(&enc_key);
+
+        let relay = SimpleMessageRelay::new();
+
+        for p in 1..shares.len() {
+            let setup: KeyExporter<_, _, Ed25519Signature> = KeyExporter::new(
+                inst,
+                sk[p].clone(),
+                p,
+                vk.clone(),
+                shares[p].clone(),
+                enc_pub_key,
+            );
+
+            let msg = if p == 1 {
+                create_abort_message(&setup, &signing_context(&setup))
+            } else {
+                export_keyshare(&mut rng, &setup).unwrap()
+            };
+
+            relay.send(msg);
+        }
+
+        let recv: KeyExportReceiver<_, _, Ed25519Signature> =
+            KeyExportReceiver::new(
+                inst,
+                sk[0].clone(),
+                0,
+                vk,
+                shares[0].clone(),
+                enc_key,
+            );
+
+        let err = receive_keyshares(recv, relay.connect()).await.unwrap_err();
+
+        assert!(matches!(err, KeyExportError::AbortProtocol(1)));
+    }
 }
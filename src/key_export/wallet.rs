@@ -0,0 +1,387 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Output adapters for an exported private key.
+//!
+//! Encodes a key reconstructed by
+//! [`receive_keyshares`](crate::key_export::receive_keyshares) together
+//! with the keyshare's `root_chain_code` into formats accepted by
+//! standard wallets: BIP-32 extended private key, WIF and Ethereum V3
+//! keystore JSON.
+
+use aes::cipher::{KeyIvInit, StreamCipher};
+use k256::{elliptic_curve::sec1::ToEncodedPoint, ProjectivePoint, Scalar};
+use rand::prelude::*;
+use sha3::{Digest, Keccak256};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use crate::proto::encode_scalar;
+
+type Aes128Ctr = ctr::Ctr128BE<aes::Aes128>;
+
+/// BIP-32 version bytes of a mainnet extended private key (`xprv`).
+pub const XPRV_MAINNET: [u8; 4] = [0x04, 0x88, 0xAD, 0xE4];
+
+/// BIP-32 version bytes of a testnet extended private key (`tprv`).
+pub const XPRV_TESTNET: [u8; 4] = [0x04, 0x35, 0x83, 0x94];
+
+/// Network a wallet encoding is produced for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    /// Bitcoin mainnet
+    Mainnet,
+    /// Bitcoin testnet
+    Testnet,
+}
+
+impl Network {
+    fn xprv_version(self) -> [u8; 4] {
+        match self {
+            Network::Mainnet => XPRV_MAINNET,
+            Network::Testnet => XPRV_TESTNET,
+        }
+    }
+
+    fn wif_prefix(self) -> u8 {
+        match self {
+            Network::Mainnet => 0x80,
+            Network::Testnet => 0xEF,
+        }
+    }
+}
+
+/// Wallet encoding errors
+#[derive(Debug, thiserror::Error)]
+pub enum WalletError {
+    /// The exported private key is zero
+    #[error("Zero private key")]
+    ZeroKey,
+
+    /// Invalid scrypt parameters
+    #[error("Invalid scrypt parameters")]
+    InvalidKdfParams,
+}
+
+/// Scrypt parameters of an Ethereum V3 keystore.
+#[derive(Debug, Clone, Copy)]
+pub struct KeystoreParams {
+    /// log2 of the scrypt cost parameter N
+    pub log_n: u8,
+    /// scrypt block size parameter
+    pub r: u32,
+    /// scrypt parallelization parameter
+    pub p: u32,
+}
+
+impl KeystoreParams {
+    /// Parameters used by geth for `--standard` keystores, N = 2^18, p = 1.
+    pub const STANDARD: Self = Self {
+        log_n: 18,
+        r: 8,
+        p: 1,
+    };
+
+    /// Parameters used by geth for `--light` keystores, N = 2^12, p = 6.
+    pub const LIGHT: Self = Self {
+        log_n: 12,
+        r: 8,
+        p: 6,
+    };
+}
+
+impl Default for KeystoreParams {
+    fn default() -> Self {
+        Self::STANDARD
+    }
+}
+
+/// Private key reconstructed from key shares together with
+/// the root chain code of the distributed key.
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct ExportedKey {
+    private_key: Scalar,
+    root_chain_code: [u8; 32],
+}
+
+impl ExportedKey {
+    /// Create an exported key. Returns an error for a zero private key.
+    pub fn new(
+        private_key: Scalar,
+        root_chain_code: [u8; 32],
+    ) -> Result<Self, WalletError> {
+        if bool::from(private_key.is_zero()) {
+            return Err(WalletError::ZeroKey);
+        }
+
+        Ok(Self {
+            private_key,
+            root_chain_code,
+        })
+    }
+
+    /// Private key.
+    pub fn private_key(&self) -> &Scalar {
+        &self.private_key
+    }
+
+    /// Root chain code of the distributed key.
+    pub fn root_chain_code(&self) -> &[u8; 32] {
+        &self.root_chain_code
+    }
+
+    /// Public key.
+    pub fn public_key(&self) -> ProjectivePoint {
+        ProjectivePoint::GENERATOR * self.private_key
+    }
+
+    /// BIP-32 master extended private key.
+    ///
+    /// Encodes the key as a root node (depth 0, no parent) so the
+    /// HD tree derived from the keyshare is preserved.
+    pub fn to_xprv(&self, network: Network) -> Zeroizing<String> {
+        let mut data = Zeroizing::new([0u8; 78]);
+
+        data[0..4].copy_from_slice(&network.xprv_version());
+        // depth (1), parent fingerprint (4) and child number (4) are zero
+        data[13..45].copy_from_slice(&self.root_chain_code);
+        data[46..78].copy_from_slice(&encode_scalar(&self.private_key));
+
+        Zeroizing::new(bs58::encode(&data[..]).with_check().into_string())
+    }
+
+    /// Wallet Import Format of the private key, with the compressed
+    /// public key flag set.
+    pub fn to_wif(&self, network: Network) -> Zeroizing<String> {
+        let mut data = Zeroizing::new([0u8; 34]);
+
+        data[0] = network.wif_prefix();
+        data[1..33].copy_from_slice(&encode_scalar(&self.private_key));
+        data[33] = 0x01;
+
+        Zeroizing::new(bs58::encode(&data[..]).with_check().into_string())
+    }
+
+    /// Ethereum address of the key.
+    pub fn eth_address(&self) -> [u8; 20] {
+        let point = self.public_key().to_affine().to_encoded_point(false);
+        let hash = Keccak256::digest(&point.as_bytes()[1..]);
+
+        let mut address = [0u8; 20];
+        address.copy_from_slice(&hash[12..]);
+
+        address
+    }
+
+    /// Ethereum V3 keystore JSON protected by `password`, using
+    /// scrypt and aes-128-ctr.
+    ///
+    /// The root chain code is encrypted by the continuation of the
+    /// key stream and stored in a non-standard `chaincode` object
+    /// with a MAC over both ciphertexts; wallets ignore unknown fields.
+    pub fn to_eth_keystore<R: RngCore + CryptoRng>(
+        &self,
+        rng: &mut R,
+        password: &[u8],
+        params: &KeystoreParams,
+    ) -> Result<String, WalletError> {
+        let salt: [u8; 32] = rng.gen();
+        let iv: [u8; 16] = rng.gen();
+        let mut uuid: [u8; 16] = rng.gen();
+
+        // random UUID, version 4, variant 1
+        uuid[6] = (uuid[6] & 0x0f) | 0x40;
+        uuid[8] = (uuid[8] & 0x3f) | 0x80;
+
+        let scrypt_params =
+            scrypt::Params::new(params.log_n, params.r, params.p, 32)
+                .map_err(|_| WalletError::InvalidKdfParams)?;
+
+        let mut derived_key = Zeroizing::new([0u8; 32]);
+        scrypt::scrypt(password, &salt, &scrypt_params, &mut derived_key[..])
+            .map_err(|_| WalletError::InvalidKdfParams)?;
+
+        let mut ciphertext = encode_scalar(&self.private_key);
+        let mut chaincode = self.root_chain_code;
+
+        let mut cipher = Aes128Ctr::new(derived_key[..16].into(), &iv.into());
+        cipher.apply_keystream(&mut ciphertext);
+        cipher.apply_keystream(&mut chaincode);
+
+        let mac = Keccak256::new()
+            .chain_update(&derived_key[16..32])
+            .chain_update(ciphertext)
+            .finalize();
+
+        let chaincode_mac = Keccak256::new()
+            .chain_update(&derived_key[16..32])
+            .chain_update(ciphertext)
+            .chain_update(chaincode)
+            .finalize();
+
+        let uuid = hex::encode(uuid);
+
+        let keystore = serde_json::json!({
+            "version": 3,
+            "id": format!(
+                "{}-{}-{}-{}-{}",
+                &uuid[0..8],
+                &uuid[8..12],
+                &uuid[12..16],
+                &uuid[16..20],
+                &uuid[20..32]
+            ),
+            "address": hex::encode(self.eth_address()),
+            "crypto": {
+                "cipher": "aes-128-ctr",
+                "cipherparams": { "iv": hex::encode(iv) },
+                "ciphertext": hex::encode(ciphertext),
+                "kdf": "scrypt",
+                "kdfparams": {
+                    "dklen": 32,
+                    "n": 1u64 << params.log_n,
+                    "r": params.r,
+                    "p": params.p,
+                    "salt": hex::encode(salt),
+                },
+                "mac": hex::encode(mac),
+            },
+            "chaincode": {
+                "ciphertext": hex::encode(chaincode),
+                "mac": hex::encode(chaincode_mac),
+            },
+        });
+
+        Ok(keystore.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(hex_key: &str, chain_code: &str) -> ExportedKey {
+        let sk: [u8; 32] = hex::decode(hex_key).unwrap().try_into().unwrap();
+        let cc: [u8; 32] =
+            hex::decode(chain_code).unwrap().try_into().unwrap();
+
+        ExportedKey::new(crate::proto::decode_scalar(&sk).unwrap(), cc)
+            .unwrap()
+    }
+
+    #[test]
+    fn xprv_and_wif() {
+        // BIP-32 test vector 1, chain m
+        let k = key(
+            "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35",
+            "873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508",
+        );
+
+        assert_eq!(
+            k.to_xprv(Network::Mainnet).as_str(),
+            "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi"
+        );
+
+        assert!(k.to_xprv(Network::Testnet).starts_with("tprv"));
+
+        let k = key(
+            "0c28fca386c7a227600b2fe50b7cae11ec86d3bf1fbe471be89827e19d72aa1d",
+            "0000000000000000000000000000000000000000000000000000000000000000",
+        );
+
+        assert_eq!(
+            k.to_wif(Network::Mainnet).as_str(),
+            "KwdMAjGmerYanjeui5SHS7JkmpZvVipYvB2LJGU1ZxJwYvP98617"
+        );
+    }
+
+    #[test]
+    fn eth_keystore() {
+        let mut rng = rand::thread_rng();
+
+        let k = key(
+            "0000000000000000000000000000000000000000000000000000000000000001",
+            "0101010101010101010101010101010101010101010101010101010101010101",
+        );
+
+        assert_eq!(
+            hex::encode(k.eth_address()),
+            "7e5f4552091a69125d5dfcb7b8c2659029395bdf"
+        );
+
+        let params = KeystoreParams {
+            log_n: 4,
+            r: 8,
+            p: 1,
+        };
+
+        let json = k.to_eth_keystore(&mut rng, b"password", &params).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        let crypto = &json["crypto"];
+        let hex_field =
+            |v: &serde_json::Value| hex::decode(v.as_str().unwrap()).unwrap();
+
+        let salt = hex_field(&crypto["kdfparams"]["salt"]);
+        let iv: [u8; 16] =
+            hex_field(&crypto["cipherparams"]["iv"]).try_into().unwrap();
+        let mut ciphertext = hex_field(&crypto["ciphertext"]);
+
+        let mut dk = [0u8; 32];
+        scrypt::scrypt(
+            b"password",
+            &salt,
+            &scrypt::Params::new(4, 8, 1, 32).unwrap(),
+            &mut dk,
+        )
+        .unwrap();
+
+        let mac = Keccak256::new()
+            .chain_update(&dk[16..])
+            .chain_update(&ciphertext)
+            .finalize();
+        assert_eq!(hex_field(&crypto["mac"]), mac.to_vec());
+
+        let mut chaincode = hex_field(&json["chaincode"]["ciphertext"]);
+        assert_ne!(chaincode, vec![1u8; 32]);
+
+        let mac = Keccak256::new()
+            .chain_update(&dk[16..])
+            .chain_update(&ciphertext)
+            .chain_update(&chaincode)
+            .finalize();
+        assert_eq!(hex_field(&json["chaincode"]["mac"]), mac.to_vec());
+
+        let mut cipher = Aes128Ctr::new(dk[..16].into(), &iv.into());
+        cipher.apply_keystream(&mut ciphertext);
+        cipher.apply_keystream(&mut chaincode);
+        assert_eq!(ciphertext, encode_scalar(k.private_key()).to_vec());
+        assert_eq!(chaincode, vec![1u8; 32]);
+
+        assert_eq!(json["version"], 3);
+        assert_eq!(json["crypto"]["kdfparams"]["n"], 16);
+    }
+
+    #[test]
+    fn eth_keystore_known_vector() {
+        // fixed salt, IV and UUID, so the whole encoding including
+        // the non-standard chaincode object can't drift
+        let mut rng = rand_chacha::ChaCha20Rng::from_seed([7; 32]);
+
+        let k = key(
+            "0000000000000000000000000000000000000000000000000000000000000001",
+            "0101010101010101010101010101010101010101010101010101010101010101",
+        );
+
+        let params = KeystoreParams {
+            log_n: 4,
+            r: 8,
+            p: 1,
+        };
+
+        let json = k.to_eth_keystore(&mut rng, b"password", &params).unwrap();
+
+        assert_eq!(json, KNOWN_KEYSTORE);
+    }
+
+    const KNOWN_KEYSTORE: &str = r#"{"address":"7e5f4552091a69125d5dfcb7b8c2659029395bdf","chaincode":{"ciphertext":"67687ca2ccaf9114928b6521caaa6a1bfaae74ba0e9c12797eb1ffc1a699c74c","mac":"4c136ac4477d6cdcf230a07185635dc0b5888162964632e2f89c12495592d938"},"crypto":{"cipher":"aes-128-ctr","cipherparams":{"iv":"c2186153b880269dc807d34d20da97da"},"ciphertext":"a919e8858952f74f37b0ca33b1eb922a1878712f10577247d1aa43ac29b49316","kdf":"scrypt","kdfparams":{"dklen":32,"n":16,"p":1,"r":8,"salt":"f45714ac8c14320745b9ab920320d8743760c0c9b611eabbde53f3fbf5efbd5e"},"mac":"6475376af42646ec8b061726c0bbbe782ca86e0381e8c7b2c5e37e68b530fdf2"},"id":"779c2972-5035-4d53-a70e-118486a1d3ba","version":3}"#;
+}
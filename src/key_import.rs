@@ -1,11 +1,80 @@
 // Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
 // This software is licensed under the Silence Laboratories License Agreement.
 
-use k256::{NonZeroScalar, ProjectivePoint, Scalar};
-use rand::{CryptoRng, RngCore};
-use sl_mpc_mate::math::Polynomial;
+use futures_util::SinkExt;
+use k256::{
+    elliptic_curve::{ops::Reduce, Field},
+    NonZeroScalar, ProjectivePoint, Scalar,
+};
+use rand::{CryptoRng, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use sl_mpc_mate::{
+    coord::Relay,
+    math::factorial_range,
+    message::{MessageTag, MsgId},
+};
+
+use crate::{
+    keygen::{
+        constants::KEY_IMPORT_LABEL, key_refresh::KeyshareForRefresh,
+        run_inner, signing_context, KeyRefreshData, KeygenError, Keyshare,
+    },
+    proto::{
+        create_abort_message, decode_point, decode_scalar, encode_point,
+        encode_scalar, new_encryption_scheme, tags::*, EncryptedMessage,
+        EncryptionScheme, PointBytes, ScalarBytes, SignedMessage,
+    },
+    setup::{KeygenSetupMessage, ProtocolParticipant, ABORT_MESSAGE_TAG},
+    Seed,
+};
+
+/// Import from a BIP-39 mnemonic or a BIP-32 seed.
+#[cfg(feature = "wallet")]
+pub mod wallet;
+
+/// Message tag of an encryption public key of a party.
+pub const KEY_IMPORT_MSG_R0: MessageTag = MessageTag::tag(20);
+
+/// Message tag of an encrypted share sent by the dealer.
+pub const KEY_IMPORT_MSG_R1: MessageTag = MessageTag::tag(21);
+
+/// Polynomial with secret coefficients, wiped on drop.
+struct SecretPolynomial {
+    coeffs: Zeroizing<Vec<Scalar>>,
+}
+
+impl SecretPolynomial {
+    fn random<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        constant: &Scalar,
+        degree: usize,
+    ) -> Self {
+        let mut coeffs = Zeroizing::new(Vec::with_capacity(degree + 1));
+
+        coeffs.push(*constant);
+        coeffs
+            .extend((0..degree).map(|_| Scalar::generate_biased(&mut *rng)));
 
-use crate::keygen::key_refresh::KeyshareForRefresh;
+        Self { coeffs }
+    }
+
+    /// Value of the `n`-th derivative at point `x`, the same
+    /// calculation as `Polynomial::derivative_at()`.
+    fn derivative_at(&self, n: usize, x: &Scalar) -> Scalar {
+        self.coeffs
+            .iter()
+            .enumerate()
+            .skip(n)
+            .map(|(i, coeff)| {
+                let num = Scalar::reduce(factorial_range(i - n, i));
+
+                num * coeff * x.pow_vartime([(i - n) as u64])
+            })
+            .sum()
+    }
+}
 
 /// Create ecdsa keyshares from a singleton private key.
 pub fn ecdsa_secret_shares<T: RngCore + CryptoRng>(
@@ -22,16 +91,14 @@ pub fn ecdsa_secret_shares<T: RngCore + CryptoRng>(
     let public_key = ProjectivePoint::GENERATOR * private_key;
 
     // u_i_k
-    let mut polynomial =
-        Polynomial::<ProjectivePoint>::random(rng, threshold as usize - 1);
-
-    polynomial.set_constant(*private_key); // making a copy of private key
+    let polynomial =
+        SecretPolynomial::random(rng, private_key, threshold as usize - 1);
 
     let x_i_list = (0..rank_list.len())
         .map(|i| NonZeroScalar::from_uint((i as u64 + 1).into()).unwrap())
         .collect::<Vec<_>>();
 
-    let shares = rank_list
+    rank_list
         .iter()
         .zip(&x_i_list)
         .enumerate()
@@ -50,12 +117,293 @@ pub fn ecdsa_secret_shares<T: RngCore + CryptoRng>(
                 i as u8,
             )
         })
-        .collect();
+        .collect()
+}
+
+/// Role of a party in the key import protocol.
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub enum KeyImportRole {
+    /// The party holding the imported key.
+    Dealer {
+        /// Imported private key
+        private_key: NonZeroScalar,
+
+        /// Root chain code of the imported key
+        root_chain_code: [u8; 32],
+    },
+
+    /// A party receiving a share of the imported key.
+    Receiver {
+        /// Party index of the dealer
+        dealer: usize,
+    },
+}
+
+/// Share of an imported key sent by the dealer.
+#[derive(Clone, Copy, bytemuck::AnyBitPattern, bytemuck::NoUninit)]
+#[repr(C)]
+struct KeyImportMsg1 {
+    s_i_0: ScalarBytes,
+    public_key: PointBytes,
+    root_chain_code: [u8; 32],
+}
+
+const _: () = assert!(core::mem::align_of::<KeyImportMsg1>() == 1);
+
+/// Execute one-shot Key Import protocol.
+///
+/// The dealer splits the imported private key into additive shares
+/// and sends them to other parties encrypted. Then all parties run
+/// the key refresh flavor of DKG in the same session, which
+/// establishes base OTs and produces final keyshares of the
+/// imported key.
+pub async fn run<R, S>(
+    setup: S,
+    seed: Seed,
+    relay: R,
+    role: KeyImportRole,
+) -> Result<Keyshare, KeygenError>
+where
+    S: KeygenSetupMessage,
+    R: Relay,
+{
+    let mut relay = FilteredMsgRelay::new(relay)
+        .with_signing_context(signing_context(&setup, &KEY_IMPORT_LABEL))
+        .with_compression(setup.message_compression());
+    let abort_msg = create_abort_message(&setup, relay.signing_context());
+
+    let result = match run_import(&setup, seed, &mut relay, &role).await {
+        Ok(share) => Ok(share),
+        Err(KeygenError::AbortProtocol(p)) => {
+            Err(KeygenError::AbortProtocol(p))
+        }
+        Err(KeygenError::SendMessage) => Err(KeygenError::SendMessage),
+        Err(err) => {
+            // ignore error of sending abort message
+            let _ = relay.send(abort_msg).await;
+            Err(err)
+        }
+    };
+
+    let _ = relay.close().await;
+
+    result
+}
+
+async fn run_import<R, S>(
+    setup: &S,
+    seed: Seed,
+    relay: &mut FilteredMsgRelay<R>,
+    role: &KeyImportRole,
+) -> Result<Keyshare, KeygenError>
+where
+    S: KeygenSetupMessage,
+    R: Relay,
+{
+    let mut rng = ChaCha20Rng::from_seed(seed);
+    let mut scheme = new_encryption_scheme(setup, &mut rng)
+        .map_err(|_| KeygenError::InvalidMessage)?;
+
+    let my_party_idx = setup.participant_index();
+    let n = setup.total_participants();
+
+    let dealer = match role {
+        KeyImportRole::Dealer { .. } => my_party_idx,
+        KeyImportRole::Receiver { dealer } => *dealer,
+    };
 
-    // FIXME: it should be Zeroize
-    polynomial.reset_contant();
+    if dealer >= n {
+        return Err(KeygenError::InvalidKeyRefresh);
+    }
+
+    relay.ask_messages(setup, ABORT_MESSAGE_TAG, false).await?;
+
+    let ctx = *relay.signing_context();
+    let send_enc_key = |receiver: Option<usize>| {
+        SignedMessage::<[u8; 32], _>::build_with_context(
+            &setup.msg_id(receiver, KEY_IMPORT_MSG_R0),
+            setup.message_ttl().as_secs() as _,
+            0,
+            &ctx,
+            setup.signer(),
+            |msg: &mut [u8; 32], _| msg.copy_from_slice(scheme.public_key()),
+        )
+    };
+
+    let key_refresh_data = match role {
+        KeyImportRole::Dealer {
+            private_key,
+            root_chain_code,
+        } => {
+            relay.ask_messages(setup, KEY_IMPORT_MSG_R0, true).await?;
+
+            relay.send(send_enc_key(None)).await?;
+
+            Round::new(n - 1, KEY_IMPORT_MSG_R0, relay)
+                .of_signed_messages(
+                    setup,
+                    KeygenError::AbortProtocol,
+                    |pk: &[u8; 32], party_idx| {
+                        receiver_public_key(setup, &mut scheme, party_idx, pk)
+                    },
+                )
+                .await?;
+
+            let public_key = ProjectivePoint::GENERATOR * **private_key;
+
+            // additive shares: \sum_{i=0}^{n-1} s_i_0 = private_key
+            let mut s_i_0 = Zeroizing::new(**private_key);
+
+            for party_idx in setup.all_other_parties() {
+                let s_j_0 = Zeroizing::new(Scalar::generate_biased(&mut rng));
+                *s_i_0 -= *s_j_0;
+
+                let mut msg = EncryptedMessage::<KeyImportMsg1>::new(
+                    &setup.msg_id(Some(party_idx), KEY_IMPORT_MSG_R1),
+                    setup.message_ttl().as_secs() as _,
+                    0,
+                    0,
+                    &scheme,
+                );
+
+                let (payload, _) = msg.payload(&scheme);
+                payload.s_i_0 = encode_scalar(&s_j_0);
+                payload.public_key = encode_point(&public_key);
+                payload.root_chain_code = *root_chain_code;
+
+                relay
+                    .feed(
+                        msg.encrypt(
+                            &mut scheme,
+                            party_idx,
+                            KEY_IMPORT_MSG_R1,
+                        )
+                        .ok_or(KeygenError::SendMessage)?,
+                    )
+                    .await?;
+            }
+
+            relay.flush().await?;
+
+            KeyRefreshData {
+                s_i_0: *s_i_0,
+                lost_keyshare_party_ids: vec![],
+                expected_public_key: public_key,
+                root_chain_code: *root_chain_code,
+            }
+        }
+
+        KeyImportRole::Receiver { dealer } => {
+            let dealer = *dealer;
+
+            relay
+                .ask_messages_from_iter(
+                    setup,
+                    KEY_IMPORT_MSG_R0,
+                    [dealer],
+                    false,
+                )
+                .await?;
+            relay
+                .ask_messages_from_iter(
+                    setup,
+                    KEY_IMPORT_MSG_R1,
+                    [dealer],
+                    true,
+                )
+                .await?;
+
+            relay.send(send_enc_key(Some(dealer))).await?;
+
+            Round::new(1, KEY_IMPORT_MSG_R0, relay)
+                .of_signed_messages(
+                    setup,
+                    KeygenError::AbortProtocol,
+                    |pk: &[u8; 32], party_idx| {
+                        receiver_public_key(setup, &mut scheme, party_idx, pk)
+                    },
+                )
+                .await?;
+
+            let mut data = None;
+
+            Round::new(1, KEY_IMPORT_MSG_R1, relay)
+                .of_encrypted_messages(
+                    setup,
+                    &mut scheme,
+                    0,
+                    KeygenError::AbortProtocol,
+                    |msg: &KeyImportMsg1, _, _, _| {
+                        let s_i_0 = decode_scalar(&msg.s_i_0)
+                            .ok_or(KeygenError::InvalidMessage)?;
+                        let public_key = decode_point(&msg.public_key)
+                            .ok_or(KeygenError::InvalidMessage)?;
+
+                        data = Some(KeyRefreshData {
+                            s_i_0,
+                            lost_keyshare_party_ids: vec![],
+                            expected_public_key: public_key,
+                            root_chain_code: msg.root_chain_code,
+                        });
+
+                        Ok(None)
+                    },
+                )
+                .await?;
+
+            data.ok_or(KeygenError::MissingMessage)?
+        }
+    };
 
-    shares
+    run_inner(setup, rng.gen(), relay, Some(&key_refresh_data)).await
+}
+
+// Accepts an encryption key of a party. A long-term key is already
+// known, so the party must send the key declared in the setup.
+fn receiver_public_key<S: ProtocolParticipant>(
+    setup: &S,
+    scheme: &mut Box<dyn EncryptionScheme + Sync>,
+    party_idx: usize,
+    pk: &[u8; 32],
+) -> Result<(), KeygenError> {
+    match setup.encryption_keys() {
+        Some(keys) => {
+            if keys.public_keys()[party_idx].as_bytes() != pk {
+                return Err(KeygenError::InvalidMessage);
+            }
+        }
+        None => scheme
+            .receiver_public_key(party_idx, pk)
+            .map_err(|_| KeygenError::InvalidMessage)?,
+    }
+
+    Ok(())
+}
+
+/// Generate message receiver map.
+///
+/// Call the passed closure for each pair (msg_id, receiver) of
+/// messages of the key import pre-round. Messages of the following
+/// DKG are reported by `keygen::message_receivers()`.
+pub fn message_receivers<S, F>(setup: &S, dealer: usize, mut msg_receiver: F)
+where
+    S: ProtocolParticipant,
+    F: FnMut(MsgId, &S::MessageVerifier),
+{
+    if setup.participant_index() == dealer {
+        setup.all_other_parties().for_each(|p| {
+            msg_receiver(
+                setup.msg_id_from(p, Some(dealer), KEY_IMPORT_MSG_R0),
+                setup.verifier(p),
+            );
+        });
+    } else {
+        let vk = setup.verifier(dealer);
+        let me = Some(setup.participant_index());
+
+        msg_receiver(setup.msg_id_from(dealer, None, KEY_IMPORT_MSG_R0), vk);
+        msg_receiver(setup.msg_id_from(dealer, me, KEY_IMPORT_MSG_R1), vk);
+    }
 }
 
 #[cfg(test)]
@@ -68,13 +416,16 @@ mod tests {
     use rand::Rng;
     use sl_mpc_mate::coord::SimpleMessageRelay;
     use tokio::task::JoinSet;
+    use x25519_dalek::{PublicKey, ReusableSecret};
 
     use crate::{
-        key_import::ecdsa_secret_shares,
+        key_import::{ecdsa_secret_shares, KeyImportRole},
         keygen::{
             key_refresh::{run, setup_key_refresh, KeyshareForRefresh},
+            utils::setup_keygen,
             Keyshare,
         },
+        setup::EncryptionKeys,
         sign::setup_dsg,
     };
 
@@ -211,4 +562,108 @@ mod tests {
             let _fini = fini.unwrap();
         }
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn import_key_one_shot() {
+        const DEALER: usize = 1;
+
+        let mut rng = rand::thread_rng();
+        let private_key = NonZeroScalar::random(&mut rng);
+        let original_pubkey = ProjectivePoint::GENERATOR * *private_key;
+        let root_chain_code: [u8; 32] = rng.gen();
+
+        let coord = SimpleMessageRelay::new();
+        let mut parties = JoinSet::new();
+
+        for (idx, (setup, seed)) in
+            setup_keygen(None, 2, 3, None).into_iter().enumerate()
+        {
+            let role = if idx == DEALER {
+                KeyImportRole::Dealer {
+                    private_key,
+                    root_chain_code,
+                }
+            } else {
+                KeyImportRole::Receiver { dealer: DEALER }
+            };
+
+            parties.spawn(super::run(setup, seed, coord.connect(), role));
+        }
+
+        let mut new_shares = vec![];
+        while let Some(fini) = parties.join_next().await {
+            new_shares.push(Arc::new(fini.unwrap().unwrap()));
+        }
+
+        for share in &new_shares {
+            assert_eq!(
+                share.public_key,
+                original_pubkey.to_encoded_point(true).as_bytes()
+            );
+            assert_eq!(share.root_chain_code, root_chain_code);
+        }
+
+        let coord = SimpleMessageRelay::new();
+
+        new_shares.sort_by_key(|share| share.party_id);
+
+        let mut parties: JoinSet<Result<_, _>> = JoinSet::new();
+        for (setup, seed) in setup_dsg(None, &new_shares[1..], "m") {
+            parties.spawn(crate::sign::run(setup, seed, coord.connect()));
+        }
+
+        while let Some(fini) = parties.join_next().await {
+            fini.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn import_key_with_long_term_keys() {
+        const DEALER: usize = 0;
+
+        let mut rng = rand::thread_rng();
+        let private_key = NonZeroScalar::random(&mut rng);
+        let original_pubkey = ProjectivePoint::GENERATOR * *private_key;
+
+        let secrets: Vec<_> = (0..3)
+            .map(|_| ReusableSecret::random_from_rng(&mut rng))
+            .collect();
+        let public_keys: Vec<_> =
+            secrets.iter().map(PublicKey::from).collect();
+
+        let coord = SimpleMessageRelay::new();
+        let mut parties = JoinSet::new();
+
+        for (idx, ((setup, seed), secret)) in setup_keygen(None, 2, 3, None)
+            .into_iter()
+            .zip(secrets)
+            .enumerate()
+        {
+            let keys =
+                EncryptionKeys::new(secret, public_keys.clone()).unwrap();
+            let role = if idx == DEALER {
+                KeyImportRole::Dealer {
+                    private_key,
+                    root_chain_code: [0; 32],
+                }
+            } else {
+                KeyImportRole::Receiver { dealer: DEALER }
+            };
+
+            parties.spawn(super::run(
+                setup.with_encryption_keys(keys),
+                seed,
+                coord.connect(),
+                role,
+            ));
+        }
+
+        while let Some(fini) = parties.join_next().await {
+            let share = fini.unwrap().unwrap();
+            assert_eq!(
+                share.public_key,
+                original_pubkey.to_encoded_point(true).as_bytes()
+            );
+        }
+    }
 }
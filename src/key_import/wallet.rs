@@ -0,0 +1,337 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Import of a key from a BIP-39 mnemonic or a BIP-32 seed.
+//!
+//! The master key and chain code are derived per BIP-32, optionally
+//! followed by an account path. The resulting extended private key
+//! becomes the root of the distributed key, so non-hardened paths
+//! below the account derived from the keyshares yield the same
+//! addresses as the original wallet.
+
+use derivation_path::DerivationPath;
+use hmac::{Hmac, Mac};
+use k256::{
+    elliptic_curve::{ops::Reduce, Curve},
+    sha2::Sha512,
+    NonZeroScalar, ProjectivePoint, Scalar, Secp256k1, U256,
+};
+use rand::{CryptoRng, RngCore};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+use sl_mpc_mate::bip32::derive_child_pubkey;
+
+use crate::{
+    key_import::{ecdsa_secret_shares, KeyImportRole},
+    keygen::key_refresh::KeyshareForRefresh,
+    proto::encode_scalar,
+};
+
+/// HMAC key of the BIP-32 master key generation.
+const MASTER_KEY_HMAC_KEY: &[u8] = b"Bitcoin seed";
+
+/// Errors of a wallet import.
+#[derive(Debug, thiserror::Error)]
+pub enum WalletImportError {
+    /// Invalid BIP-39 mnemonic phrase
+    #[error("Invalid mnemonic")]
+    InvalidMnemonic,
+
+    /// BIP-32 seed must be 16 to 64 bytes long
+    #[error("Invalid seed length")]
+    InvalidSeedLength,
+
+    /// The derived key is invalid, BIP-32 requires to skip this index
+    #[error("Invalid derived key")]
+    InvalidDerivedKey,
+}
+
+/// BIP-32 extended private key to import.
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct ExtendedPrivateKey {
+    private_key: NonZeroScalar,
+    chain_code: [u8; 32],
+}
+
+fn split_hmac_output(
+    output: &[u8],
+) -> Result<(Scalar, [u8; 32]), WalletImportError> {
+    let (il, ir) = output.split_at(32);
+
+    let il = U256::from_be_slice(il);
+    if il >= Secp256k1::ORDER {
+        return Err(WalletImportError::InvalidDerivedKey);
+    }
+
+    let mut chain_code = [0u8; 32];
+    chain_code.copy_from_slice(ir);
+
+    Ok((Scalar::reduce(il), chain_code))
+}
+
+impl ExtendedPrivateKey {
+    /// Derive the master key and chain code from a BIP-32 seed.
+    pub fn from_seed(seed: &[u8]) -> Result<Self, WalletImportError> {
+        if !(16..=64).contains(&seed.len()) {
+            return Err(WalletImportError::InvalidSeedLength);
+        }
+
+        let output = Zeroizing::new(
+            Hmac::<Sha512>::new_from_slice(MASTER_KEY_HMAC_KEY)
+                .expect("HMAC accepts keys of any size")
+                .chain_update(seed)
+                .finalize()
+                .into_bytes(),
+        );
+
+        let (private_key, chain_code) = split_hmac_output(&output)?;
+
+        Ok(Self {
+            private_key: Option::from(NonZeroScalar::new(private_key))
+                .ok_or(WalletImportError::InvalidDerivedKey)?,
+            chain_code,
+        })
+    }
+
+    /// Derive the master key from a BIP-39 mnemonic phrase and an
+    /// optional (may be empty) passphrase.
+    pub fn from_mnemonic(
+        phrase: &str,
+        passphrase: &str,
+    ) -> Result<Self, WalletImportError> {
+        let mnemonic = bip39::Mnemonic::parse(phrase)
+            .map_err(|_| WalletImportError::InvalidMnemonic)?;
+
+        let seed = Zeroizing::new(mnemonic.to_seed(passphrase));
+
+        Self::from_seed(&seed[..])
+    }
+
+    /// Derive a child extended private key, e.g. an account key
+    /// `m/44'/60'/0'/0`. Supports hardened and normal indexes.
+    pub fn derive(
+        &self,
+        path: &DerivationPath,
+    ) -> Result<Self, WalletImportError> {
+        let mut private_key = Zeroizing::new(*self.private_key);
+        let mut chain_code = self.chain_code;
+
+        for child in path {
+            let (il, child_chain_code) = if child.is_normal() {
+                let public_key = ProjectivePoint::GENERATOR * *private_key;
+                let (il, _, child_chain_code) =
+                    derive_child_pubkey(&public_key, chain_code, child)
+                        .map_err(|_| WalletImportError::InvalidDerivedKey)?;
+
+                (il, child_chain_code)
+            } else {
+                let output = Zeroizing::new(
+                    Hmac::<Sha512>::new_from_slice(&chain_code)
+                        .expect("HMAC accepts keys of any size")
+                        .chain_update([0])
+                        .chain_update(encode_scalar(&private_key))
+                        .chain_update(child.to_bits().to_be_bytes())
+                        .finalize()
+                        .into_bytes(),
+                );
+
+                split_hmac_output(&output)?
+            };
+
+            *private_key += il;
+            chain_code = child_chain_code;
+
+            if bool::from(private_key.is_zero()) {
+                return Err(WalletImportError::InvalidDerivedKey);
+            }
+        }
+
+        Ok(Self {
+            private_key: Option::from(NonZeroScalar::new(*private_key))
+                .ok_or(WalletImportError::InvalidDerivedKey)?,
+            chain_code,
+        })
+    }
+
+    /// Private key.
+    pub fn private_key(&self) -> &NonZeroScalar {
+        &self.private_key
+    }
+
+    /// Chain code.
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.chain_code
+    }
+
+    /// Public key.
+    pub fn public_key(&self) -> ProjectivePoint {
+        ProjectivePoint::GENERATOR * *self.private_key
+    }
+
+    /// Role of the dealer in the one-shot `key_import::run()`.
+    /// The chain code becomes the root chain code of the keyshares.
+    pub fn dealer_role(&self) -> KeyImportRole {
+        KeyImportRole::Dealer {
+            private_key: self.private_key,
+            root_chain_code: self.chain_code,
+        }
+    }
+
+    /// Create keyshares for refresh, see `ecdsa_secret_shares()`.
+    /// The chain code becomes the root chain code of the keyshares.
+    pub fn secret_shares<R: RngCore + CryptoRng>(
+        &self,
+        threshold: u8,
+        rank_list: Vec<u8>,
+        skip: Option<&[u8]>,
+        rng: &mut R,
+    ) -> Vec<KeyshareForRefresh> {
+        ecdsa_secret_shares(
+            threshold,
+            rank_list,
+            &self.private_key,
+            self.chain_code,
+            skip,
+            rng,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use k256::elliptic_curve::group::GroupEncoding;
+    use sl_mpc_mate::coord::SimpleMessageRelay;
+    use tokio::task::JoinSet;
+
+    use crate::{
+        key_import::{run, KeyImportRole},
+        keygen::utils::setup_keygen,
+    };
+
+    use super::*;
+
+    fn hex32(s: &str) -> [u8; 32] {
+        hex::decode(s).unwrap().try_into().unwrap()
+    }
+
+    #[test]
+    fn bip32_vectors() {
+        // BIP-32 test vector 1
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let m = ExtendedPrivateKey::from_seed(&seed).unwrap();
+
+        assert_eq!(
+            encode_scalar(m.private_key()),
+            hex32("e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35")
+        );
+        assert_eq!(
+            m.chain_code(),
+            &hex32("873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508")
+        );
+
+        let k = m
+            .derive(&DerivationPath::from_str("m/0'").unwrap())
+            .unwrap();
+        assert_eq!(
+            encode_scalar(k.private_key()),
+            hex32("edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea")
+        );
+        assert_eq!(
+            k.chain_code(),
+            &hex32("47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141")
+        );
+
+        let k = m
+            .derive(&DerivationPath::from_str("m/0'/1").unwrap())
+            .unwrap();
+        assert_eq!(
+            encode_scalar(k.private_key()),
+            hex32("3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368")
+        );
+        assert_eq!(
+            k.chain_code(),
+            &hex32("2a7857631386ba23dacac34180dd1983734e444fdbf774041578e9b6adb37c19")
+        );
+
+        assert!(ExtendedPrivateKey::from_seed(&[0; 15]).is_err());
+    }
+
+    #[test]
+    fn bip39_vector() {
+        let phrase = "abandon abandon abandon abandon abandon abandon \
+                      abandon abandon abandon abandon abandon about";
+
+        let m = ExtendedPrivateKey::from_mnemonic(phrase, "TREZOR").unwrap();
+
+        let seed = hex::decode(
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e5349553\
+             1f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04",
+        )
+        .unwrap();
+        let m2 = ExtendedPrivateKey::from_seed(&seed).unwrap();
+
+        assert_eq!(m.private_key().to_bytes(), m2.private_key().to_bytes());
+        assert_eq!(m.chain_code(), m2.chain_code());
+
+        assert!(ExtendedPrivateKey::from_mnemonic(
+            "abandon abandon abandon abandon abandon abandon \
+             abandon abandon abandon abandon abandon abandon",
+            ""
+        )
+        .is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn import_account_preserves_addresses() {
+        let phrase = "abandon abandon abandon abandon abandon abandon \
+                      abandon abandon abandon abandon abandon about";
+
+        let master = ExtendedPrivateKey::from_mnemonic(phrase, "").unwrap();
+        let account = master
+            .derive(&DerivationPath::from_str("m/44'/60'/0'/0").unwrap())
+            .unwrap();
+
+        let coord = SimpleMessageRelay::new();
+        let mut parties = JoinSet::new();
+
+        for (idx, (setup, seed)) in
+            setup_keygen(None, 2, 3, None).into_iter().enumerate()
+        {
+            let role = if idx == 0 {
+                account.dealer_role()
+            } else {
+                KeyImportRole::Receiver { dealer: 0 }
+            };
+
+            parties.spawn(run(setup, seed, coord.connect(), role));
+        }
+
+        while let Some(fini) = parties.join_next().await {
+            let share = fini.unwrap().unwrap();
+
+            for i in 0..3 {
+                let wallet_key = master
+                    .derive(
+                        &DerivationPath::from_str(&format!(
+                            "m/44'/60'/0'/0/{i}"
+                        ))
+                        .unwrap(),
+                    )
+                    .unwrap();
+
+                let share_key = share
+                    .derive_child_pubkey(
+                        &DerivationPath::from_str(&format!("m/{i}")).unwrap(),
+                    )
+                    .unwrap();
+
+                assert_eq!(
+                    wallet_key.public_key().to_bytes(),
+                    share_key.to_bytes()
+                );
+            }
+        }
+    }
+}
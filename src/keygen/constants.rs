@@ -46,6 +46,17 @@ pub const QC_COMMITMENT_1_LABEL: Label = Label::new(VERSION, 107);
 /// This commitment is used to ensure participants are bound to their final values.
 pub const QC_COMMITMENT_2_LABEL: Label = Label::new(VERSION, 108);
 
+/// Label used for signatures of messages of the key refresh protocol.
+/// It differs from [`DKG_LABEL`], so a key refresh message does not
+/// verify as a message of DKG with the same setup.
+pub const KEY_REFRESH_LABEL: Label = Label::new(VERSION, 109);
+
+/// Label used for signatures of messages of the key import protocol.
+pub const KEY_IMPORT_LABEL: Label = Label::new(VERSION, 110);
+
+/// Label used for signatures of messages of the migration protocol.
+pub const MIGRATION_LABEL: Label = Label::new(VERSION, 111);
+
 /// Message tag for the first round of the DKG protocol.
 /// This message contains initial commitments and setup information.
 pub const DKG_MSG_R1: MessageTag = MessageTag::tag(1);
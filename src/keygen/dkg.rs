@@ -30,6 +30,7 @@ use sl_mpc_mate::{
 
 use sl_oblivious::{
     endemic_ot::{EndemicOTMsg1, EndemicOTReceiver, EndemicOTSender},
+    label::Label,
     soft_spoken::{build_pprf, eval_pprf},
     utils::TranscriptProtocol,
     zkproofs::DLogProof,
@@ -37,24 +38,19 @@ use sl_oblivious::{
 
 use crate::{
     keygen::{
-        constants::*, messages::*, utils::check_secret_recovery, KeygenError,
-        Keyshare,
+        constants::*,
+        messages::*,
+        utils::{block_in_place, check_secret_recovery},
+        KeygenError, Keyshare,
     },
     proto::{tags::*, *},
-    setup::{KeygenSetupMessage, ProtocolParticipant, ABORT_MESSAGE_TAG},
+    setup::{
+        approval::keygen_setup_hash, KeygenSetupMessage, ProtocolParticipant,
+        SignatureEncoding, ABORT_MESSAGE_TAG,
+    },
+    VERSION,
 };
 
-#[cfg(feature = "multi-thread")]
-use tokio::task::block_in_place;
-
-#[cfg(not(feature = "multi-thread"))]
-fn block_in_place<F, R>(f: F) -> R
-where
-    F: FnOnce() -> R,
-{
-    f()
-}
-
 /// Seed type for the ChaCha20 random number generator
 pub type Seed = <ChaCha20Rng as SeedableRng>::Seed;
 
@@ -82,6 +78,17 @@ pub(crate) struct KeyRefreshData {
     pub(crate) root_chain_code: [u8; 32],
 }
 
+/// Context of signatures of messages of DKG and of protocols running
+/// DKG with a keygen setup: key refresh, key import and migration.
+/// Each protocol passes its own `label`, so a message of one protocol
+/// does not verify as a message of another one with the same setup.
+pub(crate) fn signing_context<T: KeygenSetupMessage>(
+    setup: &T,
+    label: &Label,
+) -> SigningContext {
+    SigningContext::new(label, VERSION, &keygen_setup_hash(setup))
+}
+
 /// Executes the Distributed Key Generation protocol
 ///
 /// This is the main entry point for the DKG protocol. It orchestrates the entire process
@@ -118,8 +125,11 @@ where
     T: KeygenSetupMessage,
     R: Relay,
 {
-    let abort_msg = create_abort_message(&setup);
-    let mut relay = FilteredMsgRelay::new(relay);
+    let mut relay = FilteredMsgRelay::new(relay)
+        .with_signing_context(signing_context(&setup, &DKG_LABEL))
+        .with_compression(setup.message_compression())
+        .with_echo_broadcast(setup.echo_broadcast());
+    let abort_msg = create_abort_message(&setup, relay.signing_context());
 
     let result = match run_inner(setup, seed, &mut relay, None).await {
         Ok(share) => Ok(share),
@@ -180,7 +190,11 @@ where
 {
     let mut rng = ChaCha20Rng::from_seed(seed);
 
-    let mut scheme = crate::proto::Scheme::new(&mut rng);
+    let mut scheme = new_encryption_scheme(&setup, &mut rng)
+        .map_err(|_| KeygenError::InvalidMessage)?;
+
+    // with long-term keys from the setup there is no key exchange
+    let static_keys = setup.encryption_keys().is_some();
 
     let T = setup.threshold() as usize;
     let N = setup.total_participants();
@@ -234,16 +248,26 @@ where
     relay.ask_messages(&setup, DKG_MSG_R3, true).await?;
     relay.ask_messages(&setup, DKG_MSG_R4, false).await?;
 
+    let own_enc_pub_key = if static_keys {
+        vec![]
+    } else {
+        scheme.public_key().to_vec()
+    };
+
     let (sid_i_list, commitment_list, x_i_list, enc_pub_key) = broadcast_4(
         &setup,
         relay,
         DKG_MSG_R1,
-        (session_id, commitment, x_i, scheme.public_key().to_vec()),
+        (session_id, commitment, x_i, own_enc_pub_key),
     )
     .await?;
 
     for (receiver, pub_key) in enc_pub_key.into_iter().enumerate() {
-        if receiver != setup.participant_index() {
+        if static_keys {
+            if !pub_key.is_empty() {
+                return Err(KeygenError::InvalidMessage);
+            }
+        } else if receiver != setup.participant_index() {
             scheme
                 .receiver_public_key(receiver, &pub_key)
                 .map_err(|_| KeygenError::InvalidMessage)?;
@@ -319,7 +343,7 @@ where
         relay
             .feed(
                 enc_msg1
-                    .encrypt(&mut scheme, receiver_id)
+                    .encrypt(&mut scheme, receiver_id, DKG_MSG_OT1)
                     .ok_or(KeygenError::SendMessage)?,
             )
             .await
@@ -380,7 +404,7 @@ where
         );
 
         if commit_hash.ct_ne(commitment).into() {
-            return Err(KeygenError::InvalidCommitmentHash);
+            return Err(KeygenError::InvalidCommitmentHash(party_id));
         }
 
         {
@@ -420,6 +444,14 @@ where
         }
     }
 
+    // Messages are processed in order of arrival, a generator for each
+    // receiver makes the output of the round a function of the seed.
+    let mut receiver_rngs: Pairs<ChaCha20Rng> = Pairs::new();
+    for receiver_id in setup.all_other_parties() {
+        let rng = ChaCha20Rng::from_seed(rng.gen());
+        receiver_rngs.push(receiver_id as u8, rng);
+    }
+
     Round::new(setup.total_participants() - 1, DKG_MSG_OT1, relay)
         .of_encrypted_messages(
             &setup,
@@ -428,6 +460,10 @@ where
             KeygenError::AbortProtocol,
             |base_ot_msg1: &EndemicOTMsg1, receiver_index, _, scheme| {
                 let receiver_id = receiver_index as u8;
+                let mut rng = receiver_rngs.pop_pair_or_err(
+                    receiver_id,
+                    KeygenError::InvalidMessage,
+                )?;
                 let rank = setup.participant_rank(receiver_id as usize);
 
                 let trailer = big_f_vec.external_size();
@@ -492,7 +528,7 @@ where
 
                 Ok(Some(
                     enc_buf
-                        .encrypt(scheme, receiver_id as usize)
+                        .encrypt(scheme, receiver_id as usize, DKG_MSG_R3)
                         .ok_or(KeygenError::SendMessage)?,
                 ))
             },
@@ -522,7 +558,7 @@ where
 
                 // also checks that msg3.big_f_vec.coeffs.len() == T
                 if msg3_big_f_vec != big_f_vec {
-                    return Err(KeygenError::BigFVecMismatch);
+                    return Err(KeygenError::BigFVecMismatch(party_index));
                 }
 
                 d_i_list[party_id as usize] = decode_scalar(&msg3.d_i)
@@ -562,7 +598,7 @@ where
 
                 bool::from(commit_hash.ct_eq(commitment_2))
                     .then_some(())
-                    .ok_or(KeygenError::InvalidCommitmentHash)?;
+                    .ok_or(KeygenError::InvalidCommitmentHash(party_index))?;
 
                 if let Some(v) = key_refresh_data {
                     if !v.lost_keyshare_party_ids.contains(&party_id) {
@@ -598,10 +634,12 @@ where
     }
 
     if big_f_i_vecs.len() != d_i_list.len() {
-        return Err(KeygenError::FailedFelmanVerify);
+        return Err(KeygenError::InvalidMessage);
     }
 
-    for (big_f_i_vec, f_i_val) in big_f_i_vecs.into_iter().zip(&d_i_list) {
+    for (party_id, (big_f_i_vec, f_i_val)) in
+        big_f_i_vecs.into_iter().zip(&d_i_list).enumerate()
+    {
         let coeffs = block_in_place(|| {
             big_f_i_vec.derivative_coeffs(my_rank as usize)
         });
@@ -612,7 +650,7 @@ where
             &ProjectivePoint::GENERATOR,
         );
         if !valid {
-            return Err(KeygenError::FailedFelmanVerify);
+            return Err(KeygenError::FailedFelmanVerify(party_id));
         }
     }
 
@@ -621,17 +659,10 @@ where
 
     // Use the root_chain_code in the final dlog proof
     // so that all parties are sure they generated the same root_chain_code
-    let final_session_id_with_root_chain_code = {
-        let mut buf = [0u8; 32];
-        let mut transcript = Transcript::new(&DKG_LABEL);
-        transcript
-            .append_message(b"final_session_id", &keyshare.final_session_id);
-        transcript
-            .append_message(b"root_chain_code", &keyshare.root_chain_code);
-        transcript
-            .challenge_bytes(&DLOG_SESSION_ID_WITH_CHAIN_CODE, &mut buf);
-        SessionId::new(buf)
-    };
+    let final_session_id_with_root_chain_code = session_id_with_chain_code(
+        &keyshare.final_session_id,
+        &keyshare.root_chain_code,
+    );
 
     let proof = {
         let mut transcript = Transcript::new_dlog_proof(
@@ -662,11 +693,11 @@ where
     }
 
     if big_s_list.len() != proof_list.len() {
-        return Err(KeygenError::InvalidDLogProof);
+        return Err(KeygenError::InvalidMessage);
     }
 
     for (party_id, (big_s_i, dlog_proof)) in
-        big_s_list.iter().zip(proof_list.into_iter()).enumerate()
+        big_s_list.iter().zip(proof_list).enumerate()
     {
         if party_id == my_party_id as usize {
             continue;
@@ -684,7 +715,7 @@ where
             .unwrap_u8()
             == 0
         {
-            return Err(KeygenError::InvalidDLogProof);
+            return Err(KeygenError::InvalidDLogProof(party_id));
         }
     }
 
@@ -745,7 +776,7 @@ where
 /// # Returns
 ///
 /// A 32-byte hash representing the commitment
-fn hash_commitment(
+pub(crate) fn hash_commitment(
     session_id: &SessionId,
     party_id: usize,
     rank: usize,
@@ -796,6 +827,31 @@ fn hash_commitment_2(
     hasher.finalize().into()
 }
 
+/// Binds the final session ID to the root chain code
+///
+/// DLog proofs of the last round use the resulting session ID, so
+/// that all parties are sure they generated the same root chain code.
+///
+/// # Arguments
+///
+/// * `final_session_id` - The final session identifier
+/// * `root_chain_code` - The root chain code of the key
+///
+/// # Returns
+///
+/// A session ID for DLog proofs of the last round
+pub(crate) fn session_id_with_chain_code(
+    final_session_id: &[u8; 32],
+    root_chain_code: &[u8; 32],
+) -> SessionId {
+    let mut buf = [0u8; 32];
+    let mut transcript = Transcript::new(&DKG_LABEL);
+    transcript.append_message(b"final_session_id", final_session_id);
+    transcript.append_message(b"root_chain_code", root_chain_code);
+    transcript.challenge_bytes(&DLOG_SESSION_ID_WITH_CHAIN_CODE, &mut buf);
+    SessionId::new(buf)
+}
+
 /// Generates a session ID for base oblivious transfer
 ///
 /// This function creates a unique session ID for the base oblivious transfer
@@ -878,7 +934,7 @@ pub(crate) fn get_all_but_one_session_id(
 ///
 /// * `Ok(())` - If all proofs are valid
 /// * `Err(KeygenError)` - If any proof is invalid
-fn verify_dlog_proofs(
+pub(crate) fn verify_dlog_proofs(
     final_session_id: &[u8],
     party_id: usize,
     proofs: &[DLogProof],
@@ -901,7 +957,7 @@ fn verify_dlog_proofs(
     }
 
     if ok.unwrap_u8() == 0 {
-        return Err(KeygenError::InvalidDLogProof);
+        return Err(KeygenError::InvalidDLogProof(party_id));
     }
 
     Ok(())
@@ -954,6 +1010,70 @@ where
     Ok((v0.into(), v1.into(), v2.into(), v3.into()))
 }
 
+/// Returns sizes of messages sent by a party during DKG.
+///
+/// # Type Parameters
+///
+/// * `S` - Type of signatures of messages
+///
+/// # Arguments
+///
+/// * `n` - Number of parties
+/// * `t` - Threshold
+/// * `scheme` - Encryption scheme of P2P messages
+/// * `static_keys` - Whether the setup has long-term encryption keys,
+///   in which case the first round carries no encryption public key
+pub fn message_sizes<S: SignatureEncoding>(
+    n: usize,
+    t: usize,
+    scheme: SchemeId,
+    static_keys: bool,
+) -> Vec<MessageSize> {
+    const POINT: usize = std::mem::size_of::<PointBytes>();
+    const SCALAR: usize = std::mem::size_of::<ScalarBytes>();
+
+    let scheme = scheme.new_scheme(
+        &InstanceId::new([0; 32]),
+        &mut ChaCha20Rng::from_seed([0; 32]),
+    );
+    let enc_pk_size = if static_keys {
+        0
+    } else {
+        scheme.public_key().len()
+    };
+
+    // trailer of a broadcast_4() message is a sum of external sizes
+    // of its parts.
+    let r1 = SessionId::SIZE + 32 + SCALAR + enc_pk_size;
+    let r2 = t * POINT + 32 + 32 + t * DLogProof::SIZE;
+    let r4 = POINT + POINT + DLogProof::SIZE;
+
+    vec![
+        MessageSize::new_broadcast(
+            DKG_MSG_R1,
+            SignedMessage::<(), S>::size(r1),
+        ),
+        MessageSize::new_p2p(
+            DKG_MSG_OT1,
+            EncryptedMessage::<EndemicOTMsg1>::size(0, 0, &scheme),
+            n,
+        ),
+        MessageSize::new_broadcast(
+            DKG_MSG_R2,
+            SignedMessage::<(), S>::size(r2),
+        ),
+        MessageSize::new_p2p(
+            DKG_MSG_R3,
+            EncryptedMessage::<KeygenMsg3>::size(0, t * POINT, &scheme),
+            n,
+        ),
+        MessageSize::new_broadcast(
+            DKG_MSG_R4,
+            SignedMessage::<(), S>::size(r4),
+        ),
+    ]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1019,6 +1139,27 @@ mod tests {
         shares
     }
 
+    #[test]
+    fn signing_contexts() {
+        let (setup, _) = setup_keygen(None, 2, 3, None).remove(0);
+
+        let labels = [
+            DKG_LABEL,
+            KEY_REFRESH_LABEL,
+            KEY_IMPORT_LABEL,
+            MIGRATION_LABEL,
+        ];
+
+        for (i, a) in labels.iter().enumerate() {
+            for b in &labels[i + 1..] {
+                assert_ne!(
+                    signing_context(&setup, a),
+                    signing_context(&setup, b)
+                );
+            }
+        }
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn dkg_r1() {
         sim(2, &[0, 1, 1], SimpleMessageRelay::new()).await;
@@ -1035,6 +1176,175 @@ mod tests {
         }
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn encryption_schemes() {
+        use crate::proto::SchemeId;
+
+        for scheme in [
+            SchemeId::X25519Aes256Gcm,
+            SchemeId::HpkeChaCha20Poly1305,
+            SchemeId::HpkeAes256Gcm,
+        ] {
+            let parties = setup_keygen(None, 2, 3, None)
+                .into_iter()
+                .map(|(setup, seed)| {
+                    (setup.with_encryption_scheme(scheme), seed)
+                })
+                .collect();
+
+            sim_parties(parties, SimpleMessageRelay::new()).await;
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn encryption_scheme_mismatch() {
+        use crate::proto::SchemeId;
+
+        let coord = SimpleMessageRelay::new();
+        let mut jset = JoinSet::new();
+
+        for (setup, seed) in setup_keygen(None, 2, 3, None) {
+            let setup = if setup.participant_index() == 2 {
+                setup.with_encryption_scheme(SchemeId::HpkeChaCha20Poly1305)
+            } else {
+                setup
+            };
+
+            jset.spawn(run(setup, seed, coord.connect()));
+        }
+
+        while let Some(fini) = jset.join_next().await {
+            assert!(matches!(
+                fini.unwrap(),
+                Err(KeygenError::InvalidMessageFrom(_)
+                    | KeygenError::AbortProtocol(_))
+            ));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn message_sizes_match() {
+        use sl_mpc_mate::coord::stats::{RelayStats, Stats};
+
+        use crate::setup::NoSignature;
+
+        let (n, t) = (3, 2);
+        let sizes =
+            message_sizes::<NoSignature>(n, t, SchemeId::default(), false);
+        let bytes: usize = sizes.iter().map(MessageSize::total).sum();
+        let count: usize = sizes.iter().map(|s| s.count).sum();
+
+        let coord = SimpleMessageRelay::new();
+        let mut jset = JoinSet::new();
+        let mut stats = vec![];
+
+        for (setup, seed) in setup_keygen(None, t as u8, n as u8, None) {
+            let s = Stats::alloc();
+            let relay = RelayStats::new(coord.connect(), s.clone());
+            stats.push(s);
+            jset.spawn(run(setup, seed, relay));
+        }
+
+        while let Some(fini) = jset.join_next().await {
+            fini.unwrap().unwrap();
+        }
+
+        // all other sent messages are header-only ASK messages
+        for s in stats {
+            let s = Stats::inner(s);
+            assert_eq!(
+                s.send_size - bytes,
+                (s.send_count - count) * MESSAGE_HEADER_SIZE
+            );
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn mixed_message_compression() {
+        use sl_mpc_mate::coord::stats::{RelayStats, Stats};
+
+        use crate::setup::{NoSignature, NoSigningKey};
+
+        let (n, t) = (3, 2);
+        let sizes =
+            message_sizes::<NoSignature>(n, t, SchemeId::default(), false);
+        let bytes: usize = sizes.iter().map(MessageSize::total).sum();
+        let count: usize = sizes.iter().map(|s| s.count).sum();
+
+        let coord = SimpleMessageRelay::new();
+        let mut jset = JoinSet::new();
+        let mut stats = vec![];
+
+        for (setup, seed) in setup_keygen(None, t as u8, n as u8, None) {
+            let compression = setup.participant_index() != 0;
+            let setup = setup.with_message_compression(compression);
+
+            let s = Stats::alloc();
+            let relay = RelayStats::new(coord.connect(), s.clone());
+            stats.push(s);
+            jset.spawn(run(setup, seed, relay));
+        }
+
+        while let Some(fini) = jset.join_next().await {
+            fini.unwrap().unwrap();
+        }
+
+        // messages of the protocol are mostly random, compression
+        // never makes them larger
+        for (idx, s) in stats.into_iter().enumerate() {
+            let s = Stats::inner(s);
+            let sent =
+                s.send_size - (s.send_count - count) * MESSAGE_HEADER_SIZE;
+
+            if idx == 0 {
+                assert_eq!(sent, bytes);
+            } else {
+                assert!(sent <= bytes);
+            }
+        }
+
+        // a compressible message is sent compressed and restored by
+        // a receiver without compression
+        let stats = Stats::alloc();
+        let mut sender = FilteredMsgRelay::new(RelayStats::new(
+            coord.connect(),
+            stats.clone(),
+        ))
+        .with_compression(true);
+        let mut receiver = FilteredMsgRelay::new(coord.connect());
+
+        let id =
+            MsgId::new(&InstanceId::new([1; 32]), &[0], None, DKG_MSG_R1);
+        let msg = SignedMessage::<[u8; 1024], NoSignature>::build(
+            &id,
+            10,
+            0,
+            &NoSigningKey,
+            |payload, _| payload[..32].fill(1),
+        );
+
+        sender.send_broadcast(msg.clone()).await.unwrap();
+        receiver
+            .expect_message(id, DKG_MSG_R1, 0, 10)
+            .await
+            .unwrap();
+
+        let (received, _, _) = receiver.recv(DKG_MSG_R1).await.unwrap();
+
+        assert_eq!(received, msg);
+        assert!(Stats::inner(stats).send_size < msg.len() / 4);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn echo_broadcast() {
+        let parties = setup_keygen(None, 2, 3, None)
+            .into_iter()
+            .map(|(setup, seed)| (setup.with_echo_broadcast(true), seed))
+            .collect();
+
+        sim_parties(parties, SimpleMessageRelay::new()).await;
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn n1() {
         let parties = setup_keygen(None, 2, 3, None);
@@ -17,7 +17,10 @@ use sl_mpc_mate::coord::*;
 use crate::keygen::utils::{get_birkhoff_coefficients, get_lagrange_coeff};
 
 use crate::{
-    keygen::{run_inner, KeyRefreshData, KeygenError, Keyshare},
+    keygen::{
+        constants::KEY_REFRESH_LABEL, run_inner, signing_context,
+        KeyRefreshData, KeygenError, Keyshare,
+    },
     proto::{tags::*, *},
     setup::KeygenSetupMessage,
     Seed,
@@ -260,19 +263,22 @@ where
     S: KeygenSetupMessage,
     R: Relay,
 {
-    let abort_msg = create_abort_message(&setup);
-    let mut relay = FilteredMsgRelay::new(relay);
+    let mut relay = FilteredMsgRelay::new(relay)
+        .with_signing_context(signing_context(&setup, &KEY_REFRESH_LABEL))
+        .with_compression(setup.message_compression())
+        .with_echo_broadcast(setup.echo_broadcast());
+    let abort_msg = create_abort_message(&setup, relay.signing_context());
 
     let my_party_id = old_keyshare.party_id;
     let n = setup.total_participants();
 
     let mut s_i_0 = Scalar::ZERO;
-    if old_keyshare.s_i.is_some() && old_keyshare.x_i_list.is_some() {
+    if let (Some(s_i), Some(x_i_list)) =
+        (&old_keyshare.s_i, &old_keyshare.x_i_list)
+    {
         // calculate additive share s_i_0 of participant_i,
         // \sum_{i=0}^{n-1} s_i_0 = private_key
-        let s_i = &old_keyshare.s_i.unwrap();
         let rank_list = &old_keyshare.rank_list;
-        let x_i_list = &old_keyshare.x_i_list.unwrap();
         let x_i = &x_i_list[my_party_id as usize];
 
         let party_ids_with_keyshares = (0..n as u8)
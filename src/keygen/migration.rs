@@ -4,6 +4,10 @@
 //! This module provides functionality for migrating existing key shares from the other threshold ECDSA protocols
 //! such as GG** to the DKLS23 protocol format. The migration process preserves the cryptographic properties
 //! of the original key shares while updating them to the new protocol format.
+//!
+//! Keyshares of GG18/GG20 ([`gg20`]) and CMP ([`cmp`]) implementations are
+//! converted to an additive share of the migrating quorum by
+//! [`ForeignKeyshare::migration_share()`] and passed to [`run_with_share()`].
 
 use crate::keygen::{KeyRefreshData, KeygenError, Keyshare};
 use crate::proto::{create_abort_message, FilteredMsgRelay};
@@ -13,6 +17,14 @@ use futures_util::SinkExt;
 use k256::{ProjectivePoint, Scalar};
 use sl_mpc_mate::coord::Relay;
 
+#[cfg(feature = "migration")]
+pub mod cmp;
+mod foreign;
+#[cfg(feature = "migration")]
+pub mod gg20;
+
+pub use foreign::{ForeignKeyshare, MigrationError, MigrationShare};
+
 /// Migrates key shares from other ECDSA threshold protocols to DKLS23 format.
 ///
 /// This function performs the migration of existing key shares to the DKLS23 protocol.
@@ -57,9 +69,14 @@ where
     S: KeygenSetupMessage,
     R: Relay,
 {
-    let abort_msg = create_abort_message(&setup);
-
-    let mut relay = FilteredMsgRelay::new(relay);
+    let mut relay = FilteredMsgRelay::new(relay)
+        .with_signing_context(keygen::signing_context(
+            &setup,
+            &keygen::constants::MIGRATION_LABEL,
+        ))
+        .with_compression(setup.message_compression())
+        .with_echo_broadcast(setup.echo_broadcast());
+    let abort_msg = create_abort_message(&setup, relay.signing_context());
 
     let key_refresh_data = KeyRefreshData {
         s_i_0,
@@ -96,6 +113,32 @@ where
     Ok(new_keyshare)
 }
 
+/// Migrates an additive share of a foreign keyshare to DKLS23 format.
+///
+/// Same as [`run()`], with `s_i_0`, `public_key` and `root_chain_code`
+/// taken from `share`. All parties of `setup` must provide shares of
+/// the same quorum.
+pub async fn run_with_share<R, S>(
+    setup: S,
+    seed: Seed,
+    relay: R,
+    share: MigrationShare,
+) -> Result<Keyshare, KeygenError>
+where
+    S: KeygenSetupMessage,
+    R: Relay,
+{
+    run(
+        setup,
+        seed,
+        relay,
+        *share.s_i_0(),
+        *share.public_key(),
+        *share.root_chain_code(),
+    )
+    .await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
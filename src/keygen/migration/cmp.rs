@@ -0,0 +1,226 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Parser of CMP keyshares in the format of the `cggmp21` crate
+//! (`key-share` `KeyShare` or `CoreKeyShare`), serialized with
+//! `serde_json`.
+//!
+//! A keyshare with `vss_setup` is Shamir shared, party `i` holding the
+//! value of a polynomial at point `vss_setup.I[i]`, and any
+//! `vss_setup.min_signers` parties may sign. Otherwise the key is
+//! additively shared between all parties.
+
+use super::foreign::{json, ForeignKeyshare, MigrationError};
+
+/// Curve name of secp256k1 in `generic-ec`.
+const CURVE_NAME: &str = "secp256k1";
+
+/// Parse a keyshare JSON and validate it against its public shares
+/// and the shared public key.
+///
+/// The party index is the `i` field of the keyshare. The optional
+/// `chain_code` of an HD-capable keyshare becomes the root chain code.
+pub fn from_key_share_json(
+    key_share: &str,
+) -> Result<ForeignKeyshare, MigrationError> {
+    let key_share = json::parse(key_share)?;
+
+    // `KeyShare` wraps the `CoreKeyShare` with auxiliary data
+    let core = key_share.get("core").unwrap_or(&key_share);
+
+    if json::str_field(core, "curve")? != CURVE_NAME {
+        return Err(MigrationError::UnsupportedCurve);
+    }
+
+    let i = json::u16_field(core, "i")? as usize;
+    let x_i = json::scalar(json::field(core, "x")?)?;
+    let public_key = json::point(json::field(core, "shared_public_key")?)?;
+
+    let public_shares = json::array_field(core, "public_shares")?
+        .iter()
+        .map(json::point)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let share = match core.get("vss_setup").filter(|v| !v.is_null()) {
+        Some(vss_setup) => {
+            let min_signers = json::u16_field(vss_setup, "min_signers")?;
+
+            let points = json::array_field(vss_setup, "I")?
+                .iter()
+                .map(json::scalar)
+                .collect::<Result<Vec<_>, _>>()?;
+
+            ForeignKeyshare::new_shamir(
+                x_i,
+                i,
+                points,
+                min_signers as usize,
+                public_shares,
+                public_key,
+            )?
+        }
+
+        None => {
+            ForeignKeyshare::new_additive(x_i, i, public_shares, public_key)?
+        }
+    };
+
+    match core.get("chain_code").filter(|v| !v.is_null()) {
+        Some(chain_code) => {
+            let chain_code = json::chain_code(chain_code)?;
+
+            Ok(share.with_root_chain_code(chain_code))
+        }
+
+        None => Ok(share),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::{
+        elliptic_curve::{group::GroupEncoding, ops::MulByGenerator},
+        ProjectivePoint, Scalar,
+    };
+    use serde_json::Value;
+    use sl_mpc_mate::coord::SimpleMessageRelay;
+    use tokio::task::JoinSet;
+
+    use crate::keygen::{
+        migration::{foreign::tests::fixture, run_with_share},
+        utils::setup_keygen,
+    };
+
+    use super::*;
+
+    fn key_share(
+        i: usize,
+        share: &Scalar,
+        points: Option<&[Scalar]>,
+        public_shares: &[ProjectivePoint],
+        public_key: &ProjectivePoint,
+        chain_code: Option<[u8; 32]>,
+    ) -> String {
+        let hex_point = |p: &ProjectivePoint| hex::encode(p.to_bytes());
+
+        let mut core = serde_json::json!({
+            "curve": CURVE_NAME,
+            "i": i,
+            "shared_public_key": hex_point(public_key),
+            "public_shares": public_shares.iter().map(hex_point).collect::<Vec<_>>(),
+            "x": hex::encode(share.to_bytes()),
+        });
+
+        if let Some(points) = points {
+            core["vss_setup"] = serde_json::json!({
+                "min_signers": 2,
+                "I": points
+                    .iter()
+                    .map(|x| hex::encode(x.to_bytes()))
+                    .collect::<Vec<_>>(),
+            });
+        }
+
+        if let Some(chain_code) = chain_code {
+            core["chain_code"] = Value::from(hex::encode(chain_code));
+        }
+
+        serde_json::json!({ "core": core, "aux": {} }).to_string()
+    }
+
+    #[test]
+    fn parse_additive_key_share() {
+        let mut rng = rand::thread_rng();
+        let shares: Vec<_> =
+            (0..2).map(|_| Scalar::generate_vartime(&mut rng)).collect();
+        let public_shares: Vec<_> = shares
+            .iter()
+            .map(ProjectivePoint::mul_by_generator)
+            .collect();
+        let public_key = public_shares.iter().sum();
+
+        let json =
+            key_share(1, &shares[1], None, &public_shares, &public_key, None);
+        let share = from_key_share_json(&json).unwrap();
+
+        assert_eq!(share.threshold(), 2);
+        assert!(share.root_chain_code().is_none());
+
+        let json =
+            key_share(1, &shares[0], None, &public_shares, &public_key, None);
+        assert!(matches!(
+            from_key_share_json(&json),
+            Err(MigrationError::PublicShareMismatch)
+        ));
+    }
+
+    #[test]
+    fn additive_key_share_fixtures() {
+        const PUBLIC_KEY: &str =
+            "0308bed7c6db3feba3dc68cc85e57d626006e72897342540f3771c5e9e4fd62c34";
+
+        let quorum = [0, 1];
+        let sum = quorum
+            .iter()
+            .map(|p| {
+                let json = fixture(&format!("cggmp21-additive-{p}.json"));
+                let share = from_key_share_json(&json).unwrap();
+
+                assert_eq!(share.party_index(), *p);
+                assert_eq!(share.threshold(), 2);
+                assert!(share.root_chain_code().is_none());
+
+                *share
+                    .with_root_chain_code([0; 32])
+                    .migration_share(&quorum)
+                    .unwrap()
+                    .s_i_0()
+            })
+            .sum::<Scalar>();
+
+        let public_key = ProjectivePoint::mul_by_generator(&sum);
+        assert_eq!(hex::encode(public_key.to_bytes()), PUBLIC_KEY);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn migrate_key_share() {
+        const PUBLIC_KEY: &str =
+            "037e67ef23b9a3d21643a88770af0273639fdb51c62a1ffa0055bbf9fbe484c59f";
+        const CHAIN_CODE: &str =
+            "78f794552fccf1a4354ff6df7c0242cd700597b0cda605b3aa431b4679c58723";
+
+        let quorum = [1, 2];
+
+        let coord = SimpleMessageRelay::new();
+        let mut parties = JoinSet::new();
+
+        for ((setup, seed), &p) in
+            setup_keygen(None, 2, 2, None).into_iter().zip(&quorum)
+        {
+            let json = fixture(&format!("cggmp21-key-share-{p}.json"));
+            let share = from_key_share_json(&json).unwrap();
+
+            assert_eq!(share.party_index(), p);
+            assert_eq!(share.threshold(), 2);
+
+            let share = share.migration_share(&quorum).unwrap();
+
+            parties.spawn(run_with_share(
+                setup,
+                seed,
+                coord.connect(),
+                share,
+            ));
+        }
+
+        while let Some(fini) = parties.join_next().await {
+            let keyshare = fini.unwrap().unwrap();
+
+            assert_eq!(
+                hex::encode(keyshare.public_key().to_bytes()),
+                PUBLIC_KEY
+            );
+            assert_eq!(hex::encode(keyshare.root_chain_code()), CHAIN_CODE);
+        }
+    }
+}
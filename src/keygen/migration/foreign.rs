@@ -0,0 +1,547 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Protocol independent model of a keyshare of another threshold ECDSA
+//! protocol, and conversion of it into an additive share of a quorum.
+
+use k256::{elliptic_curve::ops::MulByGenerator, ProjectivePoint, Scalar};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// Errors of a foreign keyshare conversion.
+#[derive(Debug, thiserror::Error)]
+pub enum MigrationError {
+    /// Keyshare is not a valid JSON document
+    #[error("Invalid JSON")]
+    InvalidJson,
+
+    /// Required field is missing or has unexpected type
+    #[error("Missing or invalid field: {0}")]
+    InvalidField(&'static str),
+
+    /// Keyshare is not a secp256k1 keyshare
+    #[error("Unsupported curve")]
+    UnsupportedCurve,
+
+    /// Invalid encoding of a scalar
+    #[error("Invalid scalar")]
+    InvalidScalar,
+
+    /// Invalid encoding of a point
+    #[error("Invalid point")]
+    InvalidPoint,
+
+    /// Inconsistent threshold, party index or share indexes
+    #[error("Invalid parameters")]
+    InvalidParameters,
+
+    /// Secret share does not match its public share
+    #[error("Secret share does not match public share")]
+    PublicShareMismatch,
+
+    /// Public shares do not interpolate to the public key
+    #[error("Public shares do not match public key")]
+    PublicKeyMismatch,
+
+    /// Quorum is smaller than the threshold, contains duplicates,
+    /// unknown parties or does not contain the local party
+    #[error("Invalid quorum")]
+    InvalidQuorum,
+
+    /// Keyshare has no chain code and none was provided
+    #[error("Missing root chain code")]
+    MissingChainCode,
+}
+
+/// Keyshare of another threshold ECDSA protocol.
+///
+/// The secret key is either Shamir shared with a polynomial of degree
+/// `threshold - 1`, party `j` holding its value at `points[j]`, or
+/// additively shared between all parties.
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct ForeignKeyshare {
+    x_i: Scalar,
+    party_index: usize,
+    points: Option<Vec<Scalar>>,
+    threshold: usize,
+    public_shares: Vec<ProjectivePoint>,
+    public_key: ProjectivePoint,
+    root_chain_code: Option<[u8; 32]>,
+}
+
+/// Lagrange coefficient of `points[j]` for interpolation at `x`
+/// over the subset `ids` of points.
+fn lagrange_coeff_at(
+    x: &Scalar,
+    j: usize,
+    points: &[Scalar],
+    ids: &[usize],
+) -> Scalar {
+    let x_j = &points[j];
+
+    ids.iter()
+        .filter(|&&k| k != j)
+        .fold(Scalar::ONE, |coeff, &k| {
+            let x_k = &points[k];
+            coeff * (x - x_k) * (x_j - x_k).invert().unwrap()
+        })
+}
+
+/// Interpolate public shares `ids` at point `x`.
+fn interpolate_at(
+    x: &Scalar,
+    points: &[Scalar],
+    public_shares: &[ProjectivePoint],
+    ids: &[usize],
+) -> ProjectivePoint {
+    ids.iter().fold(ProjectivePoint::IDENTITY, |sum, &j| {
+        sum + public_shares[j] * lagrange_coeff_at(x, j, points, ids)
+    })
+}
+
+impl ForeignKeyshare {
+    /// Create a Shamir shared keyshare.
+    ///
+    /// `points[j]` is the share index (evaluation point) of party `j`
+    /// and `public_shares[j]` its public share `x_j * G`. Any
+    /// `threshold` parties may reconstruct the key.
+    ///
+    /// Checks that `x_i` matches the public share of `party_index`,
+    /// and that all public shares lie on a polynomial of degree
+    /// `threshold - 1` whose value at zero is `public_key`.
+    pub fn new_shamir(
+        x_i: Scalar,
+        party_index: usize,
+        points: Vec<Scalar>,
+        threshold: usize,
+        public_shares: Vec<ProjectivePoint>,
+        public_key: ProjectivePoint,
+    ) -> Result<Self, MigrationError> {
+        let n = public_shares.len();
+
+        if points.len() != n
+            || party_index >= n
+            || threshold == 0
+            || threshold > n
+        {
+            return Err(MigrationError::InvalidParameters);
+        }
+
+        for (j, x_j) in points.iter().enumerate() {
+            if bool::from(x_j.is_zero()) || points[..j].contains(x_j) {
+                return Err(MigrationError::InvalidParameters);
+            }
+        }
+
+        let share = Self {
+            x_i,
+            party_index,
+            points: Some(points),
+            threshold,
+            public_shares,
+            public_key,
+            root_chain_code: None,
+        };
+
+        share.check_public_share()?;
+
+        let points = share.points.as_deref().unwrap_or_default();
+        let base: Vec<usize> = (0..threshold).collect();
+
+        if interpolate_at(&Scalar::ZERO, points, &share.public_shares, &base)
+            != share.public_key
+        {
+            return Err(MigrationError::PublicKeyMismatch);
+        }
+
+        for j in threshold..n {
+            if interpolate_at(&points[j], points, &share.public_shares, &base)
+                != share.public_shares[j]
+            {
+                return Err(MigrationError::PublicKeyMismatch);
+            }
+        }
+
+        Ok(share)
+    }
+
+    /// Create an additively shared keyshare, all parties are required
+    /// to reconstruct the key.
+    ///
+    /// Checks that `x_i` matches the public share of `party_index`,
+    /// and that the public shares sum up to `public_key`.
+    pub fn new_additive(
+        x_i: Scalar,
+        party_index: usize,
+        public_shares: Vec<ProjectivePoint>,
+        public_key: ProjectivePoint,
+    ) -> Result<Self, MigrationError> {
+        let n = public_shares.len();
+
+        if party_index >= n {
+            return Err(MigrationError::InvalidParameters);
+        }
+
+        let share = Self {
+            x_i,
+            party_index,
+            points: None,
+            threshold: n,
+            public_shares,
+            public_key,
+            root_chain_code: None,
+        };
+
+        share.check_public_share()?;
+
+        if share.public_shares.iter().sum::<ProjectivePoint>()
+            != share.public_key
+        {
+            return Err(MigrationError::PublicKeyMismatch);
+        }
+
+        Ok(share)
+    }
+
+    fn check_public_share(&self) -> Result<(), MigrationError> {
+        if ProjectivePoint::mul_by_generator(&self.x_i)
+            != self.public_shares[self.party_index]
+        {
+            return Err(MigrationError::PublicShareMismatch);
+        }
+
+        Ok(())
+    }
+
+    /// Set the root chain code of the key. Overrides a chain code
+    /// loaded from the foreign keyshare.
+    pub fn with_root_chain_code(mut self, root_chain_code: [u8; 32]) -> Self {
+        self.root_chain_code = Some(root_chain_code);
+        self
+    }
+
+    /// Zero based index of the local party in the foreign protocol.
+    pub fn party_index(&self) -> usize {
+        self.party_index
+    }
+
+    /// Number of parties of the foreign protocol.
+    pub fn total_parties(&self) -> usize {
+        self.public_shares.len()
+    }
+
+    /// Minimal number of parties to reconstruct the key.
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Public key.
+    pub fn public_key(&self) -> &ProjectivePoint {
+        &self.public_key
+    }
+
+    /// Root chain code, if any.
+    pub fn root_chain_code(&self) -> Option<&[u8; 32]> {
+        self.root_chain_code.as_ref()
+    }
+
+    /// Convert the keyshare into an additive share of `quorum`.
+    ///
+    /// `quorum` is a list of zero based indexes of the parties of
+    /// the foreign protocol taking part in the migration. It must
+    /// contain the local party and at least `threshold` parties.
+    /// The additive shares of all members of the quorum sum up to
+    /// the secret key.
+    pub fn migration_share(
+        &self,
+        quorum: &[usize],
+    ) -> Result<MigrationShare, MigrationError> {
+        let root_chain_code = self
+            .root_chain_code
+            .ok_or(MigrationError::MissingChainCode)?;
+
+        let n = self.public_shares.len();
+
+        if quorum.len() < self.threshold
+            || !quorum.contains(&self.party_index)
+            || quorum.iter().any(|&j| j >= n)
+            || (1..quorum.len()).any(|j| quorum[..j].contains(&quorum[j]))
+        {
+            return Err(MigrationError::InvalidQuorum);
+        }
+
+        let s_i_0 = match &self.points {
+            Some(points) => {
+                self.x_i
+                    * lagrange_coeff_at(
+                        &Scalar::ZERO,
+                        self.party_index,
+                        points,
+                        quorum,
+                    )
+            }
+            None => self.x_i,
+        };
+
+        Ok(MigrationShare {
+            s_i_0,
+            public_key: self.public_key,
+            root_chain_code,
+        })
+    }
+}
+
+/// Additive share of a key to migrate, an input of
+/// [`run_with_share()`](super::run_with_share).
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct MigrationShare {
+    s_i_0: Scalar,
+    public_key: ProjectivePoint,
+    root_chain_code: [u8; 32],
+}
+
+impl MigrationShare {
+    /// Additive share of the secret key.
+    pub fn s_i_0(&self) -> &Scalar {
+        &self.s_i_0
+    }
+
+    /// Public key.
+    pub fn public_key(&self) -> &ProjectivePoint {
+        &self.public_key
+    }
+
+    /// Root chain code.
+    pub fn root_chain_code(&self) -> &[u8; 32] {
+        &self.root_chain_code
+    }
+}
+
+#[cfg(feature = "migration")]
+pub(super) mod json {
+    use k256::{ProjectivePoint, PublicKey, Scalar};
+    use serde_json::Value;
+    use zeroize::Zeroizing;
+
+    use crate::proto::decode_scalar;
+
+    use super::MigrationError;
+
+    pub fn parse(json: &str) -> Result<Value, MigrationError> {
+        serde_json::from_str(json).map_err(|_| MigrationError::InvalidJson)
+    }
+
+    pub fn field<'a>(
+        value: &'a Value,
+        name: &'static str,
+    ) -> Result<&'a Value, MigrationError> {
+        value.get(name).ok_or(MigrationError::InvalidField(name))
+    }
+
+    pub fn str_field<'a>(
+        value: &'a Value,
+        name: &'static str,
+    ) -> Result<&'a str, MigrationError> {
+        field(value, name)?
+            .as_str()
+            .ok_or(MigrationError::InvalidField(name))
+    }
+
+    pub fn u16_field(
+        value: &Value,
+        name: &'static str,
+    ) -> Result<u16, MigrationError> {
+        field(value, name)?
+            .as_u64()
+            .and_then(|v| u16::try_from(v).ok())
+            .ok_or(MigrationError::InvalidField(name))
+    }
+
+    pub fn array_field<'a>(
+        value: &'a Value,
+        name: &'static str,
+    ) -> Result<&'a [Value], MigrationError> {
+        field(value, name)?
+            .as_array()
+            .map(Vec::as_slice)
+            .ok_or(MigrationError::InvalidField(name))
+    }
+
+    /// Decode bytes encoded as a hex string, or as an array of numbers
+    /// as `serde_json` encodes byte arrays.
+    pub fn bytes(value: &Value) -> Option<Zeroizing<Vec<u8>>> {
+        let bytes = match value {
+            Value::String(hex_str) => hex::decode(hex_str).ok()?,
+            Value::Array(array) => array
+                .iter()
+                .map(|b| b.as_u64().and_then(|b| u8::try_from(b).ok()))
+                .collect::<Option<_>>()?,
+            _ => return None,
+        };
+
+        Some(Zeroizing::new(bytes))
+    }
+
+    /// Decode a big-endian scalar, possibly without leading zeros.
+    pub fn scalar(value: &Value) -> Result<Scalar, MigrationError> {
+        let bytes = bytes(value).ok_or(MigrationError::InvalidScalar)?;
+
+        if bytes.len() > 32 {
+            return Err(MigrationError::InvalidScalar);
+        }
+
+        let mut repr = Zeroizing::new([0u8; 32]);
+        repr[32 - bytes.len()..].copy_from_slice(&bytes);
+
+        decode_scalar(&repr).ok_or(MigrationError::InvalidScalar)
+    }
+
+    /// Decode a SEC1 point.
+    pub fn point(value: &Value) -> Result<ProjectivePoint, MigrationError> {
+        let bytes = bytes(value).ok_or(MigrationError::InvalidPoint)?;
+
+        PublicKey::from_sec1_bytes(&bytes)
+            .map(|pk| pk.to_projective())
+            .map_err(|_| MigrationError::InvalidPoint)
+    }
+
+    /// Decode a 32 byte chain code.
+    pub fn chain_code(value: &Value) -> Result<[u8; 32], MigrationError> {
+        bytes(value)
+            .and_then(|bytes| bytes.as_slice().try_into().ok())
+            .ok_or(MigrationError::InvalidField("chain_code"))
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use k256::elliptic_curve::Field;
+    use rand::prelude::*;
+
+    use super::*;
+
+    /// Content of a file of `testdata/migration`.
+    #[cfg(feature = "migration")]
+    pub fn fixture(name: &str) -> String {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata/migration")
+            .join(name);
+
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    /// Shamir shares of a random key at points 1..=n.
+    pub fn shamir_shares<R: RngCore>(
+        rng: &mut R,
+        threshold: usize,
+        n: usize,
+    ) -> (Scalar, Vec<Scalar>, Vec<Scalar>) {
+        let coeffs: Vec<Scalar> =
+            (0..threshold).map(|_| Scalar::random(&mut *rng)).collect();
+
+        let points: Vec<Scalar> = (1..=n as u64).map(Scalar::from).collect();
+
+        let shares = points
+            .iter()
+            .map(|x| coeffs.iter().rev().fold(Scalar::ZERO, |y, c| y * x + c))
+            .collect();
+
+        (coeffs[0], points, shares)
+    }
+
+    #[test]
+    fn shamir_quorum_shares() {
+        let mut rng = rand::thread_rng();
+        let (secret, points, shares) = shamir_shares(&mut rng, 2, 3);
+
+        let public_shares: Vec<_> = shares
+            .iter()
+            .map(ProjectivePoint::mul_by_generator)
+            .collect();
+        let public_key = ProjectivePoint::mul_by_generator(&secret);
+
+        let keyshares: Vec<_> = (0..3)
+            .map(|i| {
+                ForeignKeyshare::new_shamir(
+                    shares[i],
+                    i,
+                    points.clone(),
+                    2,
+                    public_shares.clone(),
+                    public_key,
+                )
+                .unwrap()
+                .with_root_chain_code([1; 32])
+            })
+            .collect();
+
+        let quorum = [2, 0];
+        let sum = quorum
+            .iter()
+            .map(|&i| *keyshares[i].migration_share(&quorum).unwrap().s_i_0())
+            .sum::<Scalar>();
+        assert_eq!(sum, secret);
+
+        assert!(keyshares[1].migration_share(&quorum).is_err());
+        assert!(keyshares[0].migration_share(&[0]).is_err());
+        assert!(keyshares[0].migration_share(&[0, 0]).is_err());
+
+        // a wrong secret share
+        assert!(ForeignKeyshare::new_shamir(
+            shares[1],
+            0,
+            points.clone(),
+            2,
+            public_shares.clone(),
+            public_key,
+        )
+        .is_err());
+
+        // public shares are not on a line
+        let mut bad_shares = public_shares.clone();
+        bad_shares[2] += ProjectivePoint::GENERATOR;
+        assert!(ForeignKeyshare::new_shamir(
+            shares[0], 0, points, 2, bad_shares, public_key,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn additive_shares() {
+        let mut rng = rand::thread_rng();
+        let shares: Vec<_> =
+            (0..3).map(|_| Scalar::random(&mut rng)).collect();
+        let public_shares: Vec<_> = shares
+            .iter()
+            .map(ProjectivePoint::mul_by_generator)
+            .collect();
+        let public_key = public_shares.iter().sum();
+
+        let share = ForeignKeyshare::new_additive(
+            shares[1],
+            1,
+            public_shares.clone(),
+            public_key,
+        )
+        .unwrap();
+
+        assert!(matches!(
+            share.migration_share(&[0, 1, 2]),
+            Err(MigrationError::MissingChainCode)
+        ));
+
+        let share = share.with_root_chain_code([0; 32]);
+        assert_eq!(
+            share.migration_share(&[0, 1, 2]).unwrap().s_i_0(),
+            &shares[1]
+        );
+        assert!(share.migration_share(&[0, 1]).is_err());
+
+        assert!(ForeignKeyshare::new_additive(
+            shares[1],
+            1,
+            public_shares,
+            ProjectivePoint::GENERATOR,
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,283 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Parser of GG18/GG20 keyshares in the format of ZenGo
+//! `multi-party-ecdsa` `LocalKey`, serialized with `serde_json`.
+//!
+//! The secret key is Shamir shared, party `i` (one based) holds the
+//! value of a polynomial of degree `t` at point `i`, so any `t + 1`
+//! parties may sign.
+
+use k256::{elliptic_curve::ops::MulByGenerator, ProjectivePoint, Scalar};
+use serde_json::Value;
+
+use super::foreign::{json, ForeignKeyshare, MigrationError};
+
+/// Curve name of secp256k1 points and scalars in `curv`.
+const CURVE_NAME: &str = "secp256k1";
+
+// `curv` encodes points and scalars as byte arrays tagged by a curve
+// name.
+fn curv_value<'a>(
+    value: &'a Value,
+    field: &'static str,
+) -> Result<&'a Value, MigrationError> {
+    if json::str_field(value, "curve")? != CURVE_NAME {
+        return Err(MigrationError::UnsupportedCurve);
+    }
+
+    json::field(value, field)
+}
+
+fn curv_point(value: &Value) -> Result<ProjectivePoint, MigrationError> {
+    json::point(curv_value(value, "point")?)
+}
+
+fn curv_scalar(value: &Value) -> Result<Scalar, MigrationError> {
+    json::scalar(curv_value(value, "scalar")?)
+}
+
+/// Value at `x` of the polynomial committed to by Feldman VSS
+/// `commitments`.
+fn feldman_eval(
+    commitments: &[ProjectivePoint],
+    x: &Scalar,
+) -> ProjectivePoint {
+    commitments
+        .iter()
+        .rev()
+        .fold(ProjectivePoint::IDENTITY, |y, c| y * x + c)
+}
+
+/// Parse a `LocalKey` JSON and validate it against its public
+/// shares `pk_vec`, the public key `y_sum_s` and the commitments
+/// `vss_scheme` of the sum of the VSS schemes of all parties.
+///
+/// Party `i` of the `LocalKey` has zero based index `i - 1` in the
+/// returned keyshare. The `LocalKey` has no chain code, it must be set
+/// by [`ForeignKeyshare::with_root_chain_code()`].
+pub fn from_local_key_json(
+    local_key: &str,
+) -> Result<ForeignKeyshare, MigrationError> {
+    let local_key = json::parse(local_key)?;
+
+    let i = json::u16_field(&local_key, "i")? as usize;
+    let t = json::u16_field(&local_key, "t")? as usize;
+    let n = json::u16_field(&local_key, "n")? as usize;
+
+    let keys_linear = json::field(&local_key, "keys_linear")?;
+    let x_i = curv_scalar(json::field(keys_linear, "x_i")?)?;
+    let y = curv_point(json::field(keys_linear, "y")?)?;
+
+    let public_key = curv_point(json::field(&local_key, "y_sum_s")?)?;
+    if y != public_key {
+        return Err(MigrationError::PublicKeyMismatch);
+    }
+
+    let public_shares = json::array_field(&local_key, "pk_vec")?
+        .iter()
+        .map(curv_point)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if i == 0 || i > n || public_shares.len() != n {
+        return Err(MigrationError::InvalidParameters);
+    }
+
+    let vss_scheme = json::field(&local_key, "vss_scheme")?;
+    let commitments = json::array_field(vss_scheme, "commitments")?
+        .iter()
+        .map(curv_point)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if commitments.len() != t + 1 {
+        return Err(MigrationError::InvalidParameters);
+    }
+
+    if commitments[0] != public_key {
+        return Err(MigrationError::PublicKeyMismatch);
+    }
+
+    let points: Vec<Scalar> = (1..=n as u64).map(Scalar::from).collect();
+
+    if ProjectivePoint::mul_by_generator(&x_i)
+        != feldman_eval(&commitments, &points[i - 1])
+    {
+        return Err(MigrationError::PublicShareMismatch);
+    }
+
+    for (x_j, public_share) in points.iter().zip(&public_shares) {
+        if feldman_eval(&commitments, x_j) != *public_share {
+            return Err(MigrationError::PublicKeyMismatch);
+        }
+    }
+
+    ForeignKeyshare::new_shamir(
+        x_i,
+        i - 1,
+        points,
+        t + 1,
+        public_shares,
+        public_key,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::elliptic_curve::group::GroupEncoding;
+
+    use super::super::foreign::tests::{fixture, shamir_shares};
+    use super::*;
+
+    fn point(p: &ProjectivePoint) -> Value {
+        serde_json::json!({
+            "curve": CURVE_NAME,
+            "point": hex::encode(p.to_bytes()),
+        })
+    }
+
+    // `LocalKey` of a 2-of-n key, `public_shares[0]` is the value of
+    // the polynomial at point 1.
+    fn local_key(
+        i: usize,
+        share: &Scalar,
+        public_shares: &[ProjectivePoint],
+        public_key: &ProjectivePoint,
+    ) -> String {
+        let commitments = [*public_key, public_shares[0] - public_key];
+
+        serde_json::json!({
+            "paillier_dk": {},
+            "pk_vec": public_shares.iter().map(point).collect::<Vec<_>>(),
+            "keys_linear": {
+                "y": point(public_key),
+                "x_i": {
+                    "curve": CURVE_NAME,
+                    "scalar": hex::encode(share.to_bytes()),
+                },
+            },
+            "paillier_key_vec": [],
+            "y_sum_s": point(public_key),
+            "h1_h2_n_tilde_vec": [],
+            "vss_scheme": {
+                "parameters": { "threshold": 1, "share_count": public_shares.len() },
+                "commitments": commitments.iter().map(point).collect::<Vec<_>>(),
+            },
+            "i": i,
+            "t": 1,
+            "n": public_shares.len(),
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn parse_local_key() {
+        let mut rng = rand::thread_rng();
+        let (secret, _, shares) = shamir_shares(&mut rng, 2, 3);
+
+        let public_shares: Vec<_> = shares
+            .iter()
+            .map(ProjectivePoint::mul_by_generator)
+            .collect();
+        let public_key = ProjectivePoint::mul_by_generator(&secret);
+
+        let quorum = [0, 2];
+        let sum = quorum
+            .iter()
+            .map(|&p| {
+                let json =
+                    local_key(p + 1, &shares[p], &public_shares, &public_key);
+                let share = from_local_key_json(&json)
+                    .unwrap()
+                    .with_root_chain_code([0; 32]);
+
+                assert_eq!(share.party_index(), p);
+                assert_eq!(share.threshold(), 2);
+
+                *share.migration_share(&quorum).unwrap().s_i_0()
+            })
+            .sum::<Scalar>();
+
+        assert_eq!(sum, secret);
+
+        let json = local_key(1, &shares[1], &public_shares, &public_key);
+        assert!(matches!(
+            from_local_key_json(&json),
+            Err(MigrationError::PublicShareMismatch)
+        ));
+
+        let json = local_key(1, &shares[0], &public_shares, &public_key)
+            .replace(CURVE_NAME, "ed25519");
+        assert!(matches!(
+            from_local_key_json(&json),
+            Err(MigrationError::UnsupportedCurve)
+        ));
+    }
+
+    #[test]
+    fn local_key_fixtures() {
+        const PUBLIC_KEY: &str =
+            "030f2abfb36edb2e6064e4ebb22eb503b17f8f113bd12daa456cabe1a4e18119fe";
+
+        let shares: Vec<_> = (1..=3)
+            .map(|i| {
+                let json = fixture(&format!("gg20-local-key-{i}.json"));
+                from_local_key_json(&json)
+                    .unwrap()
+                    .with_root_chain_code([0; 32])
+            })
+            .collect();
+
+        for (p, share) in shares.iter().enumerate() {
+            assert_eq!(share.party_index(), p);
+            assert_eq!(share.threshold(), 2);
+            assert_eq!(
+                hex::encode(share.public_key().to_bytes()),
+                PUBLIC_KEY
+            );
+        }
+
+        for quorum in [[0, 1], [0, 2], [1, 2]] {
+            let sum = quorum
+                .iter()
+                .map(|&p| {
+                    *shares[p].migration_share(&quorum).unwrap().s_i_0()
+                })
+                .sum::<Scalar>();
+
+            let public_key = ProjectivePoint::mul_by_generator(&sum);
+            assert_eq!(hex::encode(public_key.to_bytes()), PUBLIC_KEY);
+        }
+    }
+    #[test]
+    fn corrupted_local_key_fixture() {
+        let json: Value =
+            serde_json::from_str(&fixture("gg20-local-key-1.json")).unwrap();
+
+        // a share that is not the value of the committed polynomial
+        let mut bad = json.clone();
+        bad["keys_linear"]["x_i"]["scalar"][31] = 0.into();
+        assert!(matches!(
+            from_local_key_json(&bad.to_string()),
+            Err(MigrationError::PublicShareMismatch)
+        ));
+
+        // commitments to another polynomial with the same public key
+        let mut bad = json.clone();
+        bad["vss_scheme"]["commitments"][1] =
+            point(&ProjectivePoint::GENERATOR);
+        assert!(matches!(
+            from_local_key_json(&bad.to_string()),
+            Err(MigrationError::PublicShareMismatch)
+        ));
+
+        let mut bad = json;
+        bad["vss_scheme"]["commitments"]
+            .as_array_mut()
+            .unwrap()
+            .pop();
+        assert!(matches!(
+            from_local_key_json(&bad.to_string()),
+            Err(MigrationError::InvalidParameters)
+        ));
+    }
+}
@@ -27,7 +27,7 @@ pub use types::*;
 
 pub mod key_refresh;
 
-mod messages;
+pub(crate) mod messages;
 
 pub mod keyshare;
 
@@ -34,28 +34,20 @@ use crate::{
         get_all_but_one_session_id, get_base_ot_session_id,
         messages::*,
         utils::{
-            check_secret_recovery, get_birkhoff_coefficients,
+            block_in_place, check_secret_recovery, get_birkhoff_coefficients,
             get_lagrange_coeff,
         },
         KeygenError, Keyshare,
     },
     pairs::Pairs,
     proto::{tags::*, *},
-    setup::{QuorumChangeSetupMessage, ABORT_MESSAGE_TAG},
-    Seed,
+    setup::{
+        approval::quorum_change_setup_hash, QuorumChangeSetupMessage,
+        ABORT_MESSAGE_TAG,
+    },
+    Seed, VERSION,
 };
 
-#[cfg(feature = "multi-thread")]
-use tokio::task::block_in_place;
-
-#[cfg(not(feature = "multi-thread"))]
-fn block_in_place<F, R>(f: F) -> R
-where
-    F: FnOnce() -> R,
-{
-    f()
-}
-
 /// Executes the Quorum Change Protocol.
 ///
 /// This function orchestrates the quorum change process, allowing participants to:
@@ -96,8 +88,16 @@ where
     T: QuorumChangeSetupMessage<Keyshare, ProjectivePoint>,
     R: Relay,
 {
-    let abort_msg = create_abort_message(&setup);
-    let mut relay = FilteredMsgRelay::new(relay);
+    let ctx = SigningContext::new(
+        &QC_LABEL,
+        VERSION,
+        &quorum_change_setup_hash(&setup),
+    );
+    let mut relay = FilteredMsgRelay::new(relay)
+        .with_signing_context(ctx)
+        .with_compression(setup.message_compression())
+        .with_echo_broadcast(setup.echo_broadcast());
+    let abort_msg = create_abort_message(&setup, &ctx);
 
     let result = match run_inner(setup, seed, &mut relay).await {
         Ok(share) => Ok(share),
@@ -150,7 +150,11 @@ where
 {
     let mut rng = ChaCha20Rng::from_seed(seed);
 
-    let mut scheme = crate::proto::Scheme::new(&mut rng);
+    let mut scheme = new_encryption_scheme(&setup, &mut rng)
+        .map_err(|_| KeygenError::InvalidMessage)?;
+
+    // with long-term keys from the setup there is no key exchange
+    let static_keys = setup.encryption_keys().is_some();
 
     let expected_public_key = setup.expected_public_key();
     assert!(expected_public_key != &ProjectivePoint::IDENTITY);
@@ -221,25 +225,30 @@ where
 
     let mut old_party_ids = Pairs::new();
 
+    let mut own_r0 = None;
+
     if let Some(party_id) = my_old_party_id {
         // Broadcast our old-party-id
-        relay
-            .send(SignedMessage::build(
-                &setup.msg_id(None, QC_MSG_R0),
-                setup.message_ttl().as_secs() as _,
-                0,
-                setup.signer(),
-                |msg: &mut u8, _| {
-                    *msg = party_id;
-                },
-            ))
-            .await?;
+        let ctx = *relay.signing_context();
+        let msg = SignedMessage::build_with_context(
+            &setup.msg_id(None, QC_MSG_R0),
+            setup.message_ttl().as_secs() as _,
+            0,
+            &ctx,
+            setup.signer(),
+            |msg: &mut u8, _| {
+                *msg = party_id;
+            },
+        );
+        own_r0 = Some((my_party_index, msg.clone()));
+        relay.send_broadcast(msg).await?;
         old_party_ids.push(my_party_index, party_id);
     }
 
     Round::new(_r0, QC_MSG_R0, relay)
-        .of_signed_messages(
+        .of_echoed_signed_messages(
             &setup,
+            own_r0,
             KeygenError::AbortProtocol,
             |&party_id: &u8, index| {
                 old_party_ids.push(index, party_id);
@@ -300,17 +309,27 @@ where
         [0u8; 32]
     };
 
+    let own_enc_pub_key = if static_keys {
+        vec![]
+    } else {
+        scheme.public_key().to_vec()
+    };
+
     // Broadcast 1 from all parties to all
     let (sid_i_list, enc_pub_keys, commitment1_list, _) = broadcast_4(
         &setup,
         relay,
         QC_MSG_R1,
-        (sid_i, scheme.public_key().to_vec(), commitment1_i, ()),
+        (sid_i, own_enc_pub_key, commitment1_i, ()),
     )
     .await?;
 
     for (receiver, pub_key) in enc_pub_keys.into_iter().enumerate() {
-        if receiver != setup.participant_index() {
+        if static_keys {
+            if !pub_key.is_empty() {
+                return Err(KeygenError::InvalidMessage);
+            }
+        } else if receiver != setup.participant_index() {
             scheme
                 .receiver_public_key(receiver, &pub_key)
                 .map_err(|_| KeygenError::InvalidMessage)?;
@@ -381,7 +400,7 @@ where
             relay
                 .feed(
                     enc_msg1
-                        .encrypt(&mut scheme, receiver_index)
+                        .encrypt(&mut scheme, receiver_index, QC_MSG_P2P_1)
                         .ok_or(KeygenError::SendMessage)?,
                 )
                 .await
@@ -440,7 +459,7 @@ where
             relay
                 .feed(
                     enc_msg2
-                        .encrypt(&mut scheme, receiver_index)
+                        .encrypt(&mut scheme, receiver_index, QC_MSG_P2P_2)
                         .ok_or(KeygenError::SendMessage)?,
                 )
                 .await
@@ -471,7 +490,9 @@ where
             let commit_hash1 =
                 hash_commitment_1(sid_j, old_party_index, big_p_i_poly, r1_j);
             if commit_hash1.ct_ne(commitment1).into() {
-                return Err(KeygenError::InvalidCommitmentHash);
+                return Err(KeygenError::InvalidCommitmentHash(
+                    old_party_index,
+                ));
             }
         }
 
@@ -527,7 +548,9 @@ where
                 );
 
                 if commit_hash_2.ct_ne(commitment2).into() {
-                    return Err(KeygenError::InvalidCommitmentHash);
+                    return Err(KeygenError::InvalidCommitmentHash(
+                        from_party_index,
+                    ));
                 }
 
                 p_i_list.push(from_party_id, p_j_i);
@@ -582,7 +605,9 @@ where
             let commit_hash1 =
                 hash_commitment_1(sid_j, old_party_index, big_p_i_vec, r1_j);
             if commit_hash1.ct_ne(commitment1).into() {
-                return Err(KeygenError::InvalidCommitmentHash);
+                return Err(KeygenError::InvalidCommitmentHash(
+                    old_party_index,
+                ));
             }
         }
 
@@ -597,25 +622,30 @@ where
         let old_party_id = old_party_ids.find_pair(old_party_index);
         big_p_j_poly_list_sorted_by_old_id.push(
             *old_party_id,
-            big_p_j_poly_list.find_pair(old_party_index).clone(),
+            (
+                old_party_index,
+                big_p_j_poly_list.find_pair(old_party_index).clone(),
+            ),
         );
     }
 
     let big_p_j_poly_list = big_p_j_poly_list_sorted_by_old_id.remove_ids();
     let p_i_list = p_i_list.remove_ids();
-    for v in &big_p_j_poly_list {
+    for (_, v) in &big_p_j_poly_list {
         big_p_poly.add_mut(v); // big_f_vec += v;
     }
 
     if big_p_j_poly_list.len() != p_i_list.len() {
-        return Err(KeygenError::FailedFelmanVerify);
+        return Err(KeygenError::InvalidMessage);
     }
 
     let my_party_id = my_new_party_id.unwrap();
     let my_rank = setup.new_participant_rank(my_party_id);
 
     // check that P_j(x_i) = p_j_i * G
-    for (big_p_j, p_j_i) in big_p_j_poly_list.iter().zip(&p_i_list) {
+    for ((party_index, big_p_j), p_j_i) in
+        big_p_j_poly_list.iter().zip(&p_i_list)
+    {
         let coeffs =
             block_in_place(|| big_p_j.derivative_coeffs(my_rank as usize));
         let valid = feldman_verify(
@@ -625,7 +655,7 @@ where
             &ProjectivePoint::GENERATOR,
         );
         if !valid {
-            return Err(KeygenError::FailedFelmanVerify);
+            return Err(KeygenError::FailedFelmanVerify(*party_index));
         }
     }
 
@@ -730,6 +760,7 @@ where
         .await?;
 
     let mut base_ot_receivers: Pairs<EndemicOTReceiver> = Pairs::new();
+    let mut receiver_rngs: Pairs<ChaCha20Rng> = Pairs::new();
     for &receiver_index in setup.new_party_indices() {
         if receiver_index == my_party_index {
             continue;
@@ -755,13 +786,17 @@ where
         let receiver = EndemicOTReceiver::new(&sid, msg1, &mut rng);
         base_ot_receivers.push(receiver_id, receiver);
 
+        // Messages are processed in order of arrival, a generator for
+        // each receiver makes the output a function of the seed.
+        receiver_rngs.push(receiver_id, ChaCha20Rng::from_seed(rng.gen()));
+
         // send out P2P message. We call feed() in the loop
         // and following send_broadcast() will call .send() that
         // implies feed() + flush()
         relay
             .feed(
                 enc_ot_msg1
-                    .encrypt(&mut scheme, receiver_index)
+                    .encrypt(&mut scheme, receiver_index, QC_MSG_OT1)
                     .ok_or(KeygenError::SendMessage)?,
             )
             .await
@@ -776,6 +811,10 @@ where
             KeygenError::AbortProtocol,
             |base_ot_msg1: &EndemicOTMsg1, receiver_index, _, scheme| {
                 let receiver_id = setup.new_party_id(receiver_index).unwrap();
+                let mut rng = receiver_rngs.pop_pair_or_err(
+                    receiver_id,
+                    KeygenError::InvalidMessage,
+                )?;
 
                 let mut enc_buf = EncryptedMessage::<QCOTMsg2>::new(
                     &setup.msg_id(Some(receiver_index), QC_MSG_OT2),
@@ -826,7 +865,7 @@ where
 
                 Ok(Some(
                     enc_buf
-                        .encrypt(scheme, receiver_index)
+                        .encrypt(scheme, receiver_index, QC_MSG_OT2)
                         .ok_or(KeygenError::SendMessage)?,
                 ))
             },
@@ -889,7 +928,7 @@ where
 /// # Returns
 ///
 /// A 32-byte hash commitment
-fn hash_commitment_1(
+pub(crate) fn hash_commitment_1(
     session_id: &[u8],
     party_index: usize,
     big_f_i_vec: &GroupPolynomial<ProjectivePoint>,
@@ -1176,6 +1215,26 @@ mod tests {
         }
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn echo_broadcast() {
+        let shares = gen_keyshares(2, 3, Some(&[0, 0, 0])).await;
+        let expected_public_key = shares[0].public_key;
+
+        // new parties 2 and 3 receive the second broadcast only
+        let parties = setup_quorum_change(&shares[..2], 3, &[0, 0, 1, 1])
+            .into_iter()
+            .map(|(setup, seed)| (setup.with_echo_broadcast(true), seed))
+            .collect();
+
+        let result = sim_parties(parties, SimpleMessageRelay::new()).await;
+
+        let new_shares: Vec<_> = result.iter().flatten().collect();
+        assert_eq!(new_shares.len(), 4);
+        for share in new_shares {
+            assert_eq!(share.public_key, expected_public_key);
+        }
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn quorum_change_extend_parties() {
         let old_threshold = 2;
@@ -21,13 +21,18 @@ pub enum KeygenError {
     )]
     InvalidMessage,
 
-    /// The commitment hash provided does not match the expected value
-    #[error("Invalid commitment hash")]
-    InvalidCommitmentHash,
+    /// A party sent a message which failed verification, decryption
+    /// or decoding
+    #[error("Invalid message from party {0}")]
+    InvalidMessageFrom(usize),
 
-    /// The discrete logarithm proof provided is invalid
-    #[error("Invalid DLog proof")]
-    InvalidDLogProof,
+    /// The commitment hash provided by a party does not match the expected value
+    #[error("Invalid commitment hash of party {0}")]
+    InvalidCommitmentHash(usize),
+
+    /// The discrete logarithm proof provided by a party is invalid
+    #[error("Invalid DLog proof of party {0}")]
+    InvalidDLogProof(usize),
 
     /// The polynomial point provided is invalid
     #[error("Invalid Polynomial Point")]
@@ -45,13 +50,13 @@ pub enum KeygenError {
     #[error("Not unique x_i values")]
     NotUniqueXiValues,
 
-    /// The Big F vector does not match the expected value
-    #[error("Big F vec mismatch")]
-    BigFVecMismatch,
+    /// The Big F vector of a party does not match the expected value
+    #[error("Big F vec mismatch of party {0}")]
+    BigFVecMismatch(usize),
 
-    /// The Feldman verification failed
-    #[error("Failed feldman verify")]
-    FailedFelmanVerify,
+    /// The Feldman verification of a share sent by a party failed
+    #[error("Failed feldman verify of party {0}")]
+    FailedFelmanVerify(usize),
 
     /// The public key in the message does not match the party's public key
     #[error("Public key mismatch between the message and the party")]
@@ -76,6 +81,20 @@ pub enum KeygenError {
     /// A party has decided to abort the protocol
     #[error("Abort protocol by party {0}")]
     AbortProtocol(usize),
+
+    /// An echoed hash of a broadcast message does not match the
+    /// received message: either `sender` has sent different broadcast
+    /// messages to different parties, or `echoer` has sent a false
+    /// hash
+    #[error(
+        "Broadcast message of party {sender} does not match the echo of party {echoer}"
+    )]
+    EchoMismatch {
+        /// Sender of the broadcast message
+        sender: usize,
+        /// Party which echoed the hash of the message
+        echoer: usize,
+    },
 }
 
 impl From<MessageSendError> for KeygenError {
@@ -91,6 +110,12 @@ impl From<Error> for KeygenError {
             Error::Recv => KeygenError::MissingMessage,
             Error::Send => KeygenError::SendMessage,
             Error::InvalidMessage => KeygenError::InvalidMessage,
+            Error::EchoMismatch { sender, echoer } => {
+                KeygenError::EchoMismatch { sender, echoer }
+            }
+            Error::InvalidMessageFrom(p) => {
+                KeygenError::InvalidMessageFrom(p)
+            }
         }
     }
 }
@@ -36,6 +36,35 @@ use super::Keyshare;
 
 use super::KeygenError;
 
+/// Runs a CPU-heavy closure with [`tokio::task::block_in_place()`] on
+/// a multi-thread runtime. `block_in_place()` panics on a
+/// current-thread runtime, e.g. one with paused time for a network
+/// simulation, there the closure runs directly.
+#[cfg(feature = "multi-thread")]
+pub(crate) fn block_in_place<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    use tokio::runtime::{Handle, RuntimeFlavor};
+
+    match Handle::try_current() {
+        Ok(h) if h.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
+
+/// Runs a closure, without the `multi-thread` feature there is no
+/// runtime to yield a thread to.
+#[cfg(not(feature = "multi-thread"))]
+pub(crate) fn block_in_place<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R,
+{
+    f()
+}
+
 /// Computes the Lagrange coefficient for a given point in a polynomial.
 ///
 /// This function calculates the Lagrange coefficient for a specific point `x_i`
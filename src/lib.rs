@@ -16,13 +16,14 @@
 //! The mod common module can be replicated  from the dkls23 github [repo](https://github.com/silence-laboratories/dkls23/examples/common.rs) under examples folder
 //! ### KeyGen
 //! ```
-//! use dkls23::keygen;
+//! use sl_dkls23::keygen;
 //! use k256::elliptic_curve::group::GroupEncoding;
 //! use rand::Rng;
 //! use rand_chacha::ChaCha20Rng;
 //! use rand_core::SeedableRng;
 //! use std::sync::Arc;
 //!
+//! # #[path = "../examples/common.rs"]
 //! mod common;
 //!
 //! #[tokio::main]
@@ -59,7 +60,7 @@
 //! ```
 //! ### Key Refresh
 //! ```
-//! use dkls23::keygen::key_refresh::KeyshareForRefresh;
+//! use sl_dkls23::keygen::key_refresh::KeyshareForRefresh;
 //! use k256::elliptic_curve::group::GroupEncoding;
 //! use rand::Rng;
 //! use rand_chacha::ChaCha20Rng;
@@ -68,6 +69,7 @@
 //! use std::sync::Arc;
 //! use tokio::task::JoinSet;
 //!
+//! # #[path = "../examples/common.rs"]
 //! mod common;
 //!
 //! #[tokio::main]
@@ -88,7 +90,7 @@
 //!         .collect::<Vec<_>>()
 //!     {
 //!         // run the keyrefresh protocol for each node
-//!         parties.spawn(dkls23::keygen::key_refresh::run(
+//!         parties.spawn(sl_dkls23::keygen::key_refresh::run(
 //!             setup,
 //!             rng.gen(),
 //!             coord.connect(),
@@ -134,9 +136,10 @@
 //!
 //! use k256::ecdsa::{RecoveryId, VerifyingKey};
 //!
-//! use dkls23::sign;
+//! use sl_dkls23::sign;
 //! use sl_mpc_mate::coord::SimpleMessageRelay;
 //!
+//! # #[path = "../examples/common.rs"]
 //! mod common;
 //!
 //! #[tokio::main]
@@ -240,8 +243,18 @@ pub mod key_export;
 /// Imports a singleton external key and secret shares it among parties to use dkls23 related mpc protocols.
 pub mod key_import;
 
+/// Verifiable backup of key shares to an offline recovery key.
+pub mod key_backup;
+
+/// Verification of recorded protocol transcripts.
+pub mod transcript;
+
 pub(crate) mod pairs;
 
+/// Helpers to test protocols against faulty relays and parties.
+#[cfg(any(test, feature = "test-support"))]
+pub mod testing;
+
 /// Version of domain labels
 pub const VERSION: u16 = 1;
 
@@ -59,6 +59,19 @@ impl<T, I: Ord> Pairs<T, I> {
             .ok_or(err)
     }
 
+    /// Find an item by ID and return a mutable reference to it.
+    pub fn find_pair_mut_or_err<E>(
+        &mut self,
+        party_id: I,
+        err: E,
+    ) -> Result<&mut T, E> {
+        self.0
+            .iter_mut()
+            .find(|(p, _)| *p == party_id)
+            .map(|(_, v)| v)
+            .ok_or(err)
+    }
+
     /// Find an item by ID, panics if item not found.
     pub fn find_pair(&self, party_id: I) -> &T {
         self.find_pair_or_err(party_id, ())
@@ -7,6 +7,8 @@ use k256::{
     elliptic_curve::{group::GroupEncoding, PrimeField},
     AffinePoint, NonZeroScalar, ProjectivePoint, Scalar,
 };
+use rand_core::CryptoRngCore;
+use signature::SignatureEncoding;
 use x25519_dalek::PublicKey;
 
 use sl_mpc_mate::{math::GroupPolynomial, message::*, ByteArray};
@@ -14,15 +16,25 @@ use sl_oblivious::zkproofs::DLogProof;
 
 use crate::setup::{ProtocolParticipant, ABORT_MESSAGE_TAG};
 
+mod compress;
 mod encrypted;
+mod hpke;
 mod scheme;
 mod signed;
 
 /// tags
 pub mod tags;
 
+pub use compress::{
+    compress_message, decompress_message, COMPRESSED_FLAG,
+    MAX_DECOMPRESSED_SIZE,
+};
 pub use encrypted::{EncryptedMessage, EncryptionScheme, Scheme};
-pub use signed::SignedMessage;
+pub use hpke::Hpke;
+pub use scheme::{
+    AeadX25519, PublicKeyError, SchemeAead, SchemeId, SCHEME_ID_FLAGS_MASK,
+};
+pub use signed::{SignedMessage, SigningContext};
 pub use tags::{FilteredMsgRelay, Round};
 
 /// External representation of a point on a curve
@@ -80,8 +92,18 @@ pub fn decode_nonzero(bytes: &ScalarBytes) -> Option<NonZeroScalar> {
     NonZeroScalar::new(decode_scalar(bytes)?).into()
 }
 
-/// Create an Abort Message.
-pub fn create_abort_message<P>(setup: &P) -> Vec<u8>
+// An abort message has no payload. Without a signature it would
+// consist of a message header only, which a relay takes for an ASK,
+// so such a message has a trailer of one zero byte.
+fn abort_trailer<P: ProtocolParticipant>() -> usize {
+    usize::from(
+        mem::size_of::<<P::MessageSignature as SignatureEncoding>::Repr>()
+            == 0,
+    )
+}
+
+/// Create an Abort Message signed in context `ctx`.
+pub fn create_abort_message<P>(setup: &P, ctx: &SigningContext) -> Vec<u8>
 where
     P: ProtocolParticipant,
 {
@@ -89,20 +111,98 @@ where
         &setup.msg_id(None, ABORT_MESSAGE_TAG),
         setup.message_ttl().as_secs() as _,
         0,
-        0,
+        abort_trailer::<P>(),
     )
-    .sign(setup.signer())
+    .sign_with_context(ctx, setup.signer())
+}
+
+/// Create an encryption scheme of P2P messages for a protocol execution.
+///
+/// With long-term encryption keys in the setup, the scheme is bound to
+/// the instance ID and knows public keys of all other participants.
+/// Otherwise it has a fresh key pair to send in the first round.
+pub fn new_encryption_scheme<P: ProtocolParticipant>(
+    setup: &P,
+    rng: &mut impl CryptoRngCore,
+) -> Result<Box<dyn EncryptionScheme + Sync>, PublicKeyError> {
+    let Some(keys) = setup.encryption_keys() else {
+        return Ok(setup
+            .encryption_scheme()
+            .new_scheme(setup.instance_id(), rng));
+    };
+
+    let mut scheme = setup
+        .encryption_scheme()
+        .from_secret(setup.instance_id(), keys.secret().clone());
+
+    for p in setup.all_other_parties() {
+        let pk = keys.public_keys().get(p).ok_or(PublicKeyError)?;
+        scheme.receiver_public_key(p, pk.as_bytes())?;
+    }
+
+    Ok(scheme)
 }
 
-/// Returns passed error if msg is a vaild abort message.
+/// Returns passed error if msg is a vaild abort message signed in
+/// context `ctx`.
 pub fn check_abort<P: ProtocolParticipant, E>(
     setup: &P,
+    ctx: &SigningContext,
     msg: &[u8],
     party_id: usize,
     err: impl FnOnce(usize) -> E,
 ) -> Result<(), E> {
-    SignedMessage::<(), _>::verify(msg, setup.verifier(party_id))
-        .map_or(Ok(()), |_| Err(err(party_id)))
+    SignedMessage::<(), _>::verify_with_context(
+        msg,
+        abort_trailer::<P>(),
+        ctx,
+        setup.verifier(party_id),
+    )
+    .map_or(Ok(()), |_| Err(err(party_id)))
+}
+
+/// Size of messages of one kind sent by a party during a protocol
+/// execution.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MessageSize {
+    /// Tag of the message
+    pub tag: MessageTag,
+
+    /// Whether the message is broadcast to all other parties
+    pub broadcast: bool,
+
+    /// Size of the message in bytes, including the header
+    pub size: usize,
+
+    /// Number of messages sent by a party
+    pub count: usize,
+}
+
+impl MessageSize {
+    /// Size of a broadcast message, sent once.
+    pub fn new_broadcast(tag: MessageTag, size: usize) -> Self {
+        Self {
+            tag,
+            broadcast: true,
+            size,
+            count: 1,
+        }
+    }
+
+    /// Size of a P2P message, sent to each of `n - 1` other parties.
+    pub fn new_p2p(tag: MessageTag, size: usize, n: usize) -> Self {
+        Self {
+            tag,
+            broadcast: false,
+            size,
+            count: n.saturating_sub(1),
+        }
+    }
+
+    /// Total number of bytes sent by a party.
+    pub fn total(&self) -> usize {
+        self.size * self.count
+    }
 }
 
 /// A type with some external represention.
@@ -0,0 +1,178 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Optional compression of messages.
+//!
+//! A compressed broadcast message keeps its header, sets
+//! [`COMPRESSED_FLAG`] in the flags field of the header and replaces
+//! the payload by its compressed form. Compression is applied after a
+//! message is signed. A receiver restores the original message,
+//! including the original flags, before verification of the
+//! signature, so signatures do not depend on compression.
+//!
+//! Encrypted P2P messages are never compressed: the length of a
+//! compressed plaintext would leak information about secret data.
+//!
+//! Payload is compressed by raw DEFLATE (RFC 1951). Messages are
+//! compressed only when it makes them shorter, and messages larger
+//! than [`MAX_DECOMPRESSED_SIZE`] are never compressed.
+
+use std::io::{Read, Write};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder, Compression};
+
+use sl_mpc_mate::message::{MsgHdr, MESSAGE_HEADER_SIZE, MESSAGE_ID_SIZE};
+
+use crate::proto::SCHEME_ID_FLAGS_MASK;
+
+/// Flag of the message header marking a compressed payload.
+pub const COMPRESSED_FLAG: u16 = SCHEME_ID_FLAGS_MASK + 1;
+
+/// Upper limit of the size of a decompressed message.
+pub const MAX_DECOMPRESSED_SIZE: usize = 1 << 20;
+
+const FLAGS_OFFSET: usize = MESSAGE_ID_SIZE + 2;
+
+pub(crate) fn set_flags(msg: &mut [u8], flags: u16) {
+    msg[FLAGS_OFFSET..MESSAGE_HEADER_SIZE]
+        .copy_from_slice(&flags.to_le_bytes());
+}
+
+/// Appends the compressed form of `data` to `out`.
+pub(crate) fn deflate(data: &[u8], out: Vec<u8>) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(out, Compression::default());
+
+    // writing into a vector never fails
+    encoder.write_all(data).expect("write to Vec");
+    encoder.finish().expect("write to Vec")
+}
+
+/// Appends decompressed `data` to `out`. Returns `None` if the data is
+/// malformed or decompresses to more than `limit` bytes.
+pub(crate) fn inflate(
+    data: &[u8],
+    limit: usize,
+    out: &mut Vec<u8>,
+) -> Option<()> {
+    let start = out.len();
+
+    DeflateDecoder::new(data)
+        .take(limit as u64 + 1)
+        .read_to_end(out)
+        .ok()?;
+
+    (out.len() - start <= limit).then_some(())
+}
+
+/// Compresses the payload of a message if it makes the message
+/// shorter, otherwise returns the message unchanged. A message larger
+/// than [`MAX_DECOMPRESSED_SIZE`] is returned unchanged, a receiver
+/// would reject it in compressed form.
+pub fn compress_message(msg: Vec<u8>) -> Vec<u8> {
+    if msg.len() > MAX_DECOMPRESSED_SIZE {
+        return msg;
+    }
+
+    let flags = match <&MsgHdr>::try_from(msg.as_slice()) {
+        Ok(hdr) if hdr.flags() & COMPRESSED_FLAG == 0 => hdr.flags(),
+        _ => return msg,
+    };
+
+    let mut out = Vec::with_capacity(msg.len());
+    out.extend_from_slice(&msg[..MESSAGE_HEADER_SIZE]);
+    let mut out = deflate(&msg[MESSAGE_HEADER_SIZE..], out);
+
+    if out.len() >= msg.len() {
+        return msg;
+    }
+
+    set_flags(&mut out, flags | COMPRESSED_FLAG);
+
+    out
+}
+
+/// Restores the original form of a compressed message. A message
+/// without [`COMPRESSED_FLAG`] is returned unchanged.
+///
+/// Returns `None` if the payload is malformed or the decompressed
+/// message exceeds [`MAX_DECOMPRESSED_SIZE`].
+pub fn decompress_message(msg: Vec<u8>) -> Option<Vec<u8>> {
+    let flags = <&MsgHdr>::try_from(msg.as_slice()).ok()?.flags();
+
+    if flags & COMPRESSED_FLAG == 0 {
+        return Some(msg);
+    }
+
+    let mut out = Vec::with_capacity(msg.len() * 2);
+    out.extend_from_slice(&msg[..MESSAGE_HEADER_SIZE]);
+    set_flags(&mut out, flags & !COMPRESSED_FLAG);
+
+    inflate(
+        &msg[MESSAGE_HEADER_SIZE..],
+        MAX_DECOMPRESSED_SIZE - MESSAGE_HEADER_SIZE,
+        &mut out,
+    )?;
+
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use sl_mpc_mate::message::{allocate_message, MsgId};
+
+    use super::*;
+
+    fn message(flags: u16, payload: &[u8]) -> Vec<u8> {
+        allocate_message(&MsgId::ZERO_ID, 10, flags, payload)
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut payload = vec![0u8; 1000];
+        payload[10] = 1;
+        payload[500..533].fill(0xaa);
+
+        let msg = message(0, &payload);
+        let compressed = compress_message(msg.clone());
+
+        assert!(compressed.len() < msg.len());
+        assert_eq!(
+            <&MsgHdr>::try_from(compressed.as_slice()).unwrap().flags(),
+            COMPRESSED_FLAG
+        );
+        assert_eq!(decompress_message(compressed), Some(msg));
+    }
+
+    #[test]
+    fn skip_incompressible() {
+        let payload: Vec<u8> =
+            (0..200).map(|i| (i % 255 + 1) as u8).collect();
+        let msg = message(0, &payload);
+
+        assert_eq!(compress_message(msg.clone()), msg);
+        assert_eq!(decompress_message(msg.clone()), Some(msg));
+    }
+
+    #[test]
+    fn skip_oversized() {
+        let msg = message(0, &vec![0; MAX_DECOMPRESSED_SIZE]);
+        assert_eq!(compress_message(msg.clone()), msg);
+
+        let msg =
+            message(0, &vec![0; MAX_DECOMPRESSED_SIZE - MESSAGE_HEADER_SIZE]);
+        let compressed = compress_message(msg.clone());
+        assert!(compressed.len() < msg.len());
+        assert_eq!(decompress_message(compressed), Some(msg));
+    }
+
+    #[test]
+    fn reject_malformed() {
+        assert_eq!(
+            decompress_message(message(COMPRESSED_FLAG, &[0xff, 0xff])),
+            None
+        );
+
+        let bomb = deflate(&vec![0; MAX_DECOMPRESSED_SIZE], vec![]);
+        assert_eq!(decompress_message(message(COMPRESSED_FLAG, &bomb)), None);
+    }
+}
@@ -14,6 +14,7 @@ use chacha20poly1305::ChaCha20Poly1305;
 use sl_mpc_mate::message::*;
 
 pub use crate::proto::scheme::EncryptionScheme;
+use crate::proto::scheme::{SchemeId, SCHEME_ID_FLAGS_MASK};
 
 /// Default encryption scheme using X25519 key exchange and ChaCha20Poly1305 for encryption.
 pub type Scheme = crate::proto::scheme::AeadX25519<ChaCha20Poly1305>;
@@ -28,7 +29,8 @@ pub type Scheme = crate::proto::scheme::AeadX25519<ChaCha20Poly1305>;
 /// ```
 ///
 /// Where:
-/// - `msg-hdr`: Message header containing ID, TTL, and flags
+/// - `msg-hdr`: Message header containing ID, TTL, and flags. The
+///   low bits of flags carry the [`SchemeId`] of the encryption scheme
 /// - `additional-data`: Optional unencrypted data
 /// - `payload`: The encrypted external representation of type T
 /// - `trailer`: Optional encrypted variable-sized data
@@ -124,7 +126,8 @@ impl<T: AnyBitPattern + NoUninit> EncryptedMessage<T> {
     /// * `buffer` - Existing buffer to use
     /// * `id` - Message identifier
     /// * `ttl` - Time-to-live value
-    /// * `flags` - Message flags
+    /// * `flags` - Message flags, bits of [`SCHEME_ID_FLAGS_MASK`] are
+    ///   replaced by the ID of the scheme
     /// * `additional_data` - Size of additional data in bytes
     /// * `trailer` - Size of trailer data in bytes
     /// * `scheme` - The encryption scheme to use
@@ -142,6 +145,9 @@ impl<T: AnyBitPattern + NoUninit> EncryptedMessage<T> {
     ) -> Self {
         buffer.resize(Self::size(additional_data, trailer, scheme), 0);
 
+        let flags =
+            (flags & !SCHEME_ID_FLAGS_MASK) | scheme.scheme_id().flags();
+
         if let Some(hdr) = buffer.first_chunk_mut::<MESSAGE_HEADER_SIZE>() {
             MsgHdr::encode(hdr, id, ttl, flags);
         }
@@ -153,6 +159,15 @@ impl<T: AnyBitPattern + NoUninit> EncryptedMessage<T> {
         }
     }
 
+    /// Returns the ID of the encryption scheme from the header of a
+    /// message, or `None` if the message is too short or the ID is
+    /// unknown.
+    pub fn scheme_id(buffer: &[u8]) -> Option<SchemeId> {
+        let hdr: &MsgHdr = buffer.try_into().ok()?;
+
+        SchemeId::from_flags(hdr.flags())
+    }
+
     /// Returns mutable references to the message payload, trailer, and additional data.
     ///
     /// # Arguments
@@ -203,6 +218,7 @@ impl<T: AnyBitPattern + NoUninit> EncryptedMessage<T> {
     /// # Arguments
     /// * `scheme` - The encryption scheme to use
     /// * `receiver` - The ID of the intended receiver
+    /// * `tag` - The tag of the message
     ///
     /// # Returns
     /// The encrypted message as a byte vector, or `None` if encryption failed
@@ -210,17 +226,19 @@ impl<T: AnyBitPattern + NoUninit> EncryptedMessage<T> {
         self,
         scheme: &mut dyn EncryptionScheme,
         receiver: usize,
+        tag: MessageTag,
     ) -> Option<Vec<u8>> {
         let mut buffer = self.buffer;
 
-        let last = buffer.len() - scheme.overhead();
-        let (msg, tail) = buffer.split_at_mut(last);
+        let start = MESSAGE_HEADER_SIZE + self.additional_data;
+        let overhead = scheme.overhead();
+        let last = buffer.len() - overhead;
 
-        let (associated_data, plaintext) =
-            msg.split_at_mut(MESSAGE_HEADER_SIZE + self.additional_data);
+        let (msg, tail) = buffer.split_at_mut(last);
+        let (associated_data, plaintext) = msg.split_at_mut(start);
 
         scheme
-            .encrypt(associated_data, plaintext, tail, receiver)
+            .encrypt(associated_data, plaintext, tail, receiver, tag)
             .ok()?;
 
         Some(buffer)
@@ -234,18 +252,23 @@ impl<T: AnyBitPattern + NoUninit> EncryptedMessage<T> {
     /// * `trailer` - Size of trailer data in bytes
     /// * `scheme` - The encryption scheme to use
     /// * `sender` - The ID of the message sender
+    /// * `tag` - The tag of the message
     ///
     /// # Returns
     /// A tuple containing references to the decrypted payload, trailer, and additional data,
-    /// or `None` if decryption failed
+    /// or `None` if decryption failed, the message was encrypted by
+    /// another scheme or was already decrypted once
     pub fn decrypt_with_ad<'msg>(
         buffer: &'msg mut [u8],
         additional_data: usize,
         trailer: usize,
-        scheme: &dyn EncryptionScheme,
+        scheme: &mut dyn EncryptionScheme,
         sender: usize,
+        tag: MessageTag,
     ) -> Option<(&'msg T, &'msg [u8], &'msg [u8])> {
-        if buffer.len() != Self::size(additional_data, trailer, scheme) {
+        if buffer.len() != Self::size(additional_data, trailer, scheme)
+            || Self::scheme_id(buffer) != Some(scheme.scheme_id())
+        {
             return None;
         }
 
@@ -256,7 +279,7 @@ impl<T: AnyBitPattern + NoUninit> EncryptedMessage<T> {
             body.split_at_mut(body.len() - scheme.overhead());
 
         scheme
-            .decrypt(associated_data, ciphertext, tail, sender)
+            .decrypt(associated_data, ciphertext, tail, sender, tag)
             .ok()?;
 
         let (msg, trailer) = ciphertext.split_at_mut(Self::T_SIZE);
@@ -275,6 +298,7 @@ impl<T: AnyBitPattern + NoUninit> EncryptedMessage<T> {
     /// * `trailer` - Size of trailer data in bytes
     /// * `scheme` - The encryption scheme to use
     /// * `sender` - The ID of the message sender
+    /// * `tag` - The tag of the message
     ///
     /// # Returns
     /// A tuple containing references to the decrypted payload and trailer,
@@ -282,10 +306,11 @@ impl<T: AnyBitPattern + NoUninit> EncryptedMessage<T> {
     pub fn decrypt<'msg>(
         buffer: &'msg mut [u8],
         trailer: usize,
-        scheme: &dyn EncryptionScheme,
+        scheme: &mut dyn EncryptionScheme,
         sender: usize,
+        tag: MessageTag,
     ) -> Option<(&'msg T, &'msg [u8])> {
-        Self::decrypt_with_ad(buffer, 0, trailer, scheme, sender)
+        Self::decrypt_with_ad(buffer, 0, trailer, scheme, sender, tag)
             .map(|(msg, trailer, _)| (msg, trailer))
     }
 }
@@ -0,0 +1,579 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! RFC 9180 Hybrid Public Key Encryption of P2P messages.
+//!
+//! Each message is sealed in the single-shot auth mode with
+//! DHKEM(X25519, HKDF-SHA256) and HKDF-SHA256. The auth mode binds
+//! the sender's static key, so as with [`AeadX25519`](super::AeadX25519)
+//! only the party owning the key sent in the first round of the
+//! protocol can produce a valid message.
+//!
+//! The tail of an encrypted message is the encapsulated key followed
+//! by the AEAD tag. The nonce is the `base_nonce` of a fresh HPKE
+//! context and is not transmitted. The HPKE `info` parameter binds
+//! each message to the protocol instance and the message tag. A
+//! message with an already seen encapsulated key is rejected as
+//! replayed. A message is bound to its tag, so only encapsulated keys
+//! of messages with the last received tag are kept.
+
+use std::collections::HashSet;
+
+use aead::{generic_array::typenum::Unsigned, Key, Nonce, Tag};
+use hkdf::{Hkdf, HkdfExtract};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use rand_core::CryptoRngCore;
+use sha2::{digest::Output, Sha256};
+use sl_mpc_mate::message::{InstanceId, MessageTag, MsgId};
+use x25519_dalek::{PublicKey, ReusableSecret, SharedSecret};
+use zeroize::Zeroizing;
+
+use crate::{
+    pairs::Pairs,
+    proto::scheme::{
+        instance_label, EncryptionError, EncryptionScheme, PublicKeyError,
+        SchemeAead, SchemeId,
+    },
+};
+
+/// Encapsulated keys of messages from a party with the last received
+/// tag.
+type Seen = (Option<MessageTag>, HashSet<[u8; ENC_SIZE]>);
+
+/// HPKE mode_auth
+const MODE_AUTH: u8 = 0x02;
+
+/// DHKEM(X25519, HKDF-SHA256)
+const KEM_ID: u16 = 0x0020;
+
+/// HKDF-SHA256
+const KDF_ID: u16 = 0x0001;
+
+/// Size of an encapsulated key, `Nenc`
+const ENC_SIZE: usize = 32;
+
+/// Prefix of the HPKE `info` parameter of all messages, followed by
+/// the label of the protocol instance and the message tag.
+const INFO: &[u8] = b"dkls23-p2p-message";
+
+fn labeled_extract(
+    suite_id: &[u8],
+    salt: &[u8],
+    label: &[u8],
+    ikm: &[&[u8]],
+) -> (Output<Sha256>, Hkdf<Sha256>) {
+    let mut extract = HkdfExtract::<Sha256>::new(Some(salt));
+
+    extract.input_ikm(b"HPKE-v1");
+    extract.input_ikm(suite_id);
+    extract.input_ikm(label);
+    for ikm in ikm {
+        extract.input_ikm(ikm);
+    }
+
+    extract.finalize()
+}
+
+fn labeled_expand(
+    prk: &Hkdf<Sha256>,
+    suite_id: &[u8],
+    label: &[u8],
+    info: &[&[u8]],
+    okm: &mut [u8],
+) -> Result<(), EncryptionError> {
+    let len = (okm.len() as u16).to_be_bytes();
+
+    let mut parts: Vec<&[u8]> = vec![&len, b"HPKE-v1", suite_id, label];
+    parts.extend_from_slice(info);
+
+    prk.expand_multi_info(&parts, okm)
+        .map_err(|_| EncryptionError)
+}
+
+fn kem_suite_id() -> [u8; 5] {
+    let [a, b] = KEM_ID.to_be_bytes();
+    [b'K', b'E', b'M', a, b]
+}
+
+fn hpke_suite_id<S: SchemeAead>() -> [u8; 10] {
+    let mut suite_id = *b"HPKE\0\0\0\0\0\0";
+
+    suite_id[4..6].copy_from_slice(&KEM_ID.to_be_bytes());
+    suite_id[6..8].copy_from_slice(&KDF_ID.to_be_bytes());
+    suite_id[8..].copy_from_slice(&S::HPKE_AEAD_ID.to_be_bytes());
+
+    suite_id
+}
+
+fn dh_bytes(shared: SharedSecret) -> Result<SharedSecret, EncryptionError> {
+    if shared.was_contributory() {
+        Ok(shared)
+    } else {
+        Err(EncryptionError)
+    }
+}
+
+/// `ExtractAndExpand()` of DHKEM. In auth mode `dh` is
+/// `DH(skE, pkR) || DH(skS, pkR)` and the KEM context is
+/// `enc || pkR || pkS`.
+fn kem_shared_secret(
+    dh: &[&SharedSecret],
+    kem_context: &[&[u8]],
+) -> Result<Zeroizing<[u8; 32]>, EncryptionError> {
+    let suite_id = kem_suite_id();
+
+    let dh: Vec<&[u8]> =
+        dh.iter().map(|dh| dh.as_bytes().as_slice()).collect();
+    let (_, eae_prk) = labeled_extract(&suite_id, &[], b"eae_prk", &dh);
+
+    let mut shared_secret = Zeroizing::new([0u8; 32]);
+    labeled_expand(
+        &eae_prk,
+        &suite_id,
+        b"shared_secret",
+        kem_context,
+        shared_secret.as_mut(),
+    )?;
+
+    Ok(shared_secret)
+}
+
+/// `KeySchedule()` with empty PSK, returns the AEAD key and the base
+/// nonce.
+fn key_schedule<S: SchemeAead>(
+    mode: u8,
+    shared_secret: &[u8],
+    info: &[&[u8]],
+) -> Result<(Zeroizing<Key<S>>, Nonce<S>), EncryptionError> {
+    let suite_id = hpke_suite_id::<S>();
+
+    let (psk_id_hash, _) =
+        labeled_extract(&suite_id, &[], b"psk_id_hash", &[]);
+    let (info_hash, _) = labeled_extract(&suite_id, &[], b"info_hash", info);
+
+    // key_schedule_context = mode || psk_id_hash || info_hash
+    let mut context = [0u8; 65];
+    context[0] = mode;
+    context[1..33].copy_from_slice(&psk_id_hash);
+    context[33..].copy_from_slice(&info_hash);
+
+    let (_, secret) =
+        labeled_extract(&suite_id, shared_secret, b"secret", &[]);
+
+    let mut key = Zeroizing::new(Key::<S>::default());
+    labeled_expand(&secret, &suite_id, b"key", &[&context], &mut key)?;
+
+    let mut nonce = Nonce::<S>::default();
+    labeled_expand(
+        &secret,
+        &suite_id,
+        b"base_nonce",
+        &[&context],
+        &mut nonce,
+    )?;
+
+    Ok((key, nonce))
+}
+
+/// Implementation of [`EncryptionScheme`] by RFC 9180 HPKE in auth
+/// mode, generic over the AEAD algorithm.
+pub struct Hpke<S> {
+    secret: ReusableSecret,
+    public_key: PublicKey,
+    rng: ChaCha20Rng,
+    label: MsgId,
+    pk: Pairs<(PublicKey, Seen), usize>,
+    marker: std::marker::PhantomData<S>,
+}
+
+impl<S> Hpke<S> {
+    /// Generate a new static key pair with the supplied RNG.
+    /// Messages are bound to the protocol instance.
+    pub fn new(instance: &InstanceId, rng: &mut impl CryptoRngCore) -> Self {
+        let secret = ReusableSecret::random_from_rng(&mut *rng);
+        let mut seed = Zeroizing::new([0u8; 32]);
+        rng.fill_bytes(seed.as_mut());
+
+        Self::with_rng(instance, secret, ChaCha20Rng::from_seed(*seed))
+    }
+
+    /// Create a new [`Hpke`] from a provided `ReusableSecret`.
+    /// Ephemeral keys of encrypted messages are generated by an RNG
+    /// seeded from the OS entropy source.
+    pub fn from_secret(
+        instance: &InstanceId,
+        secret: ReusableSecret,
+    ) -> Self {
+        Self::with_rng(instance, secret, ChaCha20Rng::from_entropy())
+    }
+
+    fn with_rng(
+        instance: &InstanceId,
+        secret: ReusableSecret,
+        rng: ChaCha20Rng,
+    ) -> Self {
+        Self {
+            public_key: PublicKey::from(&secret),
+            secret,
+            rng,
+            label: instance_label(instance),
+            pk: Pairs::new(),
+            marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<S: SchemeAead> EncryptionScheme for Hpke<S> {
+    fn scheme_id(&self) -> SchemeId {
+        S::HPKE_SCHEME_ID
+    }
+
+    fn overhead(&self) -> usize {
+        ENC_SIZE + S::TagSize::USIZE
+    }
+
+    fn encrypt(
+        &mut self,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+        tail: &mut [u8],
+        receive: usize,
+        tag: MessageTag,
+    ) -> Result<(), EncryptionError> {
+        if tail.len() != self.overhead() {
+            return Err(EncryptionError);
+        }
+
+        let (pk_r, _) = self.pk.find_pair_or_err(receive, EncryptionError)?;
+        let pk_r = *pk_r;
+
+        let sk_e = ReusableSecret::random_from_rng(&mut self.rng);
+        let enc = PublicKey::from(&sk_e);
+
+        let shared_secret = kem_shared_secret(
+            &[
+                &dh_bytes(sk_e.diffie_hellman(&pk_r))?,
+                &dh_bytes(self.secret.diffie_hellman(&pk_r))?,
+            ],
+            &[enc.as_bytes(), pk_r.as_bytes(), self.public_key.as_bytes()],
+        )?;
+
+        let (key, nonce) = key_schedule::<S>(
+            MODE_AUTH,
+            shared_secret.as_ref(),
+            &[INFO, &self.label, &tag.to_bytes()],
+        )?;
+
+        let auth_tag = S::new(&key)
+            .encrypt_in_place_detached(&nonce, associated_data, buffer)
+            .map_err(|_| EncryptionError)?;
+
+        tail[..ENC_SIZE].copy_from_slice(enc.as_bytes());
+        tail[ENC_SIZE..].copy_from_slice(&auth_tag);
+
+        Ok(())
+    }
+
+    fn decrypt(
+        &mut self,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+        tail: &[u8],
+        sender: usize,
+        tag: MessageTag,
+    ) -> Result<(), EncryptionError> {
+        if tail.len() != self.overhead() {
+            return Err(EncryptionError);
+        }
+
+        let (pk_s, seen) =
+            self.pk.find_pair_mut_or_err(sender, EncryptionError)?;
+
+        let (enc, auth_tag) = tail.split_at(ENC_SIZE);
+        let enc_bytes =
+            <[u8; ENC_SIZE]>::try_from(enc).map_err(|_| EncryptionError)?;
+
+        if seen.0 == Some(tag) && seen.1.contains(&enc_bytes) {
+            return Err(EncryptionError);
+        }
+
+        let pk_e = PublicKey::from(enc_bytes);
+
+        let shared_secret = kem_shared_secret(
+            &[
+                &dh_bytes(self.secret.diffie_hellman(&pk_e))?,
+                &dh_bytes(self.secret.diffie_hellman(pk_s))?,
+            ],
+            &[enc, self.public_key.as_bytes(), pk_s.as_bytes()],
+        )?;
+
+        let (key, nonce) = key_schedule::<S>(
+            MODE_AUTH,
+            shared_secret.as_ref(),
+            &[INFO, &self.label, &tag.to_bytes()],
+        )?;
+
+        S::new(&key)
+            .decrypt_in_place_detached(
+                &nonce,
+                associated_data,
+                buffer,
+                Tag::<S>::from_slice(auth_tag),
+            )
+            .map_err(|_| EncryptionError)?;
+
+        if seen.0 != Some(tag) {
+            *seen = (Some(tag), HashSet::new());
+        }
+        seen.1.insert(enc_bytes);
+
+        Ok(())
+    }
+
+    fn public_key(&self) -> &[u8] {
+        self.public_key.as_bytes()
+    }
+
+    fn receiver_public_key(
+        &mut self,
+        receiver_index: usize,
+        pk: &[u8],
+    ) -> Result<(), PublicKeyError> {
+        let pk: [u8; 32] = pk.try_into().map_err(|_| PublicKeyError)?;
+        let pk = PublicKey::from(pk);
+
+        if !self.secret.diffie_hellman(&pk).was_contributory() {
+            return Err(PublicKeyError);
+        }
+
+        self.pk.push(receiver_index, (pk, Seen::default()));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use aead::{AeadInPlace, KeyInit};
+    use aes_gcm::{Aes128Gcm, Aes256Gcm};
+    use chacha20poly1305::ChaCha20Poly1305;
+    use x25519_dalek::StaticSecret;
+
+    use crate::proto::EncryptedMessage;
+
+    use super::*;
+
+    // Only HPKE_AEAD_ID is used by the key schedule, the RFC 9180
+    // test vectors of DHKEM(X25519, HKDF-SHA256) are for AES-128-GCM.
+    impl SchemeAead for Aes128Gcm {
+        const X25519_SCHEME_ID: SchemeId = SchemeId::X25519Aes256Gcm;
+        const HPKE_SCHEME_ID: SchemeId = SchemeId::HpkeAes256Gcm;
+        const HPKE_AEAD_ID: u16 = 0x0001;
+    }
+
+    fn secret(sk: &str) -> StaticSecret {
+        StaticSecret::from(
+            <[u8; 32]>::try_from(hex::decode(sk).unwrap()).unwrap(),
+        )
+    }
+
+    const RFC_INFO: &[u8] = b"Ode on a Grecian Urn";
+
+    /// Seals the first message of the RFC 9180 test vectors, returns
+    /// the KEM shared secret, the AEAD key and nonce, and the
+    /// ciphertext.
+    fn seal_vector(
+        mode: u8,
+        sk_e: &str,
+        sk_r: &str,
+        sk_s: Option<&str>,
+    ) -> (String, String, String, String) {
+        let sk_e = secret(sk_e);
+        let pk_e = PublicKey::from(&sk_e);
+        let pk_r = PublicKey::from(&secret(sk_r));
+
+        let mut dh = vec![sk_e.diffie_hellman(&pk_r)];
+        let mut kem_context = vec![*pk_e.as_bytes(), *pk_r.as_bytes()];
+        if let Some(sk_s) = sk_s {
+            let sk_s = secret(sk_s);
+            dh.push(sk_s.diffie_hellman(&pk_r));
+            kem_context.push(*PublicKey::from(&sk_s).as_bytes());
+        }
+
+        let dh: Vec<_> = dh.iter().collect();
+        let kem_context: Vec<_> =
+            kem_context.iter().map(|pk| pk.as_slice()).collect();
+
+        let shared_secret = kem_shared_secret(&dh, &kem_context).unwrap();
+        let (key, nonce) = key_schedule::<Aes128Gcm>(
+            mode,
+            shared_secret.as_ref(),
+            &[RFC_INFO],
+        )
+        .unwrap();
+
+        let mut ct = b"Beauty is truth, truth beauty".to_vec();
+        let tag = Aes128Gcm::new(&key)
+            .encrypt_in_place_detached(&nonce, b"Count-0", &mut ct)
+            .unwrap();
+        ct.extend_from_slice(&tag);
+
+        (
+            hex::encode(shared_secret.as_ref()),
+            hex::encode(key.as_slice()),
+            hex::encode(nonce),
+            hex::encode(ct),
+        )
+    }
+
+    // RFC 9180, A.1.1. Base Setup Information
+    #[test]
+    fn rfc9180_base() {
+        let (shared_secret, key, nonce, ct) = seal_vector(
+            0x00,
+            "52c4a758a802cd8b936eceea314432798d5baf2d7e9235dc084ab1b9cfa2f736",
+            "4612c550263fc8ad58375df3f557aac531d26850903e55a9f23f21d8534e8ac8",
+            None,
+        );
+
+        assert_eq!(
+            shared_secret,
+            "fe0e18c9f024ce43799ae393c7e8fe8fce9d218875e8227b0187c04e7d2ea1fc"
+        );
+        assert_eq!(key, "4531685d41d65f03dc48f6b8302c05b0");
+        assert_eq!(nonce, "56d890e5accaaf011cff4b7d");
+        assert_eq!(
+            ct,
+            "f938558b5d72f1a23810b4be2ab4f84331acc02fc97babc53a52ae8218a355a9\
+             6d8770ac83d07bea87e13c512a"
+        );
+    }
+
+    // RFC 9180, A.1.3. Auth Setup Information
+    #[test]
+    fn rfc9180_auth() {
+        let (shared_secret, key, nonce, ct) = seal_vector(
+            MODE_AUTH,
+            "ff4442ef24fbc3c1ff86375b0be1e77e88a0de1e79b30896d73411c5ff4c3518",
+            "fdea67cf831f1ca98d8e27b1f6abeb5b7745e9d35348b80fa407ff6958f9137e",
+            Some(
+                "dc4a146313cce60a278a5323d321f051c5707e9c45ba21a3479fecdf76fc69dd",
+            ),
+        );
+
+        assert_eq!(
+            shared_secret,
+            "2d6db4cf719dc7293fcbf3fa64690708e44e2bebc81f84608677958c0d4448a7"
+        );
+        assert_eq!(key, "b062cb2c4dd4bca0ad7c7a12bbc341e6");
+        assert_eq!(nonce, "a1bc314c1942ade7051ffed0");
+        assert_eq!(
+            ct,
+            "5fd92cc9d46dbf8943e72a07e42f363ed5f721212cd90bcfd072bfd9f44e06b8\
+             0fd17824947496e21b680c141b"
+        );
+    }
+
+    fn instance() -> InstanceId {
+        InstanceId::new([1; 32])
+    }
+    const TAG: MessageTag = MessageTag::tag(1);
+
+    fn pair<S: SchemeAead>() -> (Hpke<S>, Hpke<S>) {
+        let mut rng = rand::thread_rng();
+
+        let mut a = Hpke::<S>::new(&instance(), &mut rng);
+        let mut b = Hpke::<S>::new(&instance(), &mut rng);
+
+        let pk_a = a.public_key().to_vec();
+        let pk_b = b.public_key().to_vec();
+
+        a.receiver_public_key(1, &pk_b).unwrap();
+        b.receiver_public_key(0, &pk_a).unwrap();
+
+        (a, b)
+    }
+
+    fn encrypt(scheme: &mut dyn EncryptionScheme) -> Vec<u8> {
+        let id = MsgId::new(&instance(), &[0], Some(&[1]), TAG);
+
+        let mut msg =
+            EncryptedMessage::<[u8; 32]>::new(&id, 10, 0, 3, scheme);
+
+        let (payload, trailer) = msg.payload(scheme);
+        *payload = [7; 32];
+        trailer.copy_from_slice(&[1, 2, 3]);
+
+        msg.encrypt(scheme, 1, TAG).unwrap()
+    }
+
+    fn decrypt(msg: &mut [u8], scheme: &mut dyn EncryptionScheme) -> bool {
+        EncryptedMessage::<[u8; 32]>::decrypt(msg, 3, scheme, 0, TAG)
+            .is_some()
+    }
+
+    fn round_trip<S: SchemeAead>() {
+        let (mut a, mut b) = pair::<S>();
+
+        let mut msg = encrypt(&mut a);
+
+        // every message has a fresh encapsulated key
+        assert_ne!(msg, encrypt(&mut a));
+
+        let (payload, trailer) = EncryptedMessage::<[u8; 32]>::decrypt(
+            &mut msg, 3, &mut b, 0, TAG,
+        )
+        .unwrap();
+
+        assert_eq!(payload, &[7; 32]);
+        assert_eq!(trailer, &[1, 2, 3]);
+    }
+
+    #[test]
+    fn round_trip_chacha20poly1305() {
+        round_trip::<ChaCha20Poly1305>();
+    }
+
+    #[test]
+    fn round_trip_aes256gcm() {
+        round_trip::<Aes256Gcm>();
+    }
+
+    #[test]
+    fn reject_tampered_and_unauthenticated() {
+        let (mut a, mut b) = pair::<ChaCha20Poly1305>();
+
+        // modified header is detected
+        let mut msg = encrypt(&mut a);
+        msg[0] ^= 1;
+        assert!(!decrypt(&mut msg, &mut b));
+
+        // a message from a party with a different static key
+        let (mut c, _) = pair::<ChaCha20Poly1305>();
+        c.receiver_public_key(1, b.public_key()).unwrap();
+
+        let mut msg = encrypt(&mut c);
+        assert!(!decrypt(&mut msg, &mut b));
+    }
+
+    #[test]
+    fn reject_replay_and_other_tag() {
+        let (mut a, mut b) = pair::<ChaCha20Poly1305>();
+
+        let msg = encrypt(&mut a);
+
+        let mut copy = msg.clone();
+        assert!(EncryptedMessage::<[u8; 32]>::decrypt(
+            &mut copy,
+            3,
+            &mut b,
+            0,
+            MessageTag::tag(2)
+        )
+        .is_none());
+
+        assert!(decrypt(&mut msg.clone(), &mut b));
+        assert!(!decrypt(&mut msg.clone(), &mut b));
+    }
+}
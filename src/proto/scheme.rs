@@ -14,14 +14,18 @@
 //!
 //! - Forward secrecy through ephemeral key exchange
 //! - Message authentication and integrity
-//! - Nonce reuse prevention
-//! - Secure key derivation using SHA-256
+//! - Nonce reuse prevention, nonces include the message tag
+//! - Separate keys for each direction, derived by HKDF-SHA256 and
+//!   bound to the protocol instance
+//! - Replay detection
 //!
 //! # Implementation Details
 //!
 //! The module provides:
 //! - `EncryptionScheme` trait defining the interface for secure communication
-//! - `AeadX25519` implementation using ChaCha20-Poly1305 for AEAD
+//! - `AeadX25519` implementation using ChaCha20-Poly1305 or AES-256-GCM
+//!   for AEAD
+//! - `SchemeId` selecting a concrete scheme, bound into message headers
 //! - Secure nonce generation and management
 //! - Key pair management for multiple parties
 
@@ -30,17 +34,20 @@
 use std::marker::PhantomData;
 
 use aead::{
-    consts::{U10, U32},
+    consts::{U12, U32},
     generic_array::{typenum::Unsigned, GenericArray},
     AeadCore, AeadInPlace, Key, KeyInit, KeySizeUser, Nonce, Tag,
 };
-use chacha20::hchacha;
+use aes_gcm::Aes256Gcm;
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::Hkdf;
 use rand_core::CryptoRngCore;
 use sha2::{Digest, Sha256};
+use sl_mpc_mate::message::{InstanceId, MessageTag, MsgId};
 use x25519_dalek::{PublicKey, ReusableSecret};
 use zeroize::Zeroizing;
 
-use crate::pairs::Pairs;
+use crate::{pairs::Pairs, proto::hpke::Hpke};
 
 /// Error indicating invalid public key format or operation
 #[derive(Debug)]
@@ -50,6 +57,141 @@ pub struct PublicKeyError;
 #[derive(Debug)]
 pub struct EncryptionError;
 
+/// Mask of bits of message header flags carrying the [`SchemeId`]
+/// of an encrypted message.
+pub const SCHEME_ID_FLAGS_MASK: u16 = 0x000f;
+
+/// Identifier of an encryption scheme of P2P messages.
+///
+/// All participants of a protocol must use the same scheme. The
+/// identifier is stored in the header flags of each encrypted message,
+/// and a message of a different scheme is rejected as invalid.
+///
+/// The default scheme has ID 0 and so produces the same header flags
+/// as earlier versions of the library.
+///
+/// There is no XChaCha20-Poly1305 scheme: [`AeadX25519`] derives a
+/// nonce from a message tag and a counter, and HPKE derives it from a
+/// fresh key schedule, so neither needs the extended random nonce of
+/// XChaCha20.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum SchemeId {
+    /// X25519 key exchange and ChaCha20-Poly1305
+    #[default]
+    X25519ChaCha20Poly1305 = 0,
+
+    /// X25519 key exchange and AES-256-GCM
+    X25519Aes256Gcm = 1,
+
+    /// RFC 9180 HPKE: DHKEM(X25519, HKDF-SHA256), HKDF-SHA256 and
+    /// ChaCha20-Poly1305
+    HpkeChaCha20Poly1305 = 2,
+
+    /// RFC 9180 HPKE: DHKEM(X25519, HKDF-SHA256), HKDF-SHA256 and
+    /// AES-256-GCM
+    HpkeAes256Gcm = 3,
+}
+
+impl SchemeId {
+    /// Decode a scheme ID from message header flags.
+    pub fn from_flags(flags: u16) -> Option<Self> {
+        match flags & SCHEME_ID_FLAGS_MASK {
+            0 => Some(Self::X25519ChaCha20Poly1305),
+            1 => Some(Self::X25519Aes256Gcm),
+            2 => Some(Self::HpkeChaCha20Poly1305),
+            3 => Some(Self::HpkeAes256Gcm),
+            _ => None,
+        }
+    }
+
+    /// Header flags bits of the scheme ID.
+    pub fn flags(self) -> u16 {
+        self as u16
+    }
+
+    /// Create an instance of the scheme with a fresh key pair
+    /// generated by the supplied RNG. Encryption keys are bound to
+    /// the protocol instance.
+    pub fn new_scheme(
+        self,
+        instance: &InstanceId,
+        rng: &mut impl CryptoRngCore,
+    ) -> Box<dyn EncryptionScheme + Sync> {
+        match self {
+            Self::X25519ChaCha20Poly1305 => {
+                Box::new(AeadX25519::<ChaCha20Poly1305>::new(instance, rng))
+            }
+            Self::X25519Aes256Gcm => {
+                Box::new(AeadX25519::<Aes256Gcm>::new(instance, rng))
+            }
+            Self::HpkeChaCha20Poly1305 => {
+                Box::new(Hpke::<ChaCha20Poly1305>::new(instance, rng))
+            }
+            Self::HpkeAes256Gcm => {
+                Box::new(Hpke::<Aes256Gcm>::new(instance, rng))
+            }
+        }
+    }
+
+    /// Create an instance of the scheme from a provided secret key.
+    /// Encryption keys are bound to the protocol instance, so the
+    /// same secret key may be used in many protocol executions.
+    pub fn from_secret(
+        self,
+        instance: &InstanceId,
+        secret: ReusableSecret,
+    ) -> Box<dyn EncryptionScheme + Sync> {
+        match self {
+            Self::X25519ChaCha20Poly1305 => {
+                Box::new(AeadX25519::<ChaCha20Poly1305>::from_secret(
+                    instance, secret,
+                ))
+            }
+            Self::X25519Aes256Gcm => Box::new(
+                AeadX25519::<Aes256Gcm>::from_secret(instance, secret),
+            ),
+            Self::HpkeChaCha20Poly1305 => Box::new(
+                Hpke::<ChaCha20Poly1305>::from_secret(instance, secret),
+            ),
+            Self::HpkeAes256Gcm => {
+                Box::new(Hpke::<Aes256Gcm>::from_secret(instance, secret))
+            }
+        }
+    }
+}
+
+/// Label of a protocol instance used in derivation of encryption keys.
+pub(crate) fn instance_label(instance: &InstanceId) -> MsgId {
+    MsgId::broadcast(instance, &[], MessageTag::tag(0))
+}
+
+/// An AEAD algorithm supported by [`AeadX25519`] and [`Hpke`].
+pub trait SchemeAead:
+    AeadInPlace + AeadCore<NonceSize = U12> + KeyInit + Send
+{
+    /// ID of [`AeadX25519`] with this algorithm
+    const X25519_SCHEME_ID: SchemeId;
+
+    /// ID of [`Hpke`] with this algorithm
+    const HPKE_SCHEME_ID: SchemeId;
+
+    /// RFC 9180 identifier of the algorithm
+    const HPKE_AEAD_ID: u16;
+}
+
+impl SchemeAead for ChaCha20Poly1305 {
+    const X25519_SCHEME_ID: SchemeId = SchemeId::X25519ChaCha20Poly1305;
+    const HPKE_SCHEME_ID: SchemeId = SchemeId::HpkeChaCha20Poly1305;
+    const HPKE_AEAD_ID: u16 = 0x0003;
+}
+
+impl SchemeAead for Aes256Gcm {
+    const X25519_SCHEME_ID: SchemeId = SchemeId::X25519Aes256Gcm;
+    const HPKE_SCHEME_ID: SchemeId = SchemeId::HpkeAes256Gcm;
+    const HPKE_AEAD_ID: u16 = 0x0002;
+}
+
 /// Type alias for a shared key used in encryption
 type SharedKey = Zeroizing<GenericArray<u8, U32>>;
 
@@ -71,9 +213,14 @@ type SharedKey = Zeroizing<GenericArray<u8, U32>>;
 /// Implementations must ensure:
 /// - Forward secrecy through proper key exchange
 /// - Nonce uniqueness for each encryption
-/// - Secure key derivation
+/// - Secure key derivation, bound to the protocol instance
 /// - Proper authentication of messages
+/// - Rejection of replayed messages
+/// - No panics in long running sessions
 pub trait EncryptionScheme: Send {
+    /// Return ID of the scheme, see [`SchemeId`].
+    fn scheme_id(&self) -> SchemeId;
+
     /// Return external representation of own public key
     fn public_key(&self) -> &[u8];
 
@@ -128,6 +275,10 @@ pub trait EncryptionScheme: Send {
     ///   receiver of the message. This may be used for deriving
     ///   encryption keys.
     ///
+    /// - `tag`: The tag of the message. It is bound to the
+    ///   ciphertext, a message encrypted with one tag can't be
+    ///   decrypted with another.
+    ///
     /// # Errors
     ///
     ///   `EncryptionError` if issues arise such as missing keys,
@@ -139,6 +290,7 @@ pub trait EncryptionScheme: Send {
         buffer: &mut [u8],
         tail: &mut [u8],
         receive: usize,
+        tag: MessageTag,
     ) -> Result<(), EncryptionError>;
 
     /// Decrypts the provided data buffer using associated data and a
@@ -163,35 +315,84 @@ pub trait EncryptionScheme: Send {
     ///   the message. This might be used to retrieve or derive
     ///   encryption keys.
     ///
+    /// - `tag`: The tag of the message, must be the same as passed
+    ///   to `encrypt()`.
+    ///
     /// # Errors
     ///
     /// - `EncryptionError`: This function may return an
     ///   `EncryptionError` in several situations such as when the
     ///   decryption key is not found, when the input data is tampered
-    ///   with or if the cryptographic verification of the AAD fails.
+    ///   with, if the cryptographic verification of the AAD fails or
+    ///   if the message was already decrypted once.
     fn decrypt(
-        &self,
+        &mut self,
         associated_data: &[u8],
         buffer: &mut [u8],
         tail: &[u8],
         sender: usize,
+        tag: MessageTag,
     ) -> Result<(), EncryptionError>;
 
     /// Return size of trailing segment. See method `encrypt()` and `decrypt()`.
     fn overhead(&self) -> usize;
 }
 
+impl<T: EncryptionScheme + ?Sized> EncryptionScheme for Box<T> {
+    fn scheme_id(&self) -> SchemeId {
+        (**self).scheme_id()
+    }
+
+    fn public_key(&self) -> &[u8] {
+        (**self).public_key()
+    }
+
+    fn receiver_public_key(
+        &mut self,
+        receiver_index: usize,
+        public_key: &[u8],
+    ) -> Result<(), PublicKeyError> {
+        (**self).receiver_public_key(receiver_index, public_key)
+    }
+
+    fn encrypt(
+        &mut self,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+        tail: &mut [u8],
+        receive: usize,
+        tag: MessageTag,
+    ) -> Result<(), EncryptionError> {
+        (**self).encrypt(associated_data, buffer, tail, receive, tag)
+    }
+
+    fn decrypt(
+        &mut self,
+        associated_data: &[u8],
+        buffer: &mut [u8],
+        tail: &[u8],
+        sender: usize,
+        tag: MessageTag,
+    ) -> Result<(), EncryptionError> {
+        (**self).decrypt(associated_data, buffer, tail, sender, tag)
+    }
+
+    fn overhead(&self) -> usize {
+        (**self).overhead()
+    }
+}
+
 /// Counter to create a unique nonce for encryption operations.
 ///
-/// This struct maintains a counter that is used to generate unique nonces
-/// for each encryption operation. The counter is incremented for each
-/// encryption to ensure nonce uniqueness.
+/// A nonce is the tag of a message followed by the 32-bit value of
+/// the counter. The counter is incremented for each encryption to
+/// ensure nonce uniqueness.
 ///
 /// # Security Considerations
 ///
-/// - The counter is 32-bit and will panic on overflow
-/// - Nonces are derived from the counter value
-/// - Each encryption operation must use a unique nonce
+/// - Nonces of messages with different tags never collide
+/// - Once all values of the counter are used, no more nonces are
+///   produced and encryption fails with an error
 #[derive(Default)]
 pub struct NonceCounter(u32);
 
@@ -201,104 +402,161 @@ impl NonceCounter {
         Self(0)
     }
 
-    /// Increments the counter and returns a new nonce.
-    ///
-    /// # Type Parameters
-    ///
-    /// * `S`: The AEAD scheme type that determines the nonce size
-    ///
-    /// # Returns
-    ///
-    /// A new nonce derived from the incremented counter value
+    /// Increments the counter and returns a new nonce for a message
+    /// with the given tag.
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// Panics if the counter overflows (exceeds u32::MAX)
-    pub fn next_nonce<S: AeadCore>(&mut self) -> Nonce<S> {
-        self.0 = self.0.checked_add(1).expect("nonce overflow");
+    /// Returns `EncryptionError` if the counter overflows (exceeds
+    /// u32::MAX)
+    pub fn next_nonce<S: AeadCore<NonceSize = U12>>(
+        &mut self,
+        tag: MessageTag,
+    ) -> Result<Nonce<S>, EncryptionError> {
+        self.0 = self.0.checked_add(1).ok_or(EncryptionError)?;
 
         let mut nonce = Nonce::<S>::default();
-        nonce[..4].copy_from_slice(&self.0.to_le_bytes());
+        nonce[..8].copy_from_slice(&tag.to_bytes());
+        nonce[8..].copy_from_slice(&self.0.to_le_bytes());
+
+        Ok(nonce)
+    }
+}
 
-        nonce
+/// Counters of nonces received from a party: the highest counter and
+/// a bitmap of the counters before it, as in the IPsec anti-replay
+/// window. A message with a counter older than the window is rejected,
+/// so the state of a peer does not grow with the number of messages.
+#[derive(Default)]
+struct ReplayWindow {
+    last: u32,
+    bitmap: u64,
+}
+
+impl ReplayWindow {
+    const SIZE: u32 = u64::BITS;
+
+    fn is_fresh(&self, counter: u32) -> bool {
+        // counters start at 1
+        if counter > self.last {
+            return true;
+        }
+
+        let age = self.last - counter;
+
+        counter != 0 && age < Self::SIZE && self.bitmap & (1 << age) == 0
+    }
+
+    fn accept(&mut self, counter: u32) {
+        if counter > self.last {
+            let shift = counter - self.last;
+            self.bitmap = self.bitmap.checked_shl(shift).unwrap_or(0) | 1;
+            self.last = counter;
+        } else {
+            self.bitmap |= 1 << (self.last - counter);
+        }
     }
 }
 
+/// Prefix of the HKDF `info` parameter of directional keys.
+const KEY_INFO: &[u8] = b"dkls23-p2p-key";
+
+/// Keys and state of communication with another party.
+struct PeerKeys {
+    send: SharedKey,
+    recv: SharedKey,
+    counter: NonceCounter,
+    window: ReplayWindow,
+}
+
 /// Implementation of EncryptionScheme using X25519 for key exchange
 /// and any AEAD scheme for encryption.
 ///
 /// This struct combines:
 /// - X25519 for key exchange
+/// - HKDF-SHA256 to derive separate keys for each direction of
+///   communication with another party, salted by a label of the
+///   protocol instance
 /// - A configurable AEAD scheme for encryption
-/// - Secure nonce generation
+/// - Secure nonce generation and detection of replayed messages
 /// - Key pair management for multiple parties
 ///
 /// # Type Parameters
 ///
-/// * `S`: The AEAD scheme to use for encryption (e.g., ChaCha20Poly1305)
-pub struct AeadX25519<S> {
+/// * `S`: The AEAD scheme to use for encryption (ChaCha20Poly1305 or
+///   Aes256Gcm)
+pub struct AeadX25519<S: AeadCore> {
     secret: ReusableSecret,
     public_key: PublicKey,
-    counter: NonceCounter,
-    pk: Pairs<(SharedKey, PublicKey), usize>,
+    label: MsgId,
+    pk: Pairs<PeerKeys, usize>,
     marker: PhantomData<S>,
 }
 
-impl<S> AeadX25519<S> {
+impl<S: AeadCore> AeadX25519<S> {
     /// Generate a new [`AeadX25519`] with the supplied RNG.
     ///
     /// # Arguments
     ///
+    /// * `instance`: The protocol instance the keys are bound to
     /// * `rng`: A cryptographically secure random number generator
     ///
     /// # Returns
     ///
     /// A new `AeadX25519` instance with:
     /// - A randomly generated X25519 key pair
-    /// - An initialized nonce counter
     /// - An empty key pair store
-    pub fn new(rng: &mut impl CryptoRngCore) -> Self {
-        let secret = ReusableSecret::random_from_rng(rng);
-        let public_key = PublicKey::from(&secret);
-
-        Self {
-            secret,
-            public_key,
-            counter: NonceCounter::new(),
-            pk: Pairs::new(),
-            marker: PhantomData,
-        }
+    pub fn new(instance: &InstanceId, rng: &mut impl CryptoRngCore) -> Self {
+        Self::from_secret(instance, ReusableSecret::random_from_rng(rng))
     }
 
     /// Create a new [`AeadX25519`] from a provided `ReusableSecret`.
     ///
     /// # Arguments
     ///
+    /// * `instance`: The protocol instance the keys are bound to
     /// * `secret`: A pre-existing X25519 secret key
     ///
     /// # Returns
     ///
     /// A new `AeadX25519` instance with:
     /// - The provided secret key and its corresponding public key
-    /// - An initialized nonce counter
     /// - An empty key pair store
-    pub fn from_secret(secret: ReusableSecret) -> Self {
-        let public_key = PublicKey::from(&secret);
-
+    pub fn from_secret(
+        instance: &InstanceId,
+        secret: ReusableSecret,
+    ) -> Self {
         Self {
+            public_key: PublicKey::from(&secret),
             secret,
-            public_key,
-            counter: NonceCounter::new(),
+            label: instance_label(instance),
             pk: Pairs::new(),
             marker: PhantomData,
         }
     }
 }
 
-impl<S> EncryptionScheme for AeadX25519<S>
-where
-    S: AeadInPlace + KeyInit + Send,
-{
+fn expand_key(
+    prk: &Hkdf<Sha256>,
+    from: &PublicKey,
+    to: &PublicKey,
+) -> Result<SharedKey, PublicKeyError> {
+    let mut key = SharedKey::default();
+
+    prk.expand_multi_info(
+        &[KEY_INFO, from.as_bytes(), to.as_bytes()],
+        key.as_mut_slice(),
+    )
+    .map_err(|_| PublicKeyError)?;
+
+    Ok(key)
+}
+
+impl<S: SchemeAead> EncryptionScheme for AeadX25519<S> {
+    fn scheme_id(&self) -> SchemeId {
+        S::X25519_SCHEME_ID
+    }
+
     fn overhead(&self) -> usize {
         S::TagSize::USIZE + S::NonceSize::USIZE
     }
@@ -309,62 +567,63 @@ where
         buffer: &mut [u8],
         tail: &mut [u8],
         receive: usize,
+        tag: MessageTag,
     ) -> Result<(), EncryptionError> {
         if tail.len() != self.overhead() {
             return Err(EncryptionError);
         }
 
-        let (key, public_key) =
-            self.pk.find_pair_or_err(receive, EncryptionError)?;
+        let peer = self.pk.find_pair_mut_or_err(receive, EncryptionError)?;
 
-        let key = Zeroizing::new(
-            Sha256::new_with_prefix(public_key)
-                .chain_update(key)
-                .finalize(),
-        );
+        let nonce = peer.counter.next_nonce::<S>(tag)?;
+        let key = Key::<S>::from_slice(peer.send.as_slice());
 
-        let key = Key::<S>::from_slice(key.as_slice());
-
-        let nonce = self.counter.next_nonce::<S>();
-        let tag = S::new(key)
+        let auth_tag = S::new(key)
             .encrypt_in_place_detached(&nonce, associated_data, buffer)
             .map_err(|_| EncryptionError)?;
 
-        tail[..S::TagSize::USIZE].copy_from_slice(&tag);
+        tail[..S::TagSize::USIZE].copy_from_slice(&auth_tag);
         tail[S::TagSize::USIZE..].copy_from_slice(&nonce);
 
         Ok(())
     }
 
     fn decrypt(
-        &self,
+        &mut self,
         associated_data: &[u8],
         buffer: &mut [u8],
         tail: &[u8],
         sender: usize,
+        tag: MessageTag,
     ) -> Result<(), EncryptionError> {
         if tail.len() != self.overhead() {
             return Err(EncryptionError);
         }
 
-        let (key, public_key) =
-            self.pk.find_pair_or_err(sender, EncryptionError)?;
+        let peer = self.pk.find_pair_mut_or_err(sender, EncryptionError)?;
 
-        let key = Zeroizing::new(
-            Sha256::new_with_prefix(self.public_key)
-                .chain_update(key)
-                .finalize(),
-        );
+        let nonce = Nonce::<S>::from_slice(&tail[S::TagSize::USIZE..]);
+        let auth_tag = Tag::<S>::from_slice(&tail[..S::TagSize::USIZE]);
 
-        let key = Key::<S>::from_slice(key.as_slice());
+        let counter = u32::from_le_bytes(nonce[8..].try_into().unwrap());
 
-        let nonce = Nonce::<S>::from_slice(&tail[S::TagSize::USIZE..]);
-        let tag = Tag::<S>::from_slice(&tail[..S::TagSize::USIZE]);
+        if nonce[..8] != tag.to_bytes() || !peer.window.is_fresh(counter) {
+            return Err(EncryptionError);
+        }
+
+        let key = Key::<S>::from_slice(peer.recv.as_slice());
 
         S::new(key)
-            .decrypt_in_place_detached(nonce, associated_data, buffer, tag)
+            .decrypt_in_place_detached(
+                nonce,
+                associated_data,
+                buffer,
+                auth_tag,
+            )
             .map_err(|_| EncryptionError)?;
 
+        peer.window.accept(counter);
+
         Ok(())
     }
 
@@ -386,13 +645,225 @@ where
             return Err(PublicKeyError);
         }
 
-        let shared_key = Zeroizing::new(hchacha::<U10>(
-            GenericArray::from_slice(shared_secret.as_bytes()),
-            &GenericArray::default(),
-        ));
+        let prk = Hkdf::<Sha256>::new(
+            Some(self.label.as_slice()),
+            shared_secret.as_bytes(),
+        );
 
-        self.pk.push(receiver_index, (shared_key, pk));
+        let peer = PeerKeys {
+            send: expand_key(&prk, &self.public_key, &pk)?,
+            recv: expand_key(&prk, &pk, &self.public_key)?,
+            counter: NonceCounter::new(),
+            window: ReplayWindow::default(),
+        };
+
+        self.pk.push(receiver_index, peer);
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::RngCore;
+
+    use crate::proto::EncryptedMessage;
+
+    use super::*;
+
+    const SCHEMES: [SchemeId; 4] = [
+        SchemeId::X25519ChaCha20Poly1305,
+        SchemeId::X25519Aes256Gcm,
+        SchemeId::HpkeChaCha20Poly1305,
+        SchemeId::HpkeAes256Gcm,
+    ];
+
+    const TAG: MessageTag = MessageTag::tag(1);
+
+    fn pair(
+        instance: &InstanceId,
+    ) -> (AeadX25519<ChaCha20Poly1305>, AeadX25519<ChaCha20Poly1305>) {
+        let mut rng = rand::thread_rng();
+
+        let mut a = AeadX25519::new(instance, &mut rng);
+        let mut b = AeadX25519::new(instance, &mut rng);
+
+        let pk_a = a.public_key().to_vec();
+        let pk_b = b.public_key().to_vec();
+
+        a.receiver_public_key(1, &pk_b).unwrap();
+        b.receiver_public_key(0, &pk_a).unwrap();
+
+        (a, b)
+    }
+
+    fn encrypt(
+        scheme: &mut dyn EncryptionScheme,
+        receiver: usize,
+        tag: MessageTag,
+    ) -> ([u8; 32], [u8; 28]) {
+        let mut buffer = [0u8; 32];
+        let mut tail = [0u8; 28];
+        scheme
+            .encrypt(&[], &mut buffer, &mut tail, receiver, tag)
+            .unwrap();
+
+        (buffer, tail)
+    }
+
+    fn decrypt(
+        scheme: &mut dyn EncryptionScheme,
+        (mut buffer, tail): ([u8; 32], [u8; 28]),
+        sender: usize,
+        tag: MessageTag,
+    ) -> bool {
+        scheme.decrypt(&[], &mut buffer, &tail, sender, tag).is_ok()
+    }
+
+    #[test]
+    fn scheme_id_flags() {
+        for id in SCHEMES {
+            assert_eq!(SchemeId::from_flags(id.flags() | 0xff00), Some(id));
+        }
+
+        assert_eq!(SchemeId::from_flags(SCHEME_ID_FLAGS_MASK), None);
+    }
+
+    #[test]
+    fn scheme_mismatch() {
+        let mut rng = rand::thread_rng();
+
+        let instance = InstanceId::new([1; 32]);
+        let id = MsgId::new(&instance, &[0], Some(&[1]), TAG);
+
+        for sender_id in SCHEMES {
+            for receiver_id in SCHEMES {
+                let mut sender = sender_id.new_scheme(&instance, &mut rng);
+                let mut receiver =
+                    receiver_id.new_scheme(&instance, &mut rng);
+
+                let pk = sender.public_key().to_vec();
+                receiver.receiver_public_key(0, &pk).unwrap();
+
+                let pk = receiver.public_key().to_vec();
+                sender.receiver_public_key(1, &pk).unwrap();
+
+                let mut msg =
+                    EncryptedMessage::<[u8; 32]>::new(&id, 10, 0, 0, &sender);
+                *msg.payload(&sender).0 = [5; 32];
+
+                let mut msg = msg.encrypt(&mut sender, 1, TAG).unwrap();
+
+                assert_eq!(
+                    EncryptedMessage::<[u8; 32]>::scheme_id(&msg),
+                    Some(sender_id)
+                );
+
+                let decrypted = EncryptedMessage::<[u8; 32]>::decrypt(
+                    &mut msg,
+                    0,
+                    &mut receiver,
+                    0,
+                    TAG,
+                );
+
+                assert_eq!(
+                    decrypted.map(|(payload, _)| *payload),
+                    (sender_id == receiver_id).then_some([5; 32])
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn keys_bound_to_instance() {
+        let mut rng = rand::thread_rng();
+
+        let a = ReusableSecret::random_from_rng(&mut rng);
+        let b = ReusableSecret::random_from_rng(&mut rng);
+        let pk_b = PublicKey::from(&b);
+
+        let encrypt_with = |instance| {
+            let mut scheme = AeadX25519::<ChaCha20Poly1305>::from_secret(
+                &InstanceId::new(instance),
+                a.clone(),
+            );
+            scheme.receiver_public_key(1, pk_b.as_bytes()).unwrap();
+
+            encrypt(&mut scheme, 1, TAG)
+        };
+
+        // the first nonce of each instance is the same, keys must differ
+        let (c1, t1) = encrypt_with([1; 32]);
+        let (c2, t2) = encrypt_with([2; 32]);
+
+        assert_eq!(t1[16..], t2[16..]);
+        assert_ne!(c1, c2);
+        assert_eq!(encrypt_with([1; 32]).0, c1);
+    }
+
+    #[test]
+    fn keys_per_direction() {
+        let (mut a, mut b) = pair(&InstanceId::new([1; 32]));
+
+        // the same nonce in both directions
+        let a_to_b = encrypt(&mut a, 1, TAG);
+        let b_to_a = encrypt(&mut b, 0, TAG);
+
+        assert_eq!(a_to_b.1[16..], b_to_a.1[16..]);
+        assert_ne!(a_to_b.0, b_to_a.0);
+
+        // a message reflected back to its sender is rejected
+        assert!(!decrypt(&mut a, a_to_b, 1, TAG));
+        assert!(decrypt(&mut b, a_to_b, 0, TAG));
+        assert!(decrypt(&mut a, b_to_a, 1, TAG));
+    }
+
+    #[test]
+    fn reject_replay_and_other_tag() {
+        let (mut a, mut b) = pair(&InstanceId::new([1; 32]));
+
+        let msg = encrypt(&mut a, 1, TAG);
+        assert_eq!(msg.1[16..24], TAG.to_bytes());
+
+        assert!(!decrypt(&mut b, msg, 0, MessageTag::tag(2)));
+        assert!(decrypt(&mut b, msg, 0, TAG));
+        assert!(!decrypt(&mut b, msg, 0, TAG));
+
+        // next message is accepted
+        let msg = encrypt(&mut a, 1, TAG);
+        assert!(decrypt(&mut b, msg, 0, TAG));
+    }
+
+    #[test]
+    fn replay_window() {
+        let (mut a, mut b) = pair(&InstanceId::new([1; 32]));
+
+        let msgs: Vec<_> = (0..ReplayWindow::SIZE + 3)
+            .map(|_| encrypt(&mut a, 1, TAG))
+            .collect();
+
+        // messages out of order within the window are accepted once
+        assert!(decrypt(&mut b, msgs[1], 0, TAG));
+        assert!(decrypt(&mut b, msgs[0], 0, TAG));
+        assert!(!decrypt(&mut b, msgs[0], 0, TAG));
+        assert!(!decrypt(&mut b, msgs[1], 0, TAG));
+
+        // a message older than the window is rejected
+        assert!(decrypt(&mut b, msgs[msgs.len() - 1], 0, TAG));
+        assert!(!decrypt(&mut b, msgs[2], 0, TAG));
+        assert!(decrypt(&mut b, msgs[3], 0, TAG));
+    }
+
+    #[test]
+    fn nonce_counter_overflow() {
+        let mut counter = NonceCounter(u32::MAX - 1);
+
+        let nonce = counter.next_nonce::<ChaCha20Poly1305>(TAG).unwrap();
+        assert_eq!(nonce[..8], TAG.to_bytes());
+        assert_eq!(nonce[8..], u32::MAX.to_le_bytes());
+
+        assert!(counter.next_nonce::<ChaCha20Poly1305>(TAG).is_err());
+        assert!(counter.next_nonce::<ChaCha20Poly1305>(TAG).is_err());
+    }
+}
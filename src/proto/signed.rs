@@ -6,15 +6,61 @@
 //! This module provides functionality for creating and verifying signed messages
 //! with support for additional data and trailers. It uses a pluggable signature
 //! scheme interface to allow for different signature implementations.
+//!
+//! A signature may be bound to a [`SigningContext`] that identifies
+//! the protocol, its version and the setup of a protocol execution.
 
+use std::borrow::Cow;
 use std::marker::PhantomData;
 use std::ops::Range;
 
 use bytemuck::{AnyBitPattern, NoUninit};
+use sha2::{Digest, Sha256};
 use signature::{SignatureEncoding, Signer, Verifier};
 
 use sl_mpc_mate::message::*;
 
+/// Domain separation context of message signatures.
+///
+/// A signature in a context covers the context followed by the
+/// message. A message signed in the context of one protocol never
+/// verifies in the context of another protocol, protocol version or
+/// setup, even if message tags of the protocols collide.
+///
+/// The default context is empty and a signature covers the message
+/// only.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SigningContext(Option<[u8; 32]>);
+
+impl SigningContext {
+    /// Create a context of a protocol identified by `label` and
+    /// `version`, executed with a setup with canonical hash
+    /// `setup_hash`.
+    pub fn new(label: &[u8], version: u16, setup_hash: &[u8; 32]) -> Self {
+        let ctx = Sha256::new()
+            .chain_update(b"dkls23-signed-message")
+            .chain_update((label.len() as u64).to_be_bytes())
+            .chain_update(label)
+            .chain_update(version.to_be_bytes())
+            .chain_update(setup_hash)
+            .finalize();
+
+        Self(Some(ctx.into()))
+    }
+
+    /// Returns bytes of the context, empty for the default context.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.as_ref().map_or(&[], |ctx| ctx.as_slice())
+    }
+
+    fn signed_data<'a>(&self, msg: &'a [u8]) -> Cow<'a, [u8]> {
+        match &self.0 {
+            None => Cow::Borrowed(msg),
+            Some(ctx) => Cow::Owned([ctx.as_slice(), msg].concat()),
+        }
+    }
+}
+
 /// A wrapper for a message of type T with support for in-place signing and verifying.
 ///
 /// This struct provides functionality for creating and verifying signed messages
@@ -125,12 +171,29 @@ impl<S: SignatureEncoding, T: AnyBitPattern + NoUninit> SignedMessage<T, S> {
     /// # Returns
     /// The signed message as a byte vector
     pub fn sign<K: Signer<S>>(self, signing_key: &K) -> Vec<u8> {
+        self.sign_with_context(&SigningContext::default(), signing_key)
+    }
+
+    /// Signs the message in a context and returns the underlying
+    /// byte vector.
+    ///
+    /// # Arguments
+    /// * `ctx` - The signing context
+    /// * `signing_key` - The key used to sign the message
+    ///
+    /// # Returns
+    /// The signed message as a byte vector
+    pub fn sign_with_context<K: Signer<S>>(
+        self,
+        ctx: &SigningContext,
+        signing_key: &K,
+    ) -> Vec<u8> {
         let mut buffer = self.buffer;
 
         let last = buffer.len() - Self::S_SIZE;
         let (msg, tail) = buffer.split_at_mut(last);
 
-        let sign = signing_key.sign(msg).to_bytes();
+        let sign = signing_key.sign(&ctx.signed_data(msg)).to_bytes();
 
         tail.copy_from_slice(sign.as_ref());
 
@@ -155,13 +218,47 @@ impl<S: SignatureEncoding, T: AnyBitPattern + NoUninit> SignedMessage<T, S> {
         signing_key: &K,
         f: F,
     ) -> Vec<u8>
+    where
+        F: FnOnce(&mut T, &mut [u8]),
+    {
+        Self::build_with_context(
+            id,
+            ttl,
+            trailer,
+            &SigningContext::default(),
+            signing_key,
+            f,
+        )
+    }
+
+    /// Builds and signs a message in a context using a closure to set
+    /// the payload.
+    ///
+    /// # Arguments
+    /// * `id` - Message identifier
+    /// * `ttl` - Time-to-live value
+    /// * `trailer` - Size of trailer data in bytes
+    /// * `ctx` - The signing context
+    /// * `signing_key` - The key used to sign the message
+    /// * `f` - Closure that sets the payload and trailer content
+    ///
+    /// # Returns
+    /// The signed message as a byte vector
+    pub fn build_with_context<F, K: Signer<S>>(
+        id: &MsgId,
+        ttl: u32,
+        trailer: usize,
+        ctx: &SigningContext,
+        signing_key: &K,
+        f: F,
+    ) -> Vec<u8>
     where
         F: FnOnce(&mut T, &mut [u8]),
     {
         let mut msg = Self::new(id, ttl, 0, trailer);
         let (payload, trailer) = msg.payload();
         f(payload, trailer);
-        msg.sign(signing_key)
+        msg.sign_with_context(ctx, signing_key)
     }
 
     /// Verifies a signed message and returns references to the payload and trailer.
@@ -178,6 +275,32 @@ impl<S: SignatureEncoding, T: AnyBitPattern + NoUninit> SignedMessage<T, S> {
         buffer: &'msg [u8],
         trailer: usize,
         verify_key: &V,
+    ) -> Option<(&'msg T, &'msg [u8])> {
+        Self::verify_with_context(
+            buffer,
+            trailer,
+            &SigningContext::default(),
+            verify_key,
+        )
+    }
+
+    /// Verifies a message signed in a context and returns references
+    /// to the payload and trailer.
+    ///
+    /// # Arguments
+    /// * `buffer` - The signed message buffer
+    /// * `trailer` - Size of trailer data in bytes
+    /// * `ctx` - The signing context
+    /// * `verify_key` - The key used to verify the signature
+    ///
+    /// # Returns
+    /// A tuple containing references to the payload and trailer,
+    /// or `None` if verification fails
+    pub fn verify_with_context<'msg, V: Verifier<S>>(
+        buffer: &'msg [u8],
+        trailer: usize,
+        ctx: &SigningContext,
+        verify_key: &V,
     ) -> Option<(&'msg T, &'msg [u8])> {
         // Make sure that buffer is exactly right size
         if buffer.len() != Self::size(trailer) {
@@ -188,7 +311,7 @@ impl<S: SignatureEncoding, T: AnyBitPattern + NoUninit> SignedMessage<T, S> {
         let (msg, sign) = buffer.split_at(sign_offset);
         let sign = S::try_from(sign).ok()?;
 
-        verify_key.verify(msg, &sign).ok()?;
+        verify_key.verify(&ctx.signed_data(msg), &sign).ok()?;
 
         let body = &msg[MESSAGE_HEADER_SIZE..];
         let (payload, trailer) = body.split_at(Self::T_SIZE);
@@ -237,3 +360,51 @@ impl<S: SignatureEncoding> SignedMessage<(), S> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use ed25519_dalek::{Signature, SigningKey};
+
+    use super::*;
+
+    type Msg = SignedMessage<[u8; 8], Signature>;
+
+    #[test]
+    fn signature_bound_to_context() {
+        let sk = SigningKey::generate(&mut rand::thread_rng());
+        let vk = sk.verifying_key();
+
+        let id = MsgId::broadcast(
+            &InstanceId::new([1; 32]),
+            &[0],
+            MessageTag::tag(1),
+        );
+        let hash = [7; 32];
+
+        let dkg = SigningContext::new(b"dkg", 1, &hash);
+        let contexts = [
+            SigningContext::default(),
+            SigningContext::new(b"dsg", 1, &hash),
+            SigningContext::new(b"dkg", 2, &hash),
+            SigningContext::new(b"dkg", 1, &[8; 32]),
+        ];
+
+        let msg = Msg::build_with_context(&id, 10, 0, &dkg, &sk, |p, _| {
+            *p = [5; 8]
+        });
+
+        assert_eq!(
+            Msg::verify_with_context(&msg, 0, &dkg, &vk).map(|(p, _)| *p),
+            Some([5; 8])
+        );
+
+        for ctx in &contexts {
+            assert!(Msg::verify_with_context(&msg, 0, ctx, &vk).is_none());
+        }
+
+        // the default context is the plain signature of a message
+        let msg = Msg::build(&id, 10, 0, &sk, |p, _| *p = [5; 8]);
+        assert!(Msg::verify(&msg, &vk).is_some());
+        assert!(Msg::verify_with_context(&msg, 0, &dkg, &vk).is_none());
+    }
+}
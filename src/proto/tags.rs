@@ -13,15 +13,17 @@ use std::{
 };
 
 use bytemuck::{AnyBitPattern, NoUninit};
+use sha2::{Digest, Sha256};
 use zeroize::Zeroizing;
 
-use sl_mpc_mate::coord::*;
+use sl_mpc_mate::{coord::*, message::MESSAGE_HEADER_SIZE};
 
 use crate::{
     pairs::Pairs,
     proto::{
-        check_abort, EncryptedMessage, EncryptionScheme, MessageTag, MsgId,
-        Relay, SignedMessage, Wrap,
+        check_abort, compress_message, decompress_message, EncryptedMessage,
+        EncryptionScheme, MessageTag, MsgId, Relay, SignedMessage,
+        SigningContext, Wrap,
     },
     setup::{ProtocolParticipant, ABORT_MESSAGE_TAG},
 };
@@ -37,6 +39,43 @@ pub enum Error {
     Send,
     /// Received message was invalid
     InvalidMessage,
+    /// A hash of a broadcast message of `sender` echoed by `echoer`
+    /// does not match the received message. Either `sender` sent
+    /// different messages to different parties or `echoer` sent a
+    /// false hash, the hash alone does not tell which one.
+    EchoMismatch {
+        /// Sender of the broadcast message
+        sender: usize,
+        /// Party which echoed the hash
+        echoer: usize,
+    },
+    /// A party sent a message which failed verification, decryption
+    /// or decoding
+    InvalidMessageFrom(usize),
+}
+
+// Echo messages of a broadcast round have a tag of the round with
+// this bit set.
+const ECHO_TAG_BIT: u64 = 1 << 62;
+
+/// Returns the tag of echo messages of a broadcast round with given
+/// tag, see [`FilteredMsgRelay::with_echo_broadcast()`].
+pub const fn echo_tag(tag: MessageTag) -> MessageTag {
+    MessageTag::tag(u64::from_le_bytes(tag.to_bytes()) | ECHO_TAG_BIT)
+}
+
+/// Returns the hash of a signed broadcast message exchanged by an
+/// echo round. The signature is not hashed, the same content with
+/// another valid signature of the sender is the same message.
+pub(crate) fn echo_hash<P: ProtocolParticipant>(msg: &[u8]) -> [u8; 32] {
+    let signature = SignedMessage::<(), P::MessageSignature>::size(0)
+        - MESSAGE_HEADER_SIZE;
+
+    Sha256::new()
+        .chain_update(b"dkls23-echo")
+        .chain_update(&msg[..msg.len() - signature])
+        .finalize()
+        .into()
 }
 
 /// A message relay that filters messages based on expected tags and party IDs.
@@ -45,12 +84,28 @@ pub enum Error {
 /// for filtering messages based on expected tags and party IDs. It maintains
 /// a buffer of received messages and tracks expected messages.
 ///
+/// Signed messages sent and received by rounds of the relay are
+/// bound to the signing context of the relay, see
+/// [`FilteredMsgRelay::with_signing_context()`].
+///
+/// Broadcast messages could be compressed, see
+/// [`FilteredMsgRelay::with_compression()`]. Received messages marked
+/// by [`COMPRESSED_FLAG`](crate::proto::COMPRESSED_FLAG) are always decompressed, so parties with
+/// and without compression enabled can take part in the same
+/// protocol execution.
+///
+/// A broadcast round could be followed by an echo round, see
+/// [`FilteredMsgRelay::with_echo_broadcast()`].
+///
 /// # Type Parameters
 /// * `R` - The type of the underlying relay implementation
 pub struct FilteredMsgRelay<R> {
     relay: R,
     in_buf: Vec<(Vec<u8>, usize, MessageTag)>,
     expected: HashMap<MsgId, (usize, MessageTag)>,
+    ctx: SigningContext,
+    compression: bool,
+    echo: bool,
 }
 
 impl<R: Relay> FilteredMsgRelay<R> {
@@ -66,9 +121,80 @@ impl<R: Relay> FilteredMsgRelay<R> {
             relay,
             expected: HashMap::new(),
             in_buf: vec![],
+            ctx: SigningContext::default(),
+            compression: false,
+            echo: false,
         }
     }
 
+    /// Sets the context of signatures of messages of the protocol
+    /// execution.
+    ///
+    /// # Arguments
+    /// * `ctx` - The signing context
+    pub fn with_signing_context(mut self, ctx: SigningContext) -> Self {
+        self.ctx = ctx;
+        self
+    }
+
+    /// Returns the context of signatures of messages.
+    pub fn signing_context(&self) -> &SigningContext {
+        &self.ctx
+    }
+
+    /// Enables or disables compression of broadcast messages sent
+    /// by [`FilteredMsgRelay::send_broadcast()`]. A message is
+    /// compressed only when it makes the message shorter.
+    ///
+    /// # Arguments
+    /// * `compression` - Whether to compress broadcast messages
+    pub fn with_compression(mut self, compression: bool) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Enables or disables echo rounds.
+    ///
+    /// A relay could deliver different broadcast messages of a
+    /// malicious sender to different parties. With echo rounds
+    /// enabled, after [`Round::broadcast_4()`],
+    /// [`Round::recv_broadcast_4()`] or
+    /// [`Round::of_echoed_signed_messages()`] each party broadcasts
+    /// hashes of all signed messages of the round, including its own
+    /// one, and compares them with hashes received from all other
+    /// parties. A mismatch fails the round with
+    /// [`Error::EchoMismatch`] naming the sender of the message and
+    /// the echoing party.
+    ///
+    /// Echo messages have a tag derived by [`echo_tag()`]. All
+    /// parties of a protocol execution must enable or disable echo
+    /// rounds together.
+    ///
+    /// # Arguments
+    /// * `echo` - Whether to run echo rounds
+    pub fn with_echo_broadcast(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    /// Sends a signed broadcast message, compressing it if
+    /// compression is enabled.
+    ///
+    /// # Arguments
+    /// * `msg` - The signed message to send
+    pub async fn send_broadcast(
+        &mut self,
+        msg: Vec<u8>,
+    ) -> Result<(), MessageSendError> {
+        let msg = if self.compression {
+            compress_message(msg)
+        } else {
+            msg
+        };
+
+        self.relay.send(msg).await
+    }
+
     /// Returns the underlying relay object.
     ///
     /// # Returns
@@ -136,9 +262,14 @@ impl<R: Relay> FilteredMsgRelay<R> {
         loop {
             let msg = self.relay.next().await.ok_or(Error::Recv)?;
 
-            if let Ok(id) = <&MsgId>::try_from(msg.as_slice()) {
-                if let Some(&(p, t)) = self.expected.get(id) {
-                    self.expected.remove(id);
+            if let Ok(&id) = <&MsgId>::try_from(msg.as_slice()) {
+                if let Some(&(p, t)) = self.expected.get(&id) {
+                    // drop a malformed compressed message and keep
+                    // waiting for a valid one.
+                    let Some(msg) = decompress_message(msg) else {
+                        continue;
+                    };
+                    self.expected.remove(&id);
                     match t {
                         ABORT_MESSAGE_TAG => {
                             return Ok((msg, p, true));
@@ -268,6 +399,75 @@ impl<R: Relay> FilteredMsgRelay<R> {
     pub fn round(&mut self, count: usize, tag: MessageTag) -> Round<'_, R> {
         Round::new(count, tag, self)
     }
+
+    /// Broadcasts hashes of signed messages of a broadcast round and
+    /// checks that all other parties received the same messages.
+    ///
+    /// # Arguments
+    /// * `setup` - The protocol participant setup
+    /// * `tag` - The tag of the broadcast round
+    /// * `messages` - Pairs of a sender and its signed message
+    async fn echo<P: ProtocolParticipant>(
+        &mut self,
+        setup: &P,
+        tag: MessageTag,
+        mut messages: Vec<(usize, Vec<u8>)>,
+    ) -> Result<(), Error> {
+        let echo_tag = echo_tag(tag);
+
+        messages.sort_by_key(|(p, _)| *p);
+
+        let hashes: Vec<(usize, [u8; 32])> = messages
+            .iter()
+            .map(|(p, msg)| (*p, echo_hash::<P>(msg)))
+            .collect();
+
+        let count = self
+            .ask_messages(setup, echo_tag, false)
+            .await
+            .map_err(|_| Error::Send)?;
+
+        let trailer = hashes.len() * 32;
+
+        let msg =
+            SignedMessage::<(), P::MessageSignature>::build_with_context(
+                &setup.msg_id(None, echo_tag),
+                setup.message_ttl().as_secs() as _,
+                trailer,
+                &self.ctx,
+                setup.signer(),
+                |_, out| {
+                    for ((_, hash), out) in
+                        hashes.iter().zip(out.chunks_mut(32))
+                    {
+                        out.copy_from_slice(hash);
+                    }
+                },
+            );
+
+        self.relay.send(msg).await.map_err(|_| Error::Send)?;
+
+        Round::new(count, echo_tag, self)
+            .of_signed_messages_with_trailer(
+                setup,
+                trailer,
+                Error::Abort,
+                |_: &(), echo: &[u8], echoer| {
+                    for ((sender, hash), copy) in
+                        hashes.iter().zip(echo.chunks(32))
+                    {
+                        if hash != copy {
+                            return Err(Error::EchoMismatch {
+                                sender: *sender,
+                                echoer,
+                            });
+                        }
+                    }
+                    Ok(())
+                },
+            )
+            .await
+    }
 }
 
 impl<R> Deref for FilteredMsgRelay<R> {
@@ -295,6 +495,7 @@ impl<R> DerefMut for FilteredMsgRelay<R> {
 pub struct Round<'a, R> {
     tag: MessageTag,
     count: usize,
+    rejected: Vec<usize>,
     pub(crate) relay: &'a mut FilteredMsgRelay<R>,
 }
 
@@ -313,7 +514,12 @@ impl<'a, R: Relay> Round<'a, R> {
         tag: MessageTag,
         relay: &'a mut FilteredMsgRelay<R>,
     ) -> Self {
-        Self { count, tag, relay }
+        Self {
+            count,
+            tag,
+            rejected: vec![],
+            relay,
+        }
     }
 
     /// Receives the next message in the round.
@@ -322,6 +528,10 @@ impl<'a, R: Relay> Round<'a, R> {
     /// - `Ok(Some(message, party_index, is_abort_flag))` on successful reception
     /// - `Ok(None)` when the round is complete
     /// - `Err(Error)` if an error occurs
+    ///
+    /// If no more messages are available and a message of some party
+    /// was put back and never received again, fails with
+    /// [`Error::InvalidMessageFrom`] of the party.
     pub async fn recv(
         &mut self,
     ) -> Result<Option<(Vec<u8>, usize, bool)>, Error> {
@@ -335,7 +545,12 @@ impl<'a, R: Relay> Round<'a, R> {
                     }
                 }
             }
-            let msg = msg?;
+            let msg = msg.map_err(|err| match err {
+                Error::Recv => self
+                    .rejected()
+                    .map_or(Error::Recv, Error::InvalidMessageFrom),
+                err => err,
+            })?;
             self.count -= 1;
             Some(msg)
         } else {
@@ -355,9 +570,24 @@ impl<'a, R: Relay> Round<'a, R> {
         self.relay.put_back(msg, tag, party_id);
         self.count += 1;
 
+        if tag == self.tag && !self.rejected.contains(&party_id) {
+            self.rejected.push(party_id);
+        }
+
         // TODO Should we ASK it again?
     }
 
+    // Returns a party whose invalid message was put back and which
+    // has not sent a valid one since.
+    fn rejected(&self) -> Option<usize> {
+        self.rejected.iter().copied().find(|&p| {
+            self.relay
+                .expected
+                .values()
+                .any(|&(q, t)| q == p && t == self.tag)
+        })
+    }
+
     /// Receives all messages in the round, verifies them, decodes them, and passes them to a handler.
     ///
     /// # Type Parameters
@@ -374,8 +604,77 @@ impl<'a, R: Relay> Round<'a, R> {
     /// # Returns
     /// `Ok(())` if all messages are successfully processed, or an error if any message fails
     pub async fn of_signed_messages<T, F, S, E>(
+        self,
+        setup: &S,
+        abort_err: impl Fn(usize) -> E,
+        mut handler: F,
+    ) -> Result<(), E>
+    where
+        T: AnyBitPattern + NoUninit,
+        S: ProtocolParticipant,
+        F: FnMut(&T, usize) -> Result<(), E>,
+        E: From<Error>,
+    {
+        self.of_signed_messages_with_trailer(
+            setup,
+            0,
+            abort_err,
+            |msg, _, party_idx| handler(msg, party_idx),
+        )
+        .await
+    }
+
+    /// Receives all messages in the round with a trailer of fixed size,
+    /// verifies them, decodes them, and passes them to a handler.
+    ///
+    /// # Arguments
+    /// * `setup` - The protocol participant setup
+    /// * `trailer` - Size of the trailer data
+    /// * `abort_err` - Function to create an error from an abort message
+    /// * `handler` - Function to handle each received message and its trailer
+    ///
+    /// # Returns
+    /// `Ok(())` if all messages are successfully processed, or an error if any message fails
+    pub async fn of_signed_messages_with_trailer<T, F, S, E>(
+        mut self,
+        setup: &S,
+        trailer: usize,
+        abort_err: impl Fn(usize) -> E,
+        mut handler: F,
+    ) -> Result<(), E>
+    where
+        T: AnyBitPattern + NoUninit,
+        S: ProtocolParticipant,
+        F: FnMut(&T, &[u8], usize) -> Result<(), E>,
+        E: From<Error>,
+    {
+        self.recv_signed_messages(
+            setup,
+            trailer,
+            &abort_err,
+            &mut handler,
+            None,
+        )
+        .await
+    }
+
+    /// Receives all messages of a broadcast round like
+    /// [`Round::of_signed_messages()`] and then runs an echo round, if
+    /// enabled, see [`FilteredMsgRelay::with_echo_broadcast()`].
+    ///
+    /// # Arguments
+    /// * `setup` - The protocol participant setup
+    /// * `own` - Own signed message of the round and the party index,
+    ///   if this party sent one
+    /// * `abort_err` - Function to create an error from an abort message
+    /// * `handler` - Function to handle each received message
+    ///
+    /// # Returns
+    /// `Ok(())` if all messages are successfully processed, or an error if any message fails
+    pub async fn of_echoed_signed_messages<T, F, S, E>(
         mut self,
         setup: &S,
+        own: Option<(usize, Vec<u8>)>,
         abort_err: impl Fn(usize) -> E,
         mut handler: F,
     ) -> Result<(), E>
@@ -384,24 +683,69 @@ impl<'a, R: Relay> Round<'a, R> {
         S: ProtocolParticipant,
         F: FnMut(&T, usize) -> Result<(), E>,
         E: From<Error>,
+    {
+        let echo = self.relay.echo;
+        let mut messages: Vec<_> = own.into_iter().collect();
+
+        self.recv_signed_messages(
+            setup,
+            0,
+            &abort_err,
+            &mut |msg, _, party_idx| handler(msg, party_idx),
+            echo.then_some(&mut messages),
+        )
+        .await?;
+
+        if echo {
+            self.relay.echo(setup, self.tag, messages).await?;
+        }
+
+        Ok(())
+    }
+
+    // Receives, verifies and handles all signed messages of the round.
+    // Keeps copies of the messages in `messages`, if given.
+    async fn recv_signed_messages<T, F, S, E>(
+        &mut self,
+        setup: &S,
+        trailer: usize,
+        abort_err: &impl Fn(usize) -> E,
+        handler: &mut F,
+        mut messages: Option<&mut Vec<(usize, Vec<u8>)>>,
+    ) -> Result<(), E>
+    where
+        T: AnyBitPattern + NoUninit,
+        S: ProtocolParticipant,
+        F: FnMut(&T, &[u8], usize) -> Result<(), E>,
+        E: From<Error>,
     {
         while let Some((msg, party_idx, is_abort)) = self.recv().await? {
             if is_abort {
-                check_abort(setup, &msg, party_idx, &abort_err)?;
+                let ctx = self.relay.signing_context();
+                check_abort(setup, ctx, &msg, party_idx, abort_err)?;
                 self.put_back(&msg, ABORT_MESSAGE_TAG, party_idx);
                 continue;
             }
 
-            let vk = setup.verifier(party_idx);
-            let msg: &T = match SignedMessage::verify(&msg, vk) {
-                Some(refs) => refs,
-                _ => {
-                    self.put_back(&msg, self.tag, party_idx);
-                    continue;
-                }
-            };
+            let (payload, tr): (&T, _) =
+                match SignedMessage::verify_with_context(
+                    &msg,
+                    trailer,
+                    self.relay.signing_context(),
+                    setup.verifier(party_idx),
+                ) {
+                    Some(refs) => refs,
+                    _ => {
+                        self.put_back(&msg, self.tag, party_idx);
+                        continue;
+                    }
+                };
+
+            handler(payload, tr, party_idx)?;
 
-            handler(msg, party_idx)?;
+            if let Some(messages) = messages.as_deref_mut() {
+                messages.push((party_idx, msg));
+            }
         }
 
         Ok(())
@@ -445,11 +789,20 @@ impl<'a, R: Relay> Round<'a, R> {
     {
         while let Some((msg, party_index, is_abort)) = self.recv().await? {
             if is_abort {
-                check_abort(setup, &msg, party_index, &err)?;
+                let ctx = self.relay.signing_context();
+                check_abort(setup, ctx, &msg, party_index, &err)?;
                 self.put_back(&msg, ABORT_MESSAGE_TAG, party_index);
                 continue;
             }
 
+            // a message of another encryption scheme will never
+            // decrypt, fail instead of waiting for the timeout
+            if EncryptedMessage::<T>::scheme_id(&msg)
+                != Some(scheme.scheme_id())
+            {
+                return Err(Error::InvalidMessageFrom(party_index).into());
+            }
+
             let mut msg = Zeroizing::new(msg);
 
             let (msg, trailer) = match EncryptedMessage::<T>::decrypt(
@@ -457,6 +810,7 @@ impl<'a, R: Relay> Round<'a, R> {
                 trailer,
                 scheme,
                 party_index,
+                self.tag,
             ) {
                 Some(refs) => refs,
                 _ => {
@@ -540,13 +894,26 @@ impl<'a, R: Relay> Round<'a, R> {
             out = msg.2.encode(out);
             msg.3.encode(out);
 
-            buffer.sign(setup.signer())
+            buffer.sign_with_context(
+                self.relay.signing_context(),
+                setup.signer(),
+            )
         };
 
-        self.relay.send(buffer).await.map_err(|_| Error::Send)?;
+        let own = self.relay.echo.then(|| (my_party_id, buffer.clone()));
 
-        let (mut p0, mut p1, mut p2, mut p3) =
-            self.recv_broadcast_4(setup, &sizes).await?;
+        self.relay
+            .send_broadcast(buffer)
+            .await
+            .map_err(|_| Error::Send)?;
+
+        let (mut p0, mut p1, mut p2, mut p3) = self
+            .recv_broadcast_4_and_echo(
+                setup,
+                &sizes,
+                own.into_iter().collect(),
+            )
+            .await?;
 
         p0.push(my_party_id, msg.0);
         p1.push(my_party_id, msg.1);
@@ -572,9 +939,36 @@ impl<'a, R: Relay> Round<'a, R> {
     /// # Returns
     /// A tuple of four `Pairs` containing the received messages and their senders
     pub async fn recv_broadcast_4<P, T1, T2, T3, T4>(
+        self,
+        setup: &P,
+        sizes: &[usize; 4],
+    ) -> Result<
+        (
+            Pairs<T1, usize>,
+            Pairs<T2, usize>,
+            Pairs<T3, usize>,
+            Pairs<T4, usize>,
+        ),
+        Error,
+    >
+    where
+        P: ProtocolParticipant,
+        T1: Wrap,
+        T2: Wrap,
+        T3: Wrap,
+        T4: Wrap,
+    {
+        self.recv_broadcast_4_and_echo(setup, sizes, vec![]).await
+    }
+
+    // Receives messages of a broadcast round and runs an echo round
+    // if enabled. `messages` contains own signed message of the round,
+    // if any.
+    async fn recv_broadcast_4_and_echo<P, T1, T2, T3, T4>(
         mut self,
         setup: &P,
         sizes: &[usize; 4],
+        mut messages: Vec<(usize, Vec<u8>)>,
     ) -> Result<
         (
             Pairs<T1, usize>,
@@ -600,14 +994,16 @@ impl<'a, R: Relay> Round<'a, R> {
 
         while let Some((msg, party_id, is_abort)) = self.recv().await? {
             if is_abort {
-                check_abort(setup, &msg, party_id, Error::Abort)?;
+                let ctx = self.relay.signing_context();
+                check_abort(setup, ctx, &msg, party_id, Error::Abort)?;
                 self.put_back(&msg, ABORT_MESSAGE_TAG, party_id);
                 continue;
             }
 
-            let buf = match SignedMessage::<(), _>::verify_with_trailer(
+            let buf = match SignedMessage::<(), _>::verify_with_context(
                 &msg,
                 trailer,
+                self.relay.signing_context(),
                 setup.verifier(party_id),
             ) {
                 Some((_, msg)) => msg,
@@ -618,14 +1014,17 @@ impl<'a, R: Relay> Round<'a, R> {
                 }
             };
 
-            let (buf, v1) =
-                T1::decode(buf, sizes[0]).ok_or(Error::InvalidMessage)?;
-            let (buf, v2) =
-                T2::decode(buf, sizes[1]).ok_or(Error::InvalidMessage)?;
-            let (buf, v3) =
-                T3::decode(buf, sizes[2]).ok_or(Error::InvalidMessage)?;
-            let (_bu, v4) =
-                T4::decode(buf, sizes[3]).ok_or(Error::InvalidMessage)?;
+            if self.relay.echo {
+                messages.push((party_id, msg.clone()));
+            }
+
+            // the message is verified, so its sender is to blame
+            let invalid = || Error::InvalidMessageFrom(party_id);
+
+            let (buf, v1) = T1::decode(buf, sizes[0]).ok_or_else(invalid)?;
+            let (buf, v2) = T2::decode(buf, sizes[1]).ok_or_else(invalid)?;
+            let (buf, v3) = T3::decode(buf, sizes[2]).ok_or_else(invalid)?;
+            let (_bu, v4) = T4::decode(buf, sizes[3]).ok_or_else(invalid)?;
 
             p0.push(party_id, v1);
             p1.push(party_id, v2);
@@ -633,6 +1032,10 @@ impl<'a, R: Relay> Round<'a, R> {
             p3.push(party_id, v4);
         }
 
+        if self.relay.echo {
+            self.relay.echo(setup, self.tag, messages).await?;
+        }
+
         Ok((p0, p1, p2, p3))
     }
 }
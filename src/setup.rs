@@ -12,7 +12,7 @@ pub use signature::{SignatureEncoding, Signer, Verifier};
 
 use sl_mpc_mate::message::{InstanceId, MessageTag, MsgId};
 
-use crate::{keygen::Keyshare, sign::PreSign};
+use crate::{keygen::Keyshare, proto::SchemeId, sign::PreSign};
 
 /// Tag for all setup messages
 pub const SETUP_MESSAGE_TAG: MessageTag = MessageTag::tag(0);
@@ -90,6 +90,35 @@ pub trait ProtocolParticipant {
     /// Return message Time To Live.
     fn message_ttl(&self) -> Duration;
 
+    /// Return the encryption scheme of P2P messages. All participants
+    /// of a protocol must use the same scheme.
+    fn encryption_scheme(&self) -> SchemeId {
+        SchemeId::default()
+    }
+
+    /// Return whether to compress broadcast messages after signing.
+    /// Encrypted P2P messages are never compressed. Compressed
+    /// messages are marked in the message header, so participants may
+    /// choose this independently.
+    fn message_compression(&self) -> bool {
+        false
+    }
+
+    /// Return whether parties exchange hashes of broadcast messages
+    /// they received, to detect a sender which sent different messages
+    /// to different parties. All participants of a protocol execution
+    /// must agree on this, otherwise the execution stalls.
+    fn echo_broadcast(&self) -> bool {
+        false
+    }
+
+    /// Return long-term encryption keys of all participants, or `None`
+    /// if parties exchange ephemeral keys in the first round of a
+    /// protocol.
+    fn encryption_keys(&self) -> Option<&EncryptionKeys> {
+        None
+    }
+
     /// Return reference to participant's own verifier
     fn participant_verifier(&self) -> &Self::MessageVerifier {
         self.verifier(self.participant_index())
@@ -164,6 +193,22 @@ impl<M: ProtocolParticipant> ProtocolParticipant for &M {
     fn message_ttl(&self) -> Duration {
         (**self).message_ttl()
     }
+
+    fn encryption_scheme(&self) -> SchemeId {
+        (**self).encryption_scheme()
+    }
+
+    fn message_compression(&self) -> bool {
+        (**self).message_compression()
+    }
+
+    fn echo_broadcast(&self) -> bool {
+        (**self).echo_broadcast()
+    }
+
+    fn encryption_keys(&self) -> Option<&EncryptionKeys> {
+        (**self).encryption_keys()
+    }
 }
 
 /// A setup message for keygen::run()
@@ -180,6 +225,11 @@ pub trait KeygenSetupMessage: ProtocolParticipant {
     /// Derive key_id from a public_key.
     fn derive_key_id(&self, public_key: &[u8]) -> [u8; 32];
 
+    /// Key identifier given by the setup, if any.
+    fn key_id(&self) -> Option<&[u8]> {
+        None
+    }
+
     /// Additional data to amend into the Keyshare.
     fn keyshare_extra(&self) -> &[u8] {
         &[]
@@ -199,6 +249,10 @@ impl<M: KeygenSetupMessage> KeygenSetupMessage for &M {
         (**self).derive_key_id(public_key)
     }
 
+    fn key_id(&self) -> Option<&[u8]> {
+        (**self).key_id()
+    }
+
     fn keyshare_extra(&self) -> &[u8] {
         (**self).keyshare_extra()
     }
@@ -218,6 +272,20 @@ pub trait PreSignSetupMessage: ProtocolParticipant {
     }
 }
 
+impl<M: PreSignSetupMessage> PreSignSetupMessage for &M {
+    fn keyshare(&self) -> &Keyshare {
+        (**self).keyshare()
+    }
+
+    fn chain_path(&self) -> &DerivationPath {
+        (**self).chain_path()
+    }
+
+    fn presignature_extra(&self) -> &[u8] {
+        (**self).presignature_extra()
+    }
+}
+
 /// A setup message for sign::finish()
 pub trait FinalSignSetupMessage: ProtocolParticipant {
     /// Pre-signature created by sign::pre_signature()
@@ -233,6 +301,12 @@ pub trait SignSetupMessage: PreSignSetupMessage {
     fn message_hash(&self) -> [u8; 32];
 }
 
+impl<M: SignSetupMessage> SignSetupMessage for &M {
+    fn message_hash(&self) -> [u8; 32] {
+        (**self).message_hash()
+    }
+}
+
 /// A setup message for key export.
 pub trait KeyExporterSetupMessage<PK, KS>: ProtocolParticipant {
     /// Public key of a receiver party.
@@ -338,3 +412,9 @@ mod keys;
 
 /// Setup for Quorum Change
 pub mod quorum_change;
+
+/// Signed wire encoding of setup messages
+pub mod wire;
+
+/// Setup approval pre-round
+pub mod approval;
@@ -0,0 +1,507 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Optional setup approval pre-round.
+//!
+//! Each participant broadcasts a signed canonical hash of its setup
+//! message and checks that hashes of all other participants are equal
+//! to its own. This detects a coordinator that hands different parties
+//! different protocol parameters: threshold, ranks, participants,
+//! message hash, etc.
+//!
+//! ```no_run
+//! # use sl_dkls23::{keygen, setup::{approval, keygen::SetupMessage}};
+//! # use sl_mpc_mate::coord::Relay;
+//! # async fn example<R: Relay>(
+//! #     setup: SetupMessage,
+//! #     seed: [u8; 32],
+//! #     relay: R,
+//! # ) -> Result<(), Box<dyn std::error::Error>> {
+//! let hash = approval::keygen_setup_hash(&setup);
+//! let relay = approval::run(&setup, hash, relay).await?;
+//! let share = keygen::run(setup, seed, relay).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use futures_util::SinkExt;
+use k256::{elliptic_curve::group::GroupEncoding, ProjectivePoint};
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, ReusableSecret};
+
+use sl_mpc_mate::{
+    coord::{MessageSendError, Relay},
+    message::{MessageTag, MsgId},
+};
+
+use sl_oblivious::label::Label;
+
+use crate::{
+    keygen::Keyshare,
+    proto::{
+        create_abort_message, tags::Error, FilteredMsgRelay, Round,
+        SignedMessage, SigningContext,
+    },
+    setup::{
+        FinalSignSetupMessage, KeyExportReceiverSetupMessage,
+        KeyExporterSetupMessage, KeygenSetupMessage, PreSignSetupMessage,
+        ProtocolParticipant, QuorumChangeSetupMessage, SignSetupMessage,
+        ABORT_MESSAGE_TAG, SETUP_MESSAGE_TAG,
+    },
+    VERSION,
+};
+
+/// Tag of a setup approval message, from the family of
+/// [`SETUP_MESSAGE_TAG`]. It differs from the tag of a setup broadcast
+/// by an initiator that may be a participant too.
+pub const SETUP_APPROVAL_TAG: MessageTag = MessageTag::tag1(0, 1);
+
+/// Label used for signatures of setup approval messages.
+pub const SETUP_APPROVAL_LABEL: Label = Label::new(VERSION, 500);
+
+/// Errors of the setup approval round.
+#[derive(Debug, thiserror::Error)]
+pub enum SetupApprovalError {
+    /// A participant has a different setup
+    #[error("Setup of party {0} differs")]
+    SetupMismatch(usize),
+
+    /// A required message is missing
+    #[error("Missing message")]
+    MissingMessage,
+
+    /// Failed to send a message
+    #[error("Send message")]
+    SendMessage,
+
+    /// A party has decided to abort the protocol
+    #[error("Abort protocol by party {0}")]
+    AbortProtocol(usize),
+}
+
+impl From<MessageSendError> for SetupApprovalError {
+    fn from(_err: MessageSendError) -> Self {
+        SetupApprovalError::SendMessage
+    }
+}
+
+impl From<Error> for SetupApprovalError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Abort(p) => SetupApprovalError::AbortProtocol(p),
+            Error::Recv
+            | Error::InvalidMessage
+            | Error::InvalidMessageFrom(_)
+            | Error::EchoMismatch { .. } => {
+                SetupApprovalError::MissingMessage
+            }
+            Error::Send => SetupApprovalError::SendMessage,
+        }
+    }
+}
+
+/// Run the setup approval round.
+///
+/// Broadcast `setup_hash` and wait for hashes of all other
+/// participants. Returns the relay to pass into the protocol if all
+/// hashes are equal to `setup_hash`. Otherwise broadcasts an abort
+/// message, so other participants do not wait for this one.
+pub async fn run<S, R>(
+    setup: &S,
+    setup_hash: [u8; 32],
+    relay: R,
+) -> Result<R, SetupApprovalError>
+where
+    S: ProtocolParticipant,
+    R: Relay,
+{
+    let ctx = signing_context();
+    let mut relay = FilteredMsgRelay::new(relay).with_signing_context(ctx);
+    let abort_msg = create_abort_message(setup, &ctx);
+
+    match run_inner(setup, setup_hash, &mut relay).await {
+        Ok(()) => Ok(relay.into_inner()),
+        Err(SetupApprovalError::AbortProtocol(p)) => {
+            Err(SetupApprovalError::AbortProtocol(p))
+        }
+        Err(SetupApprovalError::SendMessage) => {
+            Err(SetupApprovalError::SendMessage)
+        }
+        Err(err) => {
+            // ignore error of sending abort message
+            let _ = relay.send(abort_msg).await;
+            Err(err)
+        }
+    }
+}
+
+async fn run_inner<S, R>(
+    setup: &S,
+    setup_hash: [u8; 32],
+    relay: &mut FilteredMsgRelay<R>,
+) -> Result<(), SetupApprovalError>
+where
+    S: ProtocolParticipant,
+    R: Relay,
+{
+    let count = relay.ask_messages(setup, SETUP_APPROVAL_TAG, false).await?;
+    relay.ask_messages(setup, ABORT_MESSAGE_TAG, false).await?;
+
+    let msg = SignedMessage::<[u8; 32], _>::build_with_context(
+        &setup.msg_id(None, SETUP_APPROVAL_TAG),
+        setup.message_ttl().as_secs() as _,
+        0,
+        relay.signing_context(),
+        setup.signer(),
+        |hash: &mut [u8; 32], _| *hash = setup_hash,
+    );
+    relay.send(msg).await?;
+
+    Round::new(count, SETUP_APPROVAL_TAG, relay)
+        .of_signed_messages(
+            setup,
+            SetupApprovalError::AbortProtocol,
+            |hash: &[u8; 32], party_idx| {
+                if hash == &setup_hash {
+                    Ok(())
+                } else {
+                    Err(SetupApprovalError::SetupMismatch(party_idx))
+                }
+            },
+        )
+        .await
+}
+
+/// Context of signatures of setup approval messages.
+///
+/// It is not bound to the setup hash: parties with different setups
+/// must verify messages of each other to detect the mismatch. IDs of
+/// messages bind them to the protocol instance.
+fn signing_context() -> SigningContext {
+    SigningContext::new(&SETUP_APPROVAL_LABEL, VERSION, &[0; 32])
+}
+
+/// Hash of parameters common to all protocols: instance ID, encryption
+/// scheme, echo rounds, verifying keys and long-term encryption keys
+/// of all participants.
+///
+/// A message TTL and compression of messages are local policies of a
+/// party, parties with different TTLs or compression settings could
+/// run a protocol, so they are not a part of the hash.
+fn participant_hasher<S: ProtocolParticipant>(
+    setup: &S,
+    label: &[u8],
+) -> Sha256 {
+    let mut hasher = Sha256::new()
+        .chain_update(b"dkls23-setup-hash")
+        .chain_update(label)
+        .chain_update(
+            MsgId::broadcast(setup.instance_id(), &[], SETUP_MESSAGE_TAG)
+                .as_slice(),
+        )
+        .chain_update([setup.encryption_scheme() as u8])
+        .chain_update([setup.echo_broadcast() as u8])
+        .chain_update((setup.total_participants() as u64).to_be_bytes());
+
+    for p in 0..setup.total_participants() {
+        let vk = setup.verifier(p).as_ref();
+        hasher.update((vk.len() as u64).to_be_bytes());
+        hasher.update(vk);
+    }
+
+    match setup.encryption_keys() {
+        Some(keys) => {
+            hasher.update([1]);
+            for pk in keys.public_keys() {
+                hasher.update(pk.as_bytes());
+            }
+        }
+        None => hasher.update([0]),
+    }
+
+    hasher
+}
+
+/// Canonical hash of a setup of `keygen::run()`.
+pub fn keygen_setup_hash<S: KeygenSetupMessage>(setup: &S) -> [u8; 32] {
+    let mut hasher = participant_hasher(setup, b"keygen");
+
+    hasher.update([setup.threshold()]);
+    for p in 0..setup.total_participants() {
+        hasher.update([setup.participant_rank(p)]);
+    }
+    match setup.key_id() {
+        Some(key_id) => {
+            hasher.update([1]);
+            hasher.update((key_id.len() as u64).to_be_bytes());
+            hasher.update(key_id);
+        }
+        None => hasher.update([0]),
+    }
+    hasher.update(setup.keyshare_extra());
+
+    hasher.finalize().into()
+}
+
+/// Canonical hash of a setup of `sign::run()`.
+pub fn sign_setup_hash<S: SignSetupMessage>(setup: &S) -> [u8; 32] {
+    let keyshare = setup.keyshare();
+
+    participant_hasher(setup, b"sign")
+        .chain_update(keyshare.key_id)
+        .chain_update(keyshare.public_key)
+        .chain_update(setup.chain_path().to_string())
+        .chain_update(setup.message_hash())
+        .finalize()
+        .into()
+}
+
+/// Canonical hash of a setup of `sign::pre_signature()`.
+pub fn pre_sign_setup_hash<S: PreSignSetupMessage>(setup: &S) -> [u8; 32] {
+    let keyshare = setup.keyshare();
+
+    participant_hasher(setup, b"pre-sign")
+        .chain_update(keyshare.key_id)
+        .chain_update(keyshare.public_key)
+        .chain_update(setup.chain_path().to_string())
+        .finalize()
+        .into()
+}
+
+/// Canonical hash of a setup of `sign::finish()`.
+pub fn finish_setup_hash<S: FinalSignSetupMessage>(setup: &S) -> [u8; 32] {
+    participant_hasher(setup, b"finish")
+        .chain_update(setup.pre_signature().final_session_id)
+        .chain_update(setup.message_hash())
+        .finalize()
+        .into()
+}
+
+/// Canonical hash of a setup of `quorum_change::run()`.
+pub fn quorum_change_setup_hash<S, KS>(setup: &S) -> [u8; 32]
+where
+    S: QuorumChangeSetupMessage<KS, ProjectivePoint>,
+{
+    let mut hasher = participant_hasher(setup, b"quorum-change");
+
+    hasher.update([setup.new_threshold()]);
+    hasher.update(setup.expected_public_key().to_bytes());
+
+    hasher.update((setup.old_party_indices().len() as u64).to_be_bytes());
+    for &p in setup.old_party_indices() {
+        hasher.update((p as u64).to_be_bytes());
+    }
+
+    hasher.update((setup.new_party_indices().len() as u64).to_be_bytes());
+    for (party_id, &p) in setup.new_party_indices().iter().enumerate() {
+        hasher.update((p as u64).to_be_bytes());
+        hasher.update([setup.new_participant_rank(party_id as u8)]);
+    }
+
+    hasher.update(setup.keyshare_extra());
+
+    hasher.finalize().into()
+}
+
+fn key_export_hash<S: ProtocolParticipant>(
+    setup: &S,
+    keyshare: &Keyshare,
+    receiver_public_key: &PublicKey,
+) -> [u8; 32] {
+    participant_hasher(setup, b"key-export")
+        .chain_update(keyshare.key_id)
+        .chain_update(receiver_public_key.as_bytes())
+        .finalize()
+        .into()
+}
+
+/// Canonical hash of a setup of an exporter of a keyshare.
+pub fn key_exporter_setup_hash<S>(setup: &S) -> [u8; 32]
+where
+    S: KeyExporterSetupMessage<PublicKey, Keyshare>,
+{
+    key_export_hash(setup, setup.keyshare(), setup.receiver_public_key())
+}
+
+/// Canonical hash of a setup of the receiver of an exported key.
+/// Equal to the hash of the exporters' setups.
+pub fn key_export_receiver_setup_hash<S>(setup: &S) -> [u8; 32]
+where
+    S: KeyExportReceiverSetupMessage<ReusableSecret>,
+{
+    key_export_hash(
+        setup,
+        setup.keyshare(),
+        &PublicKey::from(setup.receiver_private_key()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use sl_mpc_mate::coord::SimpleMessageRelay;
+    use tokio::task::JoinSet;
+
+    use crate::keygen::utils::setup_keygen;
+
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keygen_with_approval() {
+        let coord = SimpleMessageRelay::new();
+        let mut parties = JoinSet::new();
+
+        for (setup, seed) in setup_keygen(None, 2, 3, None) {
+            let relay = coord.connect();
+
+            parties.spawn(async move {
+                let hash = keygen_setup_hash(&setup);
+                let relay = run(&setup, hash, relay).await.unwrap();
+
+                crate::keygen::run(setup, seed, relay).await
+            });
+        }
+
+        let mut public_keys = vec![];
+        while let Some(fini) = parties.join_next().await {
+            public_keys.push(fini.unwrap().unwrap().public_key());
+        }
+
+        assert!(public_keys.iter().all(|pk| pk == &public_keys[0]));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn different_threshold_detected() {
+        let instance = rand::random();
+
+        let mut setups = setup_keygen(Some(instance), 2, 3, None);
+        let other = setup_keygen(Some(instance), 3, 3, None);
+
+        assert_ne!(
+            keygen_setup_hash(&setups[2].0),
+            keygen_setup_hash(&other[2].0)
+        );
+        setups[2] = other.into_iter().nth(2).unwrap();
+
+        let coord = SimpleMessageRelay::new();
+        let mut parties = JoinSet::new();
+
+        for (setup, _) in setups {
+            let relay = coord.connect();
+            parties.spawn(async move {
+                let hash = keygen_setup_hash(&setup);
+                run(&setup, hash, relay).await.map(|_| ())
+            });
+        }
+
+        // a party detects the mismatch or receives an abort message
+        // of a party that has detected it.
+        while let Some(fini) = parties.join_next().await {
+            assert!(matches!(
+                fini.unwrap(),
+                Err(SetupApprovalError::SetupMismatch(_)
+                    | SetupApprovalError::AbortProtocol(_))
+            ));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn refusal_aborts() {
+        let instance = rand::random();
+
+        let setups = setup_keygen(Some(instance), 2, 3, None);
+
+        let coord = SimpleMessageRelay::new();
+        let mut parties = JoinSet::new();
+
+        for (idx, (setup, _)) in setups.into_iter().enumerate() {
+            let relay = coord.connect();
+            parties.spawn(async move {
+                // the last party does not approve the setup
+                let hash = if idx == 2 {
+                    [0; 32]
+                } else {
+                    keygen_setup_hash(&setup)
+                };
+                (idx, run(&setup, hash, relay).await.map(|_| ()))
+            });
+        }
+
+        while let Some(fini) = parties.join_next().await {
+            let (idx, res) = fini.unwrap();
+            match res {
+                Err(SetupApprovalError::SetupMismatch(p)) => {
+                    assert!(idx == 2 || p == 2)
+                }
+                Err(SetupApprovalError::AbortProtocol(p)) => {
+                    assert_ne!(p, idx)
+                }
+                res => panic!("unexpected result {res:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn key_id_is_hashed() {
+        let instance = rand::random();
+
+        let (setup, _) = setup_keygen(Some(instance), 2, 3, None).remove(0);
+        let (other, _) = setup_keygen(Some(instance), 2, 3, None).remove(0);
+        let other = other.with_key_id(Some([1; 32]));
+
+        assert_ne!(keygen_setup_hash(&setup), keygen_setup_hash(&other));
+    }
+
+    #[test]
+    fn quorum_change_hash() {
+        use sl_mpc_mate::message::InstanceId;
+
+        use crate::setup::{keys::*, quorum_change::SetupMessage};
+
+        let vk: Vec<_> = (0..3).map(NoVerifyingKey::new).collect();
+        let instance = InstanceId::new(rand::random());
+
+        let setup = |new_t| -> SetupMessage {
+            SetupMessage::new(
+                instance,
+                0,
+                &[0, 1],
+                &[(1, 0), (2, 0)],
+                new_t,
+                NoSigningKey,
+                vk.clone(),
+                ProjectivePoint::GENERATOR,
+            )
+        };
+
+        assert_eq!(
+            quorum_change_setup_hash(&setup(2)),
+            quorum_change_setup_hash(&setup(2))
+        );
+        assert_ne!(
+            quorum_change_setup_hash(&setup(2)),
+            quorum_change_setup_hash(&setup(1))
+        );
+    }
+
+    #[test]
+    fn ttl_is_not_hashed() {
+        let instance = rand::random();
+
+        let (setup, _) = setup_keygen(Some(instance), 2, 3, None).remove(0);
+        let (other, _) = setup_keygen(Some(instance), 2, 3, None).remove(0);
+        let other = other.with_ttl(std::time::Duration::from_secs(1));
+
+        assert_eq!(keygen_setup_hash(&setup), keygen_setup_hash(&other));
+    }
+
+    #[test]
+    fn compression_is_not_hashed() {
+        let instance = rand::random();
+
+        let (setup, _) = setup_keygen(Some(instance), 2, 3, None).remove(0);
+        let (other, _) = setup_keygen(Some(instance), 2, 3, None).remove(0);
+        let other = other.with_message_compression(true);
+
+        assert_eq!(keygen_setup_hash(&setup), keygen_setup_hash(&other));
+    }
+}
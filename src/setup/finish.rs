@@ -11,7 +11,7 @@ use sl_mpc_mate::message::InstanceId;
 
 use crate::{
     setup::{
-        keys::{NoSignature, NoSigningKey, NoVerifyingKey},
+        keys::{NoSignature, NoSigningKey, NoVerifyingKey, PartyKeys},
         FinalSignSetupMessage, ProtocolParticipant,
     },
     sign::PreSign,
@@ -85,6 +85,26 @@ impl<SK, VK, MS, PS> SetupMessage<SK, VK, MS, PS> {
         }
     }
 
+    /// Creates a new setup message for finalizing signing operations
+    /// from the keys of the participants.
+    ///
+    /// # Arguments
+    /// * `instance` - Instance identifier for the protocol
+    /// * `keys` - Signing key of the current party and verifying keys of all participants
+    /// * `pre` - Pre-signature used in the final signing step
+    ///
+    /// # Returns
+    /// A new `SetupMessage` instance with default TTL and zero hash
+    pub fn from_keys(
+        instance: InstanceId,
+        keys: PartyKeys<SK, VK>,
+        pre: PS,
+    ) -> Self {
+        let (sk, party_idx, vk) = keys.into_parts();
+
+        Self::new(instance, party_idx, sk, vk, pre)
+    }
+
     /// Sets the hash of the message to be signed.
     ///
     /// # Arguments
@@ -8,9 +8,10 @@ use x25519_dalek::{PublicKey, ReusableSecret};
 
 use crate::{
     keygen::Keyshare,
+    proto::SchemeId,
     setup::{
         self,
-        keys::{NoSignature, NoSigningKey, NoVerifyingKey},
+        keys::{NoSignature, NoSigningKey, NoVerifyingKey, PartyKeys},
         ProtocolParticipant,
     },
 };
@@ -29,6 +29,8 @@ pub struct KeyExporter<
     inst: InstanceId,
     /// Time-to-live duration for messages
     ttl: Duration,
+    /// Encryption scheme of P2P messages
+    enc_scheme: SchemeId,
     /// Public key of the receiver
     pub_key: PublicKey,
     /// Reference to the keyshare to be exported
@@ -64,12 +66,35 @@ impl<SK, VK, MS, KS> KeyExporter<SK, VK, MS, KS> {
             vk,
             inst,
             ttl: Duration::from_secs(DEFAULT_TTL),
+            enc_scheme: SchemeId::default(),
             marker: PhantomData,
             pub_key: enc_pub_key,
             share,
         }
     }
 
+    /// Creates a new setup message for key export operations from the
+    /// keys of the participants.
+    ///
+    /// # Arguments
+    /// * `inst` - Instance identifier for the protocol
+    /// * `keys` - Signing key of the current party and verifying keys of all participants
+    /// * `share` - Reference to the keyshare to be exported
+    /// * `enc_pub_key` - Public key of the receiver
+    ///
+    /// # Returns
+    /// A new `KeyExporter` instance with default TTL
+    pub fn from_keys(
+        inst: InstanceId,
+        keys: PartyKeys<SK, VK>,
+        share: Arc<KS>,
+        enc_pub_key: PublicKey,
+    ) -> Self {
+        let (sk, party_id, vk) = keys.into_parts();
+
+        Self::new(inst, sk, party_id, vk, share, enc_pub_key)
+    }
+
     /// Returns a reference to the keyshare to be exported.
     pub fn keyshare(&self) -> &KS {
         &self.share
@@ -86,6 +111,18 @@ impl<SK, VK, MS, KS> KeyExporter<SK, VK, MS, KS> {
         self.ttl = ttl;
         self
     }
+
+    /// Sets the encryption scheme of P2P messages.
+    ///
+    /// # Arguments
+    /// * `scheme` - ID of the encryption scheme
+    ///
+    /// # Returns
+    /// The modified `KeyExporter` instance
+    pub fn with_encryption_scheme(mut self, scheme: SchemeId) -> Self {
+        self.enc_scheme = scheme;
+        self
+    }
 }
 
 impl<SK, VK, MS, KS> ProtocolParticipant for KeyExporter<SK, VK, MS, KS>
@@ -118,6 +155,11 @@ where
         self.ttl
     }
 
+    /// Returns the encryption scheme of P2P messages.
+    fn encryption_scheme(&self) -> SchemeId {
+        self.enc_scheme
+    }
+
     /// Returns the verifying key for a specific participant.
     ///
     /// # Arguments
@@ -27,6 +27,8 @@ pub struct KeyExportReceiver<
     inst: InstanceId,
     /// Time-to-live duration for messages
     ttl: Duration,
+    /// Encryption scheme of P2P messages
+    enc_scheme: SchemeId,
     /// Reference to the keyshare to be received
     share: Arc<Keyshare>,
     /// Private key used for decryption
@@ -62,12 +64,35 @@ impl<SK, VK, MS> KeyExportReceiver<SK, VK, MS> {
             vk,
             inst,
             ttl: Duration::from_secs(DEFAULT_TTL),
+            enc_scheme: SchemeId::default(),
             marker: PhantomData,
             share,
             enc_key,
         }
     }
 
+    /// Creates a new setup message for key export receiving operations
+    /// from the keys of the participants.
+    ///
+    /// # Arguments
+    /// * `inst` - Instance identifier for the protocol
+    /// * `keys` - Signing key of the current party and verifying keys of all participants
+    /// * `share` - Reference to the keyshare to be received
+    /// * `enc_key` - Private key used for decryption
+    ///
+    /// # Returns
+    /// A new `KeyExportReceiver` instance with default TTL
+    pub fn from_keys(
+        inst: InstanceId,
+        keys: PartyKeys<SK, VK>,
+        share: Arc<Keyshare>,
+        enc_key: ReusableSecret,
+    ) -> Self {
+        let (sk, party_id, vk) = keys.into_parts();
+
+        Self::new(inst, sk, party_id, vk, share, enc_key)
+    }
+
     /// Sets a custom time-to-live duration for messages.
     ///
     /// # Arguments
@@ -79,6 +104,18 @@ impl<SK, VK, MS> KeyExportReceiver<SK, VK, MS> {
         self.ttl = ttl;
         self
     }
+
+    /// Sets the encryption scheme of P2P messages.
+    ///
+    /// # Arguments
+    /// * `scheme` - ID of the encryption scheme
+    ///
+    /// # Returns
+    /// The modified `KeyExportReceiver` instance
+    pub fn with_encryption_scheme(mut self, scheme: SchemeId) -> Self {
+        self.enc_scheme = scheme;
+        self
+    }
 }
 
 impl<SK, VK, MS> ProtocolParticipant for KeyExportReceiver<SK, VK, MS>
@@ -111,6 +148,11 @@ where
         self.ttl
     }
 
+    /// Returns the encryption scheme of P2P messages.
+    fn encryption_scheme(&self) -> SchemeId {
+        self.enc_scheme
+    }
+
     /// Returns the verifying key for a specific participant.
     ///
     /// # Arguments
@@ -14,9 +14,15 @@ use sl_mpc_mate::message::InstanceId;
 /// Default Time-To-Live (TTL) value for messages in seconds
 const DEFAULT_TTL: u64 = 100; // smaller timeout might fail tests
 
-use crate::setup::{
-    keys::{NoSignature, NoSigningKey, NoVerifyingKey},
-    KeygenSetupMessage, ProtocolParticipant,
+use crate::{
+    proto::SchemeId,
+    setup::{
+        keys::{
+            EncryptionKeys, NoSignature, NoSigningKey, NoVerifyingKey,
+            PartyKeys,
+        },
+        KeygenSetupMessage, ProtocolParticipant,
+    },
 };
 
 /// A message used for setting up key generation in a multi-party computation protocol.
@@ -51,6 +57,14 @@ pub struct SetupMessage<
     inst: InstanceId,
     /// Time-to-live duration for messages
     ttl: Duration,
+    /// Encryption scheme of P2P messages
+    enc_scheme: SchemeId,
+    /// Whether to compress messages
+    compression: bool,
+    /// Whether to exchange hashes of broadcast messages
+    echo: bool,
+    /// Optional long-term encryption keys of all participants
+    enc_keys: Option<EncryptionKeys>,
     /// Phantom data to hold the message signature type
     marker: PhantomData<MS>,
 }
@@ -85,11 +99,37 @@ impl<SK, VK, MS> SetupMessage<SK, VK, MS> {
             inst,
             key_id: None,
             ttl: Duration::from_secs(DEFAULT_TTL),
+            enc_scheme: SchemeId::default(),
+            compression: false,
+            echo: false,
+            enc_keys: None,
             ranks: ranks.to_vec(),
             marker: PhantomData,
         }
     }
 
+    /// Creates a new setup message for key generation from the keys of
+    /// the participants.
+    ///
+    /// # Arguments
+    /// * `inst` - Instance identifier for the protocol
+    /// * `keys` - Signing key of the current party and verifying keys of all participants
+    /// * `ranks` - Ranks of all participants
+    /// * `t` - Threshold value for the protocol
+    ///
+    /// # Returns
+    /// A new `SetupMessage` instance with default TTL and no key ID
+    pub fn from_keys(
+        inst: InstanceId,
+        keys: PartyKeys<SK, VK>,
+        ranks: &[u8],
+        t: usize,
+    ) -> Self {
+        let (sk, party_id, vk) = keys.into_parts();
+
+        Self::new(inst, sk, party_id, vk, ranks, t)
+    }
+
     /// Sets a custom time-to-live duration for messages.
     ///
     /// # Arguments
@@ -102,6 +142,64 @@ impl<SK, VK, MS> SetupMessage<SK, VK, MS> {
         self
     }
 
+    /// Sets the encryption scheme of P2P messages.
+    ///
+    /// # Arguments
+    /// * `scheme` - ID of the encryption scheme
+    ///
+    /// # Returns
+    /// The modified `SetupMessage` instance
+    pub fn with_encryption_scheme(mut self, scheme: SchemeId) -> Self {
+        self.enc_scheme = scheme;
+        self
+    }
+
+    /// Enables or disables compression of messages, see
+    /// [`ProtocolParticipant::message_compression()`].
+    ///
+    /// # Arguments
+    /// * `compression` - Whether to compress messages
+    ///
+    /// # Returns
+    /// The modified `SetupMessage` instance
+    pub fn with_message_compression(mut self, compression: bool) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Enables or disables an echo round after each broadcast round,
+    /// see [`ProtocolParticipant::echo_broadcast()`].
+    ///
+    /// # Arguments
+    /// * `echo` - Whether to exchange hashes of broadcast messages
+    ///
+    /// # Returns
+    /// The modified `SetupMessage` instance
+    pub fn with_echo_broadcast(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    /// Sets long-term encryption keys of all participants. Parties
+    /// will not exchange encryption keys in the first round.
+    ///
+    /// # Arguments
+    /// * `keys` - Encryption keys in the order of verifying keys
+    ///
+    /// # Returns
+    /// The modified `SetupMessage` instance
+    ///
+    /// # Panics
+    /// If the index of own key differs from the index of the party or
+    /// the number of keys differs from the number of participants
+    pub fn with_encryption_keys(mut self, keys: EncryptionKeys) -> Self {
+        assert_eq!(keys.party_idx(), self.party_id);
+        assert_eq!(keys.public_keys().len(), self.vk.len());
+
+        self.enc_keys = Some(keys);
+        self
+    }
+
     /// Sets a custom key identifier.
     ///
     /// # Arguments
@@ -153,6 +251,26 @@ where
         self.ttl
     }
 
+    /// Returns the encryption scheme of P2P messages.
+    fn encryption_scheme(&self) -> SchemeId {
+        self.enc_scheme
+    }
+
+    /// Returns whether messages are compressed.
+    fn message_compression(&self) -> bool {
+        self.compression
+    }
+
+    /// Returns whether parties exchange hashes of broadcast messages.
+    fn echo_broadcast(&self) -> bool {
+        self.echo
+    }
+
+    /// Returns long-term encryption keys of all participants.
+    fn encryption_keys(&self) -> Option<&EncryptionKeys> {
+        self.enc_keys.as_ref()
+    }
+
     /// Returns the verifying key for a specific participant.
     ///
     /// # Arguments
@@ -203,4 +321,9 @@ where
         self.key_id
             .unwrap_or_else(|| Sha256::digest(public_key).into())
     }
+
+    /// Returns the key identifier if it exists.
+    fn key_id(&self) -> Option<&[u8]> {
+        SetupMessage::key_id(self)
+    }
 }
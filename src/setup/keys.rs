@@ -6,6 +6,7 @@
 use std::sync::Arc;
 
 use signature::{Keypair, SignatureEncoding, Signer, Verifier};
+use x25519_dalek::{PublicKey, ReusableSecret};
 
 /// A zero-sized type representing an empty signature.
 /// This type is used when no actual signature is needed, but the type system
@@ -164,3 +165,416 @@ impl Verifier<NoSignature> for NoVerifyingKey {
         Ok(())
     }
 }
+
+pub use ed25519_dalek::{
+    Signature as Ed25519Signature, SigningKey as Ed25519SigningKey,
+    VerifyingKey as Ed25519VerifyingKey,
+};
+
+pub use k256::ecdsa::{
+    Signature as Secp256k1Signature, SigningKey as Secp256k1SigningKey,
+};
+
+/// A secp256k1 ECDSA verifying key.
+///
+/// Wraps `k256::ecdsa::VerifyingKey` and keeps its compressed SEC1
+/// encoding, used as an identity to derive message IDs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Secp256k1VerifyingKey {
+    key: k256::ecdsa::VerifyingKey,
+    bytes: [u8; 33],
+}
+
+impl Secp256k1VerifyingKey {
+    /// Returns the wrapped verifying key.
+    pub fn key(&self) -> &k256::ecdsa::VerifyingKey {
+        &self.key
+    }
+}
+
+impl From<k256::ecdsa::VerifyingKey> for Secp256k1VerifyingKey {
+    /// Creates a `Secp256k1VerifyingKey` from a verifying key.
+    fn from(key: k256::ecdsa::VerifyingKey) -> Self {
+        let mut bytes = [0u8; 33];
+        bytes.copy_from_slice(key.to_encoded_point(true).as_bytes());
+
+        Self { key, bytes }
+    }
+}
+
+impl From<&Secp256k1SigningKey> for Secp256k1VerifyingKey {
+    /// Creates a `Secp256k1VerifyingKey` of a signing key.
+    fn from(sk: &Secp256k1SigningKey) -> Self {
+        Self::from(*sk.verifying_key())
+    }
+}
+
+impl<'a> TryFrom<&'a [u8]> for Secp256k1VerifyingKey {
+    type Error = signature::Error;
+
+    /// Decodes a SEC1 encoded verifying key.
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        k256::ecdsa::VerifyingKey::from_sec1_bytes(value).map(Self::from)
+    }
+}
+
+impl AsRef<[u8]> for Secp256k1VerifyingKey {
+    /// Returns the compressed SEC1 encoding of the key.
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl Verifier<Secp256k1Signature> for Secp256k1VerifyingKey {
+    /// Verifies an ECDSA signature of SHA-256 hash of the message.
+    fn verify(
+        &self,
+        msg: &[u8],
+        signature: &Secp256k1Signature,
+    ) -> Result<(), signature::Error> {
+        self.key.verify(msg, signature)
+    }
+}
+
+/// Signing key of a participant together with verifying keys of all
+/// participants of a protocol.
+///
+/// Index of the participant is the position of its own verifying key
+/// in the list, so setup messages may be created without tracking
+/// party indices separately.
+pub struct PartyKeys<SK, VK> {
+    sk: SK,
+    party_idx: usize,
+    vk: Vec<VK>,
+}
+
+impl<SK, VK> PartyKeys<SK, VK>
+where
+    SK: Keypair,
+    VK: From<SK::VerifyingKey> + AsRef<[u8]>,
+{
+    /// Creates `PartyKeys` from a signing key and a list of verifying
+    /// keys of all participants.
+    ///
+    /// # Returns
+    /// * `None` if the verifying key of `sk` is not in `vk`
+    pub fn new(sk: SK, vk: Vec<VK>) -> Option<Self> {
+        let own = VK::from(sk.verifying_key());
+        let party_idx = vk.iter().position(|k| k.as_ref() == own.as_ref())?;
+
+        Some(Self { sk, party_idx, vk })
+    }
+}
+
+impl<SK, VK> PartyKeys<SK, VK> {
+    /// Returns index of the participant.
+    pub fn party_idx(&self) -> usize {
+        self.party_idx
+    }
+
+    /// Returns verifying keys of all participants.
+    pub fn verifying_keys(&self) -> &[VK] {
+        &self.vk
+    }
+
+    /// Splits into the signing key, participant index and
+    /// verifying keys.
+    pub fn into_parts(self) -> (SK, usize, Vec<VK>) {
+        (self.sk, self.party_idx, self.vk)
+    }
+}
+
+/// Long-term X25519 encryption key of a participant together with
+/// encryption public keys of all participants of a protocol.
+///
+/// A public key is bound to the participant whose verifying key has
+/// the same index in the setup. With these keys in a setup, protocols
+/// do not exchange encryption keys in their first round, and P2P
+/// messages may be encrypted to parties before they come online.
+#[derive(Clone)]
+pub struct EncryptionKeys {
+    secret: ReusableSecret,
+    party_idx: usize,
+    public_keys: Vec<PublicKey>,
+}
+
+impl EncryptionKeys {
+    /// Creates `EncryptionKeys` from a secret key and a list of public
+    /// keys of all participants.
+    ///
+    /// # Returns
+    /// * `None` if the public key of `secret` is not in `public_keys`,
+    ///   or any public key is of small order
+    pub fn new(
+        secret: ReusableSecret,
+        public_keys: Vec<PublicKey>,
+    ) -> Option<Self> {
+        let own = PublicKey::from(&secret);
+        let party_idx = public_keys.iter().position(|pk| pk == &own)?;
+
+        public_keys
+            .iter()
+            .all(|pk| secret.diffie_hellman(pk).was_contributory())
+            .then_some(Self {
+                secret,
+                party_idx,
+                public_keys,
+            })
+    }
+
+    /// Returns index of the participant.
+    pub fn party_idx(&self) -> usize {
+        self.party_idx
+    }
+
+    /// Returns the secret key of the participant.
+    pub fn secret(&self) -> &ReusableSecret {
+        &self.secret
+    }
+
+    /// Returns encryption public keys of all participants.
+    pub fn public_keys(&self) -> &[PublicKey] {
+        &self.public_keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use sl_mpc_mate::{coord::SimpleMessageRelay, message::InstanceId};
+    use tokio::task::JoinSet;
+
+    use crate::setup::{
+        key_export::{exporter::KeyExporter, receiver::KeyExportReceiver},
+        keygen, sign, ProtocolParticipant,
+    };
+
+    use super::*;
+
+    #[test]
+    fn party_keys() {
+        let mut rng = rand::thread_rng();
+
+        let sk: Vec<_> = (0..3)
+            .map(|_| Secp256k1SigningKey::random(&mut rng))
+            .collect();
+        let vk: Vec<Secp256k1VerifyingKey> =
+            sk.iter().map(Secp256k1VerifyingKey::from).collect();
+
+        let keys = PartyKeys::new(sk[2].clone(), vk.clone()).unwrap();
+        assert_eq!(keys.party_idx(), 2);
+
+        let sig: Secp256k1Signature = sk[2].sign(b"message");
+        assert!(vk[2].verify(b"message", &sig).is_ok());
+        assert!(vk[1].verify(b"message", &sig).is_err());
+
+        let vk2 = Secp256k1VerifyingKey::try_from(vk[2].as_ref()).unwrap();
+        assert_eq!(vk2, vk[2]);
+
+        let other = Secp256k1SigningKey::random(&mut rng);
+        assert!(PartyKeys::new(other, vk).is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keygen_and_sign_with_signed_messages() {
+        let mut rng = rand::thread_rng();
+
+        let sk: Vec<_> = (0..2)
+            .map(|_| Ed25519SigningKey::generate(&mut rng))
+            .collect();
+        let vk: Vec<_> =
+            sk.iter().map(Ed25519SigningKey::verifying_key).collect();
+
+        let coord = SimpleMessageRelay::new();
+        let mut parties = JoinSet::new();
+        let instance = InstanceId::new(rand::random());
+
+        for sk in sk {
+            let keys = PartyKeys::new(sk, vk.clone()).unwrap();
+            let setup: keygen::SetupMessage<_, _, Ed25519Signature> =
+                keygen::SetupMessage::from_keys(instance, keys, &[0, 0], 2);
+
+            parties.spawn(crate::keygen::run(
+                setup,
+                rand::random(),
+                coord.connect(),
+            ));
+        }
+
+        let mut shares = vec![];
+        while let Some(share) = parties.join_next().await {
+            shares.push(Arc::new(share.unwrap().unwrap()));
+        }
+        shares.sort_by_key(|share| share.party_id);
+
+        let sk: Vec<_> = (0..2)
+            .map(|_| Secp256k1SigningKey::random(&mut rng))
+            .collect();
+        let vk: Vec<Secp256k1VerifyingKey> =
+            sk.iter().map(Secp256k1VerifyingKey::from).collect();
+
+        let coord = SimpleMessageRelay::new();
+        let mut parties = JoinSet::new();
+        let instance = InstanceId::new(rand::random());
+
+        for (sk, share) in sk.into_iter().zip(shares) {
+            let keys = PartyKeys::new(sk, vk.clone()).unwrap();
+            let setup: sign::SetupMessage<_, _, Secp256k1Signature> =
+                sign::SetupMessage::from_keys(instance, keys, share)
+                    .with_hash([1; 32]);
+
+            assert_eq!(
+                setup.participant_verifier().as_ref(),
+                vk[setup.participant_index()].as_ref()
+            );
+
+            parties.spawn(crate::sign::run(
+                setup,
+                rand::random(),
+                coord.connect(),
+            ));
+        }
+
+        while let Some(fini) = parties.join_next().await {
+            fini.unwrap().unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn key_export_with_signed_messages() {
+        let mut rng = rand::thread_rng();
+
+        let shares = crate::keygen::utils::gen_keyshares(2, 2, None).await;
+        let public_key = shares[0].public_key();
+
+        let sk: Vec<_> = (0..2)
+            .map(|_| Ed25519SigningKey::generate(&mut rng))
+            .collect();
+        let vk: Vec<_> =
+            sk.iter().map(Ed25519SigningKey::verifying_key).collect();
+
+        let instance = InstanceId::new(rand::random());
+        let enc_key = ReusableSecret::random_from_rng(&mut rng);
+        let enc_pub_key = PublicKey::from(&enc_key);
+
+        let mut sk = sk.into_iter();
+        let receiver_sk = sk.next().unwrap();
+
+        let coord = SimpleMessageRelay::new();
+
+        for (sk, share) in sk.zip(&shares[1..]) {
+            let keys = PartyKeys::new(sk, vk.clone()).unwrap();
+            let setup: KeyExporter<_, _, Ed25519Signature> =
+                KeyExporter::from_keys(
+                    instance,
+                    keys,
+                    share.clone(),
+                    enc_pub_key,
+                );
+
+            coord.send(
+                crate::key_export::export_keyshare(&mut rng, &setup).unwrap(),
+            );
+        }
+
+        let keys = PartyKeys::new(receiver_sk, vk.clone()).unwrap();
+        let setup: KeyExportReceiver<_, _, Ed25519Signature> =
+            KeyExportReceiver::from_keys(
+                instance,
+                keys,
+                shares[0].clone(),
+                enc_key,
+            );
+
+        let secret =
+            crate::key_export::receive_keyshares(setup, coord.connect())
+                .await
+                .unwrap();
+
+        assert_eq!(k256::ProjectivePoint::GENERATOR * secret, public_key);
+    }
+
+    fn encryption_keys(n: usize) -> Vec<EncryptionKeys> {
+        let mut rng = rand::thread_rng();
+
+        let secrets: Vec<_> = (0..n)
+            .map(|_| ReusableSecret::random_from_rng(&mut rng))
+            .collect();
+        let public_keys: Vec<_> =
+            secrets.iter().map(PublicKey::from).collect();
+
+        secrets
+            .into_iter()
+            .map(|secret| EncryptionKeys::new(secret, public_keys.clone()))
+            .collect::<Option<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn encryption_keys_party_idx() {
+        let keys = encryption_keys(3);
+
+        for (idx, keys) in keys.iter().enumerate() {
+            assert_eq!(keys.party_idx(), idx);
+        }
+
+        let other = ReusableSecret::random_from_rng(rand::thread_rng());
+        assert!(EncryptionKeys::new(other, keys[0].public_keys().to_vec())
+            .is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keygen_and_sign_with_encryption_keys() {
+        use crate::{
+            keygen::utils::setup_keygen, proto::SchemeId, sign::setup_dsg,
+        };
+
+        let keys = encryption_keys(3);
+
+        let coord = SimpleMessageRelay::new();
+        let mut parties = JoinSet::new();
+
+        for ((setup, seed), keys) in
+            setup_keygen(None, 2, 3, None).into_iter().zip(&keys)
+        {
+            let setup = setup.with_encryption_keys(keys.clone());
+            parties.spawn(crate::keygen::run(setup, seed, coord.connect()));
+        }
+
+        let mut shares = vec![];
+        while let Some(share) = parties.join_next().await {
+            shares.push(Arc::new(share.unwrap().unwrap()));
+        }
+        shares.sort_by_key(|share| share.party_id);
+
+        // parties 1 and 2 sign with their long-term keys
+        let sign_keys = EncryptionKeys::new(
+            keys[1].secret().clone(),
+            keys[1].public_keys()[1..].to_vec(),
+        )
+        .into_iter()
+        .chain(EncryptionKeys::new(
+            keys[2].secret().clone(),
+            keys[2].public_keys()[1..].to_vec(),
+        ));
+
+        let coord = SimpleMessageRelay::new();
+        let mut parties = JoinSet::new();
+
+        for ((setup, seed), keys) in setup_dsg(None, &shares[1..], "m")
+            .into_iter()
+            .zip(sign_keys)
+        {
+            let setup = setup
+                .with_encryption_scheme(SchemeId::HpkeChaCha20Poly1305)
+                .with_encryption_keys(keys);
+
+            parties.spawn(crate::sign::run(setup, seed, coord.connect()));
+        }
+
+        while let Some(fini) = parties.join_next().await {
+            fini.unwrap().unwrap();
+        }
+    }
+}
@@ -7,8 +7,12 @@ use std::{marker::PhantomData, sync::Arc, time::Duration};
 
 use crate::{
     keygen::Keyshare,
+    proto::SchemeId,
     setup::{
-        keys::{NoSignature, NoSigningKey, NoVerifyingKey},
+        keys::{
+            EncryptionKeys, NoSignature, NoSigningKey, NoVerifyingKey,
+            PartyKeys,
+        },
         *,
     },
 };
@@ -56,6 +60,12 @@ pub struct SetupMessage<
     instance: InstanceId,
     /// Time-to-live duration for messages
     ttl: Duration,
+    /// Encryption scheme of P2P messages
+    enc_scheme: SchemeId,
+    /// Whether to exchange hashes of broadcast messages
+    echo: bool,
+    /// Optional long-term encryption keys of all participants
+    enc_keys: Option<EncryptionKeys>,
     /// Phantom data to hold the message signature type
     marker: PhantomData<MS>,
 }
@@ -120,11 +130,49 @@ impl<SK, VK, MS, KS, PK> SetupMessage<SK, VK, MS, KS, PK> {
             old_parties: old_parties.to_vec(),
             instance,
             ttl: Duration::from_secs(DEFAULT_TTL),
+            enc_scheme: SchemeId::default(),
+            echo: false,
+            enc_keys: None,
             keyshare: None,
             marker: PhantomData,
         }
     }
 
+    /// Creates a new setup message for quorum changes from the keys of
+    /// the participants.
+    ///
+    /// # Arguments
+    /// * `instance` - Instance identifier for the protocol
+    /// * `keys` - Signing key of the current party and verifying keys of all participants
+    /// * `old_parties` - Indices of old participants
+    /// * `new_parties` - Pairs of (index, rank) for new participants
+    /// * `new_t` - New threshold value
+    /// * `public_key` - Public key for the protocol
+    ///
+    /// # Panics
+    /// Panics under the same conditions as [`SetupMessage::new`]
+    pub fn from_keys(
+        instance: InstanceId,
+        keys: PartyKeys<SK, VK>,
+        old_parties: &[usize],
+        new_parties: &[(usize, u8)],
+        new_t: usize,
+        public_key: PK,
+    ) -> Self {
+        let (sk, this_party, vk) = keys.into_parts();
+
+        Self::new(
+            instance,
+            this_party,
+            old_parties,
+            new_parties,
+            new_t,
+            sk,
+            vk,
+            public_key,
+        )
+    }
+
     /// Sets a custom time-to-live duration for messages.
     ///
     /// # Arguments
@@ -137,6 +185,51 @@ impl<SK, VK, MS, KS, PK> SetupMessage<SK, VK, MS, KS, PK> {
         self
     }
 
+    /// Sets the encryption scheme of P2P messages.
+    ///
+    /// # Arguments
+    /// * `scheme` - ID of the encryption scheme
+    ///
+    /// # Returns
+    /// The modified `SetupMessage` instance
+    pub fn with_encryption_scheme(mut self, scheme: SchemeId) -> Self {
+        self.enc_scheme = scheme;
+        self
+    }
+
+    /// Enables or disables an echo round after each broadcast round,
+    /// see [`ProtocolParticipant::echo_broadcast()`].
+    ///
+    /// # Arguments
+    /// * `echo` - Whether to exchange hashes of broadcast messages
+    ///
+    /// # Returns
+    /// The modified `SetupMessage` instance
+    pub fn with_echo_broadcast(mut self, echo: bool) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    /// Sets long-term encryption keys of all participants. Parties
+    /// will not exchange encryption keys in the first round.
+    ///
+    /// # Arguments
+    /// * `keys` - Encryption keys in the order of verifying keys
+    ///
+    /// # Returns
+    /// The modified `SetupMessage` instance
+    ///
+    /// # Panics
+    /// If the index of own key differs from the index of the party or
+    /// the number of keys differs from the number of participants
+    pub fn with_encryption_keys(mut self, keys: EncryptionKeys) -> Self {
+        assert_eq!(keys.party_idx(), self.this_party);
+        assert_eq!(keys.public_keys().len(), self.vk.len());
+
+        self.enc_keys = Some(keys);
+        self
+    }
+
     /// Sets an optional keyshare for the protocol.
     ///
     /// # Arguments
@@ -198,6 +291,21 @@ where
         self.ttl
     }
 
+    /// Returns the encryption scheme of P2P messages.
+    fn encryption_scheme(&self) -> SchemeId {
+        self.enc_scheme
+    }
+
+    /// Returns whether parties exchange hashes of broadcast messages.
+    fn echo_broadcast(&self) -> bool {
+        self.echo
+    }
+
+    /// Returns long-term encryption keys of all participants.
+    fn encryption_keys(&self) -> Option<&EncryptionKeys> {
+        self.enc_keys.as_ref()
+    }
+
     /// Returns the index of the current participant.
     fn participant_index(&self) -> usize {
         self.this_party
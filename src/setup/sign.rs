@@ -13,8 +13,12 @@ use sl_mpc_mate::message::InstanceId;
 
 use crate::{
     keygen::Keyshare,
+    proto::SchemeId,
     setup::{
-        keys::{NoSignature, NoSigningKey, NoVerifyingKey},
+        keys::{
+            EncryptionKeys, NoSignature, NoSigningKey, NoVerifyingKey,
+            PartyKeys,
+        },
         PreSignSetupMessage, ProtocolParticipant, SignSetupMessage,
     },
 };
@@ -52,6 +56,12 @@ pub struct SetupMessage<
     chain_path: DerivationPath,
     /// Time-to-live duration for messages
     ttl: Duration,
+    /// Encryption scheme of P2P messages
+    enc_scheme: SchemeId,
+    /// Whether to compress messages
+    compression: bool,
+    /// Optional long-term encryption keys of all participants
+    enc_keys: Option<EncryptionKeys>,
     /// Hash of the message to be signed
     hash: [u8; 32],
     /// Phantom data to hold the message signature type
@@ -84,12 +94,35 @@ impl<SK, VK, MS, KS> SetupMessage<SK, VK, MS, KS> {
             instance,
             keyshare: share.clone(),
             ttl: Duration::from_secs(DEFAULT_TTL),
+            enc_scheme: SchemeId::default(),
+            compression: false,
+            enc_keys: None,
             chain_path: DerivationPath::from_str("m").unwrap(),
             hash: [0; 32],
             marker: PhantomData,
         }
     }
 
+    /// Creates a new setup message for signing operations from the keys
+    /// of the participants.
+    ///
+    /// # Arguments
+    /// * `instance` - Instance identifier for the protocol
+    /// * `keys` - Signing key of the current party and verifying keys of all participants
+    /// * `share` - Reference to the keyshare used in signing
+    ///
+    /// # Returns
+    /// A new `SetupMessage` instance with default TTL, root derivation path, and zero hash
+    pub fn from_keys(
+        instance: InstanceId,
+        keys: PartyKeys<SK, VK>,
+        share: Arc<KS>,
+    ) -> Self {
+        let (sk, party_idx, vk) = keys.into_parts();
+
+        Self::new(instance, sk, party_idx, vk, share)
+    }
+
     /// Sets a custom derivation path for key derivation.
     ///
     /// # Arguments
@@ -140,6 +173,51 @@ impl<SK, VK, MS, KS> SetupMessage<SK, VK, MS, KS> {
         self
     }
 
+    /// Sets the encryption scheme of P2P messages.
+    ///
+    /// # Arguments
+    /// * `scheme` - ID of the encryption scheme
+    ///
+    /// # Returns
+    /// The modified `SetupMessage` instance
+    pub fn with_encryption_scheme(mut self, scheme: SchemeId) -> Self {
+        self.enc_scheme = scheme;
+        self
+    }
+
+    /// Enables or disables compression of messages, see
+    /// [`ProtocolParticipant::message_compression()`].
+    ///
+    /// # Arguments
+    /// * `compression` - Whether to compress messages
+    ///
+    /// # Returns
+    /// The modified `SetupMessage` instance
+    pub fn with_message_compression(mut self, compression: bool) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Sets long-term encryption keys of all participants. Parties
+    /// will not exchange encryption keys in the first round.
+    ///
+    /// # Arguments
+    /// * `keys` - Encryption keys in the order of verifying keys
+    ///
+    /// # Returns
+    /// The modified `SetupMessage` instance
+    ///
+    /// # Panics
+    /// If the index of own key differs from the index of the party or
+    /// the number of keys differs from the number of participants
+    pub fn with_encryption_keys(mut self, keys: EncryptionKeys) -> Self {
+        assert_eq!(keys.party_idx(), self.party_idx);
+        assert_eq!(keys.public_keys().len(), self.vk.len());
+
+        self.enc_keys = Some(keys);
+        self
+    }
+
     /// Returns a clone of the keyshare.
     pub fn clone_keyshare(&self) -> Arc<KS> {
         self.keyshare.clone()
@@ -187,6 +265,21 @@ where
         self.ttl
     }
 
+    /// Returns the encryption scheme of P2P messages.
+    fn encryption_scheme(&self) -> SchemeId {
+        self.enc_scheme
+    }
+
+    /// Returns whether messages are compressed.
+    fn message_compression(&self) -> bool {
+        self.compression
+    }
+
+    /// Returns long-term encryption keys of all participants.
+    fn encryption_keys(&self) -> Option<&EncryptionKeys> {
+        self.enc_keys.as_ref()
+    }
+
     /// Returns the index of the current participant.
     fn participant_index(&self) -> usize {
         self.party_idx
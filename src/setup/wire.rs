@@ -0,0 +1,1513 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Canonical wire encoding of setup messages.
+//!
+//! An initiator describes a protocol execution by a
+//! [`ProtocolSetup`](crate::setup::wire::ProtocolSetup): instance ID,
+//! message TTL, verifying keys of all participants and protocol
+//! specific parameters, and broadcasts it as a message signed by its
+//! key under [`SETUP_MESSAGE_TAG`](crate::setup::SETUP_MESSAGE_TAG).
+//!
+//! A participant decodes and verifies the message, finds its own
+//! index by its signing key and converts the setup into a
+//! `SetupMessage` of the protocol, validated against local keyshare,
+//! pre-signature or encryption key.
+//!
+//! Encoding of the message payload, all integers are big-endian:
+//!
+//! ```text
+//! version: u8 | kind: u8 | instance: [u8; 32] | ttl: u32 | flags: u8 |
+//! scheme: u8 | n: u16 | n * (len: u16 | verifying key: [u8; len]) |
+//! keys: u8 | keys * n * (encryption key: [u8; 32]) | parameters
+//! ```
+//!
+//! Bit 0 of `flags` enables compression of broadcast messages, bit 1
+//! enables echo rounds. Other bits must be zero. `scheme` is the
+//! [`SchemeId`] of P2P messages. `keys` is 1 if the setup declares
+//! long-term encryption keys of all participants, and 0 otherwise.
+
+use std::{str::FromStr, sync::Arc, time::Duration};
+
+use derivation_path::DerivationPath;
+use k256::ProjectivePoint;
+use signature::{Keypair, SignatureEncoding, Signer, Verifier};
+use x25519_dalek::{PublicKey, ReusableSecret};
+
+use sl_mpc_mate::message::{InstanceId, MsgHdr, MsgId};
+
+use crate::{
+    keygen::Keyshare,
+    proto::{decode_point, PointBytes, SchemeId, SignedMessage},
+    setup::{
+        finish, key_export, keygen,
+        keys::{EncryptionKeys, PartyKeys},
+        quorum_change, sign, SETUP_MESSAGE_TAG,
+    },
+    sign::PreSign,
+};
+
+/// Version of the setup encoding.
+pub const SETUP_ENCODING_VERSION: u8 = 3;
+
+const FLAG_COMPRESSION: u8 = 1;
+const FLAG_ECHO_BROADCAST: u8 = 2;
+
+const KIND_KEYGEN: u8 = 1;
+const KIND_SIGN: u8 = 2;
+const KIND_FINISH: u8 = 3;
+const KIND_QUORUM_CHANGE: u8 = 4;
+const KIND_KEY_EXPORT: u8 = 5;
+
+/// Index of the receiver of an exported key.
+const KEY_EXPORT_RECEIVER: usize = 0;
+
+/// Errors of decoding and validation of a setup message.
+#[derive(Debug, thiserror::Error)]
+pub enum SetupError {
+    /// Invalid signature or message ID
+    #[error("Invalid setup message signature")]
+    InvalidSignature,
+
+    /// Malformed payload
+    #[error("Invalid setup message encoding")]
+    InvalidEncoding,
+
+    /// Unknown version of the encoding
+    #[error("Unsupported setup message version")]
+    UnsupportedVersion,
+
+    /// Setup is for another protocol
+    #[error("Unexpected protocol kind")]
+    UnexpectedKind,
+
+    /// Own verifying key is not in the list of participants
+    #[error("Not a participant")]
+    NotAParticipant,
+
+    /// Inconsistent protocol parameters
+    #[error("Invalid setup parameters")]
+    InvalidParameters,
+
+    /// Local keyshare does not match the setup
+    #[error("Keyshare does not match setup")]
+    KeyshareMismatch,
+
+    /// Local pre-signature does not match the setup
+    #[error("Pre-signature does not match setup")]
+    PreSignMismatch,
+
+    /// Local encryption key does not match the setup
+    #[error("Encryption key does not match setup")]
+    EncryptionKeyMismatch,
+
+    /// A value does not fit into its field of the encoding
+    #[error("Setup value is too large to encode")]
+    ValueTooLarge,
+}
+
+/// Protocol specific parameters of a setup.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SetupParams {
+    /// Parameters of `keygen::run()`
+    Keygen {
+        /// Threshold
+        threshold: u8,
+        /// Rank of each participant
+        ranks: Vec<u8>,
+        /// Optional key ID of the generated key
+        key_id: Option<[u8; 32]>,
+    },
+
+    /// Parameters of `sign::run()`
+    Sign {
+        /// Key ID of the keyshares
+        key_id: [u8; 32],
+        /// Derivation path of the signing key
+        chain_path: DerivationPath,
+        /// Hash of a message to sign
+        message_hash: [u8; 32],
+    },
+
+    /// Parameters of `sign::finish()`
+    Finish {
+        /// Final session ID of the pre-signatures
+        final_session_id: [u8; 32],
+        /// Hash of a message to sign
+        message_hash: [u8; 32],
+    },
+
+    /// Parameters of `quorum_change::run()`
+    QuorumChange {
+        /// Indices of participants holding old keyshares
+        old_parties: Vec<usize>,
+        /// Pairs of (index, rank) of participants receiving new keyshares
+        new_parties: Vec<(usize, u8)>,
+        /// New threshold
+        new_threshold: u8,
+        /// Public key of the keyshares
+        public_key: PointBytes,
+    },
+
+    /// Parameters of a key export. The participant with index 0 is
+    /// the receiver of the exported key.
+    KeyExport {
+        /// Key ID of the keyshares
+        key_id: [u8; 32],
+        /// Encryption public key of the receiver
+        receiver_public_key: [u8; 32],
+    },
+}
+
+impl SetupParams {
+    fn kind(&self) -> u8 {
+        match self {
+            SetupParams::Keygen { .. } => KIND_KEYGEN,
+            SetupParams::Sign { .. } => KIND_SIGN,
+            SetupParams::Finish { .. } => KIND_FINISH,
+            SetupParams::QuorumChange { .. } => KIND_QUORUM_CHANGE,
+            SetupParams::KeyExport { .. } => KIND_KEY_EXPORT,
+        }
+    }
+}
+
+/// Public description of a protocol execution, broadcast by an initiator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProtocolSetup<VK> {
+    /// Instance ID
+    pub instance: [u8; 32],
+    /// Time to live of protocol messages
+    pub ttl: Duration,
+    /// Whether participants compress messages. Supported by
+    /// keygen and sign setups.
+    pub compression: bool,
+    /// Whether participants run echo rounds after broadcast rounds.
+    /// Supported by keygen and quorum change setups.
+    pub echo_broadcast: bool,
+    /// Encryption scheme of P2P messages. Supported by keygen, sign
+    /// and quorum change setups.
+    pub encryption_scheme: SchemeId,
+    /// Verifying keys of all participants
+    pub parties: Vec<VK>,
+    /// Long-term X25519 encryption keys of all participants in the
+    /// order of verifying keys, or empty if parties exchange
+    /// encryption keys in the first round. Supported by keygen, sign
+    /// and quorum change setups.
+    pub encryption_keys: Vec<[u8; 32]>,
+    /// Protocol specific parameters
+    pub params: SetupParams,
+}
+
+// Whether all items are distinct.
+fn distinct<T: PartialEq>(items: &[T]) -> bool {
+    items
+        .iter()
+        .enumerate()
+        .all(|(i, item)| !items[..i].contains(item))
+}
+
+// A participant passes its long-term encryption key if and only if the
+// setup declares encryption keys, and the key must be declared for the
+// participant.
+fn encryption_keys<SK, VK>(
+    public_keys: Vec<[u8; 32]>,
+    party_keys: &PartyKeys<SK, VK>,
+    enc_key: Option<ReusableSecret>,
+) -> Result<Option<EncryptionKeys>, SetupError> {
+    let party_idx = party_keys.party_idx();
+
+    match (public_keys.is_empty(), enc_key) {
+        (true, None) => Ok(None),
+
+        (false, _)
+            if public_keys.len() != party_keys.verifying_keys().len() =>
+        {
+            Err(SetupError::InvalidParameters)
+        }
+
+        (false, Some(secret)) => {
+            let public_keys =
+                public_keys.into_iter().map(PublicKey::from).collect();
+
+            EncryptionKeys::new(secret, public_keys)
+                .filter(|keys| keys.party_idx() == party_idx)
+                .map(Some)
+                .ok_or(SetupError::EncryptionKeyMismatch)
+        }
+
+        _ => Err(SetupError::EncryptionKeyMismatch),
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    fn u16(&mut self, v: usize) -> Result<(), SetupError> {
+        let v = u16::try_from(v).map_err(|_| SetupError::ValueTooLarge)?;
+        self.0.extend(v.to_be_bytes());
+        Ok(())
+    }
+
+    fn u32(&mut self, v: u64) -> Result<(), SetupError> {
+        let v = u32::try_from(v).map_err(|_| SetupError::ValueTooLarge)?;
+        self.0.extend(v.to_be_bytes());
+        Ok(())
+    }
+
+    fn bytes(&mut self, v: &[u8]) {
+        self.0.extend_from_slice(v);
+    }
+
+    fn var_bytes(&mut self, v: &[u8]) -> Result<(), SetupError> {
+        self.u16(v.len())?;
+        self.bytes(v);
+        Ok(())
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], SetupError> {
+        if self.0.len() < len {
+            return Err(SetupError::InvalidEncoding);
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SetupError> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, SetupError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<usize, SetupError> {
+        Ok(u16::from_be_bytes(self.array()?) as usize)
+    }
+
+    fn u32(&mut self) -> Result<u32, SetupError> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn var_bytes(&mut self) -> Result<&'a [u8], SetupError> {
+        let len = self.u16()?;
+        self.bytes(len)
+    }
+
+    fn finish(self) -> Result<(), SetupError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(SetupError::InvalidEncoding)
+        }
+    }
+}
+
+impl<VK: AsRef<[u8]>> ProtocolSetup<VK> {
+    /// Creates a setup with the default TTL of 100 seconds.
+    pub fn new(
+        instance: [u8; 32],
+        parties: Vec<VK>,
+        params: SetupParams,
+    ) -> Self {
+        Self {
+            instance,
+            ttl: Duration::from_secs(100),
+            compression: false,
+            echo_broadcast: false,
+            encryption_scheme: SchemeId::default(),
+            parties,
+            encryption_keys: vec![],
+            params,
+        }
+    }
+
+    /// Sets a custom time-to-live duration for messages.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Enables or disables compression of messages.
+    pub fn with_message_compression(mut self, compression: bool) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Enables or disables echo rounds.
+    pub fn with_echo_broadcast(mut self, echo: bool) -> Self {
+        self.echo_broadcast = echo;
+        self
+    }
+
+    /// Sets the encryption scheme of P2P messages.
+    pub fn with_encryption_scheme(mut self, scheme: SchemeId) -> Self {
+        self.encryption_scheme = scheme;
+        self
+    }
+
+    /// Sets long-term encryption keys of all participants, see
+    /// [`EncryptionKeys`].
+    pub fn with_encryption_keys(mut self, keys: Vec<[u8; 32]>) -> Self {
+        self.encryption_keys = keys;
+        self
+    }
+
+    /// Returns the instance ID.
+    pub fn instance_id(&self) -> InstanceId {
+        InstanceId::new(self.instance)
+    }
+
+    /// Canonical encoding of the setup.
+    ///
+    /// Fails if a number of parties, a length of a verifying key or
+    /// a derivation path, or a party index does not fit into `u16`,
+    /// or the TTL in seconds does not fit into `u32`, or there are
+    /// encryption keys not for each participant.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SetupError> {
+        let mut w = Writer(Vec::new());
+
+        w.u8(SETUP_ENCODING_VERSION);
+        w.u8(self.params.kind());
+        w.bytes(&self.instance);
+        w.u32(self.ttl.as_secs())?;
+        w.u8(if self.compression {
+            FLAG_COMPRESSION
+        } else {
+            0
+        } | if self.echo_broadcast {
+            FLAG_ECHO_BROADCAST
+        } else {
+            0
+        });
+        w.u8(self.encryption_scheme as u8);
+
+        w.u16(self.parties.len())?;
+        for vk in &self.parties {
+            w.var_bytes(vk.as_ref())?;
+        }
+
+        // keys are not prefixed by their number
+        let keys = self.encryption_keys.len();
+        if keys != 0 && keys != self.parties.len() {
+            return Err(SetupError::InvalidParameters);
+        }
+
+        w.u8((keys != 0) as u8);
+        for key in &self.encryption_keys {
+            w.bytes(key);
+        }
+
+        match &self.params {
+            SetupParams::Keygen {
+                threshold,
+                ranks,
+                key_id,
+            } => {
+                w.u8(*threshold);
+                w.var_bytes(ranks)?;
+                match key_id {
+                    Some(key_id) => {
+                        w.u8(1);
+                        w.bytes(key_id);
+                    }
+                    None => w.u8(0),
+                }
+            }
+
+            SetupParams::Sign {
+                key_id,
+                chain_path,
+                message_hash,
+            } => {
+                w.bytes(key_id);
+                w.bytes(message_hash);
+                w.var_bytes(chain_path.to_string().as_bytes())?;
+            }
+
+            SetupParams::Finish {
+                final_session_id,
+                message_hash,
+            } => {
+                w.bytes(final_session_id);
+                w.bytes(message_hash);
+            }
+
+            SetupParams::QuorumChange {
+                old_parties,
+                new_parties,
+                new_threshold,
+                public_key,
+            } => {
+                w.u8(*new_threshold);
+                w.u16(old_parties.len())?;
+                for &p in old_parties {
+                    w.u16(p)?;
+                }
+                w.u16(new_parties.len())?;
+                for &(p, rank) in new_parties {
+                    w.u16(p)?;
+                    w.u8(rank);
+                }
+                w.bytes(public_key);
+            }
+
+            SetupParams::KeyExport {
+                key_id,
+                receiver_public_key,
+            } => {
+                w.bytes(key_id);
+                w.bytes(receiver_public_key);
+            }
+        }
+
+        Ok(w.0)
+    }
+
+    /// ID of the setup message sent by an initiator with verifying
+    /// key `initiator`.
+    pub fn msg_id(&self, initiator: &[u8]) -> MsgId {
+        MsgId::broadcast(&self.instance_id(), initiator, SETUP_MESSAGE_TAG)
+    }
+
+    /// Encodes the setup and signs it by the initiator key.
+    pub fn sign<S, K>(&self, initiator: &K) -> Result<Vec<u8>, SetupError>
+    where
+        S: SignatureEncoding,
+        K: Signer<S> + Keypair,
+        K::VerifyingKey: AsRef<[u8]>,
+    {
+        let body = self.to_bytes()?;
+        let msg_id = self.msg_id(initiator.verifying_key().as_ref());
+
+        // the TTL is checked by to_bytes()
+        Ok(SignedMessage::<(), S>::build(
+            &msg_id,
+            self.ttl.as_secs() as u32,
+            body.len(),
+            initiator,
+            |_, trailer| trailer.copy_from_slice(&body),
+        ))
+    }
+
+    fn check_parameters(&self) -> Result<(), SetupError> {
+        let n = self.parties.len();
+
+        // all participants must have distinct keys
+        for (i, vk) in self.parties.iter().enumerate() {
+            if self.parties[..i].iter().any(|p| p.as_ref() == vk.as_ref()) {
+                return Err(SetupError::InvalidParameters);
+            }
+        }
+
+        // a rank is an order of a derivative of a polynomial of degree
+        // t - 1, a share of rank t or more is always zero
+        let valid = match &self.params {
+            SetupParams::Keygen {
+                threshold, ranks, ..
+            } => {
+                ranks.len() == n
+                    && *threshold >= 2
+                    && *threshold as usize <= n
+                    && ranks.iter().all(|rank| rank < threshold)
+            }
+
+            SetupParams::Sign { .. } | SetupParams::Finish { .. } => n >= 2,
+
+            SetupParams::QuorumChange {
+                old_parties,
+                new_parties,
+                new_threshold,
+                public_key,
+            } => {
+                let new_indices: Vec<_> =
+                    new_parties.iter().map(|&(p, _)| p).collect();
+
+                !old_parties.is_empty()
+                    && old_parties.iter().all(|&p| p < n)
+                    && distinct(old_parties)
+                    && !new_parties.is_empty()
+                    && new_indices.iter().all(|&p| p < n)
+                    && distinct(&new_indices)
+                    && *new_threshold >= 2
+                    && *new_threshold as usize <= new_parties.len()
+                    && new_parties
+                        .iter()
+                        .all(|(_, rank)| rank < new_threshold)
+                    && decode_point(public_key)
+                        .is_some_and(|pk| pk != ProjectivePoint::IDENTITY)
+            }
+
+            SetupParams::KeyExport { .. } => n >= 2,
+        };
+
+        let flags = match &self.params {
+            SetupParams::Keygen { .. } => true,
+            SetupParams::Sign { .. } => !self.echo_broadcast,
+            SetupParams::QuorumChange { .. } => !self.compression,
+            SetupParams::Finish { .. } | SetupParams::KeyExport { .. } => {
+                !self.compression
+                    && !self.echo_broadcast
+                    && self.encryption_scheme == SchemeId::default()
+                    && self.encryption_keys.is_empty()
+            }
+        };
+
+        let keys = self.encryption_keys.is_empty()
+            || (self.encryption_keys.len() == n
+                && distinct(&self.encryption_keys));
+
+        if valid && flags && keys {
+            Ok(())
+        } else {
+            Err(SetupError::InvalidParameters)
+        }
+    }
+}
+
+impl<VK> ProtocolSetup<VK>
+where
+    VK: AsRef<[u8]> + for<'a> TryFrom<&'a [u8]>,
+{
+    /// Decodes a canonical encoding of a setup.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SetupError> {
+        let mut r = Reader(bytes);
+
+        if r.u8()? != SETUP_ENCODING_VERSION {
+            return Err(SetupError::UnsupportedVersion);
+        }
+
+        let kind = r.u8()?;
+        let instance = r.array()?;
+        let ttl = Duration::from_secs(r.u32()? as u64);
+
+        let flags = r.u8()?;
+        if flags & !(FLAG_COMPRESSION | FLAG_ECHO_BROADCAST) != 0 {
+            return Err(SetupError::InvalidEncoding);
+        }
+
+        let scheme = r.u8()?;
+        let encryption_scheme = SchemeId::from_flags(scheme.into())
+            .filter(|id| *id as u8 == scheme)
+            .ok_or(SetupError::InvalidEncoding)?;
+
+        let n = r.u16()?;
+        let parties = (0..n)
+            .map(|_| {
+                VK::try_from(r.var_bytes()?)
+                    .map_err(|_| SetupError::InvalidEncoding)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let encryption_keys = match r.u8()? {
+            0 => vec![],
+            1 => (0..n).map(|_| r.array()).collect::<Result<_, _>>()?,
+            _ => return Err(SetupError::InvalidEncoding),
+        };
+
+        let params = match kind {
+            KIND_KEYGEN => SetupParams::Keygen {
+                threshold: r.u8()?,
+                ranks: r.var_bytes()?.to_vec(),
+                key_id: match r.u8()? {
+                    0 => None,
+                    1 => Some(r.array()?),
+                    _ => return Err(SetupError::InvalidEncoding),
+                },
+            },
+
+            KIND_SIGN => SetupParams::Sign {
+                key_id: r.array()?,
+                message_hash: r.array()?,
+                chain_path: std::str::from_utf8(r.var_bytes()?)
+                    .ok()
+                    .and_then(|path| DerivationPath::from_str(path).ok())
+                    .ok_or(SetupError::InvalidEncoding)?,
+            },
+
+            KIND_FINISH => SetupParams::Finish {
+                final_session_id: r.array()?,
+                message_hash: r.array()?,
+            },
+
+            KIND_QUORUM_CHANGE => {
+                let new_threshold = r.u8()?;
+                let old_parties = (0..r.u16()?)
+                    .map(|_| r.u16())
+                    .collect::<Result<Vec<_>, _>>()?;
+                let new_parties = (0..r.u16()?)
+                    .map(|_| Ok((r.u16()?, r.u8()?)))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                SetupParams::QuorumChange {
+                    old_parties,
+                    new_parties,
+                    new_threshold,
+                    public_key: r.array()?,
+                }
+            }
+
+            KIND_KEY_EXPORT => SetupParams::KeyExport {
+                key_id: r.array()?,
+                receiver_public_key: r.array()?,
+            },
+
+            _ => return Err(SetupError::InvalidEncoding),
+        };
+
+        r.finish()?;
+
+        let setup = Self {
+            instance,
+            ttl,
+            compression: flags & FLAG_COMPRESSION != 0,
+            echo_broadcast: flags & FLAG_ECHO_BROADCAST != 0,
+            encryption_scheme,
+            parties,
+            encryption_keys,
+            params,
+        };
+
+        setup.check_parameters()?;
+
+        Ok(setup)
+    }
+
+    /// Verifies a setup message signed by an initiator and decodes
+    /// the setup.
+    pub fn verify<S, V>(
+        message: &[u8],
+        initiator: &V,
+    ) -> Result<Self, SetupError>
+    where
+        S: SignatureEncoding,
+        V: Verifier<S> + AsRef<[u8]>,
+    {
+        let range = SignedMessage::<(), S>::verify_buffer(message, initiator)
+            .ok_or(SetupError::InvalidSignature)?;
+
+        let setup = Self::from_bytes(&message[range])?;
+
+        let hdr = MsgHdr::try_from(message)
+            .map_err(|_| SetupError::InvalidEncoding)?;
+
+        if hdr.id() != &setup.msg_id(initiator.as_ref()) {
+            return Err(SetupError::InvalidSignature);
+        }
+
+        Ok(setup)
+    }
+}
+
+impl<VK: AsRef<[u8]>> ProtocolSetup<VK> {
+    fn party_keys<SK>(self, sk: SK) -> Result<PartyKeys<SK, VK>, SetupError>
+    where
+        SK: Keypair,
+        VK: From<SK::VerifyingKey>,
+    {
+        PartyKeys::new(sk, self.parties).ok_or(SetupError::NotAParticipant)
+    }
+
+    /// Converts the setup into a setup message of `keygen::run()`.
+    ///
+    /// A participant passes its long-term encryption key if the setup
+    /// declares encryption keys.
+    pub fn into_keygen<SK, MS>(
+        self,
+        sk: SK,
+        enc_key: Option<ReusableSecret>,
+    ) -> Result<keygen::SetupMessage<SK, VK, MS>, SetupError>
+    where
+        SK: Keypair,
+        VK: From<SK::VerifyingKey>,
+    {
+        let SetupParams::Keygen {
+            threshold,
+            ranks,
+            key_id,
+        } = self.params.clone()
+        else {
+            return Err(SetupError::UnexpectedKind);
+        };
+
+        let (inst, ttl) = (self.instance_id(), self.ttl);
+        let (compression, echo) = (self.compression, self.echo_broadcast);
+        let scheme = self.encryption_scheme;
+        let public_keys = self.encryption_keys.clone();
+        let keys = self.party_keys(sk)?;
+        let enc_keys = encryption_keys(public_keys, &keys, enc_key)?;
+
+        let setup = keygen::SetupMessage::from_keys(
+            inst,
+            keys,
+            &ranks,
+            threshold as usize,
+        )
+        .with_key_id(key_id)
+        .with_ttl(ttl)
+        .with_message_compression(compression)
+        .with_echo_broadcast(echo)
+        .with_encryption_scheme(scheme);
+
+        Ok(match enc_keys {
+            Some(keys) => setup.with_encryption_keys(keys),
+            None => setup,
+        })
+    }
+
+    /// Converts the setup into a setup message of `sign::run()`.
+    /// Checks that `keyshare` is a share of the key to sign with.
+    ///
+    /// A participant passes its long-term encryption key if the setup
+    /// declares encryption keys.
+    pub fn into_sign<SK, MS>(
+        self,
+        sk: SK,
+        keyshare: Arc<Keyshare>,
+        enc_key: Option<ReusableSecret>,
+    ) -> Result<sign::SetupMessage<SK, VK, MS>, SetupError>
+    where
+        SK: Keypair,
+        VK: From<SK::VerifyingKey>,
+    {
+        let SetupParams::Sign {
+            key_id,
+            chain_path,
+            message_hash,
+        } = self.params.clone()
+        else {
+            return Err(SetupError::UnexpectedKind);
+        };
+
+        if keyshare.key_id != key_id
+            || self.parties.len() < keyshare.threshold as usize
+        {
+            return Err(SetupError::KeyshareMismatch);
+        }
+
+        let (inst, ttl) = (self.instance_id(), self.ttl);
+        let (compression, scheme) =
+            (self.compression, self.encryption_scheme);
+        let public_keys = self.encryption_keys.clone();
+        let keys = self.party_keys(sk)?;
+        let enc_keys = encryption_keys(public_keys, &keys, enc_key)?;
+
+        let setup = sign::SetupMessage::from_keys(inst, keys, keyshare)
+            .with_chain_path(chain_path)
+            .with_hash(message_hash)
+            .with_ttl(ttl)
+            .with_message_compression(compression)
+            .with_encryption_scheme(scheme);
+
+        Ok(match enc_keys {
+            Some(keys) => setup.with_encryption_keys(keys),
+            None => setup,
+        })
+    }
+
+    /// Converts the setup into a setup message of `sign::finish()`.
+    /// Checks that `pre` is a pre-signature of the same session.
+    pub fn into_finish<SK, MS>(
+        self,
+        sk: SK,
+        pre: PreSign,
+    ) -> Result<finish::SetupMessage<SK, VK, MS>, SetupError>
+    where
+        SK: Keypair,
+        VK: From<SK::VerifyingKey>,
+    {
+        let SetupParams::Finish {
+            final_session_id,
+            message_hash,
+        } = self.params
+        else {
+            return Err(SetupError::UnexpectedKind);
+        };
+
+        if pre.final_session_id != final_session_id {
+            return Err(SetupError::PreSignMismatch);
+        }
+
+        let (inst, ttl) = (self.instance_id(), self.ttl);
+
+        Ok(
+            finish::SetupMessage::from_keys(inst, self.party_keys(sk)?, pre)
+                .with_hash(message_hash)
+                .ttl(ttl),
+        )
+    }
+
+    /// Converts the setup into a setup message of `quorum_change::run()`.
+    ///
+    /// An old party must pass its keyshare, it is checked to be a share
+    /// of the expected public key. A participant passes its long-term
+    /// encryption key if the setup declares encryption keys.
+    pub fn into_quorum_change<SK, MS>(
+        self,
+        sk: SK,
+        keyshare: Option<Arc<Keyshare>>,
+        enc_key: Option<ReusableSecret>,
+    ) -> Result<quorum_change::SetupMessage<SK, VK, MS>, SetupError>
+    where
+        SK: Keypair,
+        VK: From<SK::VerifyingKey>,
+    {
+        let SetupParams::QuorumChange {
+            old_parties,
+            new_parties,
+            new_threshold,
+            public_key,
+        } = self.params.clone()
+        else {
+            return Err(SetupError::UnexpectedKind);
+        };
+
+        let public_key: ProjectivePoint = decode_point(&public_key)
+            .filter(|pk| *pk != ProjectivePoint::IDENTITY)
+            .ok_or(SetupError::InvalidParameters)?;
+
+        let (inst, ttl) = (self.instance_id(), self.ttl);
+        let (echo, scheme) = (self.echo_broadcast, self.encryption_scheme);
+        let public_keys = self.encryption_keys.clone();
+        let keys = self.party_keys(sk)?;
+        let enc_keys = encryption_keys(public_keys, &keys, enc_key)?;
+
+        let is_old_party = old_parties.contains(&keys.party_idx());
+        let keyshare = keyshare.filter(|_| is_old_party);
+
+        match &keyshare {
+            Some(share) if share.public_key() != public_key => {
+                return Err(SetupError::KeyshareMismatch)
+            }
+            // too few old parties to recover the key
+            Some(share) if old_parties.len() < share.threshold as usize => {
+                return Err(SetupError::InvalidParameters)
+            }
+            None if is_old_party => return Err(SetupError::KeyshareMismatch),
+            _ => {}
+        }
+
+        let setup = quorum_change::SetupMessage::from_keys(
+            inst,
+            keys,
+            &old_parties,
+            &new_parties,
+            new_threshold as usize,
+            public_key,
+        )
+        .with_keyshare_opt(keyshare)
+        .with_ttl(ttl)
+        .with_echo_broadcast(echo)
+        .with_encryption_scheme(scheme);
+
+        Ok(match enc_keys {
+            Some(keys) => setup.with_encryption_keys(keys),
+            None => setup,
+        })
+    }
+
+    fn key_export_params(&self) -> Result<([u8; 32], PublicKey), SetupError> {
+        match &self.params {
+            SetupParams::KeyExport {
+                key_id,
+                receiver_public_key,
+            } => Ok((*key_id, PublicKey::from(*receiver_public_key))),
+
+            _ => Err(SetupError::UnexpectedKind),
+        }
+    }
+
+    /// Converts the setup into a setup message of an exporter of a
+    /// keyshare.
+    pub fn into_key_exporter<SK, MS>(
+        self,
+        sk: SK,
+        keyshare: Arc<Keyshare>,
+    ) -> Result<key_export::exporter::KeyExporter<SK, VK, MS>, SetupError>
+    where
+        SK: Keypair,
+        VK: From<SK::VerifyingKey>,
+    {
+        let (key_id, receiver_public_key) = self.key_export_params()?;
+
+        if keyshare.key_id != key_id {
+            return Err(SetupError::KeyshareMismatch);
+        }
+
+        let (inst, ttl) = (self.instance_id(), self.ttl);
+        let (sk, party_id, vk) = self.party_keys(sk)?.into_parts();
+
+        if party_id == KEY_EXPORT_RECEIVER {
+            return Err(SetupError::InvalidParameters);
+        }
+
+        Ok(key_export::exporter::KeyExporter::new(
+            inst,
+            sk,
+            party_id,
+            vk,
+            keyshare,
+            receiver_public_key,
+        )
+        .with_ttl(ttl))
+    }
+
+    /// Converts the setup into a setup message of the receiver of an
+    /// exported key.
+    pub fn into_key_export_receiver<SK, MS>(
+        self,
+        sk: SK,
+        keyshare: Arc<Keyshare>,
+        enc_key: ReusableSecret,
+    ) -> Result<key_export::receiver::KeyExportReceiver<SK, VK, MS>, SetupError>
+    where
+        SK: Keypair,
+        VK: From<SK::VerifyingKey>,
+    {
+        let (key_id, receiver_public_key) = self.key_export_params()?;
+
+        if keyshare.key_id != key_id {
+            return Err(SetupError::KeyshareMismatch);
+        }
+
+        if PublicKey::from(&enc_key) != receiver_public_key {
+            return Err(SetupError::EncryptionKeyMismatch);
+        }
+
+        let (inst, ttl) = (self.instance_id(), self.ttl);
+        let (sk, party_id, vk) = self.party_keys(sk)?.into_parts();
+
+        if party_id != KEY_EXPORT_RECEIVER {
+            return Err(SetupError::InvalidParameters);
+        }
+
+        Ok(key_export::receiver::KeyExportReceiver::new(
+            inst, sk, party_id, vk, keyshare, enc_key,
+        )
+        .with_ttl(ttl))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sl_mpc_mate::coord::SimpleMessageRelay;
+    use tokio::task::JoinSet;
+
+    use crate::{
+        keygen::utils::gen_keyshares,
+        proto::{encode_point, SCHEME_ID_FLAGS_MASK},
+        setup::{
+            keys::{
+                Ed25519Signature, Ed25519SigningKey, Ed25519VerifyingKey,
+            },
+            ProtocolParticipant,
+        },
+    };
+
+    use super::*;
+
+    fn keys(n: usize) -> (Vec<Ed25519SigningKey>, Vec<Ed25519VerifyingKey>) {
+        let mut rng = rand::thread_rng();
+        let sk: Vec<_> = (0..n)
+            .map(|_| Ed25519SigningKey::generate(&mut rng))
+            .collect();
+        let vk = sk.iter().map(Ed25519SigningKey::verifying_key).collect();
+
+        (sk, vk)
+    }
+
+    #[test]
+    fn encode_decode() {
+        let (sk, vk) = keys(3);
+        let initiator = Ed25519SigningKey::generate(&mut rand::thread_rng());
+
+        let setups = [
+            SetupParams::Keygen {
+                threshold: 2,
+                ranks: vec![0, 1, 0],
+                key_id: Some([5; 32]),
+            },
+            SetupParams::Sign {
+                key_id: [1; 32],
+                chain_path: DerivationPath::from_str("m/1/2'").unwrap(),
+                message_hash: [2; 32],
+            },
+            SetupParams::Finish {
+                final_session_id: [3; 32],
+                message_hash: [4; 32],
+            },
+            SetupParams::QuorumChange {
+                old_parties: vec![0, 1],
+                new_parties: vec![(1, 0), (2, 0)],
+                new_threshold: 2,
+                public_key: encode_point(&ProjectivePoint::GENERATOR),
+            },
+            SetupParams::KeyExport {
+                key_id: [6; 32],
+                receiver_public_key: [7; 32],
+            },
+        ];
+
+        for params in setups {
+            let keygen = matches!(params, SetupParams::Keygen { .. });
+            let scheme = if keygen {
+                SchemeId::HpkeAes256Gcm
+            } else {
+                SchemeId::default()
+            };
+            let setup =
+                ProtocolSetup::new(rand::random(), vk.clone(), params)
+                    .with_ttl(Duration::from_secs(10))
+                    .with_message_compression(keygen)
+                    .with_echo_broadcast(keygen)
+                    .with_encryption_scheme(scheme)
+                    .with_encryption_keys(if keygen {
+                        vec![[1; 32], [2; 32], [3; 32]]
+                    } else {
+                        vec![]
+                    });
+
+            let msg = setup.sign::<Ed25519Signature, _>(&initiator).unwrap();
+
+            let decoded = ProtocolSetup::<Ed25519VerifyingKey>::verify::<
+                Ed25519Signature,
+                _,
+            >(&msg, &initiator.verifying_key())
+            .unwrap();
+            assert_eq!(decoded, setup);
+
+            // signed by another key
+            assert!(matches!(
+                ProtocolSetup::<Ed25519VerifyingKey>::verify::<
+                    Ed25519Signature,
+                    _,
+                >(&msg, &vk[0]),
+                Err(SetupError::InvalidSignature)
+            ));
+
+            // a tampered payload
+            let mut bad = msg.clone();
+            bad[40] ^= 1;
+            assert!(ProtocolSetup::<Ed25519VerifyingKey>::verify::<
+                Ed25519Signature,
+                _,
+            >(&bad, &initiator.verifying_key())
+            .is_err());
+        }
+
+        let setup = ProtocolSetup::new(
+            [0; 32],
+            vk.clone(),
+            SetupParams::Finish {
+                final_session_id: [3; 32],
+                message_hash: [4; 32],
+            },
+        );
+        assert!(matches!(
+            setup.into_keygen::<_, Ed25519Signature>(sk[0].clone(), None),
+            Err(SetupError::UnexpectedKind)
+        ));
+    }
+
+    #[test]
+    fn encryption_keys() {
+        let (sk, vk) = keys(3);
+        let initiator = Ed25519SigningKey::generate(&mut rand::thread_rng());
+
+        let secrets: Vec<_> = (0..3)
+            .map(|_| ReusableSecret::random_from_rng(rand::thread_rng()))
+            .collect();
+        let public_keys: Vec<_> = secrets
+            .iter()
+            .map(|secret| PublicKey::from(secret).to_bytes())
+            .collect();
+
+        let setup = ProtocolSetup::new(
+            [0; 32],
+            vk.clone(),
+            SetupParams::Keygen {
+                threshold: 2,
+                ranks: vec![0; 3],
+                key_id: None,
+            },
+        );
+
+        let decode = |setup: &ProtocolSetup<Ed25519VerifyingKey>| {
+            let msg = setup.sign::<Ed25519Signature, _>(&initiator).unwrap();
+            ProtocolSetup::<Ed25519VerifyingKey>::verify::<Ed25519Signature, _>(
+                &msg,
+                &initiator.verifying_key(),
+            )
+        };
+
+        // a key for each participant, distinct keys
+        assert!(matches!(
+            setup
+                .clone()
+                .with_encryption_keys(vec![[1; 32]; 2])
+                .to_bytes(),
+            Err(SetupError::InvalidParameters)
+        ));
+        assert!(matches!(
+            decode(&setup.clone().with_encryption_keys(vec![[1; 32]; 3])),
+            Err(SetupError::InvalidParameters)
+        ));
+
+        let with_keys =
+            decode(&setup.clone().with_encryption_keys(public_keys.clone()))
+                .unwrap();
+        assert_eq!(with_keys.encryption_keys, public_keys);
+
+        let into_keygen = |setup: &ProtocolSetup<_>,
+                           secret: Option<usize>| {
+            setup.clone().into_keygen::<_, Ed25519Signature>(
+                sk[1].clone(),
+                secret.map(|p| secrets[p].clone()),
+            )
+        };
+
+        let keygen = into_keygen(&with_keys, Some(1)).unwrap();
+        let keys = keygen.encryption_keys().unwrap();
+        assert_eq!(keys.party_idx(), 1);
+        assert_eq!(keys.public_keys()[2].to_bytes(), public_keys[2]);
+
+        // a key of another participant, no key, or an undeclared key
+        for (setup, secret) in
+            [(&with_keys, Some(2)), (&with_keys, None), (&setup, Some(1))]
+        {
+            assert!(matches!(
+                into_keygen(setup, secret),
+                Err(SetupError::EncryptionKeyMismatch)
+            ));
+        }
+    }
+
+    fn quorum_change(
+        vk: &[Ed25519VerifyingKey],
+        old_parties: Vec<usize>,
+        public_key: PointBytes,
+    ) -> ProtocolSetup<Ed25519VerifyingKey> {
+        ProtocolSetup::new(
+            [0; 32],
+            vk.to_vec(),
+            SetupParams::QuorumChange {
+                old_parties,
+                new_parties: vec![(0, 0), (1, 0), (2, 0)],
+                new_threshold: 2,
+                public_key,
+            },
+        )
+    }
+
+    #[test]
+    fn invalid_quorum_change() {
+        let (_, vk) = keys(3);
+        let initiator = Ed25519SigningKey::generate(&mut rand::thread_rng());
+
+        let decode = |setup: ProtocolSetup<Ed25519VerifyingKey>| {
+            let msg = setup.sign::<Ed25519Signature, _>(&initiator).unwrap();
+            ProtocolSetup::<Ed25519VerifyingKey>::verify::<Ed25519Signature, _>(
+                &msg,
+                &initiator.verifying_key(),
+            )
+        };
+
+        let generator = encode_point(&ProjectivePoint::GENERATOR);
+        assert!(decode(quorum_change(&vk, vec![0, 1], generator)).is_ok());
+
+        // no old parties
+        assert!(matches!(
+            decode(quorum_change(&vk, vec![], generator)),
+            Err(SetupError::InvalidParameters)
+        ));
+
+        // all zeros is the encoding of the identity point
+        assert!(matches!(
+            decode(quorum_change(&vk, vec![0, 1], [0; 33])),
+            Err(SetupError::InvalidParameters)
+        ));
+
+        // duplicate old party
+        assert!(matches!(
+            decode(quorum_change(&vk, vec![0, 1, 0], generator)),
+            Err(SetupError::InvalidParameters)
+        ));
+
+        let new_parties = |new_parties| {
+            ProtocolSetup::new(
+                [0; 32],
+                vk.clone(),
+                SetupParams::QuorumChange {
+                    old_parties: vec![0, 1],
+                    new_parties,
+                    new_threshold: 2,
+                    public_key: generator,
+                },
+            )
+        };
+
+        assert!(decode(new_parties(vec![(0, 1), (1, 0)])).is_ok());
+
+        // duplicate new party
+        assert!(matches!(
+            decode(new_parties(vec![(0, 0), (1, 0), (0, 1)])),
+            Err(SetupError::InvalidParameters)
+        ));
+
+        // rank of the threshold
+        assert!(matches!(
+            decode(new_parties(vec![(0, 0), (1, 2)])),
+            Err(SetupError::InvalidParameters)
+        ));
+    }
+
+    #[test]
+    fn invalid_keygen_ranks() {
+        let (_, vk) = keys(3);
+        let initiator = Ed25519SigningKey::generate(&mut rand::thread_rng());
+
+        let decode = |ranks| {
+            let setup = ProtocolSetup::new(
+                [0; 32],
+                vk.clone(),
+                SetupParams::Keygen {
+                    threshold: 2,
+                    ranks,
+                    key_id: None,
+                },
+            );
+            let msg = setup.sign::<Ed25519Signature, _>(&initiator).unwrap();
+            ProtocolSetup::<Ed25519VerifyingKey>::verify::<Ed25519Signature, _>(
+                &msg,
+                &initiator.verifying_key(),
+            )
+        };
+
+        assert!(decode(vec![0, 1, 0]).is_ok());
+
+        assert!(matches!(
+            decode(vec![0, 2, 0]),
+            Err(SetupError::InvalidParameters)
+        ));
+
+        // a rank for each participant
+        assert!(matches!(
+            decode(vec![0, 0]),
+            Err(SetupError::InvalidParameters)
+        ));
+    }
+
+    #[test]
+    fn unsupported_flags() {
+        let (_, vk) = keys(3);
+        let initiator = Ed25519SigningKey::generate(&mut rand::thread_rng());
+
+        let decode = |setup: ProtocolSetup<Ed25519VerifyingKey>| {
+            let msg = setup.sign::<Ed25519Signature, _>(&initiator).unwrap();
+            ProtocolSetup::<Ed25519VerifyingKey>::verify::<Ed25519Signature, _>(
+                &msg,
+                &initiator.verifying_key(),
+            )
+        };
+
+        let generator = encode_point(&ProjectivePoint::GENERATOR);
+        let setup = quorum_change(&vk, vec![0, 1], generator);
+
+        assert!(decode(setup.clone().with_echo_broadcast(true)).is_ok());
+        assert!(matches!(
+            decode(setup.clone().with_message_compression(true)),
+            Err(SetupError::InvalidParameters)
+        ));
+
+        // flags follow version, kind, instance and TTL
+        let mut bytes = setup.to_bytes().unwrap();
+        bytes[1 + 1 + 32 + 4] = 4;
+        assert!(matches!(
+            ProtocolSetup::<Ed25519VerifyingKey>::from_bytes(&bytes),
+            Err(SetupError::InvalidEncoding)
+        ));
+
+        // unknown encryption scheme follows flags
+        let mut bytes = setup.to_bytes().unwrap();
+        bytes[1 + 1 + 32 + 4 + 1] = SCHEME_ID_FLAGS_MASK as u8 + 1;
+        assert!(matches!(
+            ProtocolSetup::<Ed25519VerifyingKey>::from_bytes(&bytes),
+            Err(SetupError::InvalidEncoding)
+        ));
+
+        // a pre-signature is finished without P2P messages
+        let finish = ProtocolSetup::new(
+            [0; 32],
+            vk.clone(),
+            SetupParams::Finish {
+                final_session_id: [3; 32],
+                message_hash: [4; 32],
+            },
+        );
+        assert!(decode(finish.clone()).is_ok());
+        assert!(matches!(
+            decode(finish.with_encryption_scheme(SchemeId::HpkeAes256Gcm)),
+            Err(SetupError::InvalidParameters)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn quorum_change_too_few_old_parties() {
+        let (sk, vk) = keys(3);
+        let shares = gen_keyshares(2, 3, None).await;
+        let public_key = encode_point(&shares[0].public_key());
+
+        let setup = quorum_change(&vk, vec![0], public_key);
+
+        assert!(matches!(
+            setup.into_quorum_change::<_, Ed25519Signature>(
+                sk[0].clone(),
+                Some(shares[0].clone()),
+                None
+            ),
+            Err(SetupError::InvalidParameters)
+        ));
+    }
+
+    #[test]
+    fn value_too_large() {
+        let (_, vk) = keys(3);
+
+        let setup = quorum_change(
+            &vk,
+            vec![0, 1],
+            encode_point(&ProjectivePoint::GENERATOR),
+        );
+
+        assert!(setup
+            .clone()
+            .with_ttl(Duration::from_secs(u32::MAX as u64 + 1))
+            .to_bytes()
+            .is_err_and(|err| matches!(err, SetupError::ValueTooLarge)));
+
+        let params = SetupParams::Keygen {
+            threshold: 2,
+            ranks: vec![0; u16::MAX as usize + 1],
+            key_id: None,
+        };
+        assert!(matches!(
+            ProtocolSetup::new([0; 32], vk, params).to_bytes(),
+            Err(SetupError::ValueTooLarge)
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn keygen_and_sign_from_broadcast() {
+        let (sk, vk) = keys(3);
+        let initiator = sk[0].clone();
+
+        let msg = ProtocolSetup::new(
+            rand::random(),
+            vk.clone(),
+            SetupParams::Keygen {
+                threshold: 2,
+                ranks: vec![0; 3],
+                key_id: None,
+            },
+        )
+        .with_encryption_scheme(SchemeId::HpkeChaCha20Poly1305)
+        .sign::<Ed25519Signature, _>(&initiator)
+        .unwrap();
+
+        let coord = SimpleMessageRelay::new();
+        let mut parties = JoinSet::new();
+
+        for sk in &sk {
+            let setup: keygen::SetupMessage<_, _, Ed25519Signature> =
+                ProtocolSetup::<Ed25519VerifyingKey>::verify(&msg, &vk[0])
+                    .unwrap()
+                    .into_keygen(sk.clone(), None)
+                    .unwrap();
+
+            assert_eq!(setup.participant_verifier(), &sk.verifying_key());
+            assert_eq!(
+                setup.encryption_scheme(),
+                SchemeId::HpkeChaCha20Poly1305
+            );
+
+            parties.spawn(crate::keygen::run(
+                setup,
+                rand::random(),
+                coord.connect(),
+            ));
+        }
+
+        let mut shares = vec![];
+        while let Some(share) = parties.join_next().await {
+            shares.push(Arc::new(share.unwrap().unwrap()));
+        }
+        shares.sort_by_key(|share| share.party_id);
+
+        let msg = ProtocolSetup::new(
+            rand::random(),
+            vec![vk[2], vk[0]],
+            SetupParams::Sign {
+                key_id: shares[0].key_id,
+                chain_path: DerivationPath::from_str("m/0").unwrap(),
+                message_hash: [1; 32],
+            },
+        )
+        .with_encryption_scheme(SchemeId::X25519Aes256Gcm)
+        .sign::<Ed25519Signature, _>(&initiator)
+        .unwrap();
+
+        let setup = ProtocolSetup::<Ed25519VerifyingKey>::verify::<
+            Ed25519Signature,
+            _,
+        >(&msg, &vk[0])
+        .unwrap();
+
+        // a party not in the list of participants
+        assert!(matches!(
+            setup.clone().into_sign::<_, Ed25519Signature>(
+                sk[1].clone(),
+                shares[1].clone(),
+                None
+            ),
+            Err(SetupError::NotAParticipant)
+        ));
+
+        let other = gen_keyshares(2, 2, None).await;
+        assert!(matches!(
+            setup.clone().into_sign::<_, Ed25519Signature>(
+                sk[0].clone(),
+                other[0].clone(),
+                None
+            ),
+            Err(SetupError::KeyshareMismatch)
+        ));
+
+        let coord = SimpleMessageRelay::new();
+        let mut parties = JoinSet::new();
+
+        for p in [2, 0] {
+            let setup: sign::SetupMessage<_, _, Ed25519Signature> = setup
+                .clone()
+                .into_sign(sk[p].clone(), shares[p].clone(), None)
+                .unwrap();
+
+            parties.spawn(crate::sign::run(
+                setup,
+                rand::random(),
+                coord.connect(),
+            ));
+        }
+
+        while let Some(fini) = parties.join_next().await {
+            fini.unwrap().unwrap();
+        }
+    }
+}
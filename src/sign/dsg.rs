@@ -23,7 +23,7 @@ use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha20Rng;
 use zeroize::Zeroizing;
 
-use sl_mpc_mate::{coord::*, math::birkhoff_coeffs};
+use sl_mpc_mate::{coord::*, math::birkhoff_coeffs, message::InstanceId};
 
 use sl_oblivious::rvole::{RVOLEReceiver, RVOLESender};
 
@@ -33,12 +33,13 @@ use crate::{
         create_abort_message, tags::*, EncryptedMessage, SignedMessage, *,
     },
     setup::{
+        approval::{finish_setup_hash, pre_sign_setup_hash, sign_setup_hash},
         FinalSignSetupMessage, PreSignSetupMessage, ProtocolParticipant,
-        SignSetupMessage, ABORT_MESSAGE_TAG,
+        SignSetupMessage, SignatureEncoding, ABORT_MESSAGE_TAG,
     },
     sign::constants::*,
     sign::messages::*,
-    Seed,
+    Seed, VERSION,
 };
 
 use super::SignError;
@@ -73,7 +74,8 @@ async fn pre_signature_inner<R: Relay, S: PreSignSetupMessage>(
     relay: &mut FilteredMsgRelay<R>,
 ) -> Result<PreSign, SignError> {
     let mut rng = ChaCha20Rng::from_seed(seed);
-    let mut scheme = crate::proto::Scheme::new(&mut rng);
+    let mut scheme = new_encryption_scheme(setup, &mut rng)
+        .map_err(|_| SignError::InvalidMessage)?;
 
     // For DKG part_id == part_idx.
     //
@@ -102,11 +104,13 @@ async fn pre_signature_inner<R: Relay, S: PreSignSetupMessage>(
         &blind_factor,
     );
 
+    let ctx = *relay.signing_context();
     relay
-        .send(SignedMessage::build(
+        .send_broadcast(SignedMessage::build_with_context(
             &setup.msg_id(None, DSG_MSG_R1),
             setup.message_ttl().as_secs() as _,
             0,
+            &ctx,
             setup.signer(),
             |msg: &mut SignMsg1, _| {
                 msg.session_id = commitments[my_party_idx].0;
@@ -127,9 +131,20 @@ async fn pre_signature_inner<R: Relay, S: PreSignSetupMessage>(
             |msg: &SignMsg1, party_idx| {
                 party_idx_to_id_map.push((party_idx, msg.party_id));
                 commitments[party_idx] = (msg.session_id, msg.commitment_r_i);
-                scheme
-                    .receiver_public_key(party_idx, &msg.enc_pk)
-                    .map_err(|_| SignError::InvalidMessage)?;
+                match setup.encryption_keys() {
+                    // the key is already known, it must be the
+                    // long-term key declared in the setup
+                    Some(keys) => {
+                        if keys.public_keys()[party_idx].as_bytes()
+                            != &msg.enc_pk
+                        {
+                            return Err(SignError::InvalidMessage);
+                        }
+                    }
+                    None => scheme
+                        .receiver_public_key(party_idx, &msg.enc_pk)
+                        .map_err(|_| SignError::InvalidMessage)?,
+                }
 
                 Ok(())
             },
@@ -215,7 +230,7 @@ async fn pre_signature_inner<R: Relay, S: PreSignSetupMessage>(
 
                 to_send.push(
                     enc_msg
-                        .encrypt(&mut scheme, party_idx)
+                        .encrypt(&mut scheme, party_idx, DSG_MSG_R2)
                         .ok_or(SignError::SendMessage)?,
                 );
 
@@ -258,19 +273,38 @@ async fn pre_signature_inner<R: Relay, S: PreSignSetupMessage>(
 
     let mut sender_additive_shares = vec![];
 
+    // Messages are processed in order of arrival, a generator for each
+    // party makes the output of the round a function of the seed.
+    let mut party_rngs = Pairs::from(
+        setup
+            .all_other_parties()
+            .map(|party_idx| (party_idx, ChaCha20Rng::from_seed(rng.gen())))
+            .collect::<Vec<_>>(),
+    );
+
     let mut round =
         Round::new(setup.total_participants() - 1, DSG_MSG_R2, relay);
 
     while let Some((msg, party_idx, is_abort)) = round.recv().await? {
         if is_abort {
-            check_abort(setup, &msg, party_idx, SignError::AbortProtocol)?;
+            check_abort(
+                setup,
+                round.relay.signing_context(),
+                &msg,
+                party_idx,
+                SignError::AbortProtocol,
+            )?;
             round.put_back(&msg, ABORT_MESSAGE_TAG, party_idx);
             continue;
         }
 
         let mut msg = Zeroizing::new(msg);
         let msg2 = match EncryptedMessage::<SignMsg2>::decrypt(
-            &mut msg, 0, &scheme, party_idx,
+            &mut msg,
+            0,
+            &mut scheme,
+            party_idx,
+            DSG_MSG_R2,
         ) {
             Some((refs, _)) => refs,
             _ => {
@@ -284,6 +318,9 @@ async fn pre_signature_inner<R: Relay, S: PreSignSetupMessage>(
             return Err(SignError::InvalidFinalSessionID);
         }
 
+        let mut rng = party_rngs
+            .pop_pair_or_err(party_idx, SignError::InvalidMessage)?;
+
         let receiver_id = find_party_id(party_idx);
 
         let sid = mta_session_id(&final_session_id, my_party_id, receiver_id);
@@ -329,7 +366,7 @@ async fn pre_signature_inner<R: Relay, S: PreSignSetupMessage>(
             .relay
             .send(
                 enc_msg3
-                    .encrypt(&mut scheme, party_idx)
+                    .encrypt(&mut scheme, party_idx, DSG_MSG_R3)
                     .ok_or(SignError::SendMessage)?,
             )
             .await?;
@@ -348,14 +385,24 @@ async fn pre_signature_inner<R: Relay, S: PreSignSetupMessage>(
 
     while let Some((msg, party_idx, is_abort)) = round.recv().await? {
         if is_abort {
-            check_abort(setup, &msg, party_idx, SignError::AbortProtocol)?;
+            check_abort(
+                setup,
+                round.relay.signing_context(),
+                &msg,
+                party_idx,
+                SignError::AbortProtocol,
+            )?;
             round.put_back(&msg, ABORT_MESSAGE_TAG, party_idx);
             continue;
         }
 
         let mut msg = Zeroizing::new(msg);
         let msg3 = match EncryptedMessage::<SignMsg3>::decrypt(
-            &mut msg, 0, &scheme, party_idx,
+            &mut msg,
+            0,
+            &mut scheme,
+            party_idx,
+            DSG_MSG_R3,
         ) {
             Some((refs, _)) => refs,
             _ => {
@@ -387,11 +434,11 @@ async fn pre_signature_inner<R: Relay, S: PreSignSetupMessage>(
             &msg3.blind_factor,
             commitment,
         ) {
-            return Err(SignError::InvalidCommitment);
+            return Err(SignError::InvalidCommitment(party_idx));
         }
 
         if digest_i.ct_ne(&msg3.digest_i).into() {
-            return Err(SignError::InvalidDigest);
+            return Err(SignError::InvalidDigest(party_idx));
         }
 
         let pk_j =
@@ -597,8 +644,12 @@ pub async fn run<R: Relay, S: SignSetupMessage>(
     seed: Seed,
     relay: R,
 ) -> Result<(Signature, RecoveryId), SignError> {
-    let abort_msg = create_abort_message(&setup);
-    let mut relay = FilteredMsgRelay::new(relay);
+    let ctx =
+        SigningContext::new(&DSG_LABEL, VERSION, &sign_setup_hash(&setup));
+    let mut relay = FilteredMsgRelay::new(relay)
+        .with_signing_context(ctx)
+        .with_compression(setup.message_compression());
+    let abort_msg = create_abort_message(&setup, &ctx);
 
     relay.ask_messages(&setup, ABORT_MESSAGE_TAG, false).await?;
     relay.ask_messages(&setup, DSG_MSG_R1, false).await?;
@@ -684,8 +735,15 @@ pub async fn pre_signature<R: Relay, S: PreSignSetupMessage>(
     seed: Seed,
     relay: R,
 ) -> Result<PreSign, SignError> {
-    let abort_msg = create_abort_message(&setup);
-    let mut relay = FilteredMsgRelay::new(relay);
+    let ctx = SigningContext::new(
+        &DSG_LABEL,
+        VERSION,
+        &pre_sign_setup_hash(&setup),
+    );
+    let mut relay = FilteredMsgRelay::new(relay)
+        .with_signing_context(ctx)
+        .with_compression(setup.message_compression());
+    let abort_msg = create_abort_message(&setup, &ctx);
 
     relay.ask_messages(&setup, ABORT_MESSAGE_TAG, false).await?;
     relay.ask_messages(&setup, DSG_MSG_R1, false).await?;
@@ -733,7 +791,11 @@ pub async fn finish<R: Relay, S: FinalSignSetupMessage>(
 ) -> Result<(Signature, RecoveryId), SignError> {
     let pre_signature_result = setup.pre_signature();
     let msg_hash = setup.message_hash();
-    let mut relay = FilteredMsgRelay::new(relay);
+    let ctx =
+        SigningContext::new(&DSG_LABEL, VERSION, &finish_setup_hash(&setup));
+    let mut relay = FilteredMsgRelay::new(relay)
+        .with_signing_context(ctx)
+        .with_compression(setup.message_compression());
 
     relay.ask_messages(&setup, ABORT_MESSAGE_TAG, false).await?;
     relay.ask_messages(&setup, DSG_MSG_R4, false).await?;
@@ -788,11 +850,13 @@ async fn run_final<R: Relay, S: ProtocolParticipant>(
     let partial_signature =
         create_partial_signature(pre_signature_result, msg_hash)?;
 
+    let ctx = *relay.signing_context();
     relay
-        .send(SignedMessage::build(
+        .send_broadcast(SignedMessage::build_with_context(
             &setup.msg_id(None, DSG_MSG_R4),
             setup.message_ttl().as_secs() as _,
             0,
+            &ctx,
             setup.signer(),
             |msg4: &mut SignMsg4, _| {
                 msg4.session_id = partial_signature.final_session_id;
@@ -830,6 +894,47 @@ async fn run_final<R: Relay, S: ProtocolParticipant>(
     combine_partial_signature(&partial_signatures)
 }
 
+/// Returns sizes of messages sent by a party during DSG.
+///
+/// # Type Parameters
+///
+/// * `S`: Type of signatures of messages
+///
+/// # Arguments
+///
+/// * `t`: Number of parties executing DSG
+/// * `scheme`: Encryption scheme of P2P messages
+pub fn message_sizes<S: SignatureEncoding>(
+    t: usize,
+    scheme: SchemeId,
+) -> Vec<MessageSize> {
+    let scheme = scheme.new_scheme(
+        &InstanceId::new([0; 32]),
+        &mut ChaCha20Rng::from_seed([0; 32]),
+    );
+
+    vec![
+        MessageSize::new_broadcast(
+            DSG_MSG_R1,
+            SignedMessage::<SignMsg1, S>::size(0),
+        ),
+        MessageSize::new_p2p(
+            DSG_MSG_R2,
+            EncryptedMessage::<SignMsg2>::size(0, 0, &scheme),
+            t,
+        ),
+        MessageSize::new_p2p(
+            DSG_MSG_R3,
+            EncryptedMessage::<SignMsg3>::size(0, 0, &scheme),
+            t,
+        ),
+        MessageSize::new_broadcast(
+            DSG_MSG_R4,
+            SignedMessage::<SignMsg4, S>::size(0),
+        ),
+    ]
+}
+
 /// Computes the hash of a commitment value
 ///
 /// This function computes the hash of a commitment value using the
@@ -1115,6 +1220,24 @@ mod tests {
         }
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn mixed_message_compression() {
+        let coord = SimpleMessageRelay::new();
+
+        let shares = gen_keyshares(2, 3, Some(&[0, 0, 0])).await;
+
+        let mut parties = JoinSet::new();
+        for (setup, seed) in setup_dsg(None, &shares, "m") {
+            let compression = setup.participant_index() != 0;
+            let setup = setup.with_message_compression(compression);
+            parties.spawn(run(setup, seed, coord.connect()));
+        }
+
+        while let Some(fini) = parties.join_next().await {
+            fini.unwrap().unwrap();
+        }
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn s2x3() {
         let coord = SimpleMessageRelay::new();
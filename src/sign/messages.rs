@@ -40,7 +40,8 @@ pub struct SignMsg1 {
     /// Hash of the commitment value
     pub commitment_r_i: [u8; 32],
 
-    /// Participant's encryption public key
+    /// Participant's encryption public key, the long-term key from the
+    /// setup if it declares encryption keys
     pub enc_pk: [u8; 32],
 
     /// Party ID from the key share
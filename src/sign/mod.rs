@@ -20,9 +20,9 @@
 //! - Privacy: No information about the private key is leaked
 //! - Verifiability: Signatures can be verified using standard ECDSA verification
 
-mod constants;
+pub(crate) mod constants;
 mod dsg;
-mod messages;
+pub(crate) mod messages;
 mod types;
 
 pub use dsg::*;
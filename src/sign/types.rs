@@ -18,13 +18,13 @@ use sl_mpc_mate::coord::MessageSendError;
 #[derive(thiserror::Error, Debug)]
 #[allow(missing_docs)]
 pub enum SignError {
-    /// Indicates that a cryptographic commitment is invalid
-    #[error("Invalid commitment")]
-    InvalidCommitment,
+    /// Indicates that a cryptographic commitment of a party is invalid
+    #[error("Invalid commitment of party {0}")]
+    InvalidCommitment(usize),
 
-    /// Indicates that a message digest is invalid
-    #[error("Invalid digest")]
-    InvalidDigest,
+    /// Indicates that a message digest of a party is invalid
+    #[error("Invalid digest of party {0}")]
+    InvalidDigest(usize),
 
     /// Indicates that the final session ID is invalid
     #[error("Invalid final_session_id")]
@@ -46,6 +46,11 @@ pub enum SignError {
     #[error("invalid message format")]
     InvalidMessage,
 
+    /// Indicates that a party sent a message which failed
+    /// verification, decryption or decoding
+    #[error("Invalid message from party {0}")]
+    InvalidMessageFrom(usize),
+
     /// Indicates that a required message is missing
     #[error("Missing message")]
     MissingMessage,
@@ -93,7 +98,12 @@ impl From<Error> for SignError {
             Error::Abort(p) => SignError::AbortProtocol(p as _),
             Error::Recv => SignError::MissingMessage,
             Error::Send => SignError::SendMessage,
-            Error::InvalidMessage => SignError::InvalidMessage,
+            // a mismatched echo does not prove which party cheated,
+            // so no party is banned
+            Error::InvalidMessage | Error::EchoMismatch { .. } => {
+                SignError::InvalidMessage
+            }
+            Error::InvalidMessageFrom(p) => SignError::InvalidMessageFrom(p),
         }
     }
 }
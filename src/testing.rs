@@ -0,0 +1,16 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Helpers to test protocols in an adversarial environment.
+//!
+//! This module is available with the `test-support` feature:
+//! a [`FaultyRelay`](crate::testing::FaultyRelay) delays, drops or
+//! substitutes messages, and a
+//! [`MaliciousRelay`](crate::testing::MaliciousRelay) makes a party
+//! deviate from a protocol.
+
+mod faulty;
+mod malicious;
+
+pub use faulty::{Fault, FaultPlan, FaultyRelay, Script};
+pub use malicious::{Behaviour, Deviations, MaliciousRelay};
@@ -0,0 +1,912 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! A relay wrapper injecting network faults.
+//!
+//! A [`FaultPlan`] is a list of rules shared by all parties of a
+//! protocol execution. A rule selects messages by a sender and a
+//! [`MessageTag`] and, optionally, a receiver, and describes a
+//! [`Fault`] to apply to them. Each party wraps its connection into a
+//! [`FaultyRelay`], which applies the faults to messages the party
+//! receives. So a broadcast message could be delivered intact to one
+//! party and corrupted to another.
+//!
+//! Honest parties wait for a lost or corrupted message forever, so a
+//! [`FaultyRelay`] could end its stream of messages after a period of
+//! inactivity, see [`FaultyRelay::with_recv_timeout()`]. A protocol
+//! fails with a missing message error then and aborts the execution
+//! for all other parties.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::time::{sleep, Instant, Sleep};
+
+use sl_mpc_mate::{coord::*, message::*};
+
+use crate::setup::ProtocolParticipant;
+
+/// A script of a malicious party, see [`Fault::Substitute`].
+///
+/// It is called with a message and an index of the receiver and
+/// returns a message to deliver instead, or `None` to drop it.
+pub type Script = Arc<dyn Fn(&[u8], usize) -> Option<Vec<u8>> + Send + Sync>;
+
+/// A fault to apply to a message.
+#[derive(Clone)]
+pub enum Fault {
+    /// Do not deliver the message
+    Drop,
+
+    /// Deliver the message after a delay
+    Delay(Duration),
+
+    /// Deliver the message twice
+    Duplicate,
+
+    /// Deliver the message after the next one, if another message
+    /// is ready to be delivered
+    Reorder,
+
+    /// Keep only given number of the first bytes of the message,
+    /// including the header
+    Truncate(usize),
+
+    /// Flip a bit of the message. The bit index is counted from the
+    /// end of the message header and wraps around the message size.
+    FlipBit(usize),
+
+    /// Replace the message by output of a script
+    Substitute(Script),
+}
+
+impl Fault {
+    /// Creates a [`Fault::Substitute`] from a closure.
+    pub fn substitute<F>(script: F) -> Self
+    where
+        F: Fn(&[u8], usize) -> Option<Vec<u8>> + Send + Sync + 'static,
+    {
+        Fault::Substitute(Arc::new(script))
+    }
+}
+
+struct Rule {
+    ids: HashSet<MsgId>,
+    receiver: Option<usize>,
+    fault: Fault,
+}
+
+/// A list of faults to inject into a protocol execution.
+#[derive(Default)]
+pub struct FaultPlan {
+    rules: Vec<Rule>,
+    applied: AtomicUsize,
+}
+
+impl FaultPlan {
+    /// Creates an empty plan.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies a fault to all messages with given tag from a sender:
+    /// a broadcast message and P2P messages to all receivers.
+    ///
+    /// # Arguments
+    /// * `setup` - Setup of any party of the protocol execution
+    /// * `sender` - Index of the sender
+    /// * `tag` - Message tag
+    /// * `fault` - The fault to apply
+    pub fn on<P: ProtocolParticipant>(
+        self,
+        setup: &P,
+        sender: usize,
+        tag: MessageTag,
+        fault: Fault,
+    ) -> Self {
+        self.rule(setup, sender, tag, None, fault)
+    }
+
+    /// Same as [`FaultPlan::on()`], but applies the fault only to
+    /// messages delivered to one receiver.
+    pub fn on_to<P: ProtocolParticipant>(
+        self,
+        setup: &P,
+        sender: usize,
+        tag: MessageTag,
+        receiver: usize,
+        fault: Fault,
+    ) -> Self {
+        self.rule(setup, sender, tag, Some(receiver), fault)
+    }
+
+    /// Applies a fault to messages with given IDs.
+    ///
+    /// # Arguments
+    /// * `ids` - IDs of the messages
+    /// * `receiver` - Index of the receiver, or `None` for all
+    ///   receivers
+    /// * `fault` - The fault to apply
+    pub fn on_ids(
+        mut self,
+        ids: impl IntoIterator<Item = MsgId>,
+        receiver: Option<usize>,
+        fault: Fault,
+    ) -> Self {
+        self.rules.push(Rule {
+            ids: ids.into_iter().collect(),
+            receiver,
+            fault,
+        });
+        self
+    }
+
+    /// Returns number of messages the faults were applied to.
+    pub fn applied(&self) -> usize {
+        self.applied.load(Ordering::SeqCst)
+    }
+
+    fn rule<P: ProtocolParticipant>(
+        self,
+        setup: &P,
+        sender: usize,
+        tag: MessageTag,
+        receiver: Option<usize>,
+        fault: Fault,
+    ) -> Self {
+        let p2p = (0..setup.total_participants())
+            .filter(|&p| p != sender)
+            .map(|p| setup.msg_id_from(sender, Some(p), tag));
+
+        let ids = p2p.chain([setup.msg_id_from(sender, None, tag)]);
+
+        self.on_ids(ids, receiver, fault)
+    }
+
+    fn fault(&self, id: &MsgId, receiver: usize) -> Option<&Fault> {
+        let rule = self.rules.iter().find(|rule| {
+            rule.ids.contains(id)
+                && rule.receiver.unwrap_or(receiver) == receiver
+        })?;
+
+        self.applied.fetch_add(1, Ordering::SeqCst);
+
+        Some(&rule.fault)
+    }
+}
+
+/// A relay applying faults of a [`FaultPlan`] to received messages.
+pub struct FaultyRelay<R> {
+    relay: R,
+    party: usize,
+    plan: Arc<FaultPlan>,
+    ready: VecDeque<Vec<u8>>,
+    held: Option<Vec<u8>>,
+    delayed: Vec<(Pin<Box<Sleep>>, Vec<u8>)>,
+    timeout: Option<Duration>,
+    deadline: Option<Pin<Box<Sleep>>>,
+    closed: bool,
+}
+
+impl<R: Relay> FaultyRelay<R> {
+    /// Wraps a connection of a party.
+    ///
+    /// # Arguments
+    /// * `relay` - The underlying relay
+    /// * `plan` - Faults to apply
+    /// * `party` - Index of the party in the protocol execution
+    pub fn new(relay: R, plan: Arc<FaultPlan>, party: usize) -> Self {
+        Self {
+            relay,
+            party,
+            plan,
+            ready: VecDeque::new(),
+            held: None,
+            delayed: vec![],
+            timeout: None,
+            deadline: None,
+            closed: false,
+        }
+    }
+
+    /// Ends the stream of messages when the party neither sends nor
+    /// receives a message for given time.
+    pub fn with_recv_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    fn touch(&mut self) {
+        let Some(timeout) = self.timeout else {
+            return;
+        };
+
+        match &mut self.deadline {
+            Some(deadline) => {
+                deadline.as_mut().reset(Instant::now() + timeout)
+            }
+            None => self.deadline = Some(Box::pin(sleep(timeout))),
+        }
+    }
+
+    fn deliver(&mut self, mut msg: Vec<u8>) {
+        let plan = self.plan.clone();
+
+        let fault = <&MsgHdr>::try_from(msg.as_slice())
+            .ok()
+            .and_then(|hdr| plan.fault(hdr.id(), self.party));
+
+        match fault {
+            None => self.ready.push_back(msg),
+
+            Some(Fault::Drop) => {}
+
+            Some(Fault::Delay(delay)) => {
+                self.delayed.push((Box::pin(sleep(*delay)), msg));
+            }
+
+            Some(Fault::Duplicate) => {
+                self.ready.push_back(msg.clone());
+                self.ready.push_back(msg);
+            }
+
+            Some(Fault::Reorder) => {
+                if let Some(prev) = self.held.replace(msg) {
+                    self.ready.push_back(prev);
+                }
+                return;
+            }
+
+            Some(Fault::Truncate(size)) => {
+                msg.truncate(*size);
+                self.ready.push_back(msg);
+            }
+
+            Some(Fault::FlipBit(bit)) => {
+                if msg.len() > MESSAGE_HEADER_SIZE {
+                    let payload = msg.len() - MESSAGE_HEADER_SIZE;
+                    let byte = MESSAGE_HEADER_SIZE + (bit / 8) % payload;
+                    msg[byte] ^= 1 << (bit % 8);
+                }
+                self.ready.push_back(msg);
+            }
+
+            Some(Fault::Substitute(script)) => {
+                if let Some(msg) = script(&msg, self.party) {
+                    self.ready.push_back(msg);
+                }
+            }
+        }
+
+        // a held message goes after the next delivered one
+        if !self.ready.is_empty() {
+            if let Some(msg) = self.held.take() {
+                self.ready.push_back(msg);
+            }
+        }
+    }
+}
+
+impl<R: Relay> Stream for FaultyRelay<R> {
+    type Item = Vec<u8>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if this.closed {
+            return Poll::Ready(None);
+        }
+
+        if this.deadline.is_none() {
+            this.touch();
+        }
+
+        loop {
+            if let Some(msg) = this.ready.pop_front() {
+                this.touch();
+                return Poll::Ready(Some(msg));
+            }
+
+            if let Some(idx) = this
+                .delayed
+                .iter_mut()
+                .position(|(delay, _)| delay.as_mut().poll(cx).is_ready())
+            {
+                let (_, msg) = this.delayed.swap_remove(idx);
+                this.ready.push_back(msg);
+                continue;
+            }
+
+            match this.relay.poll_next_unpin(cx) {
+                Poll::Ready(Some(msg)) => {
+                    this.deliver(msg);
+                    continue;
+                }
+
+                Poll::Ready(None) => {
+                    this.closed = true;
+                    return Poll::Ready(None);
+                }
+
+                Poll::Pending => {}
+            }
+
+            // nothing else to deliver, do not hold the message
+            // forever.
+            if let Some(msg) = this.held.take() {
+                this.ready.push_back(msg);
+                continue;
+            }
+
+            if let Some(deadline) = &mut this.deadline {
+                if deadline.as_mut().poll(cx).is_ready() {
+                    this.closed = true;
+                    return Poll::Ready(None);
+                }
+            }
+
+            return Poll::Pending;
+        }
+    }
+}
+
+impl<R: Relay> Sink<Vec<u8>> for FaultyRelay<R> {
+    type Error = MessageSendError;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().relay.poll_ready_unpin(cx)
+    }
+
+    fn start_send(
+        self: Pin<&mut Self>,
+        item: Vec<u8>,
+    ) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        this.touch();
+        this.relay.start_send_unpin(item)
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().relay.poll_flush_unpin(cx)
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().relay.poll_close_unpin(cx)
+    }
+}
+
+impl<R: Relay> Relay for FaultyRelay<R> {}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt::Debug;
+
+    use k256::ProjectivePoint;
+    use tokio::task::JoinSet;
+    use x25519_dalek::{PublicKey, ReusableSecret};
+
+    use sl_mpc_mate::coord::{simple::MessageRelay, SimpleMessageRelay};
+
+    use crate::{
+        key_export::{
+            export_keyshare, receive_keyshares, KeyExportError,
+            KEYSHARE_EXPORT_TAG,
+        },
+        keygen::{
+            self,
+            constants::{
+                DKG_MSG_R1, DKG_MSG_R2, DKG_MSG_R3, DKG_MSG_R4, QC_MSG_P2P_1,
+                QC_MSG_P2P_2, QC_MSG_R1,
+            },
+            key_refresh::{self, setup_key_refresh, KeyshareForRefresh},
+            quorum_change,
+            utils::{
+                gen_keyshares, setup_keygen, setup_quorum_change_threshold,
+            },
+            KeygenError,
+        },
+        setup::{
+            key_export::{
+                exporter::KeyExporter, receiver::KeyExportReceiver,
+            },
+            sign::SetupMessage as SignSetupMessage,
+            Ed25519Signature, Ed25519SigningKey, NoSigningKey,
+            NoVerifyingKey, PartyKeys,
+        },
+        sign::{
+            self,
+            constants::{DSG_MSG_R1, DSG_MSG_R2, DSG_MSG_R3, DSG_MSG_R4},
+            SignError,
+        },
+    };
+
+    use super::*;
+
+    const SENDER: usize = 1;
+    const VICTIM: usize = 2;
+
+    // The victim gives up first, so all other parties see its abort
+    // message instead of timing out themselves.
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    trait Attributable: Debug {
+        fn aborted_by(&self) -> Option<usize>;
+        fn culprit(&self) -> Option<usize>;
+        fn is_missing(&self) -> bool;
+    }
+
+    impl Attributable for KeygenError {
+        fn aborted_by(&self) -> Option<usize> {
+            match self {
+                KeygenError::AbortProtocol(p) => Some(*p),
+                _ => None,
+            }
+        }
+
+        fn culprit(&self) -> Option<usize> {
+            match self {
+                KeygenError::InvalidMessageFrom(p)
+                | KeygenError::InvalidCommitmentHash(p)
+                | KeygenError::InvalidDLogProof(p)
+                | KeygenError::BigFVecMismatch(p)
+                | KeygenError::FailedFelmanVerify(p) => Some(*p),
+                // faults corrupt messages of their sender only, so
+                // a mismatched echo names it
+                KeygenError::EchoMismatch { sender, .. } => Some(*sender),
+                _ => None,
+            }
+        }
+
+        fn is_missing(&self) -> bool {
+            matches!(self, KeygenError::MissingMessage)
+        }
+    }
+
+    impl Attributable for SignError {
+        fn aborted_by(&self) -> Option<usize> {
+            match self {
+                SignError::AbortProtocol(p) => Some(*p),
+                _ => None,
+            }
+        }
+
+        fn culprit(&self) -> Option<usize> {
+            match self {
+                SignError::InvalidMessageFrom(p)
+                | SignError::InvalidCommitment(p)
+                | SignError::InvalidDigest(p) => Some(*p),
+                SignError::AbortProtocolAndBanParty(p) => Some(*p as usize),
+                _ => None,
+            }
+        }
+
+        fn is_missing(&self) -> bool {
+            matches!(self, SignError::MissingMessage)
+        }
+    }
+
+    impl Attributable for KeyExportError {
+        fn aborted_by(&self) -> Option<usize> {
+            match self {
+                KeyExportError::AbortProtocol(p) => Some(*p),
+                _ => None,
+            }
+        }
+
+        fn culprit(&self) -> Option<usize> {
+            match self {
+                KeyExportError::InvalidMessageFrom(p) => Some(*p),
+                KeyExportError::AbortProtocolAndBanParty(p) => {
+                    Some(*p as usize)
+                }
+                _ => None,
+            }
+        }
+
+        fn is_missing(&self) -> bool {
+            matches!(self, KeyExportError::MissingMessage)
+        }
+    }
+
+    // name, fault, whether a protocol should tolerate the fault
+    fn faults() -> Vec<(&'static str, Fault, bool)> {
+        vec![
+            ("delay", Fault::Delay(Duration::from_millis(200)), true),
+            ("duplicate", Fault::Duplicate, true),
+            ("reorder", Fault::Reorder, true),
+            ("drop", Fault::Drop, false),
+            ("truncate", Fault::Truncate(MESSAGE_HEADER_SIZE + 8), false),
+            ("flip", Fault::FlipBit(3), false),
+            (
+                "substitute",
+                Fault::substitute(|msg, _| {
+                    let mut msg = msg.to_vec();
+                    msg[MESSAGE_HEADER_SIZE..].fill(0x55);
+                    Some(msg)
+                }),
+                false,
+            ),
+        ]
+    }
+
+    fn connect(
+        coord: &SimpleMessageRelay,
+        plan: &Arc<FaultPlan>,
+        party: usize,
+    ) -> FaultyRelay<MessageRelay> {
+        let timeout = if party == VICTIM {
+            TIMEOUT
+        } else {
+            TIMEOUT * 4
+        };
+
+        FaultyRelay::new(coord.connect(), plan.clone(), party)
+            .with_recv_timeout(timeout)
+    }
+
+    async fn join<T, E, F>(parties: Vec<F>) -> Vec<Result<T, E>>
+    where
+        F: Future<Output = Result<T, E>> + Send + 'static,
+        T: Send + 'static,
+        E: Send + 'static,
+    {
+        let mut jset = JoinSet::new();
+        for (p, party) in parties.into_iter().enumerate() {
+            jset.spawn(async move { (p, party.await) });
+        }
+
+        let mut results = vec![];
+        while let Some(fini) = jset.join_next().await {
+            results.push(fini.unwrap());
+        }
+        results.sort_by_key(|(p, _)| *p);
+
+        results.into_iter().map(|(_, res)| res).collect()
+    }
+
+    // A fault of a message of the last round is detected by the
+    // victim only, all other parties could complete the protocol.
+    #[allow(clippy::too_many_arguments)]
+    fn check<T, E, O>(
+        name: &str,
+        tag: MessageTag,
+        benign: bool,
+        last: bool,
+        victim: usize,
+        results: &[Result<T, E>],
+        output: impl Fn(&T) -> O,
+    ) where
+        E: Attributable,
+        O: PartialEq + Debug,
+    {
+        let outputs: Vec<O> = results
+            .iter()
+            .filter_map(|res| res.as_ref().ok())
+            .map(output)
+            .collect();
+
+        assert!(
+            outputs.windows(2).all(|w| w[0] == w[1]),
+            "{name} {tag:?}: {outputs:?}"
+        );
+
+        let errors: Vec<(usize, &E)> = results
+            .iter()
+            .enumerate()
+            .filter_map(|(p, res)| res.as_ref().err().map(|err| (p, err)))
+            .collect();
+
+        if benign {
+            assert!(errors.is_empty(), "{name} {tag:?}: {errors:?}");
+            return;
+        }
+
+        if !last {
+            assert_eq!(
+                errors.len(),
+                results.len(),
+                "{name} {tag:?}: a party succeeded"
+            );
+        }
+
+        // parties which detected the fault themselves
+        let detectors: Vec<usize> = errors
+            .iter()
+            .filter(|(_, err)| err.aborted_by().is_none())
+            .map(|(p, _)| *p)
+            .collect();
+
+        assert!(!detectors.is_empty(), "{name} {tag:?}: {errors:?}");
+
+        for (_, err) in &errors {
+            if let Some(p) = err.aborted_by() {
+                assert!(detectors.contains(&p), "{name} {tag:?}: {errors:?}");
+            }
+        }
+
+        // only the victim misses a message
+        if matches!(name, "drop" | "truncate") {
+            assert_eq!(detectors, [victim], "{name} {tag:?}: {errors:?}");
+        }
+
+        for (p, err) in errors {
+            if !detectors.contains(&p) {
+                continue;
+            }
+
+            if name == "drop" {
+                assert!(err.is_missing(), "{name} {tag:?}: {err:?}");
+            } else {
+                // a corrupted message is blamed on its sender
+                assert_eq!(err.culprit(), Some(SENDER), "{name} {tag:?}");
+            }
+        }
+    }
+
+    // Broadcast rounds are echoed: a corrupted copy of a message
+    // without a signature does not match the echoed hashes.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dkg() {
+        for tag in [DKG_MSG_R1, DKG_MSG_R2, DKG_MSG_R3, DKG_MSG_R4] {
+            let last = tag == DKG_MSG_R4;
+            for (name, fault, benign) in faults() {
+                let coord = SimpleMessageRelay::new();
+                let parties = setup_keygen(None, 2, 3, None);
+
+                let plan = Arc::new(FaultPlan::new().on_to(
+                    &parties[0].0,
+                    SENDER,
+                    tag,
+                    VICTIM,
+                    fault,
+                ));
+
+                let parties = parties
+                    .into_iter()
+                    .enumerate()
+                    .map(|(p, (setup, seed))| {
+                        let setup = setup.with_echo_broadcast(true);
+                        keygen::run(setup, seed, connect(&coord, &plan, p))
+                    })
+                    .collect();
+
+                let results = join(parties).await;
+
+                assert_eq!(plan.applied(), 1, "{name} {tag:?}");
+                check(name, tag, benign, last, VICTIM, &results, |s| {
+                    s.public_key()
+                });
+            }
+        }
+    }
+
+    // Messages are signed, so a corrupted message never verifies.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dsg() {
+        let shares = gen_keyshares(2, 3, None).await;
+
+        let mut rng = rand::thread_rng();
+        let sk: Vec<_> = (0..shares.len())
+            .map(|_| Ed25519SigningKey::generate(&mut rng))
+            .collect();
+        let vk: Vec<_> =
+            sk.iter().map(Ed25519SigningKey::verifying_key).collect();
+
+        for tag in [DSG_MSG_R1, DSG_MSG_R2, DSG_MSG_R3, DSG_MSG_R4] {
+            let last = tag == DSG_MSG_R4;
+            for (name, fault, benign) in faults() {
+                let coord = SimpleMessageRelay::new();
+                let instance = InstanceId::new(rand::random());
+
+                let parties: Vec<SignSetupMessage<_, _, Ed25519Signature>> =
+                    sk.iter()
+                        .zip(&shares)
+                        .map(|(sk, share)| {
+                            let keys = PartyKeys::new(sk.clone(), vk.clone())
+                                .unwrap();
+                            SignSetupMessage::from_keys(
+                                instance,
+                                keys,
+                                share.clone(),
+                            )
+                            .with_hash([1; 32])
+                        })
+                        .collect();
+
+                let plan = Arc::new(FaultPlan::new().on_to(
+                    &parties[0],
+                    SENDER,
+                    tag,
+                    VICTIM,
+                    fault,
+                ));
+
+                let parties = parties
+                    .into_iter()
+                    .enumerate()
+                    .map(|(p, setup)| {
+                        let relay = connect(&coord, &plan, p);
+                        sign::run(setup, rand::random(), relay)
+                    })
+                    .collect();
+
+                let results = join(parties).await;
+
+                assert_eq!(plan.applied(), 1, "{name} {tag:?}");
+                check(
+                    name,
+                    tag,
+                    benign,
+                    last,
+                    VICTIM,
+                    &results,
+                    |(sign, _)| *sign,
+                );
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn key_refresh() {
+        let shares = gen_keyshares(2, 3, None).await;
+
+        for tag in [DKG_MSG_R1, DKG_MSG_R3] {
+            let last = false;
+            for (name, fault, benign) in faults() {
+                let coord = SimpleMessageRelay::new();
+                let refresh = shares
+                    .iter()
+                    .map(|share| {
+                        KeyshareForRefresh::from_keyshare(share, None)
+                    })
+                    .collect();
+                let parties = setup_key_refresh(2, 3, None, refresh);
+
+                let plan = Arc::new(FaultPlan::new().on_to(
+                    &parties[0].0,
+                    SENDER,
+                    tag,
+                    VICTIM,
+                    fault,
+                ));
+
+                let parties = parties
+                    .into_iter()
+                    .enumerate()
+                    .map(|(p, (setup, seed, share))| {
+                        let setup = setup.with_echo_broadcast(true);
+                        let relay = connect(&coord, &plan, p);
+                        key_refresh::run(setup, seed, relay, share)
+                    })
+                    .collect();
+
+                let results = join(parties).await;
+
+                assert_eq!(plan.applied(), 1, "{name} {tag:?}");
+                check(name, tag, benign, last, VICTIM, &results, |s| {
+                    s.public_key()
+                });
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn quorum_change() {
+        let shares = gen_keyshares(2, 3, None).await;
+
+        for tag in [QC_MSG_R1, QC_MSG_P2P_1, QC_MSG_P2P_2] {
+            let last = false;
+            for (name, fault, benign) in faults() {
+                let coord = SimpleMessageRelay::new();
+                let parties =
+                    setup_quorum_change_threshold(&shares, 3, &[0; 3]);
+
+                let plan = Arc::new(FaultPlan::new().on_to(
+                    &parties[0].0,
+                    SENDER,
+                    tag,
+                    VICTIM,
+                    fault,
+                ));
+
+                let parties = parties
+                    .into_iter()
+                    .enumerate()
+                    .map(|(p, (setup, seed))| {
+                        let setup = setup.with_echo_broadcast(true);
+                        let relay = connect(&coord, &plan, p);
+                        quorum_change::run(setup, seed, relay)
+                    })
+                    .collect();
+
+                let results = join(parties).await;
+
+                assert_eq!(plan.applied(), 1, "{name} {tag:?}");
+                check(name, tag, benign, last, VICTIM, &results, |s| {
+                    s.as_ref().map(|s| s.public_key())
+                });
+            }
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn key_export() {
+        const RECEIVER: usize = 0;
+
+        let shares = gen_keyshares(2, 3, None).await;
+        let vk: Vec<_> = (0..shares.len()).map(NoVerifyingKey::new).collect();
+        let mut rng = rand::thread_rng();
+
+        for (name, fault, benign) in faults() {
+            let coord = SimpleMessageRelay::new();
+            let instance = InstanceId::new(rand::random());
+            let enc_key = ReusableSecret::random_from_rng(&mut rng);
+            let enc_pub_key = PublicKey::from(&enc_key);
+
+            for (p, share) in shares.iter().enumerate().skip(1) {
+                let setup = KeyExporter::new(
+                    instance,
+                    NoSigningKey,
+                    p,
+                    vk.clone(),
+                    share.clone(),
+                    enc_pub_key,
+                );
+                coord.send(export_keyshare(&mut rng, &setup).unwrap());
+            }
+
+            let setup = <KeyExportReceiver>::new(
+                instance,
+                NoSigningKey,
+                RECEIVER,
+                vk.clone(),
+                shares[RECEIVER].clone(),
+                enc_key,
+            );
+
+            let plan = Arc::new(FaultPlan::new().on(
+                &setup,
+                SENDER,
+                KEYSHARE_EXPORT_TAG,
+                fault,
+            ));
+
+            let relay = FaultyRelay::new(coord.connect(), plan.clone(), 0)
+                .with_recv_timeout(TIMEOUT);
+
+            let results = [receive_keyshares(setup, relay).await];
+
+            assert_eq!(plan.applied(), 1, "{name}");
+            check(
+                name,
+                KEYSHARE_EXPORT_TAG,
+                benign,
+                false,
+                RECEIVER,
+                &results,
+                |sk| ProjectivePoint::GENERATOR * sk,
+            );
+        }
+    }
+}
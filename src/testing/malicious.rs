@@ -0,0 +1,1125 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Scripted deviations of a malicious party.
+//!
+//! A [`Behaviour`] overrides messages a party sends, round by round.
+//! A rule selects messages of the party by a [`MessageTag`] and
+//! patches the payload of a message. The party sends messages through
+//! a [`MaliciousRelay`], which opens a selected message, patches it
+//! and signs or encrypts it again. So the malicious party sends a
+//! well-formed message with a valid signature, and honest parties
+//! have to detect the deviation by checks of the protocol itself.
+//!
+//! A P2P message is opened by the encryption key of its receiver, so
+//! to patch P2P messages all parties have to use long-term encryption
+//! keys, see [`EncryptionKeys`](crate::setup::EncryptionKeys).
+//!
+//! A broadcast message could be equivocated: the relay signs a
+//! patched copy of the message as well, and a colluding relay
+//! delivers the copy to some receivers, see
+//! [`Deviations::fault_plan()`].
+
+use std::{
+    collections::HashMap,
+    mem,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
+
+use bytemuck::{AnyBitPattern, NoUninit};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use signature::{SignatureEncoding, Signer};
+
+use sl_mpc_mate::{coord::*, message::*};
+
+use crate::{
+    proto::{
+        decompress_message, new_encryption_scheme, EncryptionScheme,
+        SchemeId, SigningContext,
+    },
+    setup::ProtocolParticipant,
+    testing::{Fault, FaultPlan},
+};
+
+type Patch = Arc<dyn Fn(&mut [u8]) + Send + Sync>;
+
+type Sign = Box<dyn Fn(&[u8]) -> Vec<u8> + Send>;
+
+type Scheme = Box<dyn EncryptionScheme + Sync>;
+
+enum Action {
+    Tamper,
+    Equivocate(Vec<usize>),
+}
+
+struct Rule {
+    // selected messages and their receivers, `None` for a broadcast
+    // message
+    ids: HashMap<MsgId, Option<usize>>,
+    tag: MessageTag,
+    action: Action,
+    patch: Patch,
+}
+
+#[derive(Default)]
+struct Rules {
+    rules: Vec<Rule>,
+    variants: Mutex<HashMap<MsgId, Vec<u8>>>,
+    applied: AtomicUsize,
+}
+
+fn typed_patch<T, F>(patch: F) -> Patch
+where
+    T: AnyBitPattern + NoUninit,
+    F: Fn(&mut T, &mut [u8]) + Send + Sync + 'static,
+{
+    Arc::new(move |body: &mut [u8]| {
+        let (msg, trailer) = body.split_at_mut(mem::size_of::<T>());
+        patch(bytemuck::from_bytes_mut(msg), trailer)
+    })
+}
+
+/// A list of deviations of a malicious party from a protocol.
+///
+/// A patch is called with a mutable reference to the payload of a
+/// message and its trailer. The payload of a broadcast message of
+/// several values, sent by `broadcast_4()`, is `()`, and the values
+/// are encoded into the trailer one after another.
+#[derive(Default)]
+pub struct Behaviour {
+    party: usize,
+    rules: Rules,
+}
+
+impl Behaviour {
+    /// Creates an honest behaviour.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Patches all messages with given tag sent by the party:
+    /// a broadcast message and P2P messages to all receivers.
+    ///
+    /// # Arguments
+    /// * `setup` - Setup of the malicious party
+    /// * `tag` - Message tag
+    /// * `patch` - Closure to patch the payload and trailer
+    pub fn tamper<P, T, F>(self, setup: &P, tag: MessageTag, patch: F) -> Self
+    where
+        P: ProtocolParticipant,
+        T: AnyBitPattern + NoUninit,
+        F: Fn(&mut T, &mut [u8]) + Send + Sync + 'static,
+    {
+        let p2p = setup
+            .all_other_parties()
+            .map(|p| (setup.msg_id(Some(p), tag), Some(p)));
+
+        let ids = p2p.chain([(setup.msg_id(None, tag), None)]);
+
+        self.rule(setup, ids, tag, Action::Tamper, typed_patch(patch))
+    }
+
+    /// Patches a P2P message with given tag sent to one receiver.
+    ///
+    /// # Arguments
+    /// * `setup` - Setup of the malicious party
+    /// * `tag` - Message tag
+    /// * `receiver` - Index of the receiver
+    /// * `patch` - Closure to patch the payload and trailer
+    pub fn tamper_to<P, T, F>(
+        self,
+        setup: &P,
+        tag: MessageTag,
+        receiver: usize,
+        patch: F,
+    ) -> Self
+    where
+        P: ProtocolParticipant,
+        T: AnyBitPattern + NoUninit,
+        F: Fn(&mut T, &mut [u8]) + Send + Sync + 'static,
+    {
+        let id = setup.msg_id(Some(receiver), tag);
+
+        self.rule(
+            setup,
+            [(id, Some(receiver))],
+            tag,
+            Action::Tamper,
+            typed_patch(patch),
+        )
+    }
+
+    /// Sends a patched copy of a broadcast message with given tag to
+    /// some receivers, and the original message to others.
+    ///
+    /// # Arguments
+    /// * `setup` - Setup of the malicious party
+    /// * `tag` - Message tag
+    /// * `receivers` - Indices of receivers of the patched copy
+    /// * `patch` - Closure to patch the payload and trailer of the copy
+    pub fn equivocate<P, T, F>(
+        self,
+        setup: &P,
+        tag: MessageTag,
+        receivers: &[usize],
+        patch: F,
+    ) -> Self
+    where
+        P: ProtocolParticipant,
+        T: AnyBitPattern + NoUninit,
+        F: Fn(&mut T, &mut [u8]) + Send + Sync + 'static,
+    {
+        let id = setup.msg_id(None, tag);
+
+        self.rule(
+            setup,
+            [(id, None)],
+            tag,
+            Action::Equivocate(receivers.to_vec()),
+            typed_patch(patch),
+        )
+    }
+
+    /// Wraps a connection of the malicious party into a relay which
+    /// applies the behaviour to messages the party sends.
+    ///
+    /// To patch P2P messages, `setups` must have long-term encryption
+    /// keys. A message to a party is opened by the keys of the party.
+    ///
+    /// # Arguments
+    /// * `relay` - Connection of the malicious party
+    /// * `setups` - Setups of all parties, in order of party indices
+    ///
+    /// # Panics
+    /// On patching of a P2P message, if a setup has no encryption keys
+    pub fn connect<P, R>(self, relay: R, setups: &[P]) -> MaliciousRelay<R>
+    where
+        P: ProtocolParticipant,
+        R: Relay,
+    {
+        // long-term keys do not need randomness
+        let mut rng = ChaCha20Rng::from_seed([0; 32]);
+        let mut scheme = |setup: &P| {
+            setup
+                .encryption_keys()
+                .and_then(|_| new_encryption_scheme(setup, &mut rng).ok())
+        };
+
+        let send = scheme(&setups[self.party]);
+        let recv = setups.iter().map(&mut scheme).collect();
+
+        MaliciousRelay {
+            relay,
+            party: self.party,
+            rules: Arc::new(self.rules),
+            sign: None,
+            sign_size: mem::size_of::<
+                <P::MessageSignature as SignatureEncoding>::Repr,
+            >(),
+            send,
+            recv,
+            nonces: HashMap::new(),
+        }
+    }
+
+    fn rule<P: ProtocolParticipant>(
+        mut self,
+        setup: &P,
+        ids: impl IntoIterator<Item = (MsgId, Option<usize>)>,
+        tag: MessageTag,
+        action: Action,
+        patch: Patch,
+    ) -> Self {
+        self.party = setup.participant_index();
+        self.rules.rules.push(Rule {
+            ids: ids.into_iter().collect(),
+            tag,
+            action,
+            patch,
+        });
+        self
+    }
+}
+
+/// Deviations of a [`MaliciousRelay`], shared with the relay.
+#[derive(Clone)]
+pub struct Deviations {
+    rules: Arc<Rules>,
+}
+
+impl Deviations {
+    /// Returns number of messages the behaviour has patched,
+    /// including equivocated copies.
+    pub fn applied(&self) -> usize {
+        self.rules.applied.load(Ordering::SeqCst)
+    }
+
+    /// Returns faults of a colluding relay which delivers
+    /// equivocated copies of broadcast messages to their receivers.
+    /// Each honest party should receive messages through a
+    /// [`FaultyRelay`](crate::testing::FaultyRelay) with the plan.
+    pub fn fault_plan(&self) -> FaultPlan {
+        let mut plan = FaultPlan::new();
+
+        for rule in &self.rules.rules {
+            let Action::Equivocate(receivers) = &rule.action else {
+                continue;
+            };
+
+            for &receiver in receivers {
+                let rules = self.rules.clone();
+                let copy = Fault::substitute(move |msg, _| {
+                    let variant =
+                        <&MsgHdr>::try_from(msg).ok().and_then(|hdr| {
+                            rules
+                                .variants
+                                .lock()
+                                .unwrap()
+                                .get(hdr.id())
+                                .cloned()
+                        });
+
+                    Some(variant.unwrap_or_else(|| msg.to_vec()))
+                });
+
+                plan = plan.on_ids(
+                    rule.ids.keys().copied(),
+                    Some(receiver),
+                    copy,
+                );
+            }
+        }
+
+        plan
+    }
+}
+
+/// A relay of a malicious party, see [`Behaviour::connect()`].
+pub struct MaliciousRelay<R> {
+    relay: R,
+    party: usize,
+    rules: Arc<Rules>,
+    sign: Option<Sign>,
+    sign_size: usize,
+    send: Option<Scheme>,
+    recv: Vec<Option<Scheme>>,
+    // nonce counters of `send` per receiver, see skip_nonces()
+    nonces: HashMap<usize, u32>,
+}
+
+impl<R: Relay> MaliciousRelay<R> {
+    /// Sets the key to sign patched broadcast messages. A key is not
+    /// required for messages without signatures.
+    ///
+    /// # Arguments
+    /// * `ctx` - Signing context of messages of the protocol
+    /// * `signing_key` - Signing key of the malicious party
+    pub fn with_signing_key<K, S>(
+        mut self,
+        ctx: SigningContext,
+        signing_key: K,
+    ) -> Self
+    where
+        K: Signer<S> + Send + 'static,
+        S: SignatureEncoding,
+    {
+        self.sign = Some(Box::new(move |msg| {
+            let sign = signing_key.sign(&[ctx.as_bytes(), msg].concat());
+            sign.to_bytes().as_ref().to_vec()
+        }));
+        self
+    }
+
+    /// Returns deviations of the relay.
+    pub fn deviations(&self) -> Deviations {
+        Deviations {
+            rules: self.rules.clone(),
+        }
+    }
+
+    fn deviate(&mut self, mut msg: Vec<u8>) -> Vec<u8> {
+        let Ok(&id) = <&MsgId>::try_from(msg.as_slice()) else {
+            return msg;
+        };
+
+        let rules = self.rules.clone();
+
+        for rule in &rules.rules {
+            let Some(&receiver) = rule.ids.get(&id) else {
+                continue;
+            };
+
+            let patched = match receiver {
+                None => self.patch_signed(&msg, &rule.patch),
+                Some(receiver) => self.patch_encrypted(
+                    &msg,
+                    receiver,
+                    rule.tag,
+                    &rule.patch,
+                ),
+            };
+
+            let Some(patched) = patched else {
+                continue;
+            };
+
+            match rule.action {
+                Action::Tamper => msg = patched,
+                Action::Equivocate(_) => {
+                    rules.variants.lock().unwrap().insert(id, patched);
+                }
+            }
+
+            rules.applied.fetch_add(1, Ordering::SeqCst);
+        }
+
+        msg
+    }
+
+    fn patch_signed(&self, msg: &[u8], patch: &Patch) -> Option<Vec<u8>> {
+        let mut msg = decompress_message(msg.to_vec())?;
+
+        let end = msg.len().checked_sub(self.sign_size)?;
+        if end < MESSAGE_HEADER_SIZE {
+            return None;
+        }
+
+        patch(&mut msg[MESSAGE_HEADER_SIZE..end]);
+
+        if self.sign_size > 0 {
+            let sign = self
+                .sign
+                .as_ref()
+                .expect("patching signed messages requires a signing key");
+            let sign = sign(&msg[..end]);
+            msg[end..].copy_from_slice(&sign);
+        }
+
+        Some(msg)
+    }
+
+    fn patch_encrypted(
+        &mut self,
+        msg: &[u8],
+        receiver: usize,
+        tag: MessageTag,
+        patch: &Patch,
+    ) -> Option<Vec<u8>> {
+        const NO_KEYS: &str =
+            "patching P2P messages requires long-term encryption keys";
+
+        let recv = self.recv.get_mut(receiver)?.as_mut().expect(NO_KEYS);
+        let send = self.send.as_mut().expect(NO_KEYS);
+
+        let mut msg = msg.to_vec();
+
+        let end = msg.len().checked_sub(send.overhead())?;
+        if end < MESSAGE_HEADER_SIZE {
+            return None;
+        }
+
+        // P2P messages of protocols have no additional data
+        let (body, tail) = msg.split_at_mut(end);
+        let (associated_data, plaintext) =
+            body.split_at_mut(MESSAGE_HEADER_SIZE);
+
+        recv.decrypt(associated_data, plaintext, tail, self.party, tag)
+            .ok()?;
+        patch(plaintext);
+
+        let used = self.nonces.entry(receiver).or_default();
+        skip_nonces(send, used, tail, receiver, tag)?;
+        send.encrypt(associated_data, plaintext, tail, receiver, tag)
+            .ok()?;
+
+        Some(msg)
+    }
+}
+
+// X25519 schemes count messages to a receiver in nonces, and the
+// receiver rejects a counter it has already seen. The party has sent
+// other messages to the receiver, so `send` skips their counters and
+// encrypts a patched message with the counter of the original one,
+// which the receiver never sees.
+fn skip_nonces(
+    send: &mut Scheme,
+    used: &mut u32,
+    original_tail: &[u8],
+    receiver: usize,
+    tag: MessageTag,
+) -> Option<()> {
+    if !matches!(
+        send.scheme_id(),
+        SchemeId::X25519ChaCha20Poly1305 | SchemeId::X25519Aes256Gcm
+    ) {
+        return Some(());
+    }
+
+    // the nonce ends the tail and the counter ends the nonce
+    let counter = u32::from_le_bytes(*original_tail.last_chunk()?);
+
+    let mut scratch = vec![0; send.overhead()];
+    while *used + 1 < counter {
+        send.encrypt(&[], &mut [], &mut scratch, receiver, tag)
+            .ok()?;
+        *used += 1;
+    }
+    *used += 1;
+
+    Some(())
+}
+
+impl<R: Relay> Stream for MaliciousRelay<R> {
+    type Item = Vec<u8>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.get_mut().relay.poll_next_unpin(cx)
+    }
+}
+
+impl<R: Relay> Sink<Vec<u8>> for MaliciousRelay<R> {
+    type Error = MessageSendError;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().relay.poll_ready_unpin(cx)
+    }
+
+    fn start_send(
+        self: Pin<&mut Self>,
+        item: Vec<u8>,
+    ) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        let msg = this.deviate(item);
+        this.relay.start_send_unpin(msg)
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().relay.poll_flush_unpin(cx)
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().relay.poll_close_unpin(cx)
+    }
+}
+
+impl<R: Relay> Relay for MaliciousRelay<R> {}
+
+#[cfg(test)]
+mod tests {
+    use std::{future::Future, time::Duration};
+
+    use k256::{ProjectivePoint, Scalar};
+    use tokio::task::JoinSet;
+    use x25519_dalek::{PublicKey, ReusableSecret};
+
+    use sl_mpc_mate::coord::SimpleMessageRelay;
+
+    use crate::{
+        keygen::{
+            self,
+            constants::{
+                DKG_LABEL, DKG_MSG_R1, DKG_MSG_R2, DKG_MSG_R3, QC_MSG_R0,
+                QC_MSG_R1,
+            },
+            dkg,
+            messages::KeygenMsg3,
+            quorum_change,
+            utils::{
+                gen_keyshares, setup_keygen, setup_quorum_change_threshold,
+            },
+            KeygenError,
+        },
+        proto::{
+            decode_scalar, encode_point, encode_scalar, tags::echo_tag,
+            PointBytes,
+        },
+        setup::{
+            keygen::SetupMessage as KeygenSetupMessage,
+            quorum_change::SetupMessage as QuorumChangeSetupMessage,
+            sign::SetupMessage as SignSetupMessage, Ed25519SigningKey,
+            EncryptionKeys,
+        },
+        sign::{
+            self, constants::DSG_MSG_R3, messages::SignMsg3, setup_dsg,
+            SignError,
+        },
+        testing::FaultyRelay,
+        Seed,
+    };
+
+    use super::*;
+
+    const CHEATER: usize = 1;
+    const VICTIM: usize = 2;
+
+    // Deviations below are detected without timeouts, the timeout only
+    // turns a regression into a failure instead of a hang.
+    const TIMEOUT: Duration = Duration::from_secs(60);
+
+    const POINT: usize = mem::size_of::<PointBytes>();
+
+    // Threshold of all keys below, so a DKG R2 message carries
+    // T points of a polynomial commitment and T DLog proofs.
+    const T: usize = 2;
+
+    // Offset of DLog proofs in the trailer of DKG R2 message:
+    // big_f_i_vec, r_i, commitment_2
+    const DLOG_PROOFS: usize = T * POINT + 32 + 32;
+    const DLOG_PROOF: usize = POINT + 32;
+
+    async fn join<O, E, F>(parties: Vec<F>) -> Vec<E>
+    where
+        F: Future<Output = Result<O, E>> + Send + 'static,
+        O: Send + 'static,
+        E: Send + 'static,
+    {
+        let mut jset = JoinSet::new();
+        for (p, party) in parties.into_iter().enumerate() {
+            jset.spawn(async move { (p, party.await) });
+        }
+
+        let mut errors = vec![];
+        while let Some(fini) = jset.join_next().await {
+            let (p, res) = fini.unwrap();
+            match res {
+                Ok(_) => panic!("party {p} succeeded"),
+                Err(err) => errors.push((p, err)),
+            }
+        }
+        errors.sort_by_key(|(p, _)| *p);
+
+        errors.into_iter().map(|(_, err)| err).collect()
+    }
+
+    fn add_one(scalar: &mut [u8; 32]) {
+        let s = decode_scalar(scalar).unwrap();
+        *scalar = encode_scalar(&(s + Scalar::ONE));
+    }
+
+    // Long-term encryption keys, so the malicious party could patch
+    // P2P messages.
+    fn encryption_keys(n: usize) -> Vec<EncryptionKeys> {
+        let mut rng = rand::thread_rng();
+
+        let secrets: Vec<_> = (0..n)
+            .map(|_| ReusableSecret::random_from_rng(&mut rng))
+            .collect();
+        let public_keys: Vec<_> =
+            secrets.iter().map(PublicKey::from).collect();
+
+        secrets
+            .into_iter()
+            .map(|secret| EncryptionKeys::new(secret, public_keys.clone()))
+            .collect::<Option<_>>()
+            .unwrap()
+    }
+
+    // Connects parties, the malicious party sends messages by the
+    // behaviour, and the relay delivers equivocated messages.
+    fn connect<P: ProtocolParticipant>(
+        setups: &[P],
+        behaviour: Behaviour,
+        signing_key: Option<(SigningContext, Ed25519SigningKey)>,
+    ) -> (Vec<impl Relay>, Deviations) {
+        let coord = SimpleMessageRelay::new();
+
+        let mut cheater = behaviour.connect(
+            FaultyRelay::new(coord.connect(), Default::default(), CHEATER)
+                .with_recv_timeout(TIMEOUT),
+            setups,
+        );
+        if let Some((ctx, signing_key)) = signing_key {
+            cheater = cheater.with_signing_key(ctx, signing_key);
+        }
+
+        let deviations = cheater.deviations();
+        let plan = Arc::new(deviations.fault_plan());
+
+        let mut relays: Vec<_> = (0..setups.len())
+            .map(|p| {
+                let relay =
+                    FaultyRelay::new(coord.connect(), plan.clone(), p)
+                        .with_recv_timeout(TIMEOUT);
+                Behaviour::new().connect(relay, setups)
+            })
+            .collect();
+        relays[CHEATER] = cheater;
+
+        (relays, deviations)
+    }
+
+    async fn dkg(
+        behaviour: impl FnOnce(&KeygenSetupMessage) -> Behaviour,
+    ) -> Vec<KeygenError> {
+        dkg_with_echo(false, behaviour).await
+    }
+
+    async fn dkg_with_echo(
+        echo: bool,
+        behaviour: impl FnOnce(&KeygenSetupMessage) -> Behaviour,
+    ) -> Vec<KeygenError> {
+        let parties = setup_keygen(None, T as u8, 3, None)
+            .into_iter()
+            .zip(encryption_keys(3))
+            .map(|((setup, seed), keys)| {
+                let setup = setup
+                    .with_echo_broadcast(echo)
+                    .with_encryption_keys(keys);
+                (setup, seed)
+            })
+            .collect();
+
+        run_dkg(parties, None, behaviour).await
+    }
+
+    async fn run_dkg<S>(
+        parties: Vec<(S, Seed)>,
+        signing_key: Option<Ed25519SigningKey>,
+        behaviour: impl FnOnce(&S) -> Behaviour,
+    ) -> Vec<KeygenError>
+    where
+        S: crate::setup::KeygenSetupMessage + Send + Sync + 'static,
+    {
+        let (setups, seeds): (Vec<_>, Vec<_>) = parties.into_iter().unzip();
+
+        let signing_key = signing_key.map(|sk| {
+            (dkg::signing_context(&setups[CHEATER], &DKG_LABEL), sk)
+        });
+        let (relays, cheater) =
+            connect(&setups, behaviour(&setups[CHEATER]), signing_key);
+
+        let parties = setups
+            .into_iter()
+            .zip(seeds)
+            .zip(relays)
+            .map(|((setup, seed), relay)| keygen::run(setup, seed, relay))
+            .collect();
+
+        let errors = join(parties).await;
+
+        assert!(cheater.applied() > 0);
+        errors
+    }
+
+    async fn quorum_change_with_echo(
+        behaviour: impl FnOnce(&QuorumChangeSetupMessage) -> Behaviour,
+    ) -> Vec<KeygenError> {
+        let shares = gen_keyshares(T as u8, 3, None).await;
+
+        let (setups, seeds): (Vec<_>, Vec<_>) =
+            setup_quorum_change_threshold(&shares, T as u8 + 1, &[0; 3])
+                .into_iter()
+                .map(|(setup, seed)| (setup.with_echo_broadcast(true), seed))
+                .unzip();
+
+        let (relays, cheater) =
+            connect(&setups, behaviour(&setups[CHEATER]), None);
+
+        let parties = setups
+            .into_iter()
+            .zip(seeds)
+            .zip(relays)
+            .map(|((setup, seed), relay)| {
+                quorum_change::run(setup, seed, relay)
+            })
+            .collect();
+
+        let errors = join(parties).await;
+
+        assert!(cheater.applied() > 0);
+        errors
+    }
+
+    async fn dsg(
+        behaviour: impl FnOnce(&SignSetupMessage) -> Behaviour,
+    ) -> Vec<SignError> {
+        let shares = gen_keyshares(T as u8, 3, None).await;
+
+        let (setups, seeds): (Vec<_>, Vec<_>) = setup_dsg(None, &shares, "m")
+            .into_iter()
+            .zip(encryption_keys(3))
+            .map(|((setup, seed), keys)| {
+                (setup.with_encryption_keys(keys), seed)
+            })
+            .unzip();
+
+        let (relays, cheater) =
+            connect(&setups, behaviour(&setups[CHEATER]), None);
+
+        let parties = setups
+            .into_iter()
+            .zip(seeds)
+            .zip(relays)
+            .map(|((setup, seed), relay)| sign::run(setup, seed, relay))
+            .collect();
+
+        let errors = join(parties).await;
+
+        assert!(cheater.applied() > 0);
+        errors
+    }
+
+    trait Aborted: std::fmt::Debug {
+        fn aborted_by(&self) -> Option<usize>;
+    }
+
+    impl Aborted for KeygenError {
+        fn aborted_by(&self) -> Option<usize> {
+            match self {
+                KeygenError::AbortProtocol(p) => Some(*p),
+                _ => None,
+            }
+        }
+    }
+
+    impl Aborted for SignError {
+        fn aborted_by(&self) -> Option<usize> {
+            match self {
+                SignError::AbortProtocol(p) => Some(*p),
+                _ => None,
+            }
+        }
+    }
+
+    // Returns the error of a party, or the error of a party which
+    // aborted it.
+    fn failure<E: Aborted>(errors: &[E], party: usize) -> &E {
+        match errors[party].aborted_by() {
+            Some(p) => &errors[p],
+            None => &errors[party],
+        }
+    }
+
+    // Each party detects a deviation or sees an abort message of an
+    // honest party which has detected it. The relay delivers messages
+    // out of order, so an honest party may see an abort message
+    // before a message of the cheater. Detectors are honest parties
+    // which always detect the deviation themselves.
+    fn detected<E: Aborted>(
+        errors: &[E],
+        detectors: &[usize],
+        detected: impl Fn(&E) -> bool,
+    ) {
+        assert!(errors[CHEATER].aborted_by().is_some(), "{errors:?}");
+
+        for p in 0..errors.len() {
+            assert_ne!(errors[p].aborted_by(), Some(CHEATER), "{errors:?}");
+            assert!(detected(failure(errors, p)), "{errors:?}");
+        }
+
+        for &p in detectors {
+            assert!(detected(&errors[p]), "{errors:?}");
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dkg_corrupted_commitment() {
+        let errors = dkg(|setup| {
+            Behaviour::new().tamper(setup, DKG_MSG_R2, |_: &mut (), tr| {
+                let point = encode_point(&ProjectivePoint::GENERATOR);
+                tr[..POINT].copy_from_slice(&point);
+            })
+        })
+        .await;
+
+        detected(&errors, &[], |err| {
+            matches!(err, KeygenError::InvalidCommitmentHash(CHEATER))
+        });
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dkg_bad_dlog_proof() {
+        let errors = dkg(|setup| {
+            Behaviour::new().tamper(setup, DKG_MSG_R2, |_: &mut (), tr| {
+                let proofs = &mut tr[DLOG_PROOFS..];
+                let (first, second) = proofs.split_at_mut(DLOG_PROOF);
+                first.swap_with_slice(&mut second[..DLOG_PROOF]);
+            })
+        })
+        .await;
+
+        detected(&errors, &[], |err| {
+            matches!(err, KeygenError::InvalidDLogProof(CHEATER))
+        });
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dkg_wrong_share() {
+        let errors = dkg(|setup| {
+            Behaviour::new().tamper_to(
+                setup,
+                DKG_MSG_R3,
+                VICTIM,
+                |msg: &mut KeygenMsg3, _| add_one(&mut msg.d_i),
+            )
+        })
+        .await;
+
+        detected(&errors, &[VICTIM], |err| {
+            matches!(err, KeygenError::FailedFelmanVerify(CHEATER))
+        });
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dkg_inconsistent_big_f_vec() {
+        let errors = dkg(|setup| {
+            Behaviour::new().tamper_to(
+                setup,
+                DKG_MSG_R3,
+                VICTIM,
+                |_: &mut KeygenMsg3, tr| {
+                    let point = encode_point(&ProjectivePoint::GENERATOR);
+                    tr[..POINT].copy_from_slice(&point);
+                },
+            )
+        })
+        .await;
+
+        detected(&errors, &[VICTIM], |err| {
+            matches!(err, KeygenError::BigFVecMismatch(CHEATER))
+        });
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dkg_equivocated_commitment() {
+        // R1 carries session_id and then the commitment
+        let errors = dkg(|setup| {
+            Behaviour::new().equivocate(
+                setup,
+                DKG_MSG_R1,
+                &[VICTIM],
+                |_: &mut (), tr| tr[32] ^= 1,
+            )
+        })
+        .await;
+
+        detected(&errors, &[VICTIM], |err| {
+            matches!(err, KeygenError::InvalidCommitmentHash(CHEATER))
+        });
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dkg_echo_detects_equivocation() {
+        let errors = dkg_with_echo(true, |setup| {
+            Behaviour::new().equivocate(
+                setup,
+                DKG_MSG_R1,
+                &[VICTIM],
+                |_: &mut (), tr| tr[32] ^= 1,
+            )
+        })
+        .await;
+
+        // every party, the cheater included, sees a mismatched echo
+        // of a message of the cheater before it uses the message
+        for p in 0..errors.len() {
+            assert!(
+                matches!(
+                    failure(&errors, p),
+                    KeygenError::EchoMismatch {
+                        sender: CHEATER,
+                        ..
+                    }
+                ),
+                "{errors:?}"
+            );
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dkg_false_echo_names_echoer() {
+        // the echo starts with the hash of the message of party 0
+        let errors = dkg_with_echo(true, |setup| {
+            Behaviour::new().tamper(
+                setup,
+                echo_tag(DKG_MSG_R1),
+                |_: &mut (), tr| tr[0] ^= 1,
+            )
+        })
+        .await;
+
+        // honest parties receive the false echo, and either one could
+        // abort the other first
+        detected(&errors, &[], |err| {
+            matches!(
+                err,
+                KeygenError::EchoMismatch {
+                    sender: 0,
+                    echoer: CHEATER
+                }
+            )
+        });
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn quorum_change_echo_detects_equivocation() {
+        // R1 carries session_id first
+        let errors = quorum_change_with_echo(|setup| {
+            Behaviour::new().equivocate(
+                setup,
+                QC_MSG_R1,
+                &[VICTIM],
+                |_: &mut (), tr| tr[0] ^= 1,
+            )
+        })
+        .await;
+
+        for p in 0..errors.len() {
+            assert!(
+                matches!(
+                    failure(&errors, p),
+                    KeygenError::EchoMismatch {
+                        sender: CHEATER,
+                        ..
+                    }
+                ),
+                "{errors:?}"
+            );
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn quorum_change_echo_detects_equivocated_party_id() {
+        let errors = quorum_change_with_echo(|setup| {
+            Behaviour::new().equivocate(
+                setup,
+                QC_MSG_R0,
+                &[VICTIM],
+                |id: &mut u8, _| *id ^= 1,
+            )
+        })
+        .await;
+
+        for p in 0..errors.len() {
+            assert!(
+                matches!(
+                    failure(&errors, p),
+                    KeygenError::EchoMismatch {
+                        sender: CHEATER,
+                        ..
+                    }
+                ),
+                "{errors:?}"
+            );
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dsg_wrong_gamma_u() {
+        let errors = dsg(|setup| {
+            Behaviour::new().tamper_to(
+                setup,
+                DSG_MSG_R3,
+                VICTIM,
+                |msg: &mut SignMsg3, _| {
+                    msg.gamma_u = encode_point(&ProjectivePoint::GENERATOR);
+                },
+            )
+        })
+        .await;
+
+        detected(&errors, &[VICTIM], |err| {
+            matches!(
+                err,
+                SignError::AbortProtocolAndBanParty(p)
+                    if *p as usize == CHEATER
+            )
+        });
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dsg_inconsistent_rvole() {
+        let errors = dsg(|setup| {
+            Behaviour::new().tamper_to(
+                setup,
+                DSG_MSG_R3,
+                VICTIM,
+                |msg: &mut SignMsg3, _| {
+                    let mta = bytemuck::bytes_of_mut(&mut msg.mta_msg2);
+                    mta[mta.len() - 1] ^= 1;
+                },
+            )
+        })
+        .await;
+
+        detected(&errors, &[VICTIM], |err| {
+            matches!(
+                err,
+                SignError::AbortProtocolAndBanParty(p)
+                    if *p as usize == CHEATER
+            )
+        });
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dsg_wrong_blind_factor() {
+        let errors = dsg(|setup| {
+            Behaviour::new().tamper_to(
+                setup,
+                DSG_MSG_R3,
+                VICTIM,
+                |msg: &mut SignMsg3, _| msg.blind_factor[0] ^= 1,
+            )
+        })
+        .await;
+
+        detected(&errors, &[VICTIM], |err| {
+            matches!(err, SignError::InvalidCommitment(CHEATER))
+        });
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dsg_wrong_digest() {
+        let errors = dsg(|setup| {
+            Behaviour::new().tamper_to(
+                setup,
+                DSG_MSG_R3,
+                VICTIM,
+                |msg: &mut SignMsg3, _| msg.digest_i[0] ^= 1,
+            )
+        })
+        .await;
+
+        detected(&errors, &[VICTIM], |err| {
+            matches!(err, SignError::InvalidDigest(CHEATER))
+        });
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dsg_wrong_psi() {
+        // psi is not checked before the final signature, which fails
+        // to verify and does not identify the cheater
+        let errors = dsg(|setup| {
+            Behaviour::new().tamper(
+                setup,
+                DSG_MSG_R3,
+                |msg: &mut SignMsg3, _| add_one(&mut msg.psi),
+            )
+        })
+        .await;
+
+        // a party which fails first aborts the others
+        for p in 0..errors.len() {
+            let failed = failure(&errors, p);
+            assert!(matches!(failed, SignError::K256Error), "{errors:?}");
+        }
+    }
+}
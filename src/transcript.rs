@@ -0,0 +1,657 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Verification of recorded protocol transcripts.
+//!
+//! A transcript is the set of messages sent by all parties of one
+//! protocol execution, as a relay sees them. A verifier needs the
+//! transcript and the public setup of the execution only. It checks
+//! signatures of all broadcast messages, opens commitments, verifies
+//! DLog proofs and checks that public key shares of parties follow
+//! from their polynomial commitments, the public part of Feldman
+//! verification. Then it derives public outputs of the execution: a
+//! public key, public key shares of parties, or checks a signature.
+//!
+//! P2P messages are encrypted to their receivers, so a verifier only
+//! checks that they are present in the transcript.
+
+use std::collections::{HashMap, HashSet};
+
+use bytemuck::{AnyBitPattern, NoUninit};
+use k256::ProjectivePoint;
+
+use sl_mpc_mate::message::*;
+
+use crate::{
+    proto::{
+        decompress_message,
+        tags::{echo_hash, echo_tag},
+        SignedMessage, SigningContext, Wrap,
+    },
+    setup::ProtocolParticipant,
+};
+
+mod keygen;
+mod quorum_change;
+mod sign;
+
+pub use keygen::{verify_dkg, verify_key_refresh};
+pub use quorum_change::verify_quorum_change;
+pub use sign::{verify_dsg, DsgParams};
+
+/// Transcript verification errors. Each error names a party whose
+/// messages do not pass a check.
+#[derive(Debug, thiserror::Error)]
+pub enum TranscriptError {
+    /// A message of a party is missing in the transcript
+    #[error("Missing message of party {0}")]
+    MissingMessage(usize),
+
+    /// The transcript contains different messages of a party with the
+    /// same ID
+    #[error("Party {0} sent different messages with the same ID")]
+    Equivocation(usize),
+
+    /// A broadcast message has an invalid signature
+    #[error("Invalid signature of a message of party {0}")]
+    InvalidSignature(usize),
+
+    /// A message could not be decoded
+    #[error("Invalid message of party {0}")]
+    InvalidMessage(usize),
+
+    /// An opened commitment does not match the committed value
+    #[error("Invalid commitment of party {0}")]
+    InvalidCommitment(usize),
+
+    /// A DLog proof does not verify
+    #[error("Invalid DLog proof of party {0}")]
+    InvalidDLogProof(usize),
+
+    /// A public key share does not follow from the polynomial
+    /// commitments
+    #[error("Public key share of party {0} fails Feldman verification")]
+    FailedFeldmanVerify(usize),
+
+    /// A party announced a different public key
+    #[error("Public key of party {0} does not match the transcript")]
+    PublicKeyMismatch(usize),
+
+    /// A party used a different session ID
+    #[error("Session ID of party {0} does not match the transcript")]
+    SessionIdMismatch(usize),
+
+    /// An echo message of a party contains a hash which does not
+    /// match a broadcast message of the transcript
+    #[error("Echo message of party {0} does not match the transcript")]
+    EchoMismatch(usize),
+
+    /// The public key does not follow from the transcript
+    #[error("Public key does not follow from the transcript")]
+    InvalidPublicKey,
+
+    /// The signature does not follow from the transcript
+    #[error("Signature does not follow from the transcript")]
+    InvalidSignatureShares,
+}
+
+/// Public outputs of a verified protocol execution.
+#[derive(Debug, Clone)]
+pub struct PublicOutput {
+    /// Public key of the key. For DSG, the public key the signature
+    /// verifies under.
+    pub public_key: ProjectivePoint,
+
+    /// Public key shares of parties, in order of party IDs of the
+    /// resulting key shares. Empty for DSG.
+    pub public_shares: Vec<ProjectivePoint>,
+
+    /// Number of verified broadcast messages, including echo messages
+    pub broadcasts: usize,
+
+    /// Number of P2P messages found in the transcript
+    pub p2p: usize,
+}
+
+/// Messages of one protocol execution.
+pub struct Transcript {
+    messages: HashMap<MsgId, Vec<u8>>,
+    conflicts: HashSet<MsgId>,
+}
+
+impl Transcript {
+    /// Creates a transcript from a list of messages.
+    ///
+    /// Compressed messages are restored. Ask messages, messages which
+    /// are too short to have an ID or fail to decompress are ignored.
+    pub fn new<I>(messages: I) -> Self
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+    {
+        let mut transcript = Transcript {
+            messages: HashMap::new(),
+            conflicts: HashSet::new(),
+        };
+
+        for msg in messages {
+            if msg.len() <= MESSAGE_HEADER_SIZE {
+                continue;
+            }
+
+            let Some(msg) = decompress_message(msg) else {
+                continue;
+            };
+
+            let Ok(id) = MsgId::try_from(msg.as_slice()) else {
+                continue;
+            };
+
+            match transcript.messages.get(&id) {
+                Some(prev) if prev != &msg => {
+                    transcript.conflicts.insert(id);
+                }
+                Some(_) => {}
+                None => {
+                    transcript.messages.insert(id, msg);
+                }
+            }
+        }
+
+        transcript
+    }
+
+    /// Returns the number of distinct messages.
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Returns true if the transcript has no messages.
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Returns a message with given ID.
+    pub fn message(&self, id: &MsgId) -> Option<&[u8]> {
+        self.messages.get(id).map(Vec::as_slice)
+    }
+
+    fn get(
+        &self,
+        id: &MsgId,
+        party: usize,
+    ) -> Result<&[u8], TranscriptError> {
+        if self.conflicts.contains(id) {
+            return Err(TranscriptError::Equivocation(party));
+        }
+
+        self.message(id)
+            .ok_or(TranscriptError::MissingMessage(party))
+    }
+}
+
+// Verifies messages of a transcript of one execution and counts them.
+struct Checker<'a, P> {
+    transcript: &'a Transcript,
+    setup: &'a P,
+    ctx: SigningContext,
+    broadcasts: usize,
+    p2p: usize,
+}
+
+impl<'a, P: ProtocolParticipant> Checker<'a, P> {
+    fn new(
+        transcript: &'a Transcript,
+        setup: &'a P,
+        ctx: SigningContext,
+    ) -> Self {
+        Self {
+            transcript,
+            setup,
+            ctx,
+            broadcasts: 0,
+            p2p: 0,
+        }
+    }
+
+    // Returns a verified broadcast message of a party and its trailer.
+    fn signed<T>(
+        &mut self,
+        party: usize,
+        tag: MessageTag,
+    ) -> Result<(&'a T, &'a [u8]), TranscriptError>
+    where
+        T: AnyBitPattern + NoUninit,
+    {
+        let id = self.setup.msg_id_from(party, None, tag);
+        let msg = self.transcript.get(&id, party)?;
+
+        let trailer = msg
+            .len()
+            .checked_sub(SignedMessage::<T, P::MessageSignature>::size(0))
+            .ok_or(TranscriptError::InvalidMessage(party))?;
+
+        let refs =
+            SignedMessage::<T, P::MessageSignature>::verify_with_context(
+                msg,
+                trailer,
+                &self.ctx,
+                self.setup.verifier(party),
+            )
+            .ok_or(TranscriptError::InvalidSignature(party))?;
+
+        self.broadcasts += 1;
+
+        Ok(refs)
+    }
+
+    // Returns the payload of a verified message sent by `broadcast_4()`
+    fn broadcast(
+        &mut self,
+        party: usize,
+        tag: MessageTag,
+    ) -> Result<Payload<'a>, TranscriptError> {
+        let (_, buf) = self.signed::<()>(party, tag)?;
+
+        Ok(Payload { buf, party })
+    }
+
+    // Checks that there is a P2P message from `sender` to `receiver`.
+    fn p2p(
+        &mut self,
+        sender: usize,
+        receiver: usize,
+        tag: MessageTag,
+    ) -> Result<(), TranscriptError> {
+        let id = self.setup.msg_id_from(sender, Some(receiver), tag);
+        self.transcript.get(&id, sender)?;
+        self.p2p += 1;
+
+        Ok(())
+    }
+
+    // Checks echo messages of a broadcast round from all parties, if
+    // the setup enables the echo round.
+    fn echo(
+        &mut self,
+        tag: MessageTag,
+        senders: &[usize],
+    ) -> Result<(), TranscriptError> {
+        if !self.setup.echo_broadcast() {
+            return Ok(());
+        }
+
+        let mut senders = senders.to_vec();
+        senders.sort();
+
+        let hashes = senders
+            .iter()
+            .map(|&p| {
+                let id = self.setup.msg_id_from(p, None, tag);
+                self.transcript.get(&id, p).map(echo_hash::<P>)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // messages of the transcript are unique, so a mismatched hash
+        // is a false echo of its party
+        for party in 0..self.setup.total_participants() {
+            let (_, echo) = self.signed::<()>(party, echo_tag(tag))?;
+
+            if echo.len() != hashes.len() * 32
+                || hashes.iter().zip(echo.chunks(32)).any(|(h, e)| h != e)
+            {
+                return Err(TranscriptError::EchoMismatch(party));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn output(
+        self,
+        public_key: ProjectivePoint,
+        public_shares: Vec<ProjectivePoint>,
+    ) -> PublicOutput {
+        PublicOutput {
+            public_key,
+            public_shares,
+            broadcasts: self.broadcasts,
+            p2p: self.p2p,
+        }
+    }
+}
+
+// Decodes values of a payload of a broadcast message one by one.
+struct Payload<'a> {
+    buf: &'a [u8],
+    party: usize,
+}
+
+impl Payload<'_> {
+    fn take<T: Wrap>(&mut self, size: usize) -> Result<T, TranscriptError> {
+        let (rest, value) = T::decode(self.buf, size)
+            .ok_or(TranscriptError::InvalidMessage(self.party))?;
+        self.buf = rest;
+
+        Ok(value)
+    }
+
+    // Size of the rest of the payload without `tail` bytes.
+    fn rest(&self, tail: usize) -> Result<usize, TranscriptError> {
+        self.buf
+            .len()
+            .checked_sub(tail)
+            .ok_or(TranscriptError::InvalidMessage(self.party))
+    }
+
+    fn end(self) -> Result<(), TranscriptError> {
+        if self.buf.is_empty() {
+            Ok(())
+        } else {
+            Err(TranscriptError::InvalidMessage(self.party))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        pin::Pin,
+        sync::{Arc, Mutex},
+        task::{Context, Poll},
+    };
+
+    use futures_util::future::join_all;
+    use k256::{ecdsa::Signature, Scalar};
+
+    use sl_mpc_mate::coord::{simple::MessageRelay, *};
+
+    use crate::{
+        keygen::{
+            self,
+            constants::DKG_MSG_R2,
+            key_refresh::{self, setup_key_refresh, KeyshareForRefresh},
+            quorum_change,
+            utils::{
+                gen_keyshares, setup_keygen, setup_quorum_change_threshold,
+            },
+            Keyshare,
+        },
+        setup::keygen::SetupMessage,
+        sign::{self, setup_dsg},
+        Seed,
+    };
+
+    use super::*;
+
+    // Records messages sent by all parties of an execution.
+    #[derive(Default)]
+    struct Recorder {
+        coord: SimpleMessageRelay,
+        sent: Arc<Mutex<Vec<Vec<u8>>>>,
+    }
+
+    impl Recorder {
+        fn connect(&self) -> Recording {
+            Recording {
+                relay: self.coord.connect(),
+                sent: self.sent.clone(),
+            }
+        }
+
+        fn messages(&self) -> Vec<Vec<u8>> {
+            self.sent.lock().unwrap().clone()
+        }
+    }
+
+    struct Recording {
+        relay: MessageRelay,
+        sent: Arc<Mutex<Vec<Vec<u8>>>>,
+    }
+
+    impl Stream for Recording {
+        type Item = Vec<u8>;
+
+        fn poll_next(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Option<Self::Item>> {
+            self.get_mut().relay.poll_next_unpin(cx)
+        }
+    }
+
+    impl Sink<Vec<u8>> for Recording {
+        type Error = MessageSendError;
+
+        fn poll_ready(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<(), Self::Error>> {
+            self.get_mut().relay.poll_ready_unpin(cx)
+        }
+
+        fn start_send(
+            self: Pin<&mut Self>,
+            item: Vec<u8>,
+        ) -> Result<(), Self::Error> {
+            let this = self.get_mut();
+            this.sent.lock().unwrap().push(item.clone());
+            this.relay.start_send_unpin(item)
+        }
+
+        fn poll_flush(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<(), Self::Error>> {
+            self.get_mut().relay.poll_flush_unpin(cx)
+        }
+
+        fn poll_close(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<(), Self::Error>> {
+            self.get_mut().relay.poll_close_unpin(cx)
+        }
+    }
+
+    impl Relay for Recording {}
+
+    async fn run_dkg(
+        echo: bool,
+    ) -> (Vec<(SetupMessage, Seed)>, Vec<Keyshare>, Vec<Vec<u8>>) {
+        let parties: Vec<_> = setup_keygen(None, 2, 3, None)
+            .into_iter()
+            .map(|(setup, seed)| (setup.with_echo_broadcast(echo), seed))
+            .collect();
+
+        let recorder = Recorder::default();
+        let shares = join_all(parties.iter().map(|(setup, seed)| {
+            keygen::run(setup, *seed, recorder.connect())
+        }))
+        .await
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+
+        (parties, shares, recorder.messages())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dkg() {
+        for echo in [false, true] {
+            let (parties, shares, messages) = run_dkg(echo).await;
+
+            let output = verify_dkg(
+                &parties[0].0,
+                &Transcript::new(messages),
+                Some(&shares[0].root_chain_code()),
+            )
+            .unwrap();
+
+            assert_eq!(output.public_key, shares[0].public_key());
+            for (party_id, big_s) in output.public_shares.iter().enumerate() {
+                assert_eq!(big_s, &shares[0].big_s(party_id as u8));
+            }
+
+            assert_eq!(output.broadcasts, if echo { 18 } else { 9 });
+            assert_eq!(output.p2p, 12);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tampered_dkg() {
+        let (parties, _, messages) = run_dkg(false).await;
+        let setup = &parties[0].0;
+
+        let r2 = setup.msg_id_from(1, None, DKG_MSG_R2);
+        let is_r2 = |msg: &Vec<u8>| {
+            msg.len() > MESSAGE_HEADER_SIZE
+                && MsgId::try_from(msg.as_slice()).is_ok_and(|id| id == r2)
+        };
+
+        // flip the first byte of the opened commitment of party 1
+        let mut tampered =
+            messages.iter().find(|m| is_r2(m)).unwrap().clone();
+        tampered[MESSAGE_HEADER_SIZE + 2 * 33] ^= 1;
+
+        let missing =
+            Transcript::new(messages.iter().filter(|m| !is_r2(m)).cloned());
+        assert!(matches!(
+            verify_dkg(setup, &missing, None),
+            Err(TranscriptError::MissingMessage(1))
+        ));
+
+        let replaced = Transcript::new(
+            messages
+                .iter()
+                .filter(|m| !is_r2(m))
+                .cloned()
+                .chain([tampered.clone()]),
+        );
+        assert!(matches!(
+            verify_dkg(setup, &replaced, None),
+            Err(TranscriptError::InvalidCommitment(1))
+        ));
+
+        let equivocated =
+            Transcript::new(messages.iter().cloned().chain([tampered]));
+        assert!(matches!(
+            verify_dkg(setup, &equivocated, None),
+            Err(TranscriptError::Equivocation(1))
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn key_refresh() {
+        let old_shares = gen_keyshares(2, 3, None).await;
+        let public_key = old_shares[0].public_key();
+
+        let parties = setup_key_refresh(
+            2,
+            3,
+            None,
+            old_shares
+                .iter()
+                .map(|share| KeyshareForRefresh::from_keyshare(share, None))
+                .collect(),
+        );
+
+        let recorder = Recorder::default();
+        let new_shares: Vec<_> =
+            join_all(parties.iter().map(|(setup, seed, share)| {
+                key_refresh::run(
+                    setup,
+                    *seed,
+                    recorder.connect(),
+                    share.clone(),
+                )
+            }))
+            .await
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+
+        let output = verify_key_refresh(
+            &parties[0].0,
+            &Transcript::new(recorder.messages()),
+            &public_key,
+            &[],
+            Some(&new_shares[0].root_chain_code()),
+        )
+        .unwrap();
+
+        for (party_id, big_s) in output.public_shares.iter().enumerate() {
+            assert_eq!(big_s, &new_shares[0].big_s(party_id as u8));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn quorum_change() {
+        let old_shares = gen_keyshares(2, 3, None).await;
+        let parties = setup_quorum_change_threshold(&old_shares, 3, &[0; 3]);
+
+        let recorder = Recorder::default();
+        let mut new_shares: Vec<_> =
+            join_all(parties.iter().map(|(setup, seed)| {
+                quorum_change::run(setup, *seed, recorder.connect())
+            }))
+            .await
+            .into_iter()
+            .filter_map(Result::unwrap)
+            .collect();
+
+        new_shares.sort_by_key(|share| share.party_id);
+
+        let output = verify_quorum_change(
+            &parties[0].0,
+            &Transcript::new(recorder.messages()),
+        )
+        .unwrap();
+
+        assert_eq!(output.public_key, old_shares[0].public_key());
+        for (party_id, big_s) in output.public_shares.iter().enumerate() {
+            assert_eq!(big_s, &new_shares[0].big_s(party_id as u8));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn dsg() {
+        let shares = gen_keyshares(2, 3, None).await;
+        let parties = setup_dsg(None, &shares[0..2], "m");
+
+        let recorder = Recorder::default();
+        let signatures = join_all(parties.iter().map(|(setup, seed)| {
+            sign::run(setup, *seed, recorder.connect())
+        }))
+        .await;
+
+        let (signature, _) = signatures[0].as_ref().unwrap();
+        let transcript = Transcript::new(recorder.messages());
+
+        let params = DsgParams::from_setup(&parties[0].0).unwrap();
+        assert_eq!(params.public_key, shares[0].public_key());
+
+        let output =
+            verify_dsg(&parties[0].0, &params, &transcript, signature)
+                .unwrap();
+        assert_eq!(output.p2p, 4);
+
+        let (r, s) = signature.split_scalars();
+        let other = Signature::from_scalars(r, (*s + Scalar::ONE).to_bytes())
+            .unwrap();
+
+        assert!(matches!(
+            verify_dsg(&parties[0].0, &params, &transcript, &other),
+            Err(TranscriptError::InvalidSignatureShares)
+        ));
+
+        let other = DsgParams {
+            public_key: shares[0].public_key() * Scalar::from(2u64),
+            ..params
+        };
+
+        assert!(matches!(
+            verify_dsg(&parties[0].0, &other, &transcript, signature),
+            Err(TranscriptError::InvalidSignatureShares)
+        ));
+    }
+}
@@ -0,0 +1,275 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Verification of transcripts of DKG and key refresh.
+
+use std::mem;
+
+use k256::{
+    elliptic_curve::subtle::ConstantTimeEq, NonZeroScalar, ProjectivePoint,
+};
+use sha2::{Digest, Sha256};
+
+use sl_mpc_mate::{
+    math::{polynomial_coeff_multipliers, GroupPolynomial},
+    SessionId,
+};
+use sl_oblivious::{
+    label::Label, utils::TranscriptProtocol, zkproofs::DLogProof,
+};
+
+use crate::{
+    keygen::{
+        constants::*,
+        dkg::{
+            hash_commitment, session_id_with_chain_code, signing_context,
+            verify_dlog_proofs,
+        },
+        utils::check_secret_recovery,
+    },
+    proto::{FixedExternalSize, PointBytes},
+    setup::KeygenSetupMessage,
+};
+
+use super::{Checker, PublicOutput, Transcript, TranscriptError};
+
+const POINT: usize = mem::size_of::<PointBytes>();
+
+/// Verifies a transcript of DKG.
+///
+/// DLog proofs of the last round are bound to the root chain code of
+/// the key, which parties exchange in encrypted messages. They are
+/// verified only if `root_chain_code` is given, e.g. taken from an
+/// extended public key.
+///
+/// # Arguments
+///
+/// * `setup` - Setup of any participant of the execution
+/// * `transcript` - Messages of the execution
+/// * `root_chain_code` - Optional root chain code of the key
+///
+/// # Returns
+///
+/// The public key and public key shares of the generated key.
+pub fn verify_dkg<S: KeygenSetupMessage>(
+    setup: &S,
+    transcript: &Transcript,
+    root_chain_code: Option<&[u8; 32]>,
+) -> Result<PublicOutput, TranscriptError> {
+    verify(setup, transcript, &DKG_LABEL, root_chain_code, None)
+}
+
+/// Verifies a transcript of key refresh.
+///
+/// In addition to checks of [`verify_dkg()`], the polynomials of
+/// parties which lost their key shares must have zero constant terms,
+/// and the refreshed key must have the expected public key.
+///
+/// # Arguments
+///
+/// * `setup` - Setup of any participant of the execution
+/// * `transcript` - Messages of the execution
+/// * `expected_public_key` - Public key of the refreshed key
+/// * `lost_keyshare_party_ids` - Parties which lost their key shares
+/// * `root_chain_code` - Optional root chain code of the key
+pub fn verify_key_refresh<S: KeygenSetupMessage>(
+    setup: &S,
+    transcript: &Transcript,
+    expected_public_key: &ProjectivePoint,
+    lost_keyshare_party_ids: &[u8],
+    root_chain_code: Option<&[u8; 32]>,
+) -> Result<PublicOutput, TranscriptError> {
+    let output = verify(
+        setup,
+        transcript,
+        &KEY_REFRESH_LABEL,
+        root_chain_code,
+        Some(lost_keyshare_party_ids),
+    )?;
+
+    if &output.public_key != expected_public_key {
+        return Err(TranscriptError::InvalidPublicKey);
+    }
+
+    Ok(output)
+}
+
+#[allow(non_snake_case)]
+fn verify<S: KeygenSetupMessage>(
+    setup: &S,
+    transcript: &Transcript,
+    label: &Label,
+    root_chain_code: Option<&[u8; 32]>,
+    lost_keyshare_party_ids: Option<&[u8]>,
+) -> Result<PublicOutput, TranscriptError> {
+    let T = setup.threshold() as usize;
+    let N = setup.total_participants();
+    let static_keys = setup.encryption_keys().is_some();
+
+    let parties: Vec<usize> = (0..N).collect();
+    let mut checker =
+        Checker::new(transcript, setup, signing_context(setup, label));
+
+    let mut sid_i_list = Vec::with_capacity(N);
+    let mut commitment_list = Vec::with_capacity(N);
+    let mut x_i_list: Vec<NonZeroScalar> = Vec::with_capacity(N);
+
+    for party_id in 0..N {
+        let mut msg = checker.broadcast(party_id, DKG_MSG_R1)?;
+
+        let sid: SessionId = msg.take(32)?;
+        let commitment: [u8; 32] = msg.take(32)?;
+        let x_i: NonZeroScalar = msg.take(32)?;
+        let enc_pub_key: Vec<u8> = msg.take(msg.rest(0)?)?;
+        msg.end()?;
+
+        if static_keys && !enc_pub_key.is_empty() {
+            return Err(TranscriptError::InvalidMessage(party_id));
+        }
+
+        if x_i_list.iter().any(|x| x.ct_eq(&x_i).into()) {
+            return Err(TranscriptError::InvalidMessage(party_id));
+        }
+
+        sid_i_list.push(sid);
+        commitment_list.push(commitment);
+        x_i_list.push(x_i);
+    }
+
+    checker.echo(DKG_MSG_R1, &parties)?;
+
+    let final_session_id: [u8; 32] = sid_i_list
+        .iter()
+        .fold(Sha256::new(), |hash, sid| hash.chain_update(sid))
+        .finalize()
+        .into();
+
+    let mut big_f_vec = GroupPolynomial::identity(T);
+
+    for party_id in 0..N {
+        let mut msg = checker.broadcast(party_id, DKG_MSG_R2)?;
+
+        let big_f_i_vec: GroupPolynomial<ProjectivePoint> =
+            msg.take(T * POINT)?;
+        let r_i: [u8; 32] = msg.take(32)?;
+        let _commitment_2: [u8; 32] = msg.take(32)?;
+        let dlog_proofs: Vec<DLogProof> = msg.take(T * DLogProof::SIZE)?;
+        msg.end()?;
+
+        let commit_hash = hash_commitment(
+            &sid_i_list[party_id],
+            party_id,
+            setup.participant_rank(party_id) as usize,
+            &x_i_list[party_id],
+            &big_f_i_vec,
+            &r_i,
+        );
+
+        if commit_hash != commitment_list[party_id] {
+            return Err(TranscriptError::InvalidCommitment(party_id));
+        }
+
+        let mut points = big_f_i_vec.points();
+        if let Some(lost) = lost_keyshare_party_ids {
+            // the constant term of a party which lost its key
+            // share is zero
+            if lost.contains(&(party_id as u8))
+                && points.next() != Some(&ProjectivePoint::IDENTITY)
+            {
+                return Err(TranscriptError::InvalidMessage(party_id));
+            }
+        }
+        if points.any(|p| p == &ProjectivePoint::IDENTITY) {
+            return Err(TranscriptError::InvalidMessage(party_id));
+        }
+
+        verify_dlog_proofs(
+            &final_session_id,
+            party_id,
+            &dlog_proofs,
+            &big_f_i_vec.coeffs,
+        )
+        .map_err(|_| TranscriptError::InvalidDLogProof(party_id))?;
+
+        big_f_vec.add_mut(&big_f_i_vec);
+    }
+
+    checker.echo(DKG_MSG_R2, &parties)?;
+
+    for sender in 0..N {
+        for receiver in (0..N).filter(|&p| p != sender) {
+            checker.p2p(sender, receiver, DKG_MSG_OT1)?;
+            checker.p2p(sender, receiver, DKG_MSG_R3)?;
+        }
+    }
+
+    let public_key = big_f_vec.get_constant();
+
+    let session_id = root_chain_code
+        .map(|code| session_id_with_chain_code(&final_session_id, code));
+
+    let mut big_s_list = Vec::with_capacity(N);
+
+    for (party_id, x_i) in x_i_list.iter().enumerate() {
+        let mut msg = checker.broadcast(party_id, DKG_MSG_R4)?;
+
+        let party_public_key: ProjectivePoint = msg.take(POINT)?;
+        let big_s_i: ProjectivePoint = msg.take(POINT)?;
+        let proof: DLogProof = msg.take(DLogProof::SIZE)?;
+        msg.end()?;
+
+        if party_public_key != public_key {
+            return Err(TranscriptError::PublicKeyMismatch(party_id));
+        }
+
+        if let Some(session_id) = &session_id {
+            let mut transcript = merlin::Transcript::new_dlog_proof(
+                session_id,
+                party_id,
+                &DLOG_PROOF2_LABEL,
+                &DKG_LABEL,
+            );
+
+            if proof
+                .verify(
+                    &big_s_i,
+                    &ProjectivePoint::GENERATOR,
+                    &mut transcript,
+                )
+                .unwrap_u8()
+                == 0
+            {
+                return Err(TranscriptError::InvalidDLogProof(party_id));
+            }
+        }
+
+        let coeff_multipliers = polynomial_coeff_multipliers(
+            x_i,
+            setup.participant_rank(party_id) as usize,
+            N,
+        );
+
+        let expected_point: ProjectivePoint = big_f_vec
+            .points()
+            .zip(coeff_multipliers)
+            .map(|(point, coeff)| point * &coeff)
+            .sum();
+
+        if expected_point != big_s_i {
+            return Err(TranscriptError::FailedFeldmanVerify(party_id));
+        }
+
+        big_s_list.push(big_s_i);
+    }
+
+    checker.echo(DKG_MSG_R4, &parties)?;
+
+    let rank_list = (0..N)
+        .map(|p| setup.participant_rank(p))
+        .collect::<Vec<_>>();
+
+    check_secret_recovery(&x_i_list, &rank_list, &big_s_list, &public_key)
+        .map_err(|_| TranscriptError::InvalidPublicKey)?;
+
+    Ok(checker.output(public_key, big_s_list))
+}
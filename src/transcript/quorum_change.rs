@@ -0,0 +1,189 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Verification of transcripts of quorum change.
+
+use std::mem;
+
+use k256::{NonZeroScalar, ProjectivePoint, Scalar};
+
+use sl_mpc_mate::{
+    math::{polynomial_coeff_multipliers, GroupPolynomial},
+    SessionId,
+};
+
+use crate::{
+    keygen::{
+        constants::*, quorum_change::hash_commitment_1,
+        utils::check_secret_recovery, Keyshare,
+    },
+    proto::{PointBytes, SigningContext},
+    setup::{approval::quorum_change_setup_hash, QuorumChangeSetupMessage},
+    VERSION,
+};
+
+use super::{Checker, PublicOutput, Transcript, TranscriptError};
+
+/// Verifies a transcript of quorum change.
+///
+/// Old parties commit to polynomials sharing their additive shares of
+/// the private key and open the commitments in the second broadcast
+/// round. The constant terms of the polynomials must sum up to the
+/// expected public key of the setup.
+///
+/// # Arguments
+///
+/// * `setup` - Setup of any participant of the execution
+/// * `transcript` - Messages of the execution
+///
+/// # Returns
+///
+/// The public key and public key shares of new parties.
+#[allow(non_snake_case)]
+pub fn verify_quorum_change<S>(
+    setup: &S,
+    transcript: &Transcript,
+) -> Result<PublicOutput, TranscriptError>
+where
+    S: QuorumChangeSetupMessage<Keyshare, ProjectivePoint>,
+{
+    let NEW_T = setup.new_threshold() as usize;
+    let NEW_N = setup.new_party_indices().len();
+    let N = setup.total_participants();
+    let static_keys = setup.encryption_keys().is_some();
+
+    let old_parties = setup.old_party_indices();
+    let new_parties = setup.new_party_indices();
+    let parties: Vec<usize> = (0..N).collect();
+
+    let ctx = SigningContext::new(
+        &QC_LABEL,
+        VERSION,
+        &quorum_change_setup_hash(setup),
+    );
+    let mut checker = Checker::new(transcript, setup, ctx);
+
+    let mut old_party_ids = Vec::with_capacity(old_parties.len());
+    for &party_index in old_parties {
+        let (&party_id, trailer) =
+            checker.signed::<u8>(party_index, QC_MSG_R0)?;
+
+        if !trailer.is_empty() || old_party_ids.contains(&party_id) {
+            return Err(TranscriptError::InvalidMessage(party_index));
+        }
+
+        old_party_ids.push(party_id);
+    }
+
+    checker.echo(QC_MSG_R0, old_parties)?;
+
+    let mut sid_i_list = Vec::with_capacity(N);
+    let mut commitment1_list = Vec::with_capacity(N);
+
+    for party_index in 0..N {
+        let mut msg = checker.broadcast(party_index, QC_MSG_R1)?;
+
+        let sid: SessionId = msg.take(32)?;
+        let enc_pub_key: Vec<u8> = msg.take(msg.rest(32)?)?;
+        let commitment1: [u8; 32] = msg.take(32)?;
+        msg.end()?;
+
+        if static_keys && !enc_pub_key.is_empty() {
+            return Err(TranscriptError::InvalidMessage(party_index));
+        }
+
+        sid_i_list.push(sid);
+        commitment1_list.push(commitment1);
+    }
+
+    checker.echo(QC_MSG_R1, &parties)?;
+
+    for &sender in old_parties {
+        for &receiver in new_parties.iter().filter(|&&p| p != sender) {
+            checker.p2p(sender, receiver, QC_MSG_P2P_1)?;
+            checker.p2p(sender, receiver, QC_MSG_P2P_2)?;
+        }
+    }
+
+    let mut big_p_poly = GroupPolynomial::identity(NEW_T);
+
+    for &party_index in old_parties {
+        let mut msg = checker.broadcast(party_index, QC_MSG_R2)?;
+
+        let big_p_i_poly: GroupPolynomial<ProjectivePoint> =
+            msg.take(NEW_T * mem::size_of::<PointBytes>())?;
+        let r1_i: [u8; 32] = msg.take(32)?;
+        msg.end()?;
+
+        if big_p_i_poly
+            .points()
+            .any(|p| p == &ProjectivePoint::IDENTITY)
+        {
+            return Err(TranscriptError::InvalidMessage(party_index));
+        }
+
+        let commit_hash1 = hash_commitment_1(
+            &sid_i_list[party_index],
+            party_index,
+            &big_p_i_poly,
+            &r1_i,
+        );
+
+        if commit_hash1 != commitment1_list[party_index] {
+            return Err(TranscriptError::InvalidCommitment(party_index));
+        }
+
+        big_p_poly.add_mut(&big_p_i_poly);
+    }
+
+    checker.echo(QC_MSG_R2, old_parties)?;
+
+    let public_key = big_p_poly.get_constant();
+
+    if &public_key != setup.expected_public_key() {
+        return Err(TranscriptError::InvalidPublicKey);
+    }
+
+    for &sender in new_parties {
+        for &receiver in new_parties.iter().filter(|&&p| p != sender) {
+            checker.p2p(sender, receiver, QC_MSG_OT1)?;
+            checker.p2p(sender, receiver, QC_MSG_OT2)?;
+        }
+    }
+
+    let new_x_i_list: Vec<NonZeroScalar> = (1..=NEW_N as u32)
+        .map(Scalar::from)
+        .map(|s| NonZeroScalar::new(s).unwrap())
+        .collect();
+
+    let rank_list: Vec<u8> = (0..NEW_N)
+        .map(|party_id| setup.new_participant_rank(party_id as u8))
+        .collect();
+
+    let big_s_list: Vec<ProjectivePoint> = new_x_i_list
+        .iter()
+        .zip(&rank_list)
+        .map(|(x_i, &rank)| {
+            let coeff_multipliers =
+                polynomial_coeff_multipliers(x_i, rank as usize, NEW_T);
+
+            big_p_poly
+                .points()
+                .zip(coeff_multipliers)
+                .map(|(point, coeff)| point * &coeff)
+                .sum()
+        })
+        .collect();
+
+    if !rank_list.iter().all(|&r| r == 0) {
+        check_secret_recovery(
+            &new_x_i_list,
+            &rank_list,
+            &big_s_list,
+            &public_key,
+        )
+        .map_err(|_| TranscriptError::InvalidPublicKey)?;
+    }
+
+    Ok(checker.output(public_key, big_s_list))
+}
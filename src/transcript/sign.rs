@@ -0,0 +1,172 @@
+// Copyright (c) Silence Laboratories Pte. Ltd. All Rights Reserved.
+// This software is licensed under the Silence Laboratories License Agreement.
+
+//! Verification of transcripts of DSG.
+
+use k256::{
+    ecdsa::{signature::hazmat::PrehashVerifier, Signature, VerifyingKey},
+    sha2::{Digest, Sha256},
+    ProjectivePoint, Scalar,
+};
+
+use crate::{
+    proto::{decode_scalar, SigningContext},
+    setup::{
+        approval::sign_setup_hash, ProtocolParticipant, SignSetupMessage,
+    },
+    sign::{constants::*, messages::*},
+    VERSION,
+};
+
+use super::{Checker, PublicOutput, Transcript, TranscriptError};
+
+/// Public parameters of a DSG execution, see [`verify_dsg()`].
+#[derive(Clone, Debug)]
+pub struct DsgParams {
+    /// Public key the signature verifies under, i.e. derived by the
+    /// chain path of the execution
+    pub public_key: ProjectivePoint,
+
+    /// Final session ID of the key shares
+    pub final_session_id: [u8; 32],
+
+    /// Number of parties holding shares of the key
+    pub total_parties: u8,
+
+    /// Hash of the signed message
+    pub message_hash: [u8; 32],
+
+    /// Hash of the setup of the execution, see
+    /// [`sign_setup_hash()`](crate::setup::approval::sign_setup_hash)
+    pub setup_hash: [u8; 32],
+}
+
+impl DsgParams {
+    /// Returns public parameters of the execution of a setup. Only
+    /// public parts of the key share of the setup are used.
+    pub fn from_setup<S: SignSetupMessage>(
+        setup: &S,
+    ) -> Result<Self, TranscriptError> {
+        let keyshare = setup.keyshare();
+
+        let (_, public_key) = keyshare
+            .derive_with_offset(setup.chain_path())
+            .map_err(|_| TranscriptError::InvalidPublicKey)?;
+
+        Ok(Self {
+            public_key,
+            final_session_id: keyshare.final_session_id,
+            total_parties: keyshare.total_parties,
+            message_hash: setup.message_hash(),
+            setup_hash: sign_setup_hash(setup),
+        })
+    }
+}
+
+/// Verifies a transcript of DSG and a resulting signature.
+///
+/// The nonce `R` of the signature is exchanged in encrypted messages,
+/// so the verifier takes the signature and checks that its `s`
+/// component combines from the partial signatures of the last round,
+/// and that the signature verifies under the public key of `params`.
+///
+/// No key share is needed, see [`DsgParams::from_setup()`] to take
+/// the parameters from a setup of a signer.
+///
+/// # Arguments
+///
+/// * `setup` - Setup of any participant of the execution, used for
+///   message IDs and verifying keys of parties
+/// * `params` - Public parameters of the execution
+/// * `transcript` - Messages of the execution
+/// * `signature` - The signature to check
+///
+/// # Returns
+///
+/// The public key of `params`.
+pub fn verify_dsg<P: ProtocolParticipant>(
+    setup: &P,
+    params: &DsgParams,
+    transcript: &Transcript,
+    signature: &Signature,
+) -> Result<PublicOutput, TranscriptError> {
+    let t = setup.total_participants();
+
+    let ctx = SigningContext::new(&DSG_LABEL, VERSION, &params.setup_hash);
+    let mut checker = Checker::new(transcript, setup, ctx);
+
+    let mut party_ids = Vec::with_capacity(t);
+    let mut session_ids = Vec::with_capacity(t);
+
+    for party_idx in 0..t {
+        let (msg1, trailer) =
+            checker.signed::<SignMsg1>(party_idx, DSG_MSG_R1)?;
+
+        let enc_pk_mismatch = setup.encryption_keys().is_some_and(|keys| {
+            keys.public_keys()[party_idx].as_bytes() != &msg1.enc_pk
+        });
+
+        if !trailer.is_empty()
+            || enc_pk_mismatch
+            || msg1.party_id >= params.total_parties
+            || party_ids.contains(&msg1.party_id)
+        {
+            return Err(TranscriptError::InvalidMessage(party_idx));
+        }
+
+        party_ids.push(msg1.party_id);
+        session_ids.push(msg1.session_id);
+    }
+
+    let final_session_id: [u8; 32] = session_ids
+        .iter()
+        .fold(Sha256::new(), |hash, sid| hash.chain_update(sid))
+        .chain_update(params.final_session_id)
+        .finalize()
+        .into();
+
+    for sender in 0..t {
+        for receiver in (0..t).filter(|&p| p != sender) {
+            checker.p2p(sender, receiver, DSG_MSG_R2)?;
+            checker.p2p(sender, receiver, DSG_MSG_R3)?;
+        }
+    }
+
+    let mut sum_s_0 = Scalar::ZERO;
+    let mut sum_s_1 = Scalar::ZERO;
+
+    for party_idx in 0..t {
+        let (msg4, trailer) =
+            checker.signed::<SignMsg4>(party_idx, DSG_MSG_R4)?;
+
+        if !trailer.is_empty() {
+            return Err(TranscriptError::InvalidMessage(party_idx));
+        }
+
+        if msg4.session_id != final_session_id {
+            return Err(TranscriptError::SessionIdMismatch(party_idx));
+        }
+
+        sum_s_0 += decode_scalar(&msg4.s_0)
+            .ok_or(TranscriptError::InvalidMessage(party_idx))?;
+        sum_s_1 += decode_scalar(&msg4.s_1)
+            .ok_or(TranscriptError::InvalidMessage(party_idx))?;
+    }
+
+    let sum_s_1_inv = Option::<Scalar>::from(sum_s_1.invert())
+        .ok_or(TranscriptError::InvalidSignatureShares)?;
+    let s = sum_s_0 * sum_s_1_inv;
+
+    // the signature has a normalized s
+    let sign_s: Scalar = *signature.s();
+    if sign_s != s && sign_s != -s {
+        return Err(TranscriptError::InvalidSignatureShares);
+    }
+
+    VerifyingKey::from_affine(params.public_key.to_affine())
+        .map_err(|_| TranscriptError::InvalidPublicKey)?
+        .verify_prehash(&params.message_hash, signature)
+        .map_err(|_| TranscriptError::InvalidSignatureShares)?;
+
+    Ok(checker.output(params.public_key, vec![]))
+}